@@ -0,0 +1,444 @@
+//! Validation of many model/formula pairs in one process.
+//!
+//! The pairs are listed in a manifest, either as CSV with one `formula,model` pair per line
+//! or as a JSON array of `{"formula": ..., "model": ...}` objects.
+//! Relative paths are resolved against the directory of the manifest.
+//! All pairs are checked with the same checker, so the server checker keeps one Isabelle session for the whole batch.
+//! With several workers, each worker has its own checker and the pairs are distributed among them.
+//!
+//! Instead of a manifest, the pairs can be discovered in a directory or by a glob pattern:
+//! each formula file `foo.smt2` (or `foo.smt`) is paired with the model `foo.model` next to it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use crate::checker::{self, WorkQueue};
+use crate::error::Error;
+use crate::pipeline::{CheckerPipeline, Stage, StageStatistics};
+use crate::validation::{self, ValidationOptions, ValidationReport, ValidationResult};
+
+/// A model/formula pair to validate
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct BatchJob {
+    /// Path to the file containing the SMT formula
+    pub formula: PathBuf,
+    /// Path to the file containing the model
+    pub model: PathBuf,
+}
+
+/// The extensions of the formula files found by [discover_jobs]
+const FORMULA_EXTENSIONS: [&str; 2] = ["smt2", "smt"];
+/// The extension of the model paired with a formula file
+const MODEL_EXTENSION: &str = "model";
+
+/// The number of pairs of a batch with each result
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct BatchSummary {
+    pub valid: usize,
+    pub invalid: usize,
+    pub unknown: usize,
+    pub error: usize,
+    /// Formula files found without a model, which were not validated
+    pub missing_model: usize,
+    /// The outcomes of each stage of the checker pipeline
+    pub stages: BTreeMap<Stage, StageStatistics>,
+}
+
+impl BatchSummary {
+    /// Counts the result of a pair and the outcomes of the stages that validated it.
+    pub fn record(&mut self, result: &Result<ValidationResult, Error>, report: &ValidationReport) {
+        match result {
+            Ok(ValidationResult::Valid) => self.valid += 1,
+            Ok(ValidationResult::Invalid) => self.invalid += 1,
+            Ok(ValidationResult::Unknown(_)) => self.unknown += 1,
+            Err(_) => self.error += 1,
+        }
+        for stage in &report.stages {
+            self.stages.entry(stage.stage).or_default().record(stage);
+        }
+    }
+
+    /// Returns the number of formula files, with and without a model.
+    pub fn total(&self) -> usize {
+        self.valid + self.invalid + self.unknown + self.error + self.missing_model
+    }
+}
+
+/// Returns true iff the path is a glob pattern rather than the name of a file or directory.
+pub fn is_pattern(path: &str) -> bool {
+    path.contains(['*', '?'])
+}
+
+/// Finds the formula files below the directory, or matching the glob pattern, and pairs each with its model.
+/// In patterns, `*` and `?` match within a path component, and `**` matches any number of components.
+/// Returns the jobs, sorted by formula, and the formula files without a model.
+pub fn discover_jobs(path: &str) -> Result<(Vec<BatchJob>, Vec<PathBuf>), Error> {
+    let (base, pattern) = if is_pattern(path) {
+        let components: Vec<&str> = path.split('/').collect();
+        let literal = components.iter().take_while(|c| !is_pattern(c)).count();
+        let base = components[..literal].join("/");
+        let base = if base.is_empty() && !path.starts_with('/') {
+            ".".to_owned()
+        } else if base.is_empty() {
+            "/".to_owned()
+        } else {
+            base
+        };
+        (PathBuf::from(base), Some(components[literal..].join("/")))
+    } else {
+        (PathBuf::from(path), None)
+    };
+    if !base.is_dir() {
+        return Err(Error::Other(format!(
+            "{} is not a directory",
+            base.display()
+        )));
+    }
+    let mut formulas = vec![];
+    let mut dirs = vec![base.clone()];
+    while let Some(dir) = dirs.pop() {
+        let entries = fs::read_dir(&dir)
+            .map_err(|e| Error::Other(format!("Could not read {}: {}", dir.display(), e)))?;
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let is_formula = path
+                .extension()
+                .is_some_and(|e| FORMULA_EXTENSIONS.iter().any(|f| e == *f));
+            let relative = path.strip_prefix(&base).unwrap_or(&path);
+            let relative = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if is_formula
+                && pattern
+                    .as_ref()
+                    .is_none_or(|p| glob_match(p.as_bytes(), relative.as_bytes()))
+            {
+                formulas.push(path);
+            }
+        }
+    }
+    formulas.sort();
+    let (jobs, missing): (Vec<_>, Vec<_>) = formulas
+        .into_iter()
+        .map(|formula| BatchJob {
+            model: formula.with_extension(MODEL_EXTENSION),
+            formula,
+        })
+        .partition(|j| j.model.is_file());
+    Ok((jobs, missing.into_iter().map(|j| j.formula).collect()))
+}
+
+/// Returns true iff the path matches the glob pattern.
+fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+    match (pattern, path) {
+        ([], []) => true,
+        ([b'*', b'*', b'/', rest @ ..], _) => {
+            glob_match(rest, path) || (!path.is_empty() && glob_match(pattern, &path[1..]))
+        }
+        ([b'*', b'*', rest @ ..], _) => {
+            glob_match(rest, path) || (!path.is_empty() && glob_match(pattern, &path[1..]))
+        }
+        ([b'*', rest @ ..], _) => {
+            glob_match(rest, path)
+                || (path.first().is_some_and(|c| *c != b'/') && glob_match(pattern, &path[1..]))
+        }
+        ([b'?', rest @ ..], [c, path @ ..]) if *c != b'/' => glob_match(rest, path),
+        ([p, rest @ ..], [c, path @ ..]) if p == c => glob_match(rest, path),
+        _ => false,
+    }
+}
+
+/// Reads the jobs listed in a manifest.
+/// A manifest with the extension `.json` or starting with `[` is read as JSON, otherwise as CSV.
+pub fn read_manifest(path: &Path) -> Result<Vec<BatchJob>, Error> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| Error::Other(format!("Could not read {}: {}", path.display(), e)))?;
+    let is_json = path.extension().is_some_and(|e| e == "json") || content.trim().starts_with('[');
+    let jobs = if is_json {
+        serde_json::from_str(&content)
+            .map_err(|e| Error::Other(format!("Invalid manifest {}: {}", path.display(), e)))?
+    } else {
+        parse_csv(&content)?
+    };
+    let base = path.parent().unwrap_or(Path::new(""));
+    Ok(jobs
+        .into_iter()
+        .map(|j: BatchJob| BatchJob {
+            formula: base.join(j.formula),
+            model: base.join(j.model),
+        })
+        .collect())
+}
+
+/// Parses a CSV manifest.
+/// Empty lines, lines starting with `#`, and a `formula,model` header are ignored.
+fn parse_csv(content: &str) -> Result<Vec<BatchJob>, Error> {
+    let mut jobs = vec![];
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        match fields.as_slice() {
+            ["formula", "model"] if jobs.is_empty() => (),
+            [formula, model] => jobs.push(BatchJob {
+                formula: PathBuf::from(formula),
+                model: PathBuf::from(model),
+            }),
+            _ => {
+                return Err(Error::Other(format!(
+                    "Invalid manifest line {}: expected 'formula,model'",
+                    i + 1
+                )))
+            }
+        }
+    }
+    Ok(jobs)
+}
+
+type JobResult = (Result<ValidationResult, Error>, ValidationReport);
+
+/// Validates all jobs using `workers` concurrent checkers and passes each result to `on_result`.
+/// Each worker reuses its checker for all jobs it takes.
+/// The results are passed in the order of the jobs, each as soon as it and all results before it are available.
+/// If a reproduction bundle is requested, the bundle of each job is written to a subdirectory named after its index.
+pub fn validate_batch(
+    jobs: &[BatchJob],
+    theory_path: &Path,
+    options: &ValidationOptions,
+    workers: usize,
+    mut on_result: impl FnMut(&BatchJob, Result<ValidationResult, Error>, ValidationReport),
+) {
+    let workers = workers.clamp(1, jobs.len().max(1));
+    let queue = WorkQueue::new(jobs);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|s| {
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let queue = &queue;
+            s.spawn(move || {
+                let mut pipeline = None;
+                while let Some((i, job)) = queue.next() {
+                    let result =
+                        validate_job(i, jobs.len(), job, theory_path, options, &mut pipeline);
+                    if sender.send((i, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        let mut results = InOrder::default();
+        for (i, result) in receiver {
+            for (i, (result, report)) in results.push(i, result) {
+                on_result(&jobs[i], result, report);
+            }
+        }
+    });
+}
+
+/// Validates a single job, reusing the given checker pipeline.
+fn validate_job(
+    index: usize,
+    count: usize,
+    job: &BatchJob,
+    theory_path: &Path,
+    options: &ValidationOptions,
+    pipeline: &mut Option<CheckerPipeline>,
+) -> JobResult {
+    log::info!(
+        target: checker::PROGRESS_TARGET,
+        "Validating {} against {} ({}/{})",
+        job.model.display(),
+        job.formula.display(),
+        index + 1,
+        count
+    );
+    let inputs = fs::read_to_string(&job.formula)
+        .and_then(|formula| fs::read_to_string(&job.model).map(|model| (formula, model)));
+    let (formula, model) = match inputs {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            let e = Error::Other(format!("Could not read job {}: {}", index + 1, e));
+            return (Err(e), ValidationReport::default());
+        }
+    };
+    let mut job_options = options.clone();
+    job_options.repro_dir = options
+        .repro_dir
+        .as_ref()
+        .map(|d| d.join(index.to_string()));
+    job_options.log_isabelle = options
+        .log_isabelle
+        .as_ref()
+        .map(|d| d.join(format!("{}.log", index)));
+    validation::validate_reusing(model, formula, theory_path, &job_options, pipeline)
+}
+
+/// Collects results arriving in any order and releases them in the order of their indices.
+struct InOrder<T> {
+    next: usize,
+    pending: BTreeMap<usize, T>,
+}
+
+impl<T> Default for InOrder<T> {
+    fn default() -> Self {
+        Self {
+            next: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> InOrder<T> {
+    /// Adds the result with the given index and returns all results that are now next in order.
+    fn push(&mut self, index: usize, result: T) -> Vec<(usize, T)> {
+        self.pending.insert(index, result);
+        let mut ready = vec![];
+        while let Some(result) = self.pending.remove(&self.next) {
+            ready.push((self.next, result));
+            self.next += 1;
+        }
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("jobs.csv");
+        fs::write(
+            &manifest,
+            "formula,model\n# comment\na.smt2, a.model\n\n/abs/b.smt2,b.model\n",
+        )
+        .unwrap();
+        let jobs = read_manifest(&manifest).unwrap();
+        assert_eq!(
+            jobs,
+            vec![
+                BatchJob {
+                    formula: dir.path().join("a.smt2"),
+                    model: dir.path().join("a.model"),
+                },
+                BatchJob {
+                    formula: PathBuf::from("/abs/b.smt2"),
+                    model: dir.path().join("b.model"),
+                },
+            ]
+        );
+        assert!(parse_csv("a.smt2\n").is_err());
+    }
+
+    #[test]
+    fn discover_suite() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        for file in [
+            "a.smt2",
+            "a.model",
+            "b.smt",
+            "notes.txt",
+            "sub/c.smt2",
+            "sub/c.model",
+        ] {
+            fs::write(dir.path().join(file), "").unwrap();
+        }
+        let root = dir.path().display().to_string();
+        let (jobs, missing) = discover_jobs(&root).unwrap();
+        let formulas: Vec<_> = jobs.iter().map(|j| j.formula.clone()).collect();
+        assert_eq!(formulas, [dir.path().join("a.smt2"), sub.join("c.smt2")]);
+        assert_eq!(jobs[1].model, sub.join("c.model"));
+        assert_eq!(missing, [dir.path().join("b.smt")]);
+
+        let (jobs, missing) = discover_jobs(&format!("{}/*.smt2", root)).unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert!(missing.is_empty());
+        let (jobs, _) = discover_jobs(&format!("{}/**/c.smt?", root)).unwrap();
+        assert_eq!(jobs[0].formula, sub.join("c.smt2"));
+        assert!(discover_jobs(&format!("{}/none/*.smt2", root)).is_err());
+    }
+
+    #[test]
+    fn results_in_order() {
+        let mut results = InOrder::default();
+        assert!(results.push(1, "b").is_empty());
+        assert!(results.push(3, "d").is_empty());
+        assert_eq!(results.push(0, "a"), vec![(0, "a"), (1, "b")]);
+        assert_eq!(results.push(2, "c"), vec![(2, "c"), (3, "d")]);
+    }
+
+    /// Proves every lemma
+    struct ProvesAll;
+
+    impl checker::LemmaChecker for ProvesAll {
+        fn check(&mut self, _lemma: &crate::lemma::Lemma) -> Result<checker::CheckResult, Error> {
+            Ok(checker::CheckResult::OK)
+        }
+    }
+
+    #[test]
+    fn checkers_follow_imports() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            ("int.smt2", "(declare-const x Int)(assert (> x 0))"),
+            ("int.model", "(define-fun x () Int 1)"),
+            ("real.smt2", "(declare-const r Real)(assert (> r 0.5))"),
+            ("real.model", "(define-fun r () Real 1.0)"),
+        ];
+        for (file, content) in files {
+            fs::write(dir.path().join(file), content).unwrap();
+        }
+        let job = |name: &str| BatchJob {
+            formula: dir.path().join(format!("{}.smt2", name)),
+            model: dir.path().join(format!("{}.model", name)),
+        };
+        let jobs = [job("int"), job("real"), job("real")];
+        let created = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut options = ValidationOptions {
+            builtin_spec: Some(crate::convert::BuiltinSpec::QfSlia),
+            ..Default::default()
+        };
+        let counter = created.clone();
+        options.register_checker(checker::CustomChecker::new("counting", move |_| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(Box::new(ProvesAll))
+        }));
+        let mut results = vec![];
+        validate_batch(&jobs, dir.path(), &options, 1, |_, result, _| {
+            results.push(result.unwrap())
+        });
+        assert_eq!(results, vec![ValidationResult::Valid; 3]);
+        // The real job needs the reals imported, so its checker is not the one of the integer job
+        assert_eq!(created.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn json_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("jobs.json");
+        fs::write(
+            &manifest,
+            r#"[{ "formula": "a.smt2", "model": "a.model" }]"#,
+        )
+        .unwrap();
+        let jobs = read_manifest(&manifest).unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].model, dir.path().join("a.model"));
+    }
+}
@@ -0,0 +1,125 @@
+//! A persistent cache of validation verdicts.
+//!
+//! The verdict of a validation is stored under a hash of everything it depends on: the version of smtmv, the formula,
+//! the model, the specs and theories, the proof method, and the options changing the generated lemma.
+//! Only definite verdicts are cached; unknown results are validated again, since a later run may have more time.
+//! Unlike [certificates](crate::certificate), which skip single lemmas, a cache hit skips the whole validation.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::certificate::update_framed;
+use crate::validation::ValidationResult;
+
+/// The directory of the cache below `$XDG_CACHE_HOME` or `~/.cache`
+const CACHE_DIR_NAME: &str = "smtmv";
+
+/// A cached verdict
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct CacheEntry {
+    /// The hash of the inputs of the validation, see [cache_key]
+    key: String,
+    /// `valid` or `invalid`
+    verdict: String,
+}
+
+/// Returns the default cache directory, `$XDG_CACHE_HOME/smtmv` or `~/.cache/smtmv`.
+/// Returns None if neither variable is set.
+pub fn default_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".cache")))
+        .map(|d| d.join(CACHE_DIR_NAME))
+}
+
+/// Returns the hash identifying a validation by its inputs.
+/// Each input is a named part, e.g. the formula or the proof method.
+pub fn cache_key(parts: &[(&str, &str)]) -> String {
+    let mut hasher = Sha256::new();
+    for (name, content) in parts {
+        hasher.update(format!("{}:", name));
+        update_framed(&mut hasher, content);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// A directory of cached verdicts, one JSON file per key
+pub struct ResultCache {
+    dir: PathBuf,
+}
+
+impl ResultCache {
+    /// Opens the cache in the given directory, creating the directory if needed.
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// Returns the cached verdict for the key, if any.
+    pub fn lookup(&self, key: &str) -> Option<ValidationResult> {
+        let content = fs::read_to_string(self.path(key)).ok()?;
+        let entry: CacheEntry = match serde_json::from_str(&content) {
+            Ok(e) => e,
+            Err(e) => {
+                log::warn!("Ignoring malformed cache entry {}: {}", key, e);
+                return None;
+            }
+        };
+        match entry.verdict.as_str() {
+            _ if entry.key != key => None,
+            "valid" => Some(ValidationResult::Valid),
+            "invalid" => Some(ValidationResult::Invalid),
+            v => {
+                log::warn!("Ignoring cache entry {} with verdict '{}'", key, v);
+                None
+            }
+        }
+    }
+
+    /// Stores the verdict for the key if it is definite.
+    pub fn store(&self, key: &str, result: &ValidationResult) -> io::Result<()> {
+        if let ValidationResult::Unknown(_) = result {
+            return Ok(());
+        }
+        let entry = CacheEntry {
+            key: key.to_owned(),
+            verdict: result.verdict().to_owned(),
+        };
+        let json = serde_json::to_string_pretty(&entry).map_err(io::Error::other)?;
+        fs::write(self.path(key), json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::UnknownReason;
+
+    #[test]
+    fn cache_verdicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResultCache::open(&dir.path().join("cache")).unwrap();
+        let key = cache_key(&[("formula", "(assert a)"), ("model", "")]);
+        assert_ne!(key, cache_key(&[("formula", "(assert a)model:0\n")]));
+        assert_eq!(cache.lookup(&key), None);
+        cache.store(&key, &ValidationResult::Invalid).unwrap();
+        assert_eq!(cache.lookup(&key), Some(ValidationResult::Invalid));
+
+        let other = cache_key(&[("formula", "(assert b)")]);
+        cache
+            .store(&other, &ValidationResult::Unknown(UnknownReason::Timeout))
+            .unwrap();
+        assert_eq!(cache.lookup(&other), None);
+    }
+}
@@ -0,0 +1,150 @@
+//! Cancellation of validations in flight, see [crate::ValidationOptions::cancellation].
+//!
+//! A validation with a cancellation token makes it the current token of its thread while it runs, as it starts the
+//! transcript of [crate::ValidationOptions::log_isabelle], so the checkers find it without passing it through the pipeline.
+//! Once the token is cancelled, the processes the checkers run are killed, the session of the Isabelle server is
+//! stopped, and the pipeline runs no further stage, so the validation promptly returns
+//! [UnknownReason::Cancelled](crate::UnknownReason::Cancelled).
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::Notify;
+
+/// A token to cancel the validations it is given to, from any thread.
+/// Clones share the cancellation: cancelling one cancels all of them.
+#[derive(Clone, Debug, Default)]
+pub struct Cancellation(Arc<State>);
+
+#[derive(Debug, Default)]
+struct State {
+    cancelled: AtomicBool,
+    /// Wakes the tasks waiting for the cancellation
+    notify: Notify,
+}
+
+impl Cancellation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the validations using this token. Cancelling a token again has no effect.
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Waits until the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            // Registered before checking the flag, so that a cancellation in between is not missed
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Cancellation>> = const { RefCell::new(None) };
+}
+
+/// Makes the token the current one of this thread until the returned guard is dropped,
+/// which restores the previous one.
+pub(crate) fn enter(token: Option<Cancellation>) -> Entered {
+    Entered(CURRENT.with(|c| c.replace(token)))
+}
+
+/// Restores the previous token of the thread when dropped, see [enter]
+pub(crate) struct Entered(Option<Cancellation>);
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        CURRENT.with(|c| *c.borrow_mut() = self.0.take());
+    }
+}
+
+/// Returns the current token of this thread.
+pub(crate) fn current() -> Option<Cancellation> {
+    CURRENT.with(|c| c.borrow().clone())
+}
+
+/// Returns whether the validation running on this thread was cancelled.
+pub(crate) fn is_cancelled() -> bool {
+    CURRENT.with(|c| c.borrow().as_ref().is_some_and(Cancellation::is_cancelled))
+}
+
+/// Waits until the token is cancelled, forever if there is none.
+pub(crate) async fn cancelled(token: Option<&Cancellation>) {
+    match token {
+        Some(token) => token.cancelled().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Cancels the token when dropped unless disarmed, e.g. when the future running a validation is dropped
+pub(crate) struct CancelOnDrop(Option<Cancellation>);
+
+impl CancelOnDrop {
+    pub(crate) fn new(token: Cancellation) -> Self {
+        Self(Some(token))
+    }
+
+    /// Keeps the token from being cancelled.
+    pub(crate) fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = self.0.take() {
+            token.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn cancel_from_another_thread() {
+        let token = Cancellation::new();
+        assert!(!is_cancelled());
+        {
+            let _entered = enter(Some(token.clone()));
+            assert!(!is_cancelled());
+            let other = token.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                other.cancel();
+            });
+            tokio::runtime::Builder::new_current_thread()
+                .enable_time()
+                .build()
+                .unwrap()
+                .block_on(async {
+                    tokio::time::timeout(Duration::from_secs(10), cancelled(current().as_ref()))
+                        .await
+                        .unwrap()
+                });
+            assert!(is_cancelled());
+        }
+        assert!(current().is_none());
+
+        let token = Cancellation::new();
+        CancelOnDrop::new(token.clone()).disarm();
+        assert!(!token.is_cancelled());
+        drop(CancelOnDrop::new(token.clone()));
+        assert!(token.is_cancelled());
+    }
+}
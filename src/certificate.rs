@@ -0,0 +1,240 @@
+//! Proof certificates for lemmas that were already proven.
+//!
+//! After a lemma was proven, a small certificate is stored under the hash of the lemma.
+//! The hash covers the premises, conclusions, proof method, and imports of the lemma, but not its name,
+//! so certificates are shared between different formulas producing the same (sub)lemmas.
+//! A certificate is only accepted if it was created for the same version of the theories.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::checker::{CheckResult, Counterexample, LemmaChecker};
+use crate::error::Error;
+use crate::lemma::Lemma;
+
+/// A record that a lemma was proven
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Certificate {
+    /// The hash of the proven lemma, see [lemma_hash]
+    pub lemma_hash: String,
+    /// The version of the theories the lemma was proven with
+    pub theory_version: String,
+    /// The proof method that proved the lemma
+    pub tactic: String,
+    /// The time the prover took, in milliseconds
+    pub prover_time_ms: u64,
+}
+
+/// Hashes the content preceded by its length, so that different splits of the same text do not collide.
+pub(crate) fn update_framed(hasher: &mut Sha256, content: &str) {
+    hasher.update(format!("{}\n{}\n", content.len(), content));
+}
+
+/// Returns the hash identifying the lemma, independent of its name.
+pub fn lemma_hash(lemma: &Lemma, imports: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    for (section, items) in [
+        ("imports", imports),
+        ("premises", lemma.premises()),
+        ("conclusions", lemma.conclusions()),
+    ] {
+        hasher.update(format!("{}:{}\n", section, items.len()));
+        for item in items {
+            update_framed(&mut hasher, item);
+        }
+    }
+    // Only hashed if present, so certificates of lemmas without fixed types or declarations stay valid
+    if !lemma.fixes().is_empty() {
+        hasher.update(format!("fixes:{}\n", lemma.fixes().len()));
+        for (var, ty) in lemma.fixes() {
+            hasher.update(format!("{}::{}\n", var, ty));
+        }
+    }
+    if !lemma.declarations().is_empty() {
+        hasher.update(format!("declarations:{}\n", lemma.declarations().len()));
+        for declaration in lemma.declarations() {
+            update_framed(&mut hasher, declaration);
+        }
+    }
+    hasher.update(format!("tactic:{}\n", lemma.tactic()));
+    format!("{:x}", hasher.finalize())
+}
+
+/// A directory of certificates, one JSON file per lemma hash
+pub struct CertificateStore {
+    dir: PathBuf,
+    /// The version of the theories lemmas are checked with
+    theory_version: String,
+    /// The theories imported by the validation theory
+    imports: Vec<String>,
+}
+
+impl CertificateStore {
+    /// Opens the store in the given directory, creating the directory if needed.
+    pub fn open(dir: PathBuf, theory_version: &str, imports: &[String]) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            theory_version: theory_version.to_owned(),
+            imports: imports.to_vec(),
+        })
+    }
+
+    fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", hash))
+    }
+
+    /// Returns the certificate of the lemma if there is a valid one.
+    /// Certificates for a different lemma hash or theory version are ignored.
+    pub fn lookup(&self, lemma: &Lemma) -> Option<Certificate> {
+        let hash = lemma_hash(lemma, &self.imports);
+        let content = fs::read_to_string(self.path(&hash)).ok()?;
+        let certificate: Certificate = match serde_json::from_str(&content) {
+            Ok(c) => c,
+            Err(e) => {
+                log::warn!("Ignoring malformed certificate {}: {}", hash, e);
+                return None;
+            }
+        };
+        if certificate.lemma_hash != hash {
+            log::warn!("Ignoring certificate {} for a different lemma", hash);
+            None
+        } else if certificate.theory_version != self.theory_version {
+            log::info!(
+                "Ignoring certificate {} for theory version {}",
+                hash,
+                certificate.theory_version
+            );
+            None
+        } else {
+            Some(certificate)
+        }
+    }
+
+    /// Stores a certificate for the lemma, replacing any existing one.
+    pub fn store(&self, lemma: &Lemma, prover_time_ms: u64) -> io::Result<Certificate> {
+        let certificate = Certificate {
+            lemma_hash: lemma_hash(lemma, &self.imports),
+            theory_version: self.theory_version.clone(),
+            tactic: lemma.tactic(),
+            prover_time_ms,
+        };
+        let json = serde_json::to_string_pretty(&certificate).map_err(io::Error::other)?;
+        fs::write(self.path(&certificate.lemma_hash), json)?;
+        Ok(certificate)
+    }
+}
+
+/// Accepts lemmas with a valid certificate and checks all other lemmas with the inner checker.
+/// Certificates are stored for all lemmas the inner checker proves.
+pub struct CertifiedChecker<C: LemmaChecker> {
+    inner: C,
+    store: CertificateStore,
+    /// Ignore existing certificates and check all lemmas again
+    recheck: bool,
+}
+
+impl<C: LemmaChecker> CertifiedChecker<C> {
+    pub fn new(inner: C, store: CertificateStore, recheck: bool) -> Self {
+        Self {
+            inner,
+            store,
+            recheck,
+        }
+    }
+}
+
+impl<C: LemmaChecker> LemmaChecker for CertifiedChecker<C> {
+    fn check(&mut self, lemma: &Lemma) -> Result<CheckResult, Error> {
+        if !self.recheck {
+            if let Some(certificate) = self.store.lookup(lemma) {
+                log::info!(
+                    "Lemma was already proven by '{}' (certificate {})",
+                    certificate.tactic,
+                    certificate.lemma_hash
+                );
+                return Ok(CheckResult::OK);
+            }
+        }
+
+        let start = Instant::now();
+        let result = self.inner.check(lemma)?;
+        if let CheckResult::OK = result {
+            let elapsed = start.elapsed().as_millis() as u64;
+            match self.store.store(lemma, elapsed) {
+                Ok(c) => log::debug!("Stored certificate {}", c.lemma_hash),
+                Err(e) => log::warn!("Could not store certificate: {}", e),
+            }
+        }
+        Ok(result)
+    }
+
+    fn find_counterexample(&mut self, lemma: &Lemma) -> Result<Option<Counterexample>, Error> {
+        self.inner.find_counterexample(lemma)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Proves every lemma and counts the calls
+    struct CountingChecker(usize);
+
+    impl LemmaChecker for CountingChecker {
+        fn check(&mut self, _lemma: &Lemma) -> Result<CheckResult, Error> {
+            self.0 += 1;
+            Ok(CheckResult::OK)
+        }
+    }
+
+    fn lemma(name: &str, conclusion: &str) -> Lemma {
+        let mut lemma = Lemma::new(name);
+        lemma.add_premise("x = 1").add_conclusion(conclusion);
+        lemma
+    }
+
+    #[test]
+    fn hash_ignores_lemma_name() {
+        let imports = vec!["smt.Core".to_owned()];
+        assert_eq!(
+            lemma_hash(&lemma("a", "x > 0"), &imports),
+            lemma_hash(&lemma("b", "x > 0"), &imports)
+        );
+        assert_ne!(
+            lemma_hash(&lemma("a", "x > 0"), &imports),
+            lemma_hash(&lemma("a", "x > 1"), &imports)
+        );
+        assert_ne!(
+            lemma_hash(&lemma("a", "x > 0"), &imports),
+            lemma_hash(&lemma("a", "x > 0"), &[])
+        );
+    }
+
+    #[test]
+    fn reuses_certificates() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = |version| CertificateStore::open(dir.path().to_owned(), version, &[]).unwrap();
+
+        let mut checker = CertifiedChecker::new(CountingChecker(0), store("0.1"), false);
+        assert!(matches!(
+            checker.check(&lemma("a", "x > 0")).unwrap(),
+            CheckResult::OK
+        ));
+        checker.check(&lemma("b", "x > 0")).unwrap();
+        assert_eq!(checker.inner.0, 1);
+
+        let mut recheck = CertifiedChecker::new(CountingChecker(0), store("0.1"), true);
+        recheck.check(&lemma("a", "x > 0")).unwrap();
+        assert_eq!(recheck.inner.0, 1);
+
+        let mut other_version = CertifiedChecker::new(CountingChecker(0), store("0.2"), false);
+        other_version.check(&lemma("a", "x > 0")).unwrap();
+        assert_eq!(other_version.inner.0, 1);
+    }
+}
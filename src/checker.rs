@@ -1,45 +1,522 @@
-use crate::error::Error;
-use crate::lemma::{Lemma, Theory};
+use crate::cancel;
+use crate::error::{Error, IsabelleFailure};
+use crate::isabelle_env;
+use crate::lemma::{Lemma, Query, Theory};
+use crate::transcript;
 use isabelle_client::client::args::{PurgeTheoryArgs, UseTheoriesArgs};
+use isabelle_client::client::results::UseTheoryResults;
 use isabelle_client::client::{AsyncResult, IsabelleClient};
 use isabelle_client::process;
+use itertools::Itertools;
 
-use std::os::unix::prelude::FileExt;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use std::{fs, io};
 
+/// The reason why a lemma could neither be proven nor refuted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnknownReason {
+    /// The prover did not finish in time
+    Timeout,
+    /// The proof method did not close the goal
+    TacticFailure,
+    /// An unsupported SMT-LIB construct prevented generating the lemma
+    Unsupported(String),
+    /// The prover ran out of memory or another resource
+    ResourceLimit,
+    /// The lemma was only generated, not checked
+    NotChecked,
+    /// The formula could not be evaluated under the model, see [crate::eval]
+    NotEvaluated(String),
+    /// The validation was cancelled, see [crate::ValidationOptions::cancellation]
+    Cancelled,
+    /// The lemma is false, but these functions were uninterpreted, so the model may still be valid,
+    /// see [crate::ValidationOptions::uninterpreted]
+    Uninterpreted(Vec<String>),
+    /// The checked assertions hold, but this many assertions were not checked, see [crate::ValidationOptions::slice]
+    Sliced(usize),
+}
+
+impl UnknownReason {
+    /// Classifies the reason of a failed check from the prover's output.
+    pub(crate) fn from_output(output: &str) -> Self {
+        if output.contains("Timeout") || output.contains("timed out") {
+            UnknownReason::Timeout
+        } else if output.contains("Out of memory")
+            || output.contains("out of memory")
+            || output.contains("Cannot allocate memory")
+            // The Java or the ML heap reached its maximum size, e.g. the memory limit of the batch checker
+            || output.contains("OutOfMemoryError")
+            || output.contains("Run out of store")
+        {
+            UnknownReason::ResourceLimit
+        } else {
+            UnknownReason::TacticFailure
+        }
+    }
+}
+
+impl Display for UnknownReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnknownReason::Timeout => write!(f, "timeout"),
+            UnknownReason::TacticFailure => write!(f, "proof method failed"),
+            UnknownReason::Unsupported(s) => write!(f, "unsupported: {}", s),
+            UnknownReason::ResourceLimit => write!(f, "resource limit exceeded"),
+            UnknownReason::NotChecked => write!(f, "not checked"),
+            UnknownReason::NotEvaluated(s) => write!(f, "not evaluated: {}", s),
+            UnknownReason::Cancelled => write!(f, "cancelled"),
+            UnknownReason::Uninterpreted(fs) => {
+                write!(f, "refuted with uninterpreted functions: {}", fs.join(", "))
+            }
+            UnknownReason::Sliced(n) => write!(f, "{} assertion(s) sliced away", n),
+        }
+    }
+}
+
 /// The result of a lemma checking
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum CheckResult {
     /// Proof checked successfully
     OK,
-    /// Proof checking failed because of an unknown reason
-    FailedUnknown,
+    /// Proof checking failed because of the given reason
+    FailedUnknown(UnknownReason),
     /// Proof checking failed because the proof is invalid (i.e. the lemma is false)
     FailedInvalid,
 }
 
+/// An assignment to the free variables of a lemma under which the lemma is false, as found by Nitpick
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(transparent)]
+pub struct Counterexample {
+    /// The values of the variables, in Isabelle syntax
+    pub assignment: BTreeMap<String, String>,
+}
+
+impl Counterexample {
+    /// Parses the genuine counterexample from the output of Nitpick.
+    /// Returns None if Nitpick found no counterexample or only a potentially spurious one.
+    pub fn parse(output: &str) -> Option<Self> {
+        let (_, rest) = output.split_once("Nitpick found a counterexample")?;
+        let mut assignment = BTreeMap::new();
+        let mut in_variables = false;
+        for line in rest.lines().skip(1).map(str::trim) {
+            if line.is_empty() {
+                if !assignment.is_empty() {
+                    break;
+                }
+            } else if line.ends_with(':') {
+                // A section header such as "Free variables:" or "Skolem constant:"
+                in_variables = line.starts_with("Free variable");
+            } else if in_variables {
+                match line.split_once(" = ") {
+                    Some((var, value)) => {
+                        assignment.insert(var.to_owned(), value.to_owned());
+                    }
+                    None => break,
+                }
+            }
+        }
+        Some(Self { assignment })
+    }
+}
+
+impl Display for Counterexample {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, (var, value)) in self.assignment.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{} = {}", var, value)?;
+        }
+        Ok(())
+    }
+}
+
 /// A trait for checking lemmas
 pub trait LemmaChecker {
     /// Checks whether the given lemma is true
     fn check(&mut self, lemma: &Lemma) -> Result<CheckResult, Error>;
+
+    /// Searches for a counterexample to the given lemma.
+    /// Returns None if none was found or the checker cannot search for counterexamples.
+    fn find_counterexample(&mut self, _lemma: &Lemma) -> Result<Option<Counterexample>, Error> {
+        Ok(None)
+    }
+
+    /// Searches for a proof method that proves the given lemma.
+    /// Returns None if none was found or the checker cannot search for proofs.
+    fn find_proof_method(&mut self, _lemma: &Lemma) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    /// Checks each conclusion of the lemma as a lemma of its own, see [Lemma::split_conclusion].
+    /// Returns the result of each conclusion, in order.
+    /// By default, the conclusions are checked one after another; checkers that can check several lemmas at once
+    /// check them together and attribute the failures to the lemmas of the conclusions.
+    fn check_conclusions(&mut self, lemma: &Lemma) -> Result<Vec<CheckResult>, Error> {
+        lemma.split_conclusion().map(|l| self.check(&l)).collect()
+    }
+}
+
+impl<C: LemmaChecker + ?Sized> LemmaChecker for Box<C> {
+    fn check(&mut self, lemma: &Lemma) -> Result<CheckResult, Error> {
+        (**self).check(lemma)
+    }
+
+    fn find_counterexample(&mut self, lemma: &Lemma) -> Result<Option<Counterexample>, Error> {
+        (**self).find_counterexample(lemma)
+    }
+
+    fn find_proof_method(&mut self, lemma: &Lemma) -> Result<Option<String>, Error> {
+        (**self).find_proof_method(lemma)
+    }
+
+    fn check_conclusions(&mut self, lemma: &Lemma) -> Result<Vec<CheckResult>, Error> {
+        (**self).check_conclusions(lemma)
+    }
+}
+
+impl<C: LemmaChecker + ?Sized> LemmaChecker for &mut C {
+    fn check(&mut self, lemma: &Lemma) -> Result<CheckResult, Error> {
+        (**self).check(lemma)
+    }
+
+    fn find_counterexample(&mut self, lemma: &Lemma) -> Result<Option<Counterexample>, Error> {
+        (**self).find_counterexample(lemma)
+    }
+
+    fn find_proof_method(&mut self, lemma: &Lemma) -> Result<Option<String>, Error> {
+        (**self).find_proof_method(lemma)
+    }
+
+    fn check_conclusions(&mut self, lemma: &Lemma) -> Result<Vec<CheckResult>, Error> {
+        (**self).check_conclusions(lemma)
+    }
+}
+
+/// The proof methods [EscalatingChecker] tries in order
+pub const ESCALATION_METHODS: [&str; 4] = ["simp", "auto", "fastforce", "eval"];
+
+/// Retries lemmas the inner checker could not prove with increasingly strong proof methods.
+/// The [ESCALATION_METHODS] are tried in order, except the method the lemma already uses.
+/// If none of them proves the lemma, Sledgehammer is asked for a proof method, which is then checked.
+pub struct EscalatingChecker<C: LemmaChecker> {
+    inner: C,
+    methods: Vec<String>,
+}
+
+impl<C: LemmaChecker> EscalatingChecker<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            methods: ESCALATION_METHODS.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    /// Checks the lemma with the given proof method.
+    fn check_with(&mut self, lemma: &Lemma, method: &str) -> Result<CheckResult, Error> {
+        log::info!("Retrying with proof method '{}'", method);
+        let mut lemma = lemma.clone();
+        lemma.set_method(method);
+        self.inner.check(&lemma)
+    }
+}
+
+impl<C: LemmaChecker> LemmaChecker for EscalatingChecker<C> {
+    fn check(&mut self, lemma: &Lemma) -> Result<CheckResult, Error> {
+        let first = lemma
+            .method()
+            .unwrap_or(crate::lemma::DEFAULT_METHOD)
+            .to_owned();
+        let mut result = self.inner.check(lemma)?;
+        for method in self.methods.clone() {
+            if !matches!(result, CheckResult::FailedUnknown(_)) {
+                return Ok(result);
+            }
+            if method != first {
+                result = self.check_with(lemma, &method)?;
+            }
+        }
+        if matches!(result, CheckResult::FailedUnknown(_)) {
+            log::info!("Searching for a proof method with Sledgehammer");
+            if let Some(method) = self.inner.find_proof_method(lemma)? {
+                result = self.check_with(lemma, &method)?;
+            }
+        }
+        Ok(result)
+    }
+
+    fn find_counterexample(&mut self, lemma: &Lemma) -> Result<Option<Counterexample>, Error> {
+        self.inner.find_counterexample(lemma)
+    }
+
+    fn find_proof_method(&mut self, lemma: &Lemma) -> Result<Option<String>, Error> {
+        self.inner.find_proof_method(lemma)
+    }
+}
+
+/// Extracts the proof method from the first proof Sledgehammer suggests,
+/// such as `Try this: using assms by simp (0.4 ms)`.
+/// Facts the proof uses are inserted before applying the method, e.g. `insert assms, simp`.
+pub fn parse_sledgehammer(output: &str) -> Option<String> {
+    let (_, rest) = output.split_once("Try this: ")?;
+    let proof = rest.lines().next()?.trim();
+    // Remove the reported time, e.g. "(12 ms)" or "(1.2 s)"
+    let proof = match proof.rsplit_once(" (") {
+        Some((p, time)) if time.ends_with(" ms)") || time.ends_with(" s)") => p,
+        _ => proof,
+    };
+    let (facts, method) = match proof.strip_prefix("using ") {
+        Some(rest) => {
+            let (facts, method) = rest.split_once(" by ")?;
+            (Some(facts), method)
+        }
+        None => (None, proof.strip_prefix("by ")?),
+    };
+    match facts {
+        Some(facts) => Some(format!("insert {}, {}", facts, method)),
+        None => Some(method.to_owned()),
+    }
+}
+
+/// The available lemma checkers, see [BatchChecker], [ClientChecker], [CoqChecker](crate::coq::CoqChecker),
+/// and [LeanChecker](crate::lean::LeanChecker)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum CheckerKind {
+    /// Run a new Isabelle process for every lemma
+    #[default]
+    Batch,
+    /// Check lemmas in a session of an Isabelle server
+    Server,
+    /// Compile lemmas converted to Coq with `coqc`, see [crate::coq]
+    Coq,
+    /// Elaborate lemmas converted to Lean 4 with `lean`, see [crate::lean]
+    Lean,
+    /// The checker of [crate::ValidationOptions::custom_checker], only available to library users
+    #[value(skip)]
+    Custom,
+}
+
+impl CheckerKind {
+    /// Returns the language of the lemmas this kind of checker checks.
+    /// Custom checkers are given the Isabelle/HOL lemma.
+    pub fn target(self) -> crate::convert::TargetKind {
+        match self {
+            CheckerKind::Coq => crate::convert::TargetKind::Coq,
+            CheckerKind::Lean => crate::convert::TargetKind::Lean,
+            _ => crate::convert::TargetKind::Isabelle,
+        }
+    }
+
+    /// Creates a checker of this kind for the theories in the given theory roots.
+    /// The first root is the one containing the Isabelle SMT theories, the others add further sessions.
+    /// The validation theories import the given theories in addition to [DEFAULT_IMPORTS].
+    /// If a timeout is given, checks running longer are aborted with [UnknownReason::Timeout].
+    /// If `keep_temp`, the working directories of checks that do not prove the lemma are kept and their paths logged.
+    /// The memory limit in megabytes only applies to the batch checker, see [BatchChecker::memory_limit].
+    pub fn create(
+        self,
+        theory_roots: &[String],
+        imports: &[String],
+        timeout: Option<Duration>,
+        keep_temp: bool,
+        memory_limit: Option<u64>,
+    ) -> Result<Box<dyn LemmaChecker>, Error> {
+        match self {
+            CheckerKind::Batch => {
+                let mut checker = BatchChecker::new(&theory_roots[0]);
+                for dir in &theory_roots[1..] {
+                    checker.session_dir(dir);
+                }
+                checker.import(imports).keep_temp(keep_temp);
+                if let Some(timeout) = timeout {
+                    checker.timeout(timeout);
+                }
+                if let Some(limit) = memory_limit {
+                    checker.memory_limit(limit);
+                }
+                Ok(Box::new(checker))
+            }
+            CheckerKind::Server => match ClientChecker::start_server(theory_roots, imports) {
+                Ok(mut checker) => {
+                    if memory_limit.is_some() {
+                        // The server starts its sessions with the settings it was started with
+                        log::warn!("The memory limit only applies to the batch checker, not to the Isabelle server");
+                    }
+                    checker.keep_temp(keep_temp);
+                    if let Some(timeout) = timeout {
+                        checker.timeout(timeout);
+                    }
+                    Ok(Box::new(checker))
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Err(Error::IsabelleNotFound(
+                    "could not run 'isabelle server', put the isabelle executable on the PATH"
+                        .to_owned(),
+                )),
+                Err(e) => {
+                    log::error!("Could not start the Isabelle server: {}", e);
+                    Err(Error::IsabelleError(IsabelleFailure::Startup))
+                }
+            },
+            CheckerKind::Coq => {
+                let mut checker = crate::coq::CoqChecker::new(&theory_roots[0]);
+                checker.import(imports).keep_temp(keep_temp);
+                if let Some(timeout) = timeout {
+                    checker.timeout(timeout);
+                }
+                Ok(Box::new(checker))
+            }
+            CheckerKind::Lean => {
+                let mut checker = crate::lean::LeanChecker::new(&theory_roots[0]);
+                checker.import(imports).keep_temp(keep_temp);
+                if let Some(timeout) = timeout {
+                    checker.timeout(timeout);
+                }
+                Ok(Box::new(checker))
+            }
+            CheckerKind::Custom => Err(Error::Other(
+                "Custom checkers are created by their CustomChecker".to_owned(),
+            )),
+        }
+    }
+}
+
+/// Creates the checkers of a [CustomChecker], given the timeout of the checks
+type CheckerFactory =
+    dyn Fn(Option<Duration>) -> Result<Box<dyn LemmaChecker>, Error> + Send + Sync;
+
+/// A checker backend provided by a library user, e.g. one translating the lemmas to another proof assistant.
+/// Validations use it for [CheckerKind::Custom], see [crate::ValidationOptions::register_checker].
+/// Each validation thread creates its own checker with the factory and reuses it like an Isabelle checker.
+#[derive(Clone)]
+pub struct CustomChecker {
+    name: String,
+    factory: Arc<CheckerFactory>,
+}
+
+impl CustomChecker {
+    /// Creates a backend with the given name, which identifies it in logs and in the verdict cache.
+    pub fn new(
+        name: impl Into<String>,
+        factory: impl Fn(Option<Duration>) -> Result<Box<dyn LemmaChecker>, Error>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            factory: Arc::new(factory),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Creates a checker, aborting checks after the timeout if one is given.
+    pub fn create(&self, timeout: Option<Duration>) -> Result<Box<dyn LemmaChecker>, Error> {
+        (self.factory)(timeout)
+    }
+}
+
+impl std::fmt::Debug for CustomChecker {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomChecker")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A queue of work shared by a pool of workers, each with its own checker.
+/// Checkers are not shared between threads: every worker creates its checker (and thus its Isabelle session)
+/// and takes items from the queue until it is empty.
+pub struct WorkQueue<T> {
+    items: Mutex<VecDeque<(usize, T)>>,
+}
+
+impl<T> WorkQueue<T> {
+    /// Creates a queue of the given items, numbered in their order.
+    pub fn new(items: impl IntoIterator<Item = T>) -> Self {
+        Self {
+            items: Mutex::new(items.into_iter().enumerate().collect()),
+        }
+    }
+
+    /// Takes the next item and its number from the queue.
+    /// Returns None if the queue is empty.
+    pub fn next(&self) -> Option<(usize, T)> {
+        self.items
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop_front()
+    }
 }
 
 /// Checks a lemma using the Isabelle process in batch mode
 pub struct BatchChecker {
-    theory_root: String,
+    /// The theory root followed by further directories containing sessions
+    session_dirs: Vec<String>,
+    /// Theories to import in addition to [DEFAULT_IMPORTS]
+    imports: Vec<String>,
+    /// Kill the Isabelle process if it runs longer than this
+    timeout: Option<Duration>,
+    /// Keep the directories of checks that do not prove the lemma
+    keep_temp: bool,
+    /// The maximum size of the heaps of the Isabelle process in megabytes
+    memory_limit: Option<u64>,
 }
 
 impl BatchChecker {
     pub fn new(theory_root: &str) -> Self {
         Self {
-            theory_root: theory_root.to_string(),
+            session_dirs: vec![theory_root.to_string()],
+            imports: vec![],
+            timeout: None,
+            keep_temp: false,
+            memory_limit: None,
         }
     }
 
-    /// Runs Isabelle in batch mode and loads the theory containing the lemma to check.
-    /// Returns the result based on the output of Isabelle.
-    fn run_isabelle(&self, dir: &Path, theory_root: &str) -> Result<CheckResult, Error> {
+    /// Limits the heaps of the Isabelle process to the given number of megabytes in total,
+    /// see [isabelle_env::memory_limit_env]. Checks exceeding the limit are [UnknownReason::ResourceLimit].
+    pub fn memory_limit(&mut self, limit_mb: u64) -> &mut Self {
+        self.memory_limit = Some(limit_mb);
+        self
+    }
+
+    /// Keeps the directory containing the validation theory if a check does not prove the lemma.
+    pub fn keep_temp(&mut self, keep: bool) -> &mut Self {
+        self.keep_temp = keep;
+        self
+    }
+
+    /// Adds a directory containing further sessions, e.g. an additional theory root.
+    pub fn session_dir(&mut self, dir: &str) -> &mut Self {
+        self.session_dirs.push(dir.to_string());
+        self
+    }
+
+    /// Kills the Isabelle process if a check takes longer than the given time.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Imports the given theories into the validation theory, in addition to [DEFAULT_IMPORTS].
+    pub fn import(&mut self, imports: &[String]) -> &mut Self {
+        self.imports.extend(imports.iter().cloned());
+        self
+    }
+
+    /// Returns the arguments of the Isabelle process that checks the validation theory.
+    fn process_args(session_dirs: &[String]) -> process::ProcessArgs {
         let mut options = process::OptionsBuilder::new();
         options
             .build_pide_reports(false)
@@ -50,27 +527,46 @@ impl BatchChecker {
             .parallel_proofs(0)
             .quick_and_dirty(true);
 
-        let args = process::ProcessArgs {
+        process::ProcessArgs {
             theories: vec!["Validation".to_owned()],
-            session_dirs: vec![theory_root.to_owned()],
+            session_dirs: session_dirs.to_vec(),
             logic: Some("smt".to_string()),
             options: options.into(),
-        };
+        }
+    }
+
+    /// Returns the command line of the Isabelle process, as run from the directory containing the validation theory.
+    pub fn command_line(session_dirs: &[String]) -> Vec<String> {
+        let args = Self::process_args(session_dirs);
+        let mut cmd = vec!["isabelle".to_owned(), "process".to_owned()];
+        for t in &args.theories {
+            cmd.extend(["-T".to_owned(), t.clone()]);
+        }
+        for d in &args.session_dirs {
+            cmd.extend(["-d".to_owned(), d.clone()]);
+        }
+        if let Some(l) = &args.logic {
+            cmd.extend(["-l".to_owned(), l.clone()]);
+        }
+        for (k, v) in args.options.iter().sorted() {
+            cmd.extend(["-o".to_owned(), format!("{}={}", k, v)]);
+        }
+        cmd
+    }
 
+    /// Runs Isabelle in batch mode and loads the theory containing the lemma to check.
+    /// Returns the result based on the output of Isabelle.
+    /// If the process does not finish within the timeout, it is killed and the result is [UnknownReason::Timeout],
+    /// or [UnknownReason::Cancelled] if the validation is cancelled.
+    fn run_isabelle(&self, dir: &Path) -> Result<CheckResult, Error> {
         log::info!("Checking lemma with Isabelle");
-        let output = match tokio::runtime::Runtime::new()
-            .unwrap()
-            .block_on(process::batch_process(&args, Some(&dir.to_owned())))
-        {
-            Ok(o) => o,
-            Err(e) => {
-                log::error!("Error running the Isabelle process:s {}", e.to_string());
-                return Err(Error::IsabelleError);
-            }
+        let output = match self.run_process(dir)? {
+            Some(o) => o,
+            None => return Ok(CheckResult::FailedUnknown(interruption("Isabelle"))),
         };
 
-        let stderr = String::from_utf8(output.stderr).expect("Failed to decode stderr");
-        let stdout = String::from_utf8(output.stdout).expect("Failed to decode stdout");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
         if output.status.success() {
             Ok(CheckResult::OK)
         } else if stdout.contains("Failed to finish proof") {
@@ -80,16 +576,76 @@ impl BatchChecker {
                 log::debug!("Lemma is invalid");
                 Ok(CheckResult::FailedInvalid)
             } else {
-                Ok(CheckResult::FailedUnknown)
+                Ok(CheckResult::FailedUnknown(UnknownReason::TacticFailure))
             }
+        } else if let reason @ (UnknownReason::Timeout | UnknownReason::ResourceLimit) =
+            UnknownReason::from_output(&format!("{}\n{}", stdout, stderr))
+        {
+            log::debug!("Isabelle was interrupted ({}): {}", reason, stdout);
+            Ok(CheckResult::FailedUnknown(reason))
+        } else if self.memory_limit.is_some() && stdout.contains("Interrupt") {
+            // Poly/ML interrupts the prover when the ML heap cannot grow any further
+            log::debug!("Isabelle was interrupted by the memory limit: {}", stdout);
+            Ok(CheckResult::FailedUnknown(UnknownReason::ResourceLimit))
+        } else if let Some(option) =
+            isabelle_env::rejected_option(&format!("{}\n{}", stdout, stderr))
+        {
+            Err(Error::UnsupportedIsabelle(format!(
+                "the installed Isabelle does not know the option '{}' (supported: {})",
+                option,
+                isabelle_env::supported_versions()
+            )))
         } else {
             log::error!(
                 "Isabelle process terminated with non-zero exit status\nSTDOUT:\n{}\n STDERR:\n{}",
                 stdout,
                 stderr
             );
-            Err(Error::IsabelleError)
+            Err(Error::IsabelleError(IsabelleFailure::Proof))
+        }
+    }
+
+    /// Runs the tool on the lemma and returns the output of Isabelle.
+    /// Returns None if the process was killed because of the timeout or a cancellation.
+    fn query(&self, lemma: &Lemma, query: Query) -> Result<Option<String>, Error> {
+        let dir = make_dir();
+        let theory = validation_theory(&lemma.with_query(query), &self.imports);
+        write_theory_for_check(&theory, &dir.path().join("Validation.thy"))?;
+        match self.run_process(dir.path())? {
+            Some(output) => Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned())),
+            None => {
+                interruption(&format!("{:?}", query));
+                Ok(None)
+            }
+        }
+    }
+
+    /// Runs Isabelle in batch mode on the validation theory in `dir`.
+    /// Returns None if the process was killed because of the timeout or a cancellation.
+    fn run_process(&self, dir: &Path) -> Result<Option<Output>, Error> {
+        let command_line = Self::command_line(&self.session_dirs);
+        let executable = isabelle_env::executable()?;
+        let mut command = tokio::process::Command::new(executable);
+        if let Some(limit) = self.memory_limit {
+            command
+                .arg("env")
+                .args(isabelle_env::memory_limit_env(limit)?)
+                .arg(executable);
         }
+        command
+            .args(&command_line[1..])
+            .current_dir(dir)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        run_command(&mut command, self.timeout).map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                return Error::IsabelleNotFound(format!("could not run {}", executable.display()));
+            }
+            log::error!("Error running the Isabelle process: {}", e);
+            Error::IsabelleError(IsabelleFailure::Startup)
+        })
     }
 }
 
@@ -100,24 +656,26 @@ impl LemmaChecker for BatchChecker {
         let dir = make_dir();
 
         // Create new theory file with lemma
-        let mut theory = Theory::new("Validation", false);
-        theory.add_theory_import("smt.Strings");
-        theory.add_theory_import("smt.Core");
-        theory.add_lemma(lemma.clone());
+        let theory = validation_theory(lemma, &self.imports);
+        write_theory_for_check(&theory, &dir.path().join("Validation.thy"))?;
 
-        let th = theory.to_isabelle();
-
-        match fs::File::create(dir.path().join("Validation.thy")) {
-            Ok(th_file) => {
-                if let Err(e) = th_file.write_all_at(th.as_bytes(), 0) {
-                    panic!("{}", e)
-                }
-            }
-            Err(e) => panic!("{}", e),
+        // Call isabelle
+        let result = self.run_isabelle(dir.path());
+        if self.keep_temp {
+            keep_on_failure(dir, &result);
         }
+        result
+    }
 
-        // Call isabelle
-        self.run_isabelle(dir.path(), &self.theory_root)
+    fn find_counterexample(&mut self, lemma: &Lemma) -> Result<Option<Counterexample>, Error> {
+        log::info!("Searching for a counterexample with Nitpick");
+        let output = self.query(lemma, Query::Nitpick)?;
+        Ok(output.and_then(|o| Counterexample::parse(&o)))
+    }
+
+    fn find_proof_method(&mut self, lemma: &Lemma) -> Result<Option<String>, Error> {
+        let output = self.query(lemma, Query::Sledgehammer)?;
+        Ok(output.and_then(|o| parse_sledgehammer(&o)))
     }
 }
 
@@ -125,51 +683,139 @@ impl LemmaChecker for BatchChecker {
 /// When verifying multiple models, this is much faster than the batch verifier, because the servers keeps the image of the base theories loaded.
 /// Uses the Isabelle server instance named 'smtmv_server' and creates it if it does not exist.
 ///
-/// ## Warning
-/// Currently, this should not be used because the server uses substantial amounts of memory that it does not seem to free after validating a model.
-/// This causes the server to run out of memory after a few validation calls.
-/// I don't know if this is a memory leak in the server or if its not properly used here.
+/// ## Memory
+/// The server uses substantial amounts of memory that it does not seem to free after validating a model,
+/// even though every validation theory is purged after checking.
+/// To keep long-running jobs from running out of memory, the session is transparently stopped and restarted
+/// after a number of checks, see [ClientChecker::recycle_after].
+///
+/// Failed checks are analyzed using the per-node status and messages reported by `use_theories`.
+/// The failed theory nodes and lemmas are logged together with Isabelle's error messages.
 ///
-/// Moreover, it currently does not check why a check failed.
-/// It only returns either CheckResult::OK or CheckResult::FailedUnknown, but never CheckResult::FailedInvalid.
+/// ## Cancellation
+/// The client waits for the reply of `use_theories` on its connection, so a cancelled check is interrupted from
+/// another connection by stopping the session. The next check starts a new session.
 pub struct ClientChecker {
     /// The client for the Isabelle server
     client: IsabelleClient,
+    /// The port and password of the server, to stop the session of a cancelled check from another connection
+    server: (u32, String),
     /// The root directory of the Isabelle SMT theories
     theory_root: String,
+    /// The theory root followed by further directories containing sessions
+    session_dirs: Vec<String>,
     /// The session id on the server
     session_id: String,
     /// The runtime for the async client
-    runtime: tokio::runtime::Runtime,
+    runtime: Executor,
     /// The temporary directory for validation theory files
     temp_dir: String,
+    /// Theories to import in addition to [DEFAULT_IMPORTS]
+    imports: Vec<String>,
+    /// The number of checks done in the current session
+    checks_in_session: usize,
+    /// Restart the session after this many checks
+    recycle_after: usize,
+    /// Abort checks that make no progress for this long
+    timeout: Option<Duration>,
+    /// Keep copies of the validation theories that could not be proven
+    keep_temp: bool,
 }
 
+/// The default number of checks after which [ClientChecker] restarts its session.
+pub const DEFAULT_RECYCLE_AFTER: usize = 20;
+
 impl ClientChecker {
     /// Starts a new Isabelle server and connects to it.
-    #[allow(unused)]
-    pub fn start_server(theory_root: &str) -> io::Result<Self> {
+    /// The first of the session directories is the theory root containing the Isabelle SMT theories.
+    pub fn start_server(session_dirs: &[String], imports: &[String]) -> io::Result<Self> {
         let server = isabelle_client::server::run_server(Some("smtmv_server"))?;
         log::debug!("Isabelle server is running on port {}", server.port());
         let client = IsabelleClient::connect(None, server.port(), server.password());
-        let runtime = tokio::runtime::Runtime::new()?;
+        let runtime = Executor::new()?;
 
         let mut v = Self {
             client,
-            theory_root: theory_root.to_string(),
+            server: (server.port(), server.password().to_owned()),
+            theory_root: session_dirs[0].clone(),
+            session_dirs: session_dirs.to_vec(),
             runtime,
             session_id: "".to_owned(),
             temp_dir: "".to_owned(),
+            imports: imports.to_vec(),
+            checks_in_session: 0,
+            recycle_after: DEFAULT_RECYCLE_AFTER,
+            timeout: None,
+            keep_temp: false,
         };
 
         v.start_session()?;
         Ok(v)
     }
 
+    /// Sets the number of checks after which the session is restarted to release the memory of the server.
+    pub fn recycle_after(&mut self, checks: usize) -> &mut Self {
+        self.recycle_after = checks.max(1);
+        self
+    }
+
+    /// Aborts checks that take longer than the given time, using the watchdog of `use_theories`.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Keeps a copy of the validation theory if a check does not prove the lemma.
+    /// The server checks all theories in the same directory, so the theory is copied to a directory of its own.
+    pub fn keep_temp(&mut self, keep: bool) -> &mut Self {
+        self.keep_temp = keep;
+        self
+    }
+
+    /// Keeps a copy of the last validation theory if `keep_temp` and the check did not prove the lemma.
+    fn keep_theory(&self, result: &Result<CheckResult, Error>) {
+        if !self.keep_temp {
+            return;
+        }
+        let dir = make_dir();
+        let file = Path::new(&self.temp_dir).join("Validation.thy");
+        match fs::copy(&file, dir.path().join("Validation.thy")) {
+            Ok(_) => keep_on_failure(dir, result),
+            Err(e) => log::warn!("Could not keep {}: {}", file.display(), e),
+        }
+    }
+
+    /// Stops the current session and starts a new one.
+    fn recycle_session(&mut self) -> io::Result<()> {
+        log::info!(
+            "Restarting session after {} checks to release memory",
+            self.checks_in_session
+        );
+        self.stop_session()?;
+        self.start_session()
+    }
+
+    fn stop_session(&mut self) -> io::Result<()> {
+        log::debug!("Stopping session {}", self.session_id);
+        let args = isabelle_client::client::args::SessionStopArgs {
+            session_id: std::mem::take(&mut self.session_id),
+        };
+        match self.runtime.block_on(self.client.session_stop(&args))? {
+            AsyncResult::Finished(_) => Ok(()),
+            AsyncResult::Error(m) => {
+                Err(io::Error::other(format!("Could not stop session: {:?}", m)))
+            }
+            AsyncResult::Failed(f) => Err(io::Error::other(format!(
+                "Could not stop session: {:?}",
+                f.message
+            ))),
+        }
+    }
+
     fn start_session(&mut self) -> io::Result<()> {
         log::debug!("Staring HOL session");
         let mut args = isabelle_client::client::args::SessionBuildArgs::session("HOL");
-        args.dirs = Some(vec![self.theory_root.clone()]);
+        args.dirs = Some(self.session_dirs.clone());
         args.include_sessions = vec![String::from("smt")];
         args.options = Some(vec![
             "system_log=false".to_owned(),
@@ -178,42 +824,78 @@ impl ClientChecker {
             "pide_reports=false".to_owned(),
             "build_pide_reports=false".to_owned(),
             "headless_check_limit=1".to_owned(),
+            // Allows assuming the termination of recursive functions
+            "quick_and_dirty=true".to_owned(),
         ]);
 
+        transcript::record("session_start", || {
+            serde_json::to_string_pretty(&args).unwrap_or_default()
+        });
         let res = async { self.client.session_start(&args).await };
         let resp = self.runtime.block_on(res)?;
         match resp {
             AsyncResult::Finished(r) => {
                 self.session_id = r.session_id;
                 self.temp_dir = r.tmp_dir.unwrap();
+                self.checks_in_session = 0;
                 Ok(())
             }
-            AsyncResult::Error(m) => panic!("{:?}", m),
-            AsyncResult::Failed(f) => panic!("{:?}", f),
+            AsyncResult::Error(m) => Err(io::Error::other(format!(
+                "Could not start session: {:?}",
+                m
+            ))),
+            AsyncResult::Failed(f) => Err(io::Error::other(format!(
+                "Could not start session: {:?}",
+                f.message
+            ))),
         }
     }
 }
 
-impl LemmaChecker for ClientChecker {
-    fn check(&mut self, lemma: &Lemma) -> Result<CheckResult, Error> {
-        // Create temporary folder
+impl Drop for ClientChecker {
+    fn drop(&mut self) {
+        if !self.session_id.is_empty() {
+            if let Err(e) = self.stop_session() {
+                log::warn!("{}", e);
+            }
+        }
+    }
+}
+
+/// A validation theory loaded in the session, the result of `use_theories`, and the time it took
+type LoadedTheory = (Theory, AsyncResult<UseTheoryResults, ()>, Duration);
+
+impl ClientChecker {
+    /// Loads the validation theory of the lemma in the session and purges it afterwards.
+    /// If `split`, the theory contains a lemma per conclusion, see [Theory::split].
+    /// Returns the theory, the result of `use_theories`, and the time it took,
+    /// or None if the validation was cancelled, stopping the session if the theory was being loaded.
+    fn use_theory(&mut self, lemma: &Lemma, split: bool) -> Result<Option<LoadedTheory>, Error> {
+        if cancel::is_cancelled() {
+            return Ok(None);
+        }
+        if self.session_id.is_empty() {
+            self.start_session().map_err(|e| {
+                log::error!("Could not restart the Isabelle session: {}", e);
+                Error::IsabelleError(IsabelleFailure::Startup)
+            })?;
+        } else if self.checks_in_session >= self.recycle_after {
+            self.recycle_session().map_err(|e| {
+                log::error!("Could not restart the Isabelle session: {}", e);
+                Error::IsabelleError(IsabelleFailure::Startup)
+            })?;
+        }
+        self.checks_in_session += 1;
 
         let session_id = self.session_id.clone();
 
-        let mut theory = Theory::new("Validation", false);
-        theory.add_theory_import("smt.Strings");
-        theory.add_theory_import("smt.Core");
-        theory.add_lemma(lemma.clone());
+        let mut theory = validation_theory(lemma, &self.imports);
+        if split {
+            theory.split();
+        }
 
         let dir = PathBuf::from_str(&self.temp_dir).unwrap();
-        match fs::File::create(dir.join("Validation.thy")) {
-            Ok(th_file) => {
-                if let Err(e) = th_file.write_all_at(theory.to_isabelle().as_bytes(), 0) {
-                    panic!("{}", e)
-                }
-            }
-            Err(e) => panic!("{}", e),
-        }
+        write_theory_for_check(&theory, &dir.join("Validation.thy"))?;
 
         let path = dir.join("Validation");
         let path = path.to_str().unwrap();
@@ -223,44 +905,545 @@ impl LemmaChecker for ClientChecker {
         //args.nodes_status_delay = Some(-1.0);
         args.check_limit = Some(1);
         args.unicode_symbols = Some(true);
+        args.watchdog_timeout = self.timeout.map(|t| t.as_secs_f64());
 
         log::debug!("Checking\n{}", theory.to_isabelle());
+        transcript::record("use_theories", || {
+            serde_json::to_string_pretty(&args).unwrap_or_default()
+        });
+        let start = Instant::now();
 
-        let result = match self
-            .runtime
-            .block_on(self.client.use_theories(&args))
-            .unwrap()
-        {
+        let watcher = cancel::current().map(|token| {
+            let (port, password) = &self.server;
+            let mut client = IsabelleClient::connect(None, *port, password);
+            let args = isabelle_client::client::args::SessionStopArgs {
+                session_id: session_id.clone(),
+            };
+            self.runtime.spawn(async move {
+                token.cancelled().await;
+                log::debug!("Stopping session {} to cancel the check", args.session_id);
+                if let Err(e) = client.session_stop(&args).await {
+                    log::warn!("Could not stop the session of the cancelled check: {}", e);
+                }
+            })
+        });
+        let result = self.runtime.block_on(with_heartbeat(
+            "Isabelle server",
+            self.client.use_theories(&args),
+        ));
+        // The watcher either stopped the session already or is aborted before it does
+        let stopped = watcher.is_some_and(|watcher| {
+            watcher.abort();
+            self.runtime.block_on(watcher).is_ok()
+        });
+        if stopped {
+            transcript::record("Session stopped, the validation was cancelled", String::new);
+            self.session_id.clear();
+            return Ok(None);
+        }
+        let result = result.map_err(|e| {
+            log::error!("Could not check the theory with the Isabelle server: {}", e);
+            Error::IsabelleError(IsabelleFailure::Connection)
+        })?;
+        let elapsed = start.elapsed();
+        transcript::record(
+            &format!("use_theories finished after {:.3}s", elapsed.as_secs_f64()),
+            || match &result {
+                AsyncResult::Finished(r) => serde_json::to_string_pretty(r).unwrap_or_default(),
+                AsyncResult::Failed(f) => format!("FAILED {:?}", f),
+                AsyncResult::Error(m) => format!("ERROR {:?}", m),
+            },
+        );
+
+        // Purge theory to release resources
+        let mut args: PurgeTheoryArgs = PurgeTheoryArgs::for_session(&session_id, &[path]);
+        args.master_dir = Some(self.theory_root.clone());
+
+        if let Err(e) = self.runtime.block_on(self.client.purge_theories(args)) {
+            log::error!("Could not purge the validation theory: {}", e);
+            return Err(Error::IsabelleError(IsabelleFailure::Connection));
+        }
+
+        Ok(Some((theory, result, elapsed)))
+    }
+
+    /// Runs the tool on the lemma and returns the regular output of the theory nodes.
+    /// Returns None if the theory could not be processed or the validation was cancelled.
+    fn query(&mut self, lemma: &Lemma, query: Query) -> Result<Option<String>, Error> {
+        let Some((_, result, _)) = self.use_theory(&lemma.with_query(query), false)? else {
+            interruption(&format!("{:?}", query));
+            return Ok(None);
+        };
+        match result {
+            AsyncResult::Finished(f) => Ok(Some(
+                NodeReport::from_results(&f)
+                    .iter()
+                    .flat_map(|r| r.messages.iter())
+                    .filter(|m| m.kind == "writeln")
+                    .map(|m| m.message.as_str())
+                    .join("\n"),
+            )),
+            AsyncResult::Error(e) => {
+                log::warn!("Error running {:?}: {:?}", query, e);
+                Ok(None)
+            }
+            AsyncResult::Failed(f) => {
+                log::warn!("Running {:?} failed: {:?}", query, f.message);
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl LemmaChecker for ClientChecker {
+    fn check(&mut self, lemma: &Lemma) -> Result<CheckResult, Error> {
+        let Some((theory, result, elapsed)) = self.use_theory(lemma, false)? else {
+            return Ok(CheckResult::FailedUnknown(interruption("Isabelle")));
+        };
+        let result = match result {
             AsyncResult::Error(e) => {
                 log::warn!("Error proving theory: {:?}", e);
-                CheckResult::FailedUnknown
+                CheckResult::FailedUnknown(UnknownReason::from_output(&format!("{:?}", e)))
             }
             AsyncResult::Failed(f) => {
-                // TODO: Check why, return FailedInvalid if possible
                 log::warn!("Proving theory failed: {:?}", f.message);
-                CheckResult::FailedUnknown
+                CheckResult::FailedUnknown(UnknownReason::from_output(&format!("{:?}", f.message)))
             }
             AsyncResult::Finished(f) => {
                 if f.ok {
                     CheckResult::OK
+                } else if self.timeout.is_some_and(|t| elapsed >= t) {
+                    log::info!("Isabelle did not finish within the timeout");
+                    CheckResult::FailedUnknown(UnknownReason::Timeout)
                 } else {
-                    log::warn!("Could not check proof: {}", theory.to_isabelle());
-                    // TODO: Check why, return FailedInvalid if possible
-                    CheckResult::FailedUnknown
+                    let reports = NodeReport::from_results(&f);
+                    let theory_text = theory.to_isabelle();
+                    for report in reports.iter().filter(|r| !r.ok) {
+                        report.log_failure(&theory_text);
+                    }
+                    classify_failure(&reports)
                 }
             }
         };
+        let result = Ok(result);
+        self.keep_theory(&result);
+        result
+    }
 
-        // Purge theory to release resources
-        let mut args: PurgeTheoryArgs = PurgeTheoryArgs::for_session(&session_id, &[path]);
-        args.master_dir = Some(self.theory_root.clone());
+    fn find_counterexample(&mut self, lemma: &Lemma) -> Result<Option<Counterexample>, Error> {
+        log::info!("Searching for a counterexample with Nitpick");
+        let output = self.query(lemma, Query::Nitpick)?;
+        Ok(output.and_then(|o| Counterexample::parse(&o)))
+    }
+
+    fn find_proof_method(&mut self, lemma: &Lemma) -> Result<Option<String>, Error> {
+        let output = self.query(lemma, Query::Sledgehammer)?;
+        Ok(output.and_then(|o| parse_sledgehammer(&o)))
+    }
+
+    /// Checks the lemmas of all conclusions in a single theory, whose failed lemmas are located by the error messages.
+    fn check_conclusions(&mut self, lemma: &Lemma) -> Result<Vec<CheckResult>, Error> {
+        let names: Vec<String> = lemma
+            .split_conclusion()
+            .map(|l| l.name().to_owned())
+            .collect();
+        let failed = |result: CheckResult| vec![result; names.len()];
+        let Some((theory, result, elapsed)) = self.use_theory(lemma, true)? else {
+            return Ok(failed(CheckResult::FailedUnknown(interruption("Isabelle"))));
+        };
+        let results = match result {
+            AsyncResult::Error(e) => {
+                log::warn!("Error proving theory: {:?}", e);
+                failed(CheckResult::FailedUnknown(UnknownReason::from_output(
+                    &format!("{:?}", e),
+                )))
+            }
+            AsyncResult::Failed(f) => {
+                log::warn!("Proving theory failed: {:?}", f.message);
+                failed(CheckResult::FailedUnknown(UnknownReason::from_output(
+                    &format!("{:?}", f.message),
+                )))
+            }
+            AsyncResult::Finished(f) if f.ok => failed(CheckResult::OK),
+            AsyncResult::Finished(_) if self.timeout.is_some_and(|t| elapsed >= t) => {
+                log::info!("Isabelle did not finish within the timeout");
+                failed(CheckResult::FailedUnknown(UnknownReason::Timeout))
+            }
+            AsyncResult::Finished(f) => {
+                let reports = NodeReport::from_results(&f);
+                let theory_text = theory.to_isabelle();
+                for report in reports.iter().filter(|r| !r.ok) {
+                    report.log_failure(&theory_text);
+                }
+                attribute_failures(&reports, &theory_text, &names)
+            }
+        };
+        // The theory contains all conclusions, so it is kept if any of them failed
+        let failure = results.iter().find(|r| **r != CheckResult::OK).cloned();
+        self.keep_theory(&Ok(failure.unwrap_or(CheckResult::OK)));
+        Ok(results)
+    }
+}
+
+/// A message Isabelle reported for a theory node
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct NodeMessage {
+    /// The kind of the message, mainly `writeln`, `warning`, or `error`
+    kind: String,
+    message: String,
+    /// The line in the theory file the message refers to
+    line: Option<usize>,
+}
+
+/// The status of a theory node after checking it with `use_theories`
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct NodeReport {
+    node_name: String,
+    theory_name: String,
+    ok: bool,
+    messages: Vec<NodeMessage>,
+}
+
+impl NodeReport {
+    /// Extracts the node reports from the results of `use_theories`.
+    /// The fields of the node results are not public, so they are read from their serialized form.
+    /// Errors not attached to any node are reported under an unnamed node.
+    fn from_results(results: &UseTheoryResults) -> Vec<Self> {
+        let value = match serde_json::to_value(results) {
+            Ok(v) => v,
+            Err(e) => {
+                log::warn!("Could not inspect the results of use_theories: {}", e);
+                return vec![];
+            }
+        };
+        let str_field = |v: &serde_json::Value, f: &str| {
+            v.get(f)
+                .and_then(|s| s.as_str())
+                .unwrap_or_default()
+                .to_owned()
+        };
+        let messages = |v: &serde_json::Value| -> Vec<NodeMessage> {
+            v.as_array()
+                .map(|ms| {
+                    ms.iter()
+                        .map(|m| NodeMessage {
+                            kind: str_field(m, "kind"),
+                            message: str_field(m, "message"),
+                            line: m
+                                .get("pos")
+                                .and_then(|p| p.get("line"))
+                                .and_then(|l| l.as_u64())
+                                .map(|l| l as usize),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
 
-        match self.runtime.block_on(self.client.purge_theories(args)) {
-            Ok(_ok) => (),
-            Err(e) => panic!("Failed to purge theory, aborting: {:?}", e),
+        let mut reports = vec![];
+        let errors = messages(&value["errors"]);
+        if !errors.is_empty() {
+            reports.push(NodeReport {
+                node_name: String::new(),
+                theory_name: String::new(),
+                ok: false,
+                messages: errors,
+            });
         }
+        if let Some(nodes) = value["nodes"].as_array() {
+            for node in nodes {
+                reports.push(NodeReport {
+                    node_name: str_field(node, "node_name"),
+                    theory_name: str_field(node, "theory_name"),
+                    ok: node["status"]["ok"].as_bool().unwrap_or(false),
+                    messages: messages(&node["messages"]),
+                });
+            }
+        }
+        reports
+    }
 
-        Ok(result)
+    /// Returns the error messages of the node.
+    fn errors(&self) -> impl Iterator<Item = &NodeMessage> {
+        self.messages.iter().filter(|m| m.kind == "error")
+    }
+
+    /// Logs which lemmas of the node failed and why.
+    /// The lemmas are located in the given source of the theory by the line numbers of the messages.
+    fn log_failure(&self, theory_text: &str) {
+        log::warn!(
+            "Theory node {} ({}) failed",
+            self.theory_name,
+            self.node_name
+        );
+        for m in self.errors() {
+            let lemma = m.line.and_then(|l| lemma_at_line(theory_text, l));
+            match (lemma, m.line) {
+                (Some(lemma), Some(line)) => {
+                    log::warn!("Lemma {} (line {}): {}", lemma, line, m.message)
+                }
+                (None, Some(line)) => log::warn!("Line {}: {}", line, m.message),
+                _ => log::warn!("{}", m.message),
+            }
+        }
+    }
+}
+
+/// Returns the name of the lemma whose statement or proof contains the given (1-based) line.
+fn lemma_at_line(theory_text: &str, line: usize) -> Option<String> {
+    theory_text
+        .lines()
+        .take(line)
+        .filter_map(|l| l.strip_prefix("lemma "))
+        .last()
+        .and_then(|l| l.split(':').next())
+        .map(|name| name.trim().to_owned())
+}
+
+/// Determines the result of a failed check from the error messages of the theory nodes.
+fn classify_failure(reports: &[NodeReport]) -> CheckResult {
+    classify_errors(reports.iter().flat_map(|r| r.errors()))
+}
+
+/// Determines the results of the named lemmas of a failed check from the error messages of the theory nodes.
+/// A lemma without errors was proven; errors outside of the lemmas, e.g. in the imports, fail all of them,
+/// as does a failure without any error message.
+fn attribute_failures(
+    reports: &[NodeReport],
+    theory_text: &str,
+    names: &[String],
+) -> Vec<CheckResult> {
+    let errors: Vec<_> = reports
+        .iter()
+        .flat_map(|r| r.errors())
+        .map(|m| (m.line.and_then(|l| lemma_at_line(theory_text, l)), m))
+        .collect();
+    let unattributed = errors.is_empty()
+        || errors
+            .iter()
+            .any(|(lemma, _)| lemma.as_ref().is_none_or(|l| !names.contains(l)));
+    if unattributed {
+        return vec![classify_failure(reports); names.len()];
+    }
+    names
+        .iter()
+        .map(|name| {
+            let mut own = errors
+                .iter()
+                .filter(|(lemma, _)| lemma.as_ref() == Some(name))
+                .map(|(_, m)| *m)
+                .peekable();
+            if own.peek().is_none() {
+                CheckResult::OK
+            } else {
+                classify_errors(own)
+            }
+        })
+        .collect()
+}
+
+/// Determines the result of a failed check from the error messages.
+fn classify_errors<'a>(errors: impl Iterator<Item = &'a NodeMessage>) -> CheckResult {
+    let errors = errors.map(|m| m.message.as_str()).join("\n");
+    if errors.contains("Failed to finish proof") && errors.contains("1. False") {
+        // Same heuristic as in batch mode
+        log::debug!("Lemma is invalid");
+        CheckResult::FailedInvalid
+    } else {
+        CheckResult::FailedUnknown(UnknownReason::from_output(&errors))
+    }
+}
+
+/// The theories every validation theory imports
+pub const DEFAULT_IMPORTS: [&str; 2] = ["smt.Strings", "smt.Core"];
+
+/// Creates the theory `Validation` containing the lemma to check.
+/// The theory imports the [DEFAULT_IMPORTS] followed by the given additional theories, without duplicates.
+pub fn validation_theory(lemma: &Lemma, imports: &[String]) -> Theory {
+    let mut theory = Theory::new("Validation", false);
+    for import in DEFAULT_IMPORTS
+        .into_iter()
+        .chain(imports.iter().map(|i| i.as_str()))
+        .unique()
+    {
+        theory.add_theory_import(import);
+    }
+    theory.add_lemma(lemma.clone());
+    theory
+}
+
+/// Writes the theory to the given file, streaming lemma by lemma.
+pub fn write_theory(theory: &Theory, path: &Path) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(fs::File::create(path)?);
+    theory.write_isabelle(&mut writer)?;
+    writer.flush()
+}
+
+/// Writes the theory to the given file for checking it.
+fn write_theory_for_check(theory: &Theory, path: &Path) -> Result<(), Error> {
+    transcript::record(&format!("Theory {}", path.display()), || {
+        theory.to_isabelle()
+    });
+    write_theory(theory, path)
+        .map_err(|e| Error::Other(format!("Could not write {}: {}", path.display(), e)))
+}
+
+/// The log target of progress messages, such as the number of models validated in a batch,
+/// which the command line shows by default
+pub const PROGRESS_TARGET: &str = "smtmv::progress";
+/// How often a check that takes long reports that it is still running
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Awaits the future, reporting every [HEARTBEAT_INTERVAL] that `what` is still running, so that long checks
+/// do not appear hung.
+pub(crate) async fn with_heartbeat<F: std::future::Future>(what: &str, future: F) -> F::Output {
+    let start = Instant::now();
+    let mut heartbeat = tokio::time::interval_at(
+        tokio::time::Instant::now() + HEARTBEAT_INTERVAL,
+        HEARTBEAT_INTERVAL,
+    );
+    tokio::pin!(future);
+    loop {
+        tokio::select! {
+            output = &mut future => return output,
+            _ = heartbeat.tick() => log::info!(
+                target: PROGRESS_TARGET,
+                "{} still running ({} seconds)",
+                what,
+                start.elapsed().as_secs()
+            ),
+        }
+    }
+}
+
+/// The runtime the checkers run their futures on.
+/// Checkers running on a blocking thread of an async runtime, as in [crate::validation::validate_async], use that
+/// runtime, since starting a runtime within another one panics; otherwise they start a runtime of their own.
+pub(crate) enum Executor {
+    Caller(tokio::runtime::Handle),
+    Own(tokio::runtime::Runtime),
+}
+
+impl Executor {
+    pub(crate) fn new() -> io::Result<Self> {
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => Ok(Executor::Caller(handle)),
+            Err(_) => Ok(Executor::Own(tokio::runtime::Runtime::new()?)),
+        }
+    }
+
+    /// Runs the future to completion on the runtime.
+    pub(crate) fn block_on<F: std::future::Future>(&self, future: F) -> F::Output {
+        match self {
+            Executor::Caller(handle) => handle.block_on(future),
+            Executor::Own(runtime) => runtime.block_on(future),
+        }
+    }
+
+    /// Runs the future in the background on the runtime.
+    pub(crate) fn spawn<F>(&self, future: F) -> tokio::task::JoinHandle<F::Output>
+    where
+        F: std::future::Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        match self {
+            Executor::Caller(handle) => handle.spawn(future),
+            Executor::Own(runtime) => runtime.spawn(future),
+        }
+    }
+}
+
+/// Returns why a check was interrupted before it finished, and logs it:
+/// [UnknownReason::Cancelled] if its validation was cancelled, otherwise [UnknownReason::Timeout].
+pub(crate) fn interruption(what: &str) -> UnknownReason {
+    if cancel::is_cancelled() {
+        log::info!("{} was cancelled", what);
+        UnknownReason::Cancelled
+    } else {
+        log::info!("{} did not finish within the timeout", what);
+        UnknownReason::Timeout
+    }
+}
+
+/// Runs the command to completion and returns its output.
+/// Returns None if the process was killed because it ran longer than the timeout or the validation was cancelled,
+/// see [interruption].
+pub(crate) fn run_command(
+    command: &mut tokio::process::Command,
+    timeout: Option<Duration>,
+) -> io::Result<Option<Output>> {
+    command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let program = Path::new(command.as_std().get_program())
+        .file_name()
+        .map_or_else(String::new, |p| p.to_string_lossy().into_owned());
+    transcript::record("Command", || {
+        let std = command.as_std();
+        let dir = std
+            .get_current_dir()
+            .map(|d| format!("cd {}\n", d.display()))
+            .unwrap_or_default();
+        let args = std::iter::once(std.get_program())
+            .chain(std.get_args())
+            .map(|a| a.to_string_lossy())
+            .join(" ");
+        format!("{}{}", dir, args)
+    });
+    let start = Instant::now();
+    let cancellation = cancel::current();
+    let output = Executor::new()?.block_on(async {
+        let output = async {
+            let output = with_heartbeat(&program, command.output());
+            match timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, output).await {
+                    Ok(output) => output.map(Some),
+                    Err(_) => Ok(None),
+                },
+                None => output.await.map(Some),
+            }
+        };
+        // Dropping the output future kills the process
+        tokio::select! {
+            output = output => output,
+            () = cancel::cancelled(cancellation.as_ref()) => Ok(None),
+        }
+    });
+    let elapsed = start.elapsed().as_secs_f64();
+    match &output {
+        Ok(Some(o)) => {
+            let title = format!("{} exited after {:.3}s with {}", program, elapsed, o.status);
+            transcript::record(&title, || {
+                format!(
+                    "STDOUT:\n{}\nSTDERR:\n{}",
+                    String::from_utf8_lossy(&o.stdout),
+                    String::from_utf8_lossy(&o.stderr)
+                )
+            })
+        }
+        Ok(None) if cancel::is_cancelled() => transcript::record(
+            &format!(
+                "{} killed after {:.3}s, the validation was cancelled",
+                program, elapsed
+            ),
+            String::new,
+        ),
+        Ok(None) => transcript::record(
+            &format!("{} killed after the timeout of {:.3}s", program, elapsed),
+            String::new,
+        ),
+        Err(e) => transcript::record(&format!("{} could not be run", program), || e.to_string()),
+    }
+    output
+}
+
+/// Keeps the working directory of a check unless it proved the lemma, so that failed checks can be inspected,
+/// and logs its path. Otherwise, the directory is removed.
+pub(crate) fn keep_on_failure(dir: tempfile::TempDir, result: &Result<CheckResult, Error>) {
+    if !matches!(result, Ok(CheckResult::OK)) {
+        let path = dir.into_path();
+        log::warn!("Kept the files of the failed check in {}", path.display());
     }
 }
 
@@ -273,3 +1456,297 @@ fn make_dir() -> tempfile::TempDir {
     }
     temp_dir*/
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_failed_checks() {
+        let dir = make_dir();
+        let path = dir.path().to_owned();
+        keep_on_failure(dir, &Ok(CheckResult::OK));
+        assert!(!path.exists());
+        for result in [
+            Ok(CheckResult::FailedInvalid),
+            Err(Error::IsabelleError(IsabelleFailure::Proof)),
+        ] {
+            let dir = make_dir();
+            let path = dir.path().to_owned();
+            keep_on_failure(dir, &result);
+            assert!(path.exists());
+            fs::remove_dir(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn command_output() {
+        let mut command = tokio::process::Command::new("echo");
+        command.arg("done");
+        transcript::start();
+        let output = run_command(&mut command, Some(Duration::from_secs(10)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "done\n");
+        let transcript = transcript::finish().unwrap();
+        assert!(transcript.contains("====\necho done\n"));
+        assert!(transcript.contains("echo exited after"));
+        assert!(transcript.contains("STDOUT:\ndone\n"));
+        let mut command = tokio::process::Command::new("sleep");
+        command.arg("5");
+        assert!(run_command(&mut command, Some(Duration::from_millis(100)))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn classify_unknown_reasons() {
+        assert_eq!(
+            UnknownReason::from_output("*** Timeout\n*** At command \"apply\""),
+            UnknownReason::Timeout
+        );
+        assert_eq!(
+            UnknownReason::from_output("Poly/ML: Out of memory"),
+            UnknownReason::ResourceLimit
+        );
+        assert_eq!(
+            UnknownReason::from_output("java.lang.OutOfMemoryError: Java heap space"),
+            UnknownReason::ResourceLimit
+        );
+        assert_eq!(
+            UnknownReason::from_output("*** Failed to finish proof"),
+            UnknownReason::TacticFailure
+        );
+    }
+
+    #[test]
+    fn report_failed_nodes() {
+        let results: UseTheoryResults = serde_json::from_str(
+            r#"{
+                "task": "t1",
+                "ok": false,
+                "errors": [],
+                "nodes": [{
+                    "node_name": "/tmp/Validation.thy",
+                    "theory_name": "Draft.Validation",
+                    "status": {
+                        "ok": false, "total": 3, "unprocessed": 0, "running": 0, "warned": 0,
+                        "failed": 1, "canceled": false, "consolidated": true, "percentage": 99
+                    },
+                    "messages": [
+                        { "kind": "writeln", "message": "proof (prove)", "pos": { "line": 5 } },
+                        {
+                            "kind": "error",
+                            "message": "Failed to finish proof:\ngoal (1 subgoal):\n 1. False",
+                            "pos": { "line": 6 }
+                        }
+                    ],
+                    "exports": []
+                }]
+            }"#,
+        )
+        .unwrap();
+        let reports = NodeReport::from_results(&results);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].theory_name, "Draft.Validation");
+        assert!(!reports[0].ok);
+        let errors: Vec<_> = reports[0].errors().collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, Some(6));
+        assert!(matches!(
+            classify_failure(&reports),
+            CheckResult::FailedInvalid
+        ));
+    }
+
+    #[test]
+    fn locate_failed_lemma() {
+        let theory = "theory Validation\nimports smt.Core\nbegin\n\nlemma check_0: shows \"a\"\n    apply(simp add: )\n    done\n\nlemma check_1: shows \"b\"\n    apply(simp add: )\n    done\nend";
+        assert_eq!(lemma_at_line(theory, 2), None);
+        assert_eq!(lemma_at_line(theory, 6).unwrap(), "check_0");
+        assert_eq!(lemma_at_line(theory, 10).unwrap(), "check_1");
+    }
+
+    #[test]
+    fn attribute_failures_to_lemmas() {
+        let theory = "theory Validation\nimports smt.Core\nbegin\n\nlemma check_0: shows \"a\"\n    apply(simp add: )\n    done\n\nlemma check_1: shows \"b\"\n    apply(simp add: )\n    done\nend";
+        let names = vec!["check_0".to_owned(), "check_1".to_owned()];
+        let report = |messages: &[(&str, Option<usize>)]| NodeReport {
+            node_name: String::new(),
+            theory_name: String::new(),
+            ok: false,
+            messages: messages
+                .iter()
+                .map(|(message, line)| NodeMessage {
+                    kind: "error".to_owned(),
+                    message: message.to_string(),
+                    line: *line,
+                })
+                .collect(),
+        };
+        assert_eq!(
+            attribute_failures(
+                &[report(&[("Failed to finish proof:\n 1. False", Some(10))])],
+                theory,
+                &names
+            ),
+            [CheckResult::OK, CheckResult::FailedInvalid]
+        );
+        assert_eq!(
+            attribute_failures(
+                &[report(&[
+                    ("Failed to finish proof:\n 1. x = 1", Some(6)),
+                    ("Failed to finish proof:\n 1. False", Some(10))
+                ])],
+                theory,
+                &names
+            ),
+            [
+                CheckResult::FailedUnknown(UnknownReason::TacticFailure),
+                CheckResult::FailedInvalid
+            ]
+        );
+        // An error outside of the lemmas fails all of them
+        assert_eq!(
+            attribute_failures(&[report(&[("Bad theory import", Some(2))])], theory, &names),
+            vec![CheckResult::FailedUnknown(UnknownReason::TacticFailure); 2]
+        );
+    }
+
+    #[test]
+    fn classify_node_errors() {
+        let report = |message: &str| NodeReport {
+            node_name: String::new(),
+            theory_name: String::new(),
+            ok: false,
+            messages: vec![NodeMessage {
+                kind: "error".to_owned(),
+                message: message.to_owned(),
+                line: None,
+            }],
+        };
+        assert!(matches!(
+            classify_failure(&[report("Timeout")]),
+            CheckResult::FailedUnknown(UnknownReason::Timeout)
+        ));
+        assert!(matches!(
+            classify_failure(&[report("Failed to finish proof:\n 1. x = 1")]),
+            CheckResult::FailedUnknown(UnknownReason::TacticFailure)
+        ));
+    }
+
+    #[test]
+    fn parse_nitpick_counterexample() {
+        let output = "Nitpicking formula...\nNitpick found a counterexample for card int = 5:\n\n  Free variables:\n    x = 1\n    s = ''ab''\n\n  Skolem constant:\n    y = 2\n";
+        let counterexample = Counterexample::parse(output).unwrap();
+        assert_eq!(
+            counterexample.assignment,
+            BTreeMap::from([
+                ("s".to_owned(), "''ab''".to_owned()),
+                ("x".to_owned(), "1".to_owned())
+            ])
+        );
+        assert_eq!(counterexample.to_string(), "s = ''ab''\nx = 1");
+        assert_eq!(
+            Counterexample::parse(
+                "Nitpick found a potentially spurious counterexample:\n  Free variable:\n    x = 1"
+            ),
+            None
+        );
+        assert_eq!(
+            Counterexample::parse("Nitpick found no counterexample"),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_sledgehammer_proofs() {
+        assert_eq!(
+            parse_sledgehammer(
+                "Sledgehammering...\n\"cvc4\": Try this: using assms by simp (0.4 ms)\n"
+            )
+            .unwrap(),
+            "insert assms, simp"
+        );
+        assert_eq!(
+            parse_sledgehammer("\"z3\": Try this: by (smt (verit) assms(1)) (12 ms)").unwrap(),
+            "(smt (verit) assms(1))"
+        );
+        assert_eq!(parse_sledgehammer("No proof found"), None);
+    }
+
+    /// Proves lemmas only with the given method
+    struct MethodChecker {
+        proves: &'static str,
+        tried: Vec<String>,
+    }
+
+    impl LemmaChecker for MethodChecker {
+        fn check(&mut self, lemma: &Lemma) -> Result<CheckResult, Error> {
+            let method = lemma.method().unwrap_or("simp").to_owned();
+            let proven = method == self.proves;
+            self.tried.push(method);
+            if proven {
+                Ok(CheckResult::OK)
+            } else {
+                Ok(CheckResult::FailedUnknown(UnknownReason::TacticFailure))
+            }
+        }
+
+        fn find_proof_method(&mut self, _lemma: &Lemma) -> Result<Option<String>, Error> {
+            Ok(Some("metis".to_owned()))
+        }
+    }
+
+    #[test]
+    fn escalate_proof_methods() {
+        let mut lemma = Lemma::new("l");
+        lemma.add_premise("x = 1").add_conclusion("x > 0");
+
+        let mut checker = EscalatingChecker::new(MethodChecker {
+            proves: "fastforce",
+            tried: vec![],
+        });
+        assert_eq!(checker.check(&lemma).unwrap(), CheckResult::OK);
+        assert_eq!(checker.inner.tried, vec!["simp", "auto", "fastforce"]);
+
+        let mut checker = EscalatingChecker::new(MethodChecker {
+            proves: "metis",
+            tried: vec![],
+        });
+        lemma.set_method("auto");
+        assert_eq!(checker.check(&lemma).unwrap(), CheckResult::OK);
+        assert_eq!(
+            checker.inner.tried,
+            vec!["auto", "simp", "fastforce", "eval", "metis"]
+        );
+    }
+
+    #[test]
+    fn work_queue_across_threads() {
+        let queue = WorkQueue::new(0..100);
+        let taken = Mutex::new(vec![]);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while let Some((i, item)) = queue.next() {
+                        assert_eq!(i, item);
+                        taken.lock().unwrap().push(item);
+                    }
+                });
+            }
+        });
+        let mut taken = taken.into_inner().unwrap();
+        taken.sort();
+        assert_eq!(taken, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn validation_theory_imports() {
+        let mut lemma = Lemma::new("validation");
+        lemma.add_conclusion("True");
+        let imports = vec!["My.Extra".to_owned(), "smt.Core".to_owned()];
+        let theory = validation_theory(&lemma, &imports).to_isabelle();
+        assert!(theory.contains("imports smt.Strings smt.Core My.Extra \n"));
+    }
+}
@@ -1,103 +1,492 @@
 #![allow(unused_imports)]
 use itertools::Itertools;
-use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fs,
-    path::PathBuf,
+    io::{self, Write},
+    path::{Path, PathBuf},
 };
 
 use smt2parser::{
     concrete::{Command, Constant},
-    concrete::{QualIdentifier, Term},
-    visitors::{FunctionDec, Identifier},
+    concrete::{QualIdentifier, Sort, Symbol, Term},
+    visitors::{AttributeValue, DatatypeDec, FunctionDec, Identifier, Index},
     *,
 }; // 0.8.0
 
-use crate::error::Error;
+use crate::convert_coq::Coq;
+pub use crate::convert_isabelle::Isabelle;
+use crate::convert_lean::Lean;
+use crate::desugar;
+use crate::error::{Error, SourcePosition};
+use crate::eval;
+use crate::ir;
+use crate::lemma::{self, Lemma, Theory};
+use crate::normalize;
+use crate::scopes;
+use crate::simplify::Simplifier;
+use crate::solver_output;
+use crate::sorts::{self, SortContext};
+use crate::spec::{self, Spec, SpecDef};
 
-/// The specification to map an SMT-LIB function to Isabelle/HOL using the Isabelle SMT theories.
-#[derive(Serialize, Deserialize, Clone, Debug)]
-struct Spec {
-    mapsto: Option<String>,
-    assoc: Option<String>,
-    chainable: bool,
+/// The Isabelle theory that defines the real numbers, imported if the converted terms contain reals.
+pub const REAL_THEORY: &str = "Complex_Main";
+/// The Isabelle theory that defines machine words, imported if the converted terms contain bit-vectors.
+pub const WORD_THEORY: &str = "HOL-Library.Word";
+
+/// An SMT-LIB command converted to an Isabelle/HOL term.
+#[derive(Clone, Debug)]
+pub struct ConvertedTerm {
+    /// The converted Isabelle/HOL term
+    pub term: String,
+    /// The free variables occurring in the command
+    pub vars: HashSet<String>,
+    /// The label given to the assertion with the `:named` attribute
+    pub name: Option<String>,
+    /// Why the assertion was skipped instead of converted, see [Converter::lenient].
+    /// The term of a skipped assertion is empty.
+    pub unsupported: Option<String>,
+    /// The command before printing, None for skipped assertions
+    pub command: Option<ir::Command>,
+}
+
+/// A converter from SMT-LIB to Isabelle/HOL or, see [TargetKind], to another proof assistant.
+pub struct Converter {
+    spec: SpecDef,
+    /// The language of the converted terms
+    target: TargetKind,
+    /// The logic declared by the last `set-logic` command, selects the per-logic section of the spec.
+    logic: Option<String>,
+    /// The index of the `check-sat` whose live assertions are converted, the last one if None
+    check_index: Option<usize>,
+    /// The SMT-LIB theories included in the declared logic; None if all theories are active.
+    /// Mappings of other SMT-LIB theories of the spec are ignored.
+    active_theories: Option<HashSet<&'static str>>,
+    vars_used: HashSet<String>,
+    vars_defined: HashSet<String>,
+    /// Symbols declared inside the model (e.g. solver-internal helper functions).
+    auxiliaries: HashSet<String>,
+    /// Whether the input currently being converted is a model rather than a formula.
+    converting_model: bool,
+    /// SMT-LIB functions mapped to fresh uninterpreted constants, with the names of the constants.
+    uninterpreted: HashMap<String, String>,
+    /// The SMT-LIB functions that were actually converted as uninterpreted.
+    uninterpreted_used: HashSet<String>,
+    /// The variables used in the command currently being converted.
+    command_vars: HashSet<String>,
+    /// Whether assertions are normalized before conversion, see [normalize].
+    normalize: bool,
+    /// Whether converted terms are simplified, see [crate::simplify].
+    simplify: bool,
+    /// Whether assertions with unsupported functions are skipped instead of failing the conversion.
+    lenient: bool,
+    /// The symbols bound by enclosing binders, innermost last, with their Isabelle/HOL names.
+    bound: Vec<(String, String)>,
+    /// Additional simplification rules the generated terms need to be proven.
+    simps: HashSet<String>,
+    /// The declared constants and functions with their Isabelle/HOL types, in declaration order.
+    declared: Vec<(String, String)>,
+    /// The SMT-LIB sorts of the declared symbols and of the bound variables in scope.
+    sorts: SortContext,
+    /// The SMT-LIB functions with sort-specific mappings in the spec.
+    overloaded: HashSet<String>,
+    /// Whether any converted term contains reals, which require importing the [REAL_THEORY].
+    uses_reals: bool,
+    /// Whether any converted term contains bit-vectors, which require importing the [WORD_THEORY].
+    uses_words: bool,
+    /// The declared datatypes with their number of type parameters.
+    datatypes: HashMap<String, usize>,
+    /// The constructors, selectors, and testers of the declared datatypes with their Isabelle/HOL names.
+    /// Testers are keyed by their SMT-LIB 2.5 name `is-c`.
+    datatype_functions: HashMap<String, String>,
+    /// The type parameters of the datatype currently being declared.
+    type_params: Vec<String>,
+    /// The Isabelle/HOL `datatype` and `typedecl` commands declaring the types, in declaration order.
+    type_decls: Vec<String>,
+    /// How uninterpreted sorts are represented in Isabelle/HOL.
+    uninterpreted_sorts: UninterpretedSorts,
+    /// The uninterpreted sorts declared with `declare-sort`.
+    declared_sorts: HashSet<String>,
+    /// The declared functions with parameters, which are free variables applied to their arguments.
+    declared_functions: HashSet<String>,
+    /// The functions defined with `define-fun-rec` or `define-funs-rec` with their Isabelle/HOL names.
+    recursive_functions: HashMap<String, String>,
+    /// The Isabelle/HOL `function` definitions of the recursive functions, in definition order.
+    definitions: Vec<String>,
+    /// The measure proving the termination of recursive functions; termination is assumed with `sorry` if None.
+    termination_measure: Option<String>,
+    /// The values of the constants and functions defined by the model, see [Converter::substitute_model].
+    model_values: HashMap<String, ir::Term>,
+    /// The constants of uninterpreted sorts declared inside the model, by sort in declaration order.
+    /// These are the elements of the sort's domain in the model, so they are distinct.
+    sort_elements: BTreeMap<String, Vec<String>>,
+}
+
+/// The specs shipped with this tool for the companion Isabelle SMT theories, selected by logic
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum BuiltinSpec {
+    /// Strings and regular expressions
+    #[value(name = "QF_S")]
+    QfS,
+    /// Strings and regular expressions with linear integer arithmetic
+    #[value(name = "QF_SLIA")]
+    QfSlia,
 }
 
-impl Spec {
-    /// Returns true iff the SMT-LIB function is declared `left-assoc`.
-    fn is_left_assoc(&self) -> bool {
-        match &self.assoc {
-            Some(a) => a == "left",
-            None => false,
+impl BuiltinSpec {
+    /// Returns the JSON representation of the spec.
+    pub fn json(self) -> &'static str {
+        match self {
+            BuiltinSpec::QfS => include_str!("../specs/QF_S.json"),
+            BuiltinSpec::QfSlia => include_str!("../specs/QF_SLIA.json"),
         }
     }
 
-    /// Returns true iff the SMT-LIB function is declared `left-assoc`.
-    fn is_right_assoc(&self) -> bool {
-        match &self.assoc {
-            Some(a) => a == "right",
-            None => false,
-        }
+    /// Returns the JSON representation of the spec for the target, see [Target::builtin_spec].
+    pub fn json_for(self, target: TargetKind) -> &'static str {
+        target.target().builtin_spec(self)
     }
 }
 
-/// The specification to map SMT-LIB functions to Isabelle/HOL using the Isabelle SMT theories.
-#[derive(Serialize, Deserialize, Debug)]
-struct SpecDef {
-    version: String,
-    #[serde(rename = "smt-lib-version")]
-    smt_lib_version: String,
-    specs: HashMap<String, HashMap<String, Spec>>,
+/// The representation of the uninterpreted sorts declared with `declare-sort` in Isabelle/HOL
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum UninterpretedSorts {
+    /// A type declared with `typedecl` in the validation theory
+    #[default]
+    Typedecl,
+    /// A type variable, so the lemma is proven for every type
+    TypeVariables,
 }
 
-impl SpecDef {
-    /// Returns the name of the Isabelle/HOL function to use for the given SMT-LIB function.
-    fn get_spec(&self, op: &str) -> Option<(String, Spec)> {
-        for (th, specs) in self.specs.iter() {
-            if let Some(spec) = specs.get(op) {
-                return Some((th.clone(), spec.clone()));
-            }
+/// The languages the converter can translate SMT-LIB to, see [Target]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum TargetKind {
+    /// Isabelle/HOL terms using the Isabelle SMT theories
+    #[default]
+    Isabelle,
+    /// Coq terms using the Boolean functions a Coq spec maps to, see [crate::convert_coq]
+    Coq,
+    /// Lean 4 terms using the Boolean functions a Lean spec maps to, see [crate::convert_lean]
+    Lean,
+}
+
+impl TargetKind {
+    /// Returns the syntax of the target language.
+    pub fn target(self) -> &'static dyn Target {
+        match self {
+            TargetKind::Isabelle => &Isabelle,
+            TargetKind::Coq => &Coq,
+            TargetKind::Lean => &Lean,
         }
-        None
+    }
+
+    /// Returns the names of the spec file of this target in a theory root, in order of preference.
+    pub fn spec_files(self) -> &'static [&'static str] {
+        self.target().spec_files()
     }
 }
 
-/// A converter from SMT-LIB to Isabelle/HOL.
-pub struct Converter {
-    spec: SpecDef,
-    vars_used: HashSet<String>,
-    vars_defined: HashSet<String>,
+/// A language the converter translates SMT-LIB to.
+/// The converter traverses the SMT-LIB terms and maps their functions and sorts with the spec;
+/// the target renders the constructs whose syntax differs between the languages, and the lemma stating the validation.
+///
+/// Targets other than Isabelle/HOL state assertions as Boolean terms being true,
+/// so their specs map the SMT-LIB predicates to functions returning Booleans.
+pub trait Target: Sync {
+    /// The name of the language, used in messages
+    fn name(&self) -> &'static str;
+
+    /// Returns the names of the spec file of this target in a theory root, in order of preference.
+    fn spec_files(&self) -> &'static [&'static str];
+
+    /// The keywords of the language and the names of its library that SMT-LIB symbols must not be mapped to
+    fn reserved_names(&self) -> &'static [&'static str];
+
+    /// Returns the name of a constant, function, or variable of the SMT-LIB input, see [target_name].
+    fn identifier(&self, symbol: &str) -> String {
+        target_name(symbol, self.reserved_names())
+    }
+
+    /// Returns the JSON representation of the spec shipped with this tool for the logic.
+    fn builtin_spec(&self, spec: BuiltinSpec) -> &'static str;
+
+    /// Whether the converter translates the constructs the spec cannot map, such as quantifiers, datatypes,
+    /// declared sorts, recursive functions, arrays, and real and bit-vector literals.
+    /// These translations produce Isabelle/HOL, so other targets only support the functions and sorts their spec maps.
+    fn full_translation(&self) -> bool {
+        false
+    }
+
+    /// Returns the statement that the converted assertion holds.
+    fn assertion(&self, term: String) -> String;
+
+    /// Returns the type of a function, given the types of its parameters followed by its result type.
+    fn function_type(&self, types: &[String]) -> String;
+
+    /// Returns the conjunction of the terms, e.g. of the pairs of a `pairwise` application.
+    fn conjunction(&self, terms: &[String]) -> String;
+
+    /// Returns the term annotated with its type.
+    fn annotate(&self, term: &str, ty: &str) -> String;
+
+    /// Returns the anonymous function of the (possibly annotated) parameters.
+    fn lambda(&self, params: &[String], body: &str) -> String;
+
+    /// Returns the term binding the names to the values in parallel in the body.
+    fn let_in(&self, names: &[String], values: &[String], body: &str) -> String;
+
+    /// Returns the integer literal, given the decimal value, e.g. `-5`.
+    fn int_literal(&self, value: &str) -> String;
+
+    /// Returns the string literal of the unescaped string.
+    fn string_literal(&self, s: &str) -> Result<String, Error>;
+
+    /// Returns the application of a function of the spec, e.g. `((+)  a b)`.
+    /// The function is parenthesized if it has several arguments, so that operators are applied in prefix form.
+    fn application(&self, function: &str, args: &[String]) -> String {
+        let mut s = if args.len() <= 1 {
+            format!("({} ", function)
+        } else {
+            format!("(({}) ", function)
+        };
+        for a in args {
+            s += " ";
+            s += a;
+        }
+        s += ")";
+        s
+    }
+
+    /// Returns the curried application of the function to the arguments.
+    fn call(&self, function: &str, args: &[String]) -> String {
+        let mut s = format!("({}", function);
+        for a in args {
+            s += " ";
+            s += a;
+        }
+        s += ")";
+        s
+    }
+
+    /// Returns the conditional term.
+    fn ite(&self, condition: &str, then: &str, otherwise: &str) -> String {
+        format!("(if {} then {} else {})", condition, then, otherwise)
+    }
+
+    /// Returns the quantification over the annotated variables, universal if `forall` and existential otherwise.
+    fn quantifier(&self, _forall: bool, _vars: &[String], _body: &str) -> Result<String, Error> {
+        Err(self.unsupported("quantifiers"))
+    }
+
+    /// Returns the case distinction over the scrutinee, given the patterns and bodies of the cases.
+    fn case(&self, _scrutinee: &str, _cases: &[(String, String)]) -> Result<String, Error> {
+        Err(self.unsupported("match"))
+    }
+
+    /// Returns the function updated to map the index to the value.
+    fn update(&self, _function: &str, _index: &str, _value: &str) -> Result<String, Error> {
+        Err(self.unsupported("store"))
+    }
+
+    /// Returns the error for a construct the target does not support.
+    fn unsupported(&self, construct: &str) -> Error {
+        Error::unsupported(format!("{} in {} output", construct, self.name()))
+    }
+
+    /// The proof method of lemmas that do not set one
+    fn default_method(&self) -> &'static str;
+
+    /// The name of the file the validation theory is written to
+    fn file_name(&self) -> &'static str;
+
+    /// Returns the theory containing the lemma to check, importing the given theories.
+    fn validation_theory(&self, lemma: &Lemma, imports: &[String]) -> Theory;
+
+    /// Writes the lemma in the syntax of the target.
+    fn write_lemma(&self, lemma: &Lemma, w: &mut dyn Write) -> io::Result<()>;
+
+    /// Writes the theory in the syntax of the target.
+    fn write_theory(&self, theory: &Theory, w: &mut dyn Write) -> io::Result<()>;
+
+    /// Writes the theory to the file.
+    fn write_file(&self, theory: &Theory, path: &Path) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(fs::File::create(path)?);
+        self.write_theory(theory, &mut writer)?;
+        writer.flush()
+    }
+
+    /// Returns the lemma in the syntax of the target.
+    fn lemma_text(&self, lemma: &Lemma) -> String {
+        let mut buf = vec![];
+        self.write_lemma(lemma, &mut buf)
+            .expect("Writing to a buffer does not fail");
+        String::from_utf8(buf).expect("Lemma is valid UTF-8")
+    }
 }
 
 impl Converter {
     /// Creates a new converter from the given specification.
     pub fn new(spec_json: String) -> Result<Self, Error> {
-        let spec: SpecDef = match serde_json::from_str(&spec_json) {
-            Ok(s) => s,
-            Err(e) => return Err(Error::Other(format!("Could not load spec.json: {}", e))),
-        };
+        Self::from_spec(SpecDef::from_json(&spec_json)?)
+    }
+
+    fn from_spec(spec: SpecDef) -> Result<Self, Error> {
+        spec.check_supported()?;
         Ok(Self {
+            target: TargetKind::default(),
+            logic: None,
+            check_index: None,
+            active_theories: None,
             vars_used: HashSet::new(),
             vars_defined: HashSet::new(),
+            auxiliaries: HashSet::new(),
+            converting_model: false,
+            uninterpreted: HashMap::new(),
+            uninterpreted_used: HashSet::new(),
+            command_vars: HashSet::new(),
+            normalize: false,
+            simplify: false,
+            lenient: false,
+            bound: vec![],
+            simps: HashSet::new(),
+            declared: vec![],
+            sorts: SortContext::default(),
+            overloaded: spec.overloaded_ops(),
+            uses_reals: false,
+            uses_words: false,
+            datatypes: HashMap::new(),
+            datatype_functions: HashMap::new(),
+            type_params: vec![],
+            type_decls: vec![],
+            uninterpreted_sorts: UninterpretedSorts::default(),
+            declared_sorts: HashSet::new(),
+            declared_functions: HashSet::new(),
+            model_values: HashMap::new(),
+            sort_elements: BTreeMap::new(),
+            recursive_functions: HashMap::new(),
+            definitions: vec![],
+            termination_measure: None,
             spec,
         })
     }
 
+    /// Creates a new converter from a spec shipped with this tool.
+    pub fn builtin(spec: BuiltinSpec) -> Result<Self, Error> {
+        Converter::new(spec.json().to_owned())
+    }
+
+    /// Creates a new converter to the target from the spec shipped with this tool for the target.
+    pub fn builtin_for(spec: BuiltinSpec, target: TargetKind) -> Result<Self, Error> {
+        let mut converter = Converter::new(spec.json_for(target).to_owned())?;
+        converter.target(target);
+        Ok(converter)
+    }
+
     /// Creates a new converter from the given specification file.
-    pub fn from_spec_file(spec_file: &PathBuf) -> Result<Self, Error> {
-        let spec = match fs::read_to_string(spec_file) {
-            Ok(b) => b,
-            Err(e) => {
-                return Err(Error::Other(format!(
-                    "Error loading {:?}: {}",
-                    spec_file.as_os_str(),
-                    e
-                )))
-            }
-        };
+    /// The file is read as TOML or YAML if it has the extension `.toml` or `.yaml`/`.yml`, and as JSON otherwise.
+    pub fn from_spec_file(spec_file: &Path) -> Result<Self, Error> {
+        Self::from_spec(SpecDef::from_file(spec_file)?)
+    }
+
+    /// Checks that the loaded spec matches the version of the theories in the given theory root.
+    pub fn check_theory_root(&self, theory_root: &Path) -> Result<(), Error> {
+        self.spec.check_theory_root(theory_root)
+    }
+
+    /// Loads the spec of an additional theory root, whose mappings override those loaded before.
+    /// The spec is checked against the theory root like the spec the converter was created with.
+    pub fn add_theory_root(&mut self, theory_root: &Path) -> Result<(), Error> {
+        let spec = SpecDef::from_file(&spec::find_target_spec_file(theory_root, self.target))?;
+        spec.check_supported()?;
+        spec.check_theory_root(theory_root)?;
+        self.spec.merge(spec);
+        self.overloaded = self.spec.overloaded_ops();
+        Ok(())
+    }
+
+    /// Sets the language of the converted terms, Isabelle/HOL by default.
+    /// The spec has to map the SMT-LIB functions and sorts to names of the target language.
+    pub fn target(&mut self, target: TargetKind) -> &mut Self {
+        self.target = target;
+        self
+    }
+
+    /// Converts only the assertions live at the `check-sat` with the given index (counting from 0) instead of the last one.
+    /// Assertions in scopes popped before the `check-sat` or made after it are ignored.
+    pub fn check_index(&mut self, index: Option<usize>) -> &mut Self {
+        self.check_index = index;
+        self
+    }
+
+    /// Proves the termination of recursive functions with the given measure, e.g. `\<lambda>(s, n). nat n`.
+    /// Without a measure, termination is assumed with `sorry`.
+    pub fn termination_measure(&mut self, measure: Option<String>) -> &mut Self {
+        self.termination_measure = measure;
+        self
+    }
+
+    /// Treats the given SMT-LIB functions as uninterpreted, ignoring their spec mapping.
+    /// Each function is converted to a fresh constant which is universally quantified in the generated lemma.
+    pub fn uninterpret(&mut self, ops: &[String]) -> &mut Self {
+        for op in ops {
+            let name = format!("{}uninterp_{}", RENAMED_PREFIX, escape_symbol(op));
+            self.uninterpreted.insert(op.clone(), name);
+        }
+        self
+    }
+
+    /// Enables or disables the Boolean normalization of assertions before conversion.
+    /// Normalized assertions are split into their top-level conjuncts.
+    pub fn normalize(&mut self, enabled: bool) -> &mut Self {
+        self.normalize = enabled;
+        self
+    }
+
+    /// Enables or disables the simplification of the converted terms, e.g. folding ground arithmetic.
+    pub fn simplify(&mut self, enabled: bool) -> &mut Self {
+        self.simplify = enabled;
+        self
+    }
+
+    /// Enables or disables skipping assertions that use unsupported SMT-LIB functions.
+    /// A skipped assertion is returned with the reason in [ConvertedTerm::unsupported] instead of failing the conversion.
+    /// Unsupported functions in declarations, definitions, and models still fail the conversion.
+    pub fn lenient(&mut self, enabled: bool) -> &mut Self {
+        self.lenient = enabled;
+        self
+    }
+
+    /// Sets how uninterpreted sorts are represented in Isabelle/HOL.
+    pub fn uninterpreted_sorts(&mut self, mode: UninterpretedSorts) -> &mut Self {
+        self.uninterpreted_sorts = mode;
+        self
+    }
+
+    /// Returns the version of the loaded spec, which is also the version of the theories it maps to.
+    pub fn get_spec_version(&self) -> &str {
+        &self.spec.version
+    }
 
-        Converter::new(spec)
+    /// Returns the additional Isabelle theories the spec and the converted terms require to be imported.
+    pub fn get_imports(&self) -> Vec<String> {
+        let mut imports = self.spec.imports.clone();
+        if self.uses_reals {
+            imports.push(REAL_THEORY.to_owned());
+        }
+        if self.uses_words {
+            imports.push(WORD_THEORY.to_owned());
+        }
+        imports
+    }
+
+    /// Returns the simplification rules the converted terms require in addition to the assumptions.
+    pub fn get_simps(&self) -> HashSet<String> {
+        self.simps.clone()
+    }
+
+    /// Returns the SMT-LIB functions that were converted as uninterpreted constants so far.
+    pub fn get_uninterpreted_used(&self) -> HashSet<String> {
+        self.uninterpreted_used.clone()
     }
 
     /// Returns the names of the variables used in the converted SMT-LIB formula.
@@ -110,285 +499,2605 @@ impl Converter {
         self.vars_defined.clone()
     }
 
+    /// Returns the declared constants and functions with their Isabelle/HOL types, in declaration order.
+    /// Symbols whose sorts the spec does not map are left out, their types are inferred by Isabelle.
+    pub fn get_declared(&self) -> &[(String, String)] {
+        &self.declared
+    }
+
+    /// Returns the Isabelle/HOL `datatype` and `typedecl` commands declaring the converted types, in declaration order.
+    pub fn get_type_declarations(&self) -> &[String] {
+        &self.type_decls
+    }
+
+    /// Returns the Isabelle/HOL `function` definitions of the recursive functions, in definition order.
+    /// They have to precede the lemma in the theory.
+    pub fn get_definitions(&self) -> &[String] {
+        &self.definitions
+    }
+
+    /// Returns the facts that the elements of the uninterpreted sorts declared in the model are distinct,
+    /// e.g. `distinct [U!val!0, U!val!1]`.
+    pub fn get_element_axioms(&self) -> Vec<String> {
+        self.sort_elements
+            .values()
+            .filter(|elements| elements.len() > 1)
+            .map(|elements| format!("distinct [{}]", elements.join(", ")))
+            .collect()
+    }
+
+    /// Returns the constants and functions the converted model defines.
+    pub fn get_model_vars(&self) -> HashSet<String> {
+        self.model_values.keys().cloned().collect()
+    }
+
+    /// Replaces the variables the converted model defines by their values in the converted commands,
+    /// which are simplified again if simplification is enabled.
+    /// The resulting commands state that the model satisfies the formula without the model's equations as premises,
+    /// and are ground unless the model refers to auxiliary functions or leaves variables undefined.
+    pub fn substitute_model(&self, converted: &mut [ConvertedTerm]) -> Result<(), Error> {
+        let target = self.target.target();
+        for c in converted {
+            if let Some(command) = c.command.take() {
+                let command = match command.substitute(&self.model_values) {
+                    ir::Command::Assertion(t) => ir::Command::Assertion(self.simplified(t)),
+                    ir::Command::Definition(name, t) => {
+                        ir::Command::Definition(name, self.simplified(t))
+                    }
+                };
+                c.term = command.print(target)?;
+                c.command = Some(command);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the auxiliary symbols declared inside the converted model.
+    /// These are not assigned by the model and are universally quantified in the generated lemma.
+    pub fn get_auxiliaries(&self) -> HashSet<String> {
+        self.auxiliaries.clone()
+    }
+
+    /// Converts the given SMT-LIB model to Isabelle/HOL.
+    /// Declarations inside the model introduce auxiliary symbols, see [Converter::get_auxiliaries].
+    pub fn convert_model(&mut self, input: String) -> Result<Vec<ConvertedTerm>, Error> {
+        self.converting_model = true;
+        let converted = self.convert(input);
+        self.converting_model = false;
+        converted
+    }
+
     /// Converts the given SMT-LIB formula to Isabelle/HOL.
     /// The results is a list of Isabelle/HOL terms that in conjunction are equivalent to the input formula.
-    pub fn convert(&mut self, input: String) -> Result<Vec<String>, Error> {
-        // Convert from smt 2.5 to smt 2.6
-
-        let input = input
-            .replace("str.to.re", "str.to_re")
-            .replace("str.in.re", "str.in_re")
-            .replace("str.to.int", "str.to_int")
-            .replace("str.from.int", "str.from_int");
+    /// Each term comes with the variables used by the command it was converted from.
+    pub fn convert(&mut self, source: String) -> Result<Vec<ConvertedTerm>, Error> {
+        let input = preprocess(&source);
         let stream = CommandStream::new(input.as_bytes(), concrete::SyntaxBuilder, None);
         let commands = match stream.collect::<Result<Vec<_>, _>>() {
             Ok(c) => c,
             Err(e) => return Err(Error::ParseError(e)),
         };
         log::trace!("Parsed formula");
+        let commands = if self.converting_model {
+            commands
+        } else {
+            scopes::live_commands(commands, self.check_index)?
+        };
+        let commands = if self.normalize {
+            normalize::normalize_commands(commands)
+        } else {
+            commands
+        };
+        self.sorts.declare_commands(&commands);
         let mut converted = vec![];
         for c in &commands {
-            if let Some(conv) = match c {
-                Command::Assert { term } => Some(self.convert_term(term)?),
-                Command::DefineFun { sig, term } => Some(self.convert_fun_defines(sig, term)?),
-                _ => None,
-            } {
-                converted.push(conv);
+            self.command_vars.clear();
+            let lenient =
+                self.lenient && !self.converting_model && matches!(c, Command::Assert { .. });
+            // The variables of a skipped assertion are not used
+            let vars_used = lenient.then(|| self.vars_used.clone());
+            let converted_command = self.convert_command(c).and_then(|command| {
+                command
+                    .map(|command| Ok((command.print(self.target.target())?, command)))
+                    .transpose()
+            });
+            let (conv, unsupported) = match converted_command {
+                Err(e @ Error::Unsupported(..)) if lenient => {
+                    let Error::Unsupported(op, context) = locate_command(e, &source, c) else {
+                        unreachable!("Locating keeps the error")
+                    };
+                    let reason = format!("{}{}", op, context);
+                    log::warn!("Skipping assertion with unsupported function {}", reason);
+                    self.vars_used = vars_used.unwrap_or_default();
+                    self.command_vars.clear();
+                    (Some((String::new(), None)), Some(reason))
+                }
+                r => (
+                    r.map_err(|e| locate_command(e, &source, c))?
+                        .map(|(term, command)| (term, Some(command))),
+                    None,
+                ),
+            };
+            if let Some((term, command)) = conv {
+                let name = match c {
+                    Command::Assert { term } => named_label(term),
+                    _ => None,
+                };
+                converted.push(ConvertedTerm {
+                    term,
+                    vars: std::mem::take(&mut self.command_vars),
+                    name,
+                    unsupported,
+                    command,
+                });
             }
         }
         Ok(converted)
     }
 
-    /// Convert a function definition to an Isabelle/HOL term.
-    #[allow(unstable_name_collisions)]
-    fn convert_fun_defines(&mut self, decl: &FunctionDec, term: &Term) -> Result<String, Error> {
-        self.vars_defined.insert(decl.name.to_string());
-        Ok(format!("{} = {}", decl.name, self.convert_term(term)?))
+    /// Converts a single command, returning the converted assertions and definitions.
+    fn convert_command(&mut self, c: &Command) -> Result<Option<ir::Command>, Error> {
+        Ok(match c {
+            Command::SetLogic { symbol } => {
+                self.set_logic(&symbol.0);
+                None
+            }
+            Command::DeclareConst { symbol, sort } => {
+                self.declare(&symbol.0, &[], sort);
+                self.declare_auxiliary(&symbol.0);
+                self.declare_element(&symbol.0, sort);
+                None
+            }
+            Command::DeclareFun {
+                symbol,
+                parameters,
+                sort,
+            } => {
+                self.declare(&symbol.0, parameters, sort);
+                self.declare_auxiliary(&symbol.0);
+                if parameters.is_empty() {
+                    self.declare_element(&symbol.0, sort);
+                } else {
+                    self.declared_functions.insert(symbol.0.clone());
+                }
+                None
+            }
+            Command::DeclareSort { symbol, arity } => {
+                self.declare_sort(&symbol.0, arity)?;
+                None
+            }
+            Command::DeclareDatatype { symbol, datatype } => {
+                self.declare_datatypes(&[(symbol, datatype)])?;
+                None
+            }
+            Command::DeclareDatatypes { datatypes } => {
+                let datatypes: Vec<_> = datatypes.iter().map(|(s, _, d)| (s, d)).collect();
+                self.declare_datatypes(&datatypes)?;
+                None
+            }
+            Command::Assert { term } => {
+                Some(ir::Command::Assertion(self.convert_simplified(term)?))
+            }
+            Command::DefineFun { sig, term } => Some(self.convert_fun_defines(sig, term)?),
+            Command::DefineFunRec { sig, term } => {
+                self.define_recursive(&[(sig, term)])?;
+                None
+            }
+            Command::DefineFunsRec { funs } => {
+                let funs: Vec<_> = funs.iter().map(|(sig, term)| (sig, term)).collect();
+                self.define_recursive(&funs)?;
+                None
+            }
+            _ => None,
+        })
     }
 
-    /// Convert a term to an Isabelle/HOL term.
-    #[allow(unused_variables)]
-    fn convert_term(&mut self, t: &Term) -> Result<String, Error> {
-        match t {
-            Term::Constant(c) => self.convert_constant(c),
-            Term::QualIdentifier(i) => self.convert_identifier(i),
-            Term::Application {
-                qual_identifier,
-                arguments,
-            } => self.convert_application(qual_identifier, arguments),
-            Term::Let { var_bindings, term } => todo!(),
-            Term::Forall { vars, term } => todo!(),
-            Term::Exists { vars, term } => todo!(),
-            Term::Match { term, cases } => todo!(),
-            Term::Attributes { term, attributes } => todo!(),
+    /// Selects the per-logic section of the spec used for all subsequent conversions.
+    fn set_logic(&mut self, logic: &str) {
+        if self.spec.has_logic(logic) {
+            log::debug!("Using spec section for logic {}", logic);
+        } else {
+            log::debug!("No spec section for logic {}, using global mappings", logic);
         }
+        self.active_theories = spec::logic_theories(logic);
+        match &self.active_theories {
+            Some(theories) => log::debug!("Logic {} includes {:?}", logic, theories),
+            None => log::debug!("Logic {} includes all theories", logic),
+        }
+        self.logic = Some(logic.to_owned());
     }
 
-    fn convert_chr(&self, c: &char) -> String {
-        format!("(chr {})", u32::from(*c))
+    /// Records the use of a free variable.
+    fn use_var(&mut self, name: &str) {
+        self.vars_used.insert(name.to_owned());
+        self.command_vars.insert(name.to_owned());
     }
 
-    /// Convert a constant to an Isabelle/HOL term.
-    fn convert_constant(&self, c: &Constant) -> Result<String, Error> {
-        match c {
-            Constant::Numeral(n) => Ok(format!("({}::int)", n)),
-            Constant::Decimal(d) => Ok(format!("{}", d)),
-            Constant::Hexadecimal(_) => todo!(),
-            Constant::Binary(_) => todo!(),
-            Constant::String(s) => {
-                let s_unescaped = unicode_unescape(s, true)?;
-                log::debug!("Unescaped string: {} => {}", s, s_unescaped);
-                let mut as_char_list = String::from("[");
-                let chars = s_unescaped.chars().collect::<Vec<_>>();
-                for (i, c) in chars.iter().enumerate() {
-                    if i == chars.len() - 1 {
-                        as_char_list.push_str(&self.convert_chr(c));
-                    } else {
-                        as_char_list.push_str(&self.convert_chr(c));
-                        as_char_list.push(',');
-                    }
-                }
-                assert!(!as_char_list.ends_with(','), "{}", as_char_list);
-                as_char_list.push(']');
-                Ok(as_char_list)
+    /// Records the Isabelle/HOL type of a declared constant or function.
+    /// Only the first declaration of a symbol is recorded.
+    fn declare(&mut self, symbol: &str, parameters: &[Sort], sort: &Sort) {
+        if self.declared.iter().any(|(s, _)| s == symbol) {
+            return;
+        }
+        let types: Result<Vec<String>, Error> = parameters
+            .iter()
+            .chain([sort])
+            .map(|s| self.convert_sort(s))
+            .collect();
+        match types {
+            Ok(types) => {
+                let ty = self.target.target().function_type(&types);
+                self.declared.push((symbol.to_owned(), ty));
             }
+            Err(e) => log::debug!("Not fixing the type of {}: {}", symbol, e),
         }
     }
 
-    /// Convert an identifier to an Isabelle/HOL identifier.
-    fn convert_identifier(&mut self, identifier: &QualIdentifier) -> Result<String, Error> {
-        let op = &self.identifier_name(identifier);
-        match self.spec.get_spec(op) {
-            Some(m) => match m.1.mapsto {
-                Some(m) => Ok(m),
-                None => Err(Error::Unsupported(op.to_string())),
-            },
-            None => {
-                // Variables
-                self.vars_used.insert(op.clone());
-                Ok(op.clone())
-            }
+    /// Declares an uninterpreted sort, as a `typedecl` or a type variable depending on [UninterpretedSorts].
+    fn declare_sort(&mut self, symbol: &str, arity: &Numeral) -> Result<(), Error> {
+        self.check_target_supports("declare-sort")?;
+        if *arity != Numeral::from(0u32) {
+            return Err(Error::unsupported(format!(
+                "sort {} with {} parameters",
+                symbol, arity
+            )));
         }
+        if self.declared_sorts.insert(symbol.to_owned())
+            && self.uninterpreted_sorts == UninterpretedSorts::Typedecl
+        {
+            self.type_decls.push(format!("typedecl {}", symbol));
+        }
+        Ok(())
     }
 
-    /// Retrieve the name of an identifier.
-    fn identifier_name(&self, identifier: &QualIdentifier) -> String {
-        match identifier {
-            QualIdentifier::Simple { identifier } | QualIdentifier::Sorted { identifier, .. } => {
-                match identifier {
-                    Identifier::Simple { symbol } => symbol.0.to_string(),
-                    Identifier::Indexed { .. } => todo!(), // Not needed for Strings
-                }
+    /// Records a constant of an uninterpreted sort declared inside the model as an element of the sort.
+    fn declare_element(&mut self, symbol: &str, sort: &Sort) {
+        let sort = sort.to_string();
+        if self.converting_model && self.declared_sorts.contains(&sort) {
+            let elements = self.sort_elements.entry(sort).or_default();
+            if !elements.iter().any(|e| e == symbol) {
+                elements.push(symbol.to_owned());
             }
         }
     }
 
-    /// Unrolls an n-ary `left-assoc` application to a series of binary applications.
-    fn unroll_assoc_left(&self, identifier: &QualIdentifier, args: &Vec<Term>) -> Term {
-        if args.len() >= 2 {
-            let mut term = Term::Application {
-                qual_identifier: identifier.clone(),
-                arguments: vec![args[0].clone(), args[1].clone()],
-            };
-            for arg in args.iter().skip(2) {
-                term = Term::Application {
-                    qual_identifier: identifier.clone(),
-                    arguments: vec![term, arg.clone()],
-                };
-            }
-            term
-        } else {
-            Term::Application {
-                qual_identifier: identifier.clone(),
-                arguments: args.clone(),
-            }
+    /// Records a symbol declared inside the model as auxiliary.
+    /// Declarations in the formula are the variables the model has to assign, so they are not recorded.
+    fn declare_auxiliary(&mut self, symbol: &str) {
+        if self.converting_model {
+            log::debug!("Model declares auxiliary symbol {}", symbol);
+            self.auxiliaries.insert(symbol.to_owned());
         }
     }
 
-    /// Unrolls an n-ary `right-assoc` application to a series of binary applications.
-    #[allow(unused_variables)]
-    fn unroll_assoc_right(&self, identifier: &QualIdentifier, args: &[Term]) -> Term {
-        unimplemented!()
+    /// Converts a group of possibly mutually recursive datatypes to a single Isabelle/HOL `datatype` command.
+    /// Each constructor `c` gets the discriminator `is_c`, which SMT-LIB testers `(_ is c)` are converted to.
+    /// Datatypes that were already declared, e.g. by the formula if the model repeats the declaration, are skipped.
+    fn declare_datatypes(&mut self, datatypes: &[(&Symbol, &DatatypeDec)]) -> Result<(), Error> {
+        self.check_target_supports("datatypes")?;
+        if datatypes
+            .iter()
+            .all(|(name, _)| self.datatypes.contains_key(&name.0))
+        {
+            return Ok(());
+        }
+        // Register all names first, the datatypes may refer to each other
+        for (name, datatype) in datatypes {
+            self.datatypes
+                .insert(name.0.clone(), datatype.parameters.len());
+        }
+        let mut stanzas = vec![];
+        for (name, datatype) in datatypes {
+            self.type_params = datatype.parameters.iter().map(|p| p.0.clone()).collect();
+            let stanza = self.datatype_stanza(&name.0, datatype);
+            self.type_params.clear();
+            stanzas.push(stanza?);
+        }
+        self.type_decls
+            .push(format!("datatype {}", stanzas.join("\n  and ")));
+        Ok(())
     }
 
-    /// Convert a function application to an Isabelle/HOL term.
-    fn convert_application(
-        &mut self,
-        identifier: &QualIdentifier,
-        args: &Vec<Term>,
-    ) -> Result<String, Error> {
-        let op = &self.identifier_name(identifier);
-        let spec = match self.spec.get_spec(op) {
-            Some(m) => m.1,
-            None => return Err(Error::Unsupported(op.to_string())),
+    /// Returns the declaration of a single datatype, e.g. `'T List = is_nil: nil | is_cons: cons (head: "'T") ...`.
+    fn datatype_stanza(&mut self, name: &str, datatype: &DatatypeDec) -> Result<String, Error> {
+        let params: Vec<String> = self.type_params.iter().map(|p| format!("'{}", p)).collect();
+        let header = match params.len() {
+            0 => name.to_owned(),
+            1 => format!("{} {}", params[0], name),
+            _ => format!("({}) {}", params.join(", "), name),
         };
-
-        if spec.is_left_assoc() && args.len() > 2 {
-            self.convert_term(&self.unroll_assoc_left(identifier, args))
-        } else if spec.is_right_assoc() && args.len() > 2 {
-            self.convert_term(&self.unroll_assoc_right(identifier, args))
-        } else {
-            let name = match spec.mapsto {
-                Some(n) => n,
-                None => return Err(Error::Unsupported(op.to_string())),
-            };
-            let mut s = if args.len() <= 1 {
-                format!("({} ", name)
-            } else {
-                format!("(({}) ", name)
-            };
-            for t in args {
-                s += " ";
-                s += &self.convert_term(t)?;
+        let mut constructors = vec![];
+        for constructor in &datatype.constructors {
+            let c = &constructor.symbol.0;
+            let tester = format!("is_{}", c);
+            self.datatype_functions.insert(c.clone(), c.clone());
+            self.datatype_functions
+                .insert(format!("is-{}", c), tester.clone());
+            let mut s = format!("{}: {}", tester, c);
+            for (selector, sort) in &constructor.selectors {
+                self.datatype_functions
+                    .insert(selector.0.clone(), selector.0.clone());
+                s += &format!(" ({}: \"{}\")", selector.0, self.convert_sort(sort)?);
             }
-            s += ")";
-            Ok(s)
+            constructors.push(s);
         }
+        Ok(format!("{} = {}", header, constructors.join(" | ")))
     }
-}
 
-/// Unescape a string literal as specified in the SMT-LIB standard.
-/// If `legacy` is true, additionally unescapes unicode escape sequences in SMT-LIB 2.5 syntax (`\xAB` with A, B hex chars).
-fn unicode_unescape(s: &str, legacy: bool) -> Result<String, Error> {
-    let mut res = String::new();
-    let mut chars = s.chars().peekable();
-    while let Some(c) = chars.next() {
-        if c == '\\' {
-            match chars.next() {
-                Some('u') => match chars.next() {
-                    Some('{') => {
-                        let mut code = String::new();
-                        #[allow(clippy::while_let_on_iterator)]
-                        while let Some(n) = chars.next() {
-                            if n == '}' {
-                                break;
-                            }
-                            code.push(n);
-                        }
-                        let code = u32::from_str_radix(&code, 16).unwrap();
-                        res.push(char::from_u32(code).unwrap());
-                    }
-                    Some(c) => {
-                        let mut code = String::new();
-                        code.push(c);
-                        for _ in 0..4 {
-                            code.push(chars.next().unwrap());
-                        }
-                        let code = u32::from_str_radix(&code, 16).unwrap();
-                        res.push(char::from_u32(code).unwrap());
-                    }
-                    None => return Err(Error::Other("Invalid escape sequence".to_string())),
-                },
-                Some('x') if legacy => {
-                    let mut code = String::new();
-                    for _ in 0..2 {
-                        code.push(chars.next().unwrap());
-                    }
-                    let code = u32::from_str_radix(&code, 16).unwrap();
-                    res.push(char::from_u32(code).unwrap());
-                }
-                Some(c) => {
-                    res.push(c);
-                }
-                None => return Err(Error::Other("Invalid escape sequence".to_string())),
+    /// Returns the Isabelle/HOL name of the datatype constructor, selector, or tester the identifier refers to.
+    /// Testers are written `(_ is c)` or, in SMT-LIB 2.5, `is-c`.
+    fn datatype_function(&self, identifier: &QualIdentifier) -> Option<String> {
+        let name = self.identifier_name(identifier);
+        if name == "is" {
+            if let [Index::Symbol(tested)] = index_list(identifier) {
+                return self
+                    .datatype_functions
+                    .get(&format!("is-{}", tested.0))
+                    .cloned();
             }
+        }
+        self.datatype_functions.get(&name).cloned()
+    }
+
+    /// Returns an error if the target does not support a construct that the spec cannot map, see [Target::full_translation].
+    fn check_target_supports(&self, construct: &str) -> Result<(), Error> {
+        let target = self.target.target();
+        if target.full_translation() {
+            Ok(())
         } else {
-            res.push(c);
+            Err(target.unsupported(construct))
         }
     }
-    Ok(res)
-}
 
-#[cfg(test)]
-mod tests {
-    use super::unicode_unescape;
+    /// Returns the spec for the given SMT-LIB function, taking the declared logic into account.
+    /// Only the theories included in the logic are considered.
+    fn get_spec(&self, op: &str) -> Option<(String, Spec)> {
+        self.spec
+            .get_active_spec(op, self.logic.as_deref(), &|th| self.is_active(th))
+    }
 
-    #[test]
-    fn basic_unescapes() {
-        assert_eq!(
-            unicode_unescape("hello\\u{21}", false).unwrap(),
-            "hello!".to_owned()
-        );
-        assert_eq!(
-            unicode_unescape("\\u{1f600}", false).unwrap(),
-            "😀".to_owned()
-        );
-        assert_eq!(
-            unicode_unescape("\\u1f600", false).unwrap(),
-            "😀".to_owned()
-        );
+    /// Returns the non-standard names of SMT-LIB functions to rewrite before converting, with the SMT-LIB function:
+    /// the built-in aliases of the names z3 uses for string functions, and the aliases of the spec, which take precedence.
+    /// Names the spec maps are kept.
+    pub fn aliases(&self) -> HashMap<String, String> {
+        let mut aliases: HashMap<String, String> = spec::DEFAULT_ALIASES
+            .iter()
+            .map(|(alias, op)| (alias.to_string(), op.to_string()))
+            .collect();
+        aliases.extend(self.spec.aliases.clone());
+        aliases.retain(|alias, _| self.get_spec(alias).is_none());
+        aliases
     }
 
-    #[test]
-    fn smt25_unescapes() {
-        assert_eq!(
-            unicode_unescape("hello\\x21", true).unwrap(),
-            "hello!".to_owned()
-        );
-        assert_eq!(unicode_unescape("\\x65", true).unwrap(), "e".to_owned());
+    /// Returns whether the function is converted without being declared or defined:
+    /// the spec maps it, or it is a solver function that is rewritten, see [desugar::SEQUENCE_FUNCTIONS].
+    pub(crate) fn knows(&self, op: &str) -> bool {
+        self.get_spec(op).is_some() || desugar::SEQUENCE_FUNCTIONS.contains(&op)
     }
 
-    #[test]
-    #[should_panic]
-    fn invalid_escape_sequence1() {
-        _ = unicode_unescape("\\u{}", false);
+    /// Returns true iff the mappings of the theory of the spec are active in the declared logic.
+    fn is_active(&self, theory: &str) -> bool {
+        match &self.active_theories {
+            Some(active) => active.contains(theory) || !spec::SMT_LIB_THEORIES.contains(&theory),
+            None => true,
+        }
     }
 
-    #[test]
-    #[should_panic]
-    fn tooshort_escape_sequence() {
-        _ = unicode_unescape("\\u12", false);
+    /// Returns the error for an SMT-LIB function that is only mapped by theories outside the declared logic.
+    fn check_in_logic(&self, op: &str) -> Result<(), Error> {
+        match (&self.logic, self.spec.get_spec(op, self.logic.as_deref())) {
+            (Some(logic), Some(_)) if self.get_spec(op).is_none() => {
+                Err(Error::NotInLogic(op.to_owned(), logic.clone()))
+            }
+            _ => Ok(()),
+        }
     }
 
-    #[test]
-    #[should_panic]
-    fn nonhex_escape_sequence() {
-        _ = unicode_unescape("\\u{12g}", false);
+    /// Returns the sort-specific spec for the application of the given SMT-LIB function, if the spec has one
+    /// for the sort of the first argument whose sort can be inferred.
+    fn get_sorted_spec(&mut self, op: &str, args: &[Term]) -> Option<(String, Spec)> {
+        if !self.overloaded.contains(op) {
+            return None;
+        }
+        let sort = args.iter().find_map(|t| self.sorts.sort_of(t))?;
+        self.spec
+            .get_sorted_spec(op, &sort, self.logic.as_deref(), &|th| self.is_active(th))
     }
 
-    #[test]
-    #[should_panic]
-    fn smt25_invalid() {
-        assert_eq!(unicode_unescape("\\xFG", true).unwrap(), "e".to_owned());
+    /// Returns for each argument of the application whether it has to be coerced from an integer to a real.
+    /// This is the case for integer arguments of arithmetic functions and comparisons applied to a real argument,
+    /// and for integer arguments of the real division `/`.
+    fn int_coercions(&mut self, op: &str, args: &[Term]) -> Vec<bool> {
+        if !sorts::ARITHMETIC.contains(&op) {
+            return vec![false; args.len()];
+        }
+        let arg_sorts: Vec<_> = args.iter().map(|t| self.sorts.sort_of(t)).collect();
+        let real = op == "/" || arg_sorts.iter().any(|s| s.as_deref() == Some(sorts::REAL));
+        arg_sorts
+            .iter()
+            .map(|s| real && s.as_deref() == Some(sorts::INT))
+            .collect()
+    }
+
+    /// Returns the name of the SMT-LIB symbol in the target, see [target_name].
+    fn target_name(&self, symbol: &str) -> String {
+        self.target.target().identifier(symbol)
+    }
+
+    /// Enters the scope of a bound SMT-LIB symbol with the given Isabelle/HOL name.
+    fn bind(&mut self, symbol: &str, name: String, sort: Option<String>) {
+        self.bound.push((symbol.to_owned(), name));
+        self.sorts.bind(symbol, sort);
+    }
+
+    /// Leaves the scopes of the `n` innermost bound symbols.
+    fn unbind(&mut self, n: usize) {
+        self.bound.truncate(self.bound.len() - n);
+        self.sorts.unbind(n);
+    }
+
+    /// Convert a function definition to an equation, e.g. `f = (\<lambda>(x::int). x + 1)`.
+    /// The values defined by the model are recorded for [Converter::substitute_model].
+    fn convert_fun_defines(
+        &mut self,
+        decl: &FunctionDec,
+        term: &Term,
+    ) -> Result<ir::Command, Error> {
+        self.vars_defined.insert(decl.name.0.clone());
+        let value = self.convert_definition_value(decl, term)?;
+        if self.converting_model {
+            let value = value.clone().substitute(&self.model_values);
+            self.model_values.insert(decl.name.0.clone(), value);
+        }
+        Ok(ir::Command::Definition(decl.name.0.clone(), value))
+    }
+
+    /// Convert the value of a definition; a function with parameters is a lambda abstraction.
+    /// Parameters and results are annotated with their types if the spec maps their sorts.
+    #[allow(unstable_name_collisions)]
+    fn convert_definition_value(
+        &mut self,
+        decl: &FunctionDec,
+        term: &Term,
+    ) -> Result<ir::Term, Error> {
+        if decl.parameters.is_empty() {
+            return self.convert_simplified(term);
+        }
+        let mut params = vec![];
+        for (s, sort) in &decl.parameters {
+            let name = self.target_name(&s.0);
+            params.push((name.clone(), self.convert_sort(sort).ok()));
+            self.bind(&s.0, name, Some(sort.to_string()));
+        }
+        let body = self.convert_simplified(term);
+        self.unbind(decl.parameters.len());
+        let body = match self.convert_sort(&decl.result) {
+            Ok(ty) => body?.annotated(ty),
+            Err(_) => body?,
+        };
+        Ok(ir::Term::Lambda {
+            params,
+            body: Box::new(body),
+        })
+    }
+
+    /// Adds the Isabelle/HOL `function` definition of the (mutually) recursive functions.
+    /// The definitions are part of the theory rather than the lemma, so they must not refer to declared constants.
+    fn define_recursive(&mut self, funs: &[(&FunctionDec, &Term)]) -> Result<(), Error> {
+        self.check_target_supports("define-fun-rec")?;
+        // Register all names first, the functions may call each other
+        for (decl, _) in funs {
+            if decl.parameters.is_empty() {
+                return Err(Error::unsupported(format!(
+                    "define-fun-rec without parameters ({})",
+                    decl.name
+                )));
+            }
+            self.recursive_functions
+                .insert(decl.name.0.clone(), self.target_name(&decl.name.0));
+        }
+        let mut signatures = vec![];
+        let mut equations = vec![];
+        for (decl, term) in funs {
+            let name = self.target_name(&decl.name.0);
+            let mut types = vec![];
+            for (_, sort) in &decl.parameters {
+                types.push(self.convert_sort(sort)?);
+            }
+            types.push(self.convert_sort(&decl.result)?);
+            signatures.push(format!(
+                "{} :: \"{}\"",
+                name,
+                types.join(" \\<Rightarrow> ")
+            ));
+
+            let mut params = vec![];
+            for (s, sort) in &decl.parameters {
+                let param = self.target_name(&s.0);
+                params.push(param.clone());
+                self.bind(&s.0, param, Some(sort.to_string()));
+            }
+            let body = self.convert_simplified(term);
+            self.unbind(decl.parameters.len());
+            let body = body?.print(self.target.target())?;
+            equations.push(format!("\"{} {} = {}\"", name, params.join(" "), body));
+        }
+        if !self.command_vars.is_empty() {
+            return Err(Error::unsupported(format!(
+                "recursive function referring to declared constants ({})",
+                self.command_vars.iter().sorted().join(", ")
+            )));
+        }
+        let termination = match &self.termination_measure {
+            Some(measure) => format!("termination by (relation \"measure ({})\") auto", measure),
+            None => "termination sorry".to_owned(),
+        };
+        self.definitions.push(format!(
+            "function {} where\n  {}\n  by pat_completeness auto\n{}",
+            signatures.join("\n  and "),
+            equations.join("\n| "),
+            termination
+        ));
+        Ok(())
+    }
+
+    /// Convert the term of a command, simplifying it if enabled.
+    fn convert_simplified(&mut self, t: &Term) -> Result<ir::Term, Error> {
+        let term = self.convert_term(t)?;
+        Ok(self.simplified(term))
+    }
+
+    /// Returns the simplified term if simplification is enabled, and the term otherwise.
+    fn simplified(&self, term: ir::Term) -> ir::Term {
+        if !self.simplify {
+            return term;
+        }
+        self.simplifier().simplify(term)
+    }
+
+    fn simplifier(&self) -> Simplifier {
+        let boolean = |name| self.get_spec(name).and_then(|(_, spec)| spec.mapsto);
+        Simplifier::new(boolean("true"), boolean("false"))
+    }
+
+    /// Folds the application of a function the spec does not map if its arguments are literals,
+    /// e.g. `(str.from_int 42)` for a spec without `str.from_int`, see [Simplifier::fold].
+    fn fold_unmapped(&mut self, op: &str, args: &[Term]) -> Option<ir::Term> {
+        let simplifier = self.simplifier();
+        let args: Vec<ir::Term> = args
+            .iter()
+            .map(|a| Some(simplifier.simplify(self.convert_term(a).ok()?)))
+            .collect::<Option<_>>()?;
+        simplifier.fold(op, &args)
+    }
+
+    /// Convert a term to the intermediate representation, see [ir].
+    #[allow(unused_variables)]
+    fn convert_term(&mut self, t: &Term) -> Result<ir::Term, Error> {
+        match t {
+            Term::Constant(c) => self.convert_constant(c),
+            Term::QualIdentifier(i) => self.convert_identifier(i),
+            Term::Application {
+                qual_identifier,
+                arguments,
+            } => self
+                .convert_application(qual_identifier, arguments)
+                .map_err(|e| locate_term(e, t)),
+            Term::Let { var_bindings, term } => self.convert_let(var_bindings, term),
+            Term::Forall { vars, term } => self.convert_quantifier(true, vars, term),
+            Term::Exists { vars, term } => self.convert_quantifier(false, vars, term),
+            Term::Match { term, cases } => self.convert_match(term, cases),
+            Term::Attributes { term, attributes } => {
+                // Annotations such as `:named` and `:pattern` do not change the meaning of the term
+                log::trace!(
+                    "Ignoring attributes {}",
+                    attributes.iter().map(|(k, _)| k).join(" ")
+                );
+                self.convert_term(term)
+            }
+        }
+    }
+
+    /// Convert a let-binding to a `let` expression.
+    /// SMT-LIB binds all variables in parallel, so multiple bindings are converted to a single tuple binding.
+    fn convert_let(&mut self, bindings: &[(Symbol, Term)], body: &Term) -> Result<ir::Term, Error> {
+        // The bound terms are converted in the enclosing scope
+        let mut values = vec![];
+        for (_, t) in bindings {
+            values.push(self.convert_term(t)?);
+        }
+        let sorts: Vec<_> = bindings
+            .iter()
+            .map(|(_, t)| self.sorts.sort_of(t))
+            .collect();
+        let names: Vec<String> = bindings
+            .iter()
+            .map(|(s, _)| self.target_name(&s.0))
+            .collect();
+        for (((s, _), n), sort) in bindings.iter().zip(&names).zip(sorts) {
+            self.bind(&s.0, n.clone(), sort);
+        }
+        let body = self.convert_term(body);
+        self.unbind(bindings.len());
+        let body = body?;
+
+        // Let the simplifier unfold the bindings
+        self.simps.insert("Let_def".to_owned());
+        Ok(ir::Term::Let {
+            names,
+            values,
+            body: Box::new(body),
+        })
+    }
+
+    /// Convert a `match` term to an Isabelle/HOL `case` expression.
+    /// A pattern of a single symbol that is not a constructor is a variable matching any value.
+    fn convert_match(
+        &mut self,
+        term: &Term,
+        cases: &[(Vec<Symbol>, Term)],
+    ) -> Result<ir::Term, Error> {
+        self.check_target_supports("match")?;
+        let scrutinee = self.convert_term(term)?;
+        let scrutinee_sort = self.sorts.sort_of(term);
+        let mut converted = vec![];
+        for (pattern, body) in cases {
+            let (constructor, vars) = match pattern.split_first() {
+                Some((c, vars))
+                    if !vars.is_empty() || self.datatype_functions.contains_key(&c.0) =>
+                {
+                    match self.datatype_functions.get(&c.0) {
+                        Some(name) => (Some(name.clone()), vars),
+                        None => return Err(Error::unsupported(format!("constructor {}", c))),
+                    }
+                }
+                _ => (None, pattern.as_slice()),
+            };
+            let mut names: Vec<String> = constructor.into_iter().collect();
+            for v in vars {
+                let name = self.target_name(&v.0);
+                names.push(name.clone());
+                // Only a variable pattern has the sort of the matched term
+                let sort = if names.len() == 1 {
+                    scrutinee_sort.clone()
+                } else {
+                    None
+                };
+                self.bind(&v.0, name, sort);
+            }
+            let body = self.convert_term(body);
+            self.unbind(vars.len());
+            converted.push((names, body?));
+        }
+        Ok(ir::Term::Case {
+            scrutinee: Box::new(scrutinee),
+            cases: converted,
+        })
+    }
+
+    /// Convert a universally (if `forall`) or existentially quantified term to a binder with typed variables.
+    fn convert_quantifier(
+        &mut self,
+        forall: bool,
+        vars: &[(Symbol, Sort)],
+        body: &Term,
+    ) -> Result<ir::Term, Error> {
+        self.check_target_supports("quantifiers")?;
+        let mut typed = vec![];
+        for (s, sort) in vars {
+            let name = self.target_name(&s.0);
+            typed.push((name.clone(), self.convert_sort(sort)?));
+            self.bind(&s.0, name, Some(sort.to_string()));
+        }
+        let body = self.convert_term(body);
+        self.unbind(vars.len());
+        Ok(ir::Term::Quantifier {
+            forall,
+            vars: typed,
+            body: Box::new(body?),
+        })
+    }
+
+    /// Convert an SMT-LIB sort to an Isabelle/HOL type.
+    fn convert_sort(&mut self, sort: &Sort) -> Result<String, Error> {
+        if !self.target.target().full_translation() {
+            // The specs of other targets map all sorts they support, including the Isabelle/HOL default sorts
+            let mapped = match sort {
+                Sort::Simple {
+                    identifier: Identifier::Simple { symbol },
+                } => self.spec.sorts.get(&symbol.0).cloned(),
+                _ => None,
+            };
+            return mapped.ok_or_else(|| Error::unsupported(format!("sort {}", sort)));
+        }
+        let mapped = match sort {
+            Sort::Simple {
+                identifier: Identifier::Simple { symbol },
+            } => {
+                if self.type_params.contains(&symbol.0)
+                    || (self.declared_sorts.contains(&symbol.0)
+                        && self.uninterpreted_sorts == UninterpretedSorts::TypeVariables)
+                {
+                    Some(format!("'{}", symbol.0))
+                } else if self.datatypes.contains_key(&symbol.0)
+                    || self.declared_sorts.contains(&symbol.0)
+                {
+                    Some(symbol.0.clone())
+                } else {
+                    self.uses_reals |= symbol.0 == sorts::REAL;
+                    self.spec.get_sort(&symbol.0)
+                }
+            }
+            Sort::Parameterized {
+                identifier: Identifier::Simple { symbol },
+                parameters,
+            } if self.datatypes.get(&symbol.0) == Some(&parameters.len()) => {
+                let mut params = vec![];
+                for p in parameters {
+                    params.push(self.convert_sort(p)?);
+                }
+                Some(format!("(({}) {})", params.join(", "), symbol.0))
+            }
+            Sort::Simple {
+                identifier: Identifier::Indexed { symbol, indices },
+            } if symbol.0 == "BitVec" => match (indices.as_slice(), self.spec.get_sort("BitVec")) {
+                ([Index::Numeral(width)], Some(word)) => {
+                    self.uses_words = true;
+                    Some(format!("{} {}", width, word))
+                }
+                _ => None,
+            },
+            Sort::Parameterized {
+                identifier: Identifier::Simple { symbol },
+                parameters,
+            } if symbol.0 == "Array" && parameters.len() == 2 => {
+                let index = self.convert_sort(&parameters[0])?;
+                let element = self.convert_sort(&parameters[1])?;
+                // Arrays are functions from indices to elements, unless the spec maps them to a type constructor
+                Some(match self.spec.get_sort("Array") {
+                    Some(array) => format!("(({}, {}) {})", index, element, array),
+                    None => format!("({} \\<Rightarrow> {})", index, element),
+                })
+            }
+            _ => None,
+        };
+        mapped.ok_or_else(|| Error::unsupported(format!("sort {}", sort)))
+    }
+
+    /// Returns the Isabelle/HOL name of the innermost bound variable with the given name, if any.
+    fn lookup_bound(&self, name: &str) -> Option<&str> {
+        self.bound
+            .iter()
+            .rev()
+            .find(|(s, _)| s == name)
+            .map(|(_, n)| n.as_str())
+    }
+
+    /// Convert a constant to a literal.
+    fn convert_constant(&mut self, c: &Constant) -> Result<ir::Term, Error> {
+        let target = self.target.target();
+        match c {
+            Constant::Numeral(n) => Ok(ir::Term::Int(n.to_string())),
+            Constant::String(s) => {
+                let s_unescaped = unicode_unescape(s)?;
+                log::debug!("Unescaped string: {} => {}", s, s_unescaped);
+                Ok(ir::Term::Str(s_unescaped))
+            }
+            _ if !target.full_translation() => Err(Error::unsupported(format!(
+                "literal {} in {} output",
+                c,
+                target.name()
+            ))),
+            Constant::Decimal(d) => {
+                self.uses_reals = true;
+                Ok(ir::Term::Name(d.to_string()).annotated("real"))
+            }
+            Constant::Hexadecimal(h) => {
+                let value = h
+                    .iter()
+                    .fold(Numeral::from(0u32), |v, n| v * 16u32 + u32::from(*n));
+                Ok(self.convert_bit_literal(value, 4 * h.len()))
+            }
+            Constant::Binary(b) => {
+                let value = b
+                    .iter()
+                    .fold(Numeral::from(0u32), |v, b| v * 2u32 + u32::from(*b));
+                Ok(self.convert_bit_literal(value, b.len()))
+            }
+        }
+    }
+
+    /// Convert the value of a hexadecimal or binary literal of the given width to an Isabelle/HOL numeral.
+    /// SMT-LIB literals of this form are bit-vectors, so if the spec maps the `BitVec` sort,
+    /// the numeral is annotated with the word type of the width (e.g. `(65::8 word)`).
+    /// Otherwise, its type is left to be inferred.
+    fn convert_bit_literal(&mut self, value: Numeral, width: usize) -> ir::Term {
+        let numeral = ir::Term::Name(value.to_string());
+        match self.spec.get_sort("BitVec") {
+            Some(word) => {
+                self.uses_words = true;
+                numeral.annotated(format!("{} {}", width, word))
+            }
+            None => numeral,
+        }
+    }
+
+    /// Convert an identifier to a name of the target language.
+    fn convert_identifier(&mut self, identifier: &QualIdentifier) -> Result<ir::Term, Error> {
+        let op = &self.identifier_name(identifier);
+        if let Some(name) = self.lookup_bound(op) {
+            return Ok(ir::Term::Name(name.to_owned()));
+        }
+        if let Some(name) = self.uninterpreted.get(op) {
+            self.uninterpreted_used.insert(op.clone());
+            return Ok(ir::Term::Name(name.clone()));
+        }
+        if let Some(name) = self.datatype_function(identifier) {
+            // Constructors of parametric datatypes may be annotated with their sort, as in `(as nil (List Int))`
+            if let QualIdentifier::Sorted { sort, .. } = identifier {
+                if let Ok(ty) = self.convert_sort(sort) {
+                    return Ok(ir::Term::Name(name).annotated(ty));
+                }
+            }
+            return Ok(ir::Term::Name(name));
+        }
+        match self.get_spec(op) {
+            Some(m) => match m.1.mapsto {
+                Some(m) => {
+                    let indices = identifier_indices(identifier);
+                    if indices.is_empty() {
+                        Ok(ir::Term::Name(m))
+                    } else {
+                        Ok(ir::Term::call(ir::Term::Name(m), indices))
+                    }
+                }
+                None => Err(Error::unsupported(op.to_string())),
+            },
+            None => {
+                if let Some(term) = desugar::desugar(op, index_list(identifier), &[]) {
+                    return self.convert_term(&term);
+                }
+                self.check_in_logic(op)?;
+                // Variables
+                self.use_var(op);
+                Ok(ir::Term::Var(op.clone()))
+            }
+        }
+    }
+
+    /// Retrieve the name of an identifier.
+    /// The name of an indexed identifier such as `(_ re.loop 2 5)` is its symbol (`re.loop`),
+    /// which is also the key of its mapping in the spec.
+    fn identifier_name(&self, identifier: &QualIdentifier) -> String {
+        match identifier {
+            QualIdentifier::Simple { identifier } | QualIdentifier::Sorted { identifier, .. } => {
+                match identifier {
+                    Identifier::Simple { symbol } | Identifier::Indexed { symbol, .. } => {
+                        symbol.0.to_string()
+                    }
+                }
+            }
+        }
+    }
+
+    /// Unrolls an n-ary `left-assoc` application to a series of binary applications.
+    fn unroll_assoc_left(&self, identifier: &QualIdentifier, args: &[Term]) -> Term {
+        if args.len() >= 2 {
+            let mut term = Term::Application {
+                qual_identifier: identifier.clone(),
+                arguments: vec![args[0].clone(), args[1].clone()],
+            };
+            for arg in args.iter().skip(2) {
+                term = Term::Application {
+                    qual_identifier: identifier.clone(),
+                    arguments: vec![term, arg.clone()],
+                };
+            }
+            term
+        } else {
+            Term::Application {
+                qual_identifier: identifier.clone(),
+                arguments: args.to_vec(),
+            }
+        }
+    }
+
+    /// Unrolls an n-ary `right-assoc` application to a series of binary applications.
+    fn unroll_assoc_right(&self, identifier: &QualIdentifier, args: &[Term]) -> Term {
+        if args.len() >= 2 {
+            let (last, init) = args.split_last().unwrap();
+            let mut term = last.clone();
+            for arg in init.iter().rev() {
+                term = Term::Application {
+                    qual_identifier: identifier.clone(),
+                    arguments: vec![arg.clone(), term],
+                };
+            }
+            term
+        } else {
+            Term::Application {
+                qual_identifier: identifier.clone(),
+                arguments: args.to_vec(),
+            }
+        }
+    }
+
+    /// Convert a function application to a term of the target language.
+    fn convert_application(
+        &mut self,
+        identifier: &QualIdentifier,
+        args: &[Term],
+    ) -> Result<ir::Term, Error> {
+        let op = &self.identifier_name(identifier);
+        if let Some(name) = self.uninterpreted.get(op).cloned() {
+            self.uninterpreted_used.insert(op.clone());
+            return self.convert_uninterpreted_application(ir::Term::Name(name), args);
+        }
+        if let Some(name) = self.datatype_function(identifier) {
+            return self.convert_uninterpreted_application(ir::Term::Name(name), args);
+        }
+        if let Some(name) = self.recursive_functions.get(op).cloned() {
+            return self.convert_uninterpreted_application(ir::Term::Name(name), args);
+        }
+        if let Some((value, ty)) = application_value(op, args) {
+            let target = self.target.target();
+            return if ty == "int" {
+                Ok(ir::Term::Int(value.to_string()))
+            } else if target.full_translation() {
+                self.uses_reals = true;
+                Ok(ir::Term::Name(value.to_string()).annotated(ty))
+            } else {
+                Err(Error::unsupported(format!(
+                    "real literal {} in {} output",
+                    value,
+                    target.name()
+                )))
+            };
+        }
+        if op == "ite" && args.len() == 3 {
+            return self.convert_ite(&args[0], &args[1], &args[2]);
+        }
+        if let Some(array) = self.convert_array_op(identifier, op, args)? {
+            return Ok(array);
+        }
+        let indices = index_list(identifier);
+        if let Some(term) = desugar::rewrite_legacy(op, indices, args) {
+            return self.convert_term(&term);
+        }
+        if self.get_spec(op).is_none() {
+            if let Some(term) = desugar::desugar(op, indices, args) {
+                return self.convert_term(&term);
+            }
+        }
+        let spec = match self.get_sorted_spec(op, args).or_else(|| self.get_spec(op)) {
+            Some(m) => m.1,
+            None if self.auxiliaries.contains(op) || self.declared_functions.contains(op) => {
+                // Applications of declared and auxiliary functions are kept uninterpreted
+                self.use_var(op);
+                return self.convert_uninterpreted_application(ir::Term::Var(op.clone()), args);
+            }
+            None => {
+                self.check_in_logic(op)?;
+                if let Some(term) = self.fold_unmapped(op, args) {
+                    return Ok(term);
+                }
+                return Err(Error::unsupported(op.to_string()));
+            }
+        };
+
+        if spec.is_left_assoc() && args.len() > 2 {
+            self.convert_term(&self.unroll_assoc_left(identifier, args))
+        } else if spec.is_right_assoc() && args.len() > 2 {
+            self.convert_term(&self.unroll_assoc_right(identifier, args))
+        } else if spec.pairwise && args.len() > 2 {
+            let mut pairs = vec![];
+            for (a, b) in args.iter().tuple_combinations() {
+                pairs.push(self.convert_term(&Term::Application {
+                    qual_identifier: identifier.clone(),
+                    arguments: vec![a.clone(), b.clone()],
+                })?);
+            }
+            Ok(ir::Term::Conjunction(pairs))
+        } else if spec.chainable && args.len() > 2 {
+            let mut links = vec![];
+            for pair in args.windows(2) {
+                links.push(self.convert_term(&Term::Application {
+                    qual_identifier: identifier.clone(),
+                    arguments: pair.to_vec(),
+                })?);
+            }
+            Ok(ir::Term::Conjunction(links))
+        } else {
+            let template = spec.is_template();
+            let name = match spec.mapsto {
+                Some(n) => n,
+                None => return Err(Error::unsupported(op.to_string())),
+            };
+            // The indices of an indexed identifier are passed as leading arguments
+            let mut converted = identifier_indices(identifier);
+            let coercions = self.int_coercions(op, args);
+            for (t, coerce) in args.iter().zip(coercions) {
+                let t = self.convert_term(t)?;
+                if coerce {
+                    self.uses_reals = true;
+                    let int_to_real = ir::Term::Name(sorts::INT_TO_REAL.to_owned());
+                    converted.push(ir::Term::call(int_to_real, vec![t]));
+                } else {
+                    converted.push(t);
+                }
+            }
+            Ok(if template {
+                ir::Term::Template {
+                    op: op.clone(),
+                    template: name,
+                    args: converted,
+                }
+            } else {
+                ir::Term::Apply {
+                    op: op.clone(),
+                    function: name,
+                    args: converted,
+                }
+            })
+        }
+    }
+
+    /// Convert an `ite` application to an `if-then-else` expression.
+    fn convert_ite(&mut self, c: &Term, a: &Term, b: &Term) -> Result<ir::Term, Error> {
+        Ok(ir::Term::Ite(
+            Box::new(self.convert_term(c)?),
+            Box::new(self.convert_term(a)?),
+            Box::new(self.convert_term(b)?),
+        ))
+    }
+
+    /// Convert an operation of the SMT-LIB ArraysEx theory that the spec does not map.
+    /// Arrays are modelled as functions: `(select a i)` is `(a i)`, `(store a i v)` is `(a(i := v))`,
+    /// and the constant array `((as const (Array Int Int)) v)` is `(\\<lambda>_. v)`.
+    /// Returns None if the application is not an array operation.
+    fn convert_array_op(
+        &mut self,
+        identifier: &QualIdentifier,
+        op: &str,
+        args: &[Term],
+    ) -> Result<Option<ir::Term>, Error> {
+        // The specs of other targets have to map the array operations
+        if self.get_spec(op).is_some() || !self.target.target().full_translation() {
+            return Ok(None);
+        }
+        let converted = match (op, args) {
+            ("select", [a, i]) => {
+                ir::Term::call(self.convert_term(a)?, vec![self.convert_term(i)?])
+            }
+            ("store", [a, i, v]) => ir::Term::Update {
+                function: Box::new(self.convert_term(a)?),
+                index: Box::new(self.convert_term(i)?),
+                value: Box::new(self.convert_term(v)?),
+            },
+            ("const", [v]) => {
+                let constant = ir::Term::Lambda {
+                    params: vec![("_".to_owned(), None)],
+                    body: Box::new(self.convert_term(v)?),
+                };
+                match identifier {
+                    QualIdentifier::Sorted { sort, .. } => match self.convert_sort(sort) {
+                        Ok(ty) => constant.annotated(ty),
+                        Err(_) => constant,
+                    },
+                    QualIdentifier::Simple { .. } => return Ok(None),
+                }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(converted))
+    }
+
+    /// Convert an application of a function without interpretation to a curried application.
+    fn convert_uninterpreted_application(
+        &mut self,
+        function: ir::Term,
+        args: &[Term],
+    ) -> Result<ir::Term, Error> {
+        let mut converted = vec![];
+        for t in args {
+            converted.push(self.convert_term(t)?);
+        }
+        Ok(ir::Term::call(function, converted))
+    }
+}
+
+/// Returns the indices of an indexed identifier.
+fn index_list(identifier: &QualIdentifier) -> &[Index] {
+    match identifier {
+        QualIdentifier::Simple { identifier } | QualIdentifier::Sorted { identifier, .. } => {
+            match identifier {
+                Identifier::Simple { .. } => &[],
+                Identifier::Indexed { indices, .. } => indices,
+            }
+        }
+    }
+}
+
+/// Returns the indices of an indexed identifier as terms.
+/// Numerals are left without type annotation, so their type is inferred from the mapped function.
+fn identifier_indices(identifier: &QualIdentifier) -> Vec<ir::Term> {
+    match identifier {
+        QualIdentifier::Simple { identifier } | QualIdentifier::Sorted { identifier, .. } => {
+            match identifier {
+                Identifier::Simple { .. } => vec![],
+                Identifier::Indexed { indices, .. } => indices
+                    .iter()
+                    .map(|i| match i {
+                        Index::Numeral(n) => ir::Term::Name(n.to_string()),
+                        Index::Symbol(s) => ir::Term::Name(s.0.clone()),
+                    })
+                    .collect(),
+            }
+        }
+    }
+}
+
+/// Returns the value and the Isabelle/HOL type of a numeric literal, a negated literal, or a rational.
+fn literal_value(term: &Term) -> Option<(Decimal, &'static str)> {
+    match term {
+        Term::Constant(Constant::Numeral(n)) => {
+            Some((Decimal::from_integer(n.clone().into()), "int"))
+        }
+        Term::Constant(Constant::Decimal(d)) => Some((d.clone(), "real")),
+        Term::Application {
+            qual_identifier,
+            arguments,
+        } => application_value(&qual_identifier.to_string(), arguments),
+        _ => None,
+    }
+}
+
+/// Returns the value and the Isabelle/HOL type of `(- v)` and `(/ v w)` for literal values `v` and `w`.
+/// Models write negative numbers such as `(- 5)` and rationals such as `(/ 1 3)` as applications,
+/// which are converted to literals like `(-5::int)` and `(1/3::real)`.
+/// The division is real, even of integer literals.
+fn application_value(op: &str, args: &[Term]) -> Option<(Decimal, &'static str)> {
+    match (op, args) {
+        ("-", [t]) => literal_value(t).map(|(v, ty)| (-v, ty)),
+        ("/", [n, d]) => {
+            let (n, _) = literal_value(n)?;
+            let (d, _) = literal_value(d)?;
+            if d.to_string() == "0" {
+                // Division by zero is left to the spec mapping of `/`
+                return None;
+            }
+            Some((n / d, "real"))
+        }
+        _ => None,
+    }
+}
+
+/// Rewrites character literals `(_ char #xHH)` to the equivalent string literals `"\u{HH}"`.
+/// The parser only accepts numerals and symbols as indices, so these literals cannot be parsed otherwise.
+/// String literals, quoted symbols, and comments are kept as they are.
+fn rewrite_char_literals(input: &str) -> String {
+    const PREFIX: &str = "(_ char #x";
+    let mut res = String::with_capacity(input.len());
+    for segment in segments(input) {
+        let mut rest = match segment {
+            Segment::Code(code) => code,
+            Segment::Literal(text) | Segment::Verbatim(text) => {
+                res += text;
+                continue;
+            }
+        };
+        while let Some(start) = rest.find(PREFIX) {
+            res += &rest[..start];
+            let after = &rest[start + PREFIX.len()..];
+            let digits = after
+                .find(|c: char| !c.is_ascii_hexdigit())
+                .unwrap_or(after.len());
+            let tail = after[digits..].trim_start();
+            match tail.strip_prefix(')') {
+                Some(tail) if digits > 0 => {
+                    res += &format!("\"\\u{{{}}}\"", &after[..digits]);
+                    rest = tail;
+                }
+                _ => {
+                    // Not a well-formed literal, keep it for the parser to report
+                    res += PREFIX;
+                    rest = after;
+                }
+            }
+        }
+        res += rest;
+    }
+    res
+}
+
+/// Rewrites the syntax the parser does not accept in the input before parsing, see [rewrite_char_literals].
+pub(crate) fn preprocess(source: &str) -> String {
+    rewrite_char_literals(source)
+}
+
+/// The SMT-LIB 2.5 names of the functions of the theory of strings that SMT-LIB 2.6 renamed, with their new names.
+/// Solvers accepted some of them with a hyphen as well.
+const SMTLIB25_NAMES: [(&str, &str); 8] = [
+    ("str.in.re", "str.in_re"),
+    ("str.to.re", "str.to_re"),
+    ("str.to.int", "str.to_int"),
+    ("str.to-int", "str.to_int"),
+    ("int.to.str", "str.from_int"),
+    ("int.to-str", "str.from_int"),
+    ("str.from.int", "str.from_int"),
+    ("re.nostr", "re.none"),
+];
+
+/// Rewrites input in SMT-LIB 2.5 syntax to SMT-LIB 2.6, token by token: the renamed functions of the theory of strings
+/// (see [SMTLIB25_NAMES]), and the escape sequences `\xAB` of string literals, which become `\u{AB}`.
+/// Other symbols and the rest of string literals, quoted symbols, and comments are kept as they are.
+pub fn rewrite_smtlib25(input: &str) -> String {
+    rewrite_symbols(input, true, |symbol| {
+        SMTLIB25_NAMES
+            .iter()
+            .find(|(old, _)| *old == symbol)
+            .map(|(_, new)| new.to_string())
+    })
+}
+
+/// Rewrites the non-standard names solvers use for SMT-LIB functions to the SMT-LIB functions, token by token,
+/// see [Converter::aliases]. String literals, quoted symbols, and comments are kept as they are.
+pub fn rewrite_aliases(input: &str, aliases: &HashMap<String, String>) -> String {
+    rewrite_symbols(input, false, |symbol| aliases.get(symbol).cloned())
+}
+
+/// Replaces the symbols of the input for which `rename` returns a new name, and, if `legacy_escapes`,
+/// the SMT-LIB 2.5 escape sequences `\xAB` of string literals by `\u{AB}`.
+/// Quoted symbols and comments are not symbols, and are kept as they are.
+fn rewrite_symbols(
+    input: &str,
+    legacy_escapes: bool,
+    rename: impl Fn(&str) -> Option<String>,
+) -> String {
+    let symbol_char = |c: char| !c.is_whitespace() && !matches!(c, '(' | ')');
+    let mut res = String::with_capacity(input.len());
+    for segment in segments(input) {
+        match segment {
+            Segment::Code(code) => {
+                let mut chars = code.chars().peekable();
+                while let Some(c) = chars.next() {
+                    if !symbol_char(c) {
+                        res.push(c);
+                        continue;
+                    }
+                    let mut token = String::from(c);
+                    while let Some(c) = chars.next_if(|c| symbol_char(*c)) {
+                        token.push(c);
+                    }
+                    match rename(&token) {
+                        Some(new) => res += &new,
+                        None => res += &token,
+                    }
+                }
+            }
+            Segment::Literal(literal) if legacy_escapes => {
+                let mut chars = literal.chars().peekable();
+                while let Some(c) = chars.next() {
+                    if c != '\\' {
+                        res.push(c);
+                        continue;
+                    }
+                    // An escaped backslash does not start an escape sequence
+                    if chars.peek() == Some(&'\\') {
+                        chars.next();
+                        res.push_str("\\\\");
+                        continue;
+                    }
+                    let digits: String = chars.clone().skip(1).take(2).collect();
+                    if chars.peek() == Some(&'x')
+                        && digits.len() == 2
+                        && digits.chars().all(|d| d.is_ascii_hexdigit())
+                    {
+                        res += &format!("\\u{{{}}}", digits);
+                        chars.nth(2);
+                    } else {
+                        res.push('\\');
+                    }
+                }
+            }
+            Segment::Literal(text) | Segment::Verbatim(text) => res += text,
+        }
+    }
+    res
+}
+
+/// A part of SMT-LIB input, see [segments]
+enum Segment<'a> {
+    /// Symbols, keywords, numerals, and parentheses
+    Code(&'a str),
+    /// A string literal, including its quotes
+    Literal(&'a str),
+    /// A quoted symbol or a comment, which are never rewritten
+    Verbatim(&'a str),
+}
+
+/// Splits the input into string literals, quoted symbols, comments, and the code between them.
+/// An unterminated literal, quoted symbol, or comment extends to the end of the input.
+fn segments(input: &str) -> Vec<Segment<'_>> {
+    let mut res = vec![];
+    let mut rest = input;
+    while let Some(start) = rest.find(['"', '|', ';']) {
+        if start > 0 {
+            res.push(Segment::Code(&rest[..start]));
+        }
+        let tail = &rest[start..];
+        let end = match tail.as_bytes()[0] {
+            b'"' | b'|' => solver_output::quoted_len(tail),
+            _ => tail.find('\n').map(|i| i + 1),
+        }
+        .map_or(rest.len(), |end| start + end);
+        let text = &rest[start..end];
+        res.push(if text.starts_with('"') {
+            Segment::Literal(text)
+        } else {
+            Segment::Verbatim(text)
+        });
+        rest = &rest[end..];
+    }
+    if !rest.is_empty() {
+        res.push(Segment::Code(rest));
+    }
+    res
+}
+
+/// Adds the application to the context of an unsupported function, unless an inner application is already there.
+fn locate_term(error: Error, term: &Term) -> Error {
+    match error {
+        Error::Unsupported(op, mut context) if context.term.is_none() => {
+            context.term = Some(term.to_string());
+            Error::Unsupported(op, context)
+        }
+        e => e,
+    }
+}
+
+/// Adds the command's assertion index and position in the source to the context of an unsupported function.
+/// The command is looked up among the parsed commands of the source. Assertions that were rewritten before
+/// conversion, e.g. by normalization, are found by the unsupported application they contain.
+fn locate_command(error: Error, source: &str, command: &Command) -> Error {
+    let Error::Unsupported(op, mut context) = error else {
+        return error;
+    };
+    let input = preprocess(source);
+    let Ok(commands) = CommandStream::new(input.as_bytes(), concrete::SyntaxBuilder, None)
+        .collect::<Result<Vec<_>, _>>()
+    else {
+        return Error::Unsupported(op, context);
+    };
+    let contains_term = |c: &Command| {
+        matches!(c, Command::Assert { .. })
+            && context
+                .term
+                .as_ref()
+                .is_some_and(|t| c.to_string().contains(t.as_str()))
+    };
+    let index = commands
+        .iter()
+        .position(|c| c == command)
+        .or_else(|| commands.iter().position(contains_term));
+    if let Some(index) = index {
+        if matches!(commands[index], Command::Assert { .. }) {
+            context.assertion = Some(
+                commands[..index]
+                    .iter()
+                    .filter(|c| matches!(c, Command::Assert { .. }))
+                    .count(),
+            );
+        }
+        context.position = command_positions(source).and_then(|p| p.get(index).copied());
+    }
+    Error::Unsupported(op, context)
+}
+
+/// Returns the positions at which the commands of the source start.
+fn command_positions(source: &str) -> Option<Vec<SourcePosition>> {
+    let commands = solver_output::sexprs(source)?;
+    Some(
+        commands
+            .iter()
+            .map(|c| {
+                // The commands are slices of the source
+                let before = &source[..c.as_ptr() as usize - source.as_ptr() as usize];
+                let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+                SourcePosition {
+                    line: before.matches('\n').count() + 1,
+                    column: before[line_start..].chars().count() + 1,
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Returns the label the term is annotated with by `(! term :named label)`, if any.
+fn named_label(term: &Term) -> Option<String> {
+    match term {
+        Term::Attributes { term, attributes } => attributes
+            .iter()
+            .find_map(|(k, v)| match v {
+                AttributeValue::Symbol(s) if k.0 == "named" => Some(s.0.clone()),
+                _ => None,
+            })
+            .or_else(|| named_label(term)),
+        _ => None,
+    }
+}
+
+/// The prefix of the names of the SMT-LIB symbols that are renamed in the target, see [target_name]
+pub const RENAMED_PREFIX: &str = "smt_";
+
+/// Returns the name of an SMT-LIB symbol in the target language.
+/// SMT-LIB symbols such as `x!0`, `a.b`, or `|hello world|` are not identifiers of the targets,
+/// and others, such as `value` or `fun`, are reserved. Solvers name their auxiliary and bound variables like `k!0`.
+/// These symbols are renamed to [RENAMED_PREFIX] followed by their ASCII letters and digits,
+/// with any other character written as its hexadecimal code between underscores, e.g. `x!0` as `smt_x_21_0`.
+/// Symbols starting with the prefix are renamed as well, so different symbols never get the same name.
+/// Other symbols keep their name.
+pub fn target_name(symbol: &str, reserved: &[&str]) -> String {
+    let mut chars = symbol.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '\'');
+    if valid && !reserved.contains(&symbol) && !symbol.starts_with(RENAMED_PREFIX) {
+        return symbol.to_owned();
+    }
+    format!("{}{}", RENAMED_PREFIX, escape_symbol(symbol))
+}
+
+/// Returns the ASCII letters and digits of the symbol, with any other character written as its hexadecimal code between underscores.
+/// Different symbols are escaped differently, because every underscore of the result starts or ends a code.
+fn escape_symbol(symbol: &str) -> String {
+    let mut name = String::new();
+    for c in symbol.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c);
+        } else {
+            name += &format!("_{:x}_", u32::from(c));
+        }
+    }
+    name
+}
+
+/// Unescapes a string literal as specified by the SMT-LIB 2.6 theory of strings, like the evaluation does:
+/// `\ud₃d₂d₁d₀` and `\u{d₀}` to `\u{d₄d₃d₂d₁d₀}` denote the character with the hexadecimal code (see [eval::escape_sequence]),
+/// and a backslash not starting such a sequence is a character of its own. The parser already reads `""` as a quote.
+/// Fails with the offending literal if an escape sequence denotes a surrogate code point, which is not a character.
+fn unicode_unescape(s: &str) -> Result<String, Error> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut res = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (code, len) = eval::escape_sequence(&chars[i..]).unwrap_or((u32::from(chars[i]), 1));
+        let c = char::from_u32(code).ok_or_else(|| {
+            let sequence: String = chars[i..i + len].iter().collect();
+            Error::Other(format!(
+                "Invalid escape sequence {} in the string literal {}: the code point {:X} is not a character",
+                sequence,
+                Constant::String(s.to_owned()),
+                code
+            ))
+        })?;
+        res.push(c);
+        i += len;
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        rewrite_aliases, rewrite_char_literals, rewrite_smtlib25, unicode_unescape, BuiltinSpec,
+        Converter, TargetKind, UninterpretedSorts, REAL_THEORY, WORD_THEORY,
+    };
+    use crate::error::{Error, SourcePosition};
+
+    const SPEC: &str = r#"{
+        "version": "0.1",
+        "smt-lib-version": "2.6",
+        "specs": {
+            "Core": {
+                "=": { "mapsto": "=", "chainable": true },
+                "distinct": { "mapsto": "\\<noteq>", "chainable": false, "pairwise": true },
+                "and": { "mapsto": "\\<and>", "assoc": "left", "chainable": false },
+                "not": { "mapsto": "\\<not>", "chainable": false },
+                "=>": { "mapsto": "\\<longrightarrow>", "assoc": "right", "chainable": false }
+            },
+            "Ints": {
+                "+": { "mapsto": "+", "assoc": "left", "chainable": false }
+            }
+        },
+        "logics": {
+            "QF_LIA": {
+                "specs": { "Ints": { "+": { "mapsto": "lia_plus", "chainable": false } } }
+            }
+        }
+    }"#;
+
+    fn convert(input: &str) -> Vec<String> {
+        Converter::new(SPEC.to_owned())
+            .unwrap()
+            .convert(input.to_owned())
+            .unwrap()
+            .into_iter()
+            .map(|c| c.term)
+            .collect()
+    }
+
+    #[test]
+    fn logic_selects_spec_section() {
+        assert_eq!(
+            convert("(assert (= x (+ 1 2)))"),
+            vec!["((=)  x ((+)  (1::int) (2::int)))"]
+        );
+        assert_eq!(
+            convert("(set-logic QF_LIA)(assert (= x (+ 1 2)))"),
+            vec!["((=)  x ((lia_plus)  (1::int) (2::int)))"]
+        );
+    }
+
+    #[test]
+    fn operators_outside_logic() {
+        let convert_in = |logic: &str| {
+            Converter::new(SPEC.to_owned())
+                .unwrap()
+                .convert(format!("(set-logic {})(assert (= x (+ 1 2)))", logic))
+        };
+        assert!(matches!(
+            convert_in("QF_S"),
+            Err(Error::NotInLogic(op, logic)) if op == "+" && logic == "QF_S"
+        ));
+        assert!(convert_in("QF_UFLIA").is_ok());
+        assert!(convert_in("ALL").is_ok());
+        assert!(convert_in("QF_CUSTOM").is_ok());
+    }
+
+    #[test]
+    fn model_auxiliaries() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        converter
+            .convert("(declare-fun x () Int)(assert (= x 1))".to_owned())
+            .unwrap();
+        let model = converter
+            .convert_model("(declare-fun k!0 (Int) Int)(define-fun x () Int (k!0 1))".to_owned())
+            .unwrap();
+        assert_eq!(model[0].term, "x = (smt_k_21_0 (1::int))");
+        assert!(model[0].vars.contains("k!0"));
+        assert!(converter.get_auxiliaries().contains("k!0"));
+        assert!(!converter.get_auxiliaries().contains("x"));
+    }
+
+    #[test]
+    fn function_definitions() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        let model = converter
+            .convert_model(
+                "(define-fun f ((x Int) (s String)) Int (+ x y))(define-fun c () Int 1)".to_owned(),
+            )
+            .unwrap();
+        assert_eq!(
+            model[0].term,
+            "f = (\\<lambda>(x::int) s. (((+)  x y)::int))"
+        );
+        assert_eq!(model[0].vars, ["y".to_owned()].into());
+        assert_eq!(model[1].term, "c = (1::int)");
+        assert!(converter.get_vars_defined().contains("f"));
+    }
+
+    #[test]
+    fn declared_sorts() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        converter
+            .convert(
+                "(declare-const x Int)(declare-fun p (Int Int) Bool)(declare-const r RegLan)(declare-const x Bool)"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(
+            converter.get_declared(),
+            [
+                ("x".to_owned(), "int".to_owned()),
+                (
+                    "p".to_owned(),
+                    "int \\<Rightarrow> int \\<Rightarrow> bool".to_owned()
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn sort_specific_mappings() {
+        let spec = SPEC.replace(
+            r#""distinct": {"#,
+            r#""=::String": { "mapsto": "str_eq", "chainable": true },
+                "distinct": {"#,
+        );
+        let mut converter = Converter::new(spec).unwrap();
+        let converted = converter
+            .convert(
+                "(declare-const s String)(assert (= s \"a\" t))(assert (= t s))(assert (= x 1))(assert (let ((v s)) (= v s)))"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(
+            converted[0].term,
+            "(((str_eq)  s [(chr 97)]) \\<and> ((str_eq)  [(chr 97)] t))"
+        );
+        assert_eq!(converted[1].term, "((str_eq)  t s)");
+        assert_eq!(converted[2].term, "((=)  x (1::int))");
+        assert_eq!(converted[3].term, "(let v = s in ((str_eq)  v s))");
+    }
+
+    #[test]
+    fn int_to_real_coercions() {
+        assert_eq!(
+            convert("(declare-const r Real)(declare-const i Int)(assert (= r (+ i 1.5)))(assert (= i (+ i 1)))"),
+            vec![
+                "((=)  r ((+)  (real_of_int i) (3/2::real)))",
+                "((=)  i ((+)  i (1::int)))"
+            ]
+        );
+    }
+
+    #[test]
+    fn if_then_else() {
+        assert_eq!(
+            convert("(assert (= x (ite (= y 1) 2 (ite b y 3))))"),
+            vec!["((=)  x (if ((=)  y (1::int)) then (2::int) else (if b then y else (3::int))))"]
+        );
+    }
+
+    #[test]
+    fn negative_and_rational_literals() {
+        assert_eq!(
+            convert("(assert (= x (- 5)))(assert (= r (/ (- 1) 2)))(assert (= r (/ 1.0 4.0)))(assert (= r (- (/ 4 2))))"),
+            vec![
+                "((=)  x (-5::int))",
+                "((=)  r (-1/2::real))",
+                "((=)  r (1/4::real))",
+                "((=)  r (-2::real))"
+            ]
+        );
+        // Unary minus of other terms is still converted with the spec mapping
+        let spec = SPEC.replace(
+            r#""+": {"#,
+            r#""-": { "mapsto": "-", "assoc": "left", "chainable": false },
+                "+": {"#,
+        );
+        let mut converter = Converter::new(spec).unwrap();
+        let converted = converter
+            .convert("(assert (= x (- y)))".to_owned())
+            .unwrap();
+        assert_eq!(converted[0].term, "((=)  x (-  y))");
+    }
+
+    #[test]
+    fn reals() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        converter
+            .convert("(declare-const i Int)(assert (= i 1))".to_owned())
+            .unwrap();
+        assert!(converter.get_imports().is_empty());
+        converter
+            .convert("(declare-const r Real)(assert (= r 0.5))".to_owned())
+            .unwrap();
+        assert_eq!(converter.get_imports(), [REAL_THEORY]);
+        assert_eq!(
+            converter.get_declared()[1],
+            ("r".to_owned(), "real".to_owned())
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        let converted = converter
+            .convert(
+                "(declare-const a (Array Int Bool))(assert (select (store a 1 b) 2))".to_owned(),
+            )
+            .unwrap();
+        assert_eq!(converted[0].term, "((a((1::int) := b)) (2::int))");
+        assert_eq!(
+            converter.get_declared(),
+            [("a".to_owned(), "(int \\<Rightarrow> bool)".to_owned())]
+        );
+        let model = converter
+            .convert_model(
+                "(define-fun a () (Array Int Bool) ((as const (Array Int Bool)) c))".to_owned(),
+            )
+            .unwrap();
+        assert_eq!(
+            model[0].term,
+            "a = ((\\<lambda>_. c)::(int \\<Rightarrow> bool))"
+        );
+
+        // Array operations mapped by the spec are converted with the mapping
+        let spec = SPEC.replace(
+            r#""specs": {"#,
+            r#""sorts": { "Array": "smt_array" },
+            "specs": {
+            "ArraysEx": { "select": { "mapsto": "smt_select", "chainable": false } },"#,
+        );
+        let mut converter = Converter::new(spec).unwrap();
+        let converted = converter
+            .convert("(declare-const a (Array Int Int))(assert (= (select a 1) 2))".to_owned())
+            .unwrap();
+        assert_eq!(
+            converted[0].term,
+            "((=)  ((smt_select)  a (1::int)) (2::int))"
+        );
+        assert_eq!(converter.get_declared()[0].1, "((int, int) smt_array)");
+    }
+
+    #[test]
+    fn bit_vectors() {
+        let spec = SPEC.replace(
+            r#""specs": {"#,
+            r#""sorts": { "BitVec": "word" },
+            "specs": {
+            "FixedSizeBitVectors": {
+                "bvult": { "mapsto": "(<)", "chainable": false },
+                "bvadd": { "mapsto": "(+)", "assoc": "left", "chainable": false },
+                "extract": { "mapsto": "smt_extract", "chainable": false }
+            },"#,
+        );
+        let mut converter = Converter::new(spec).unwrap();
+        let converted = converter
+            .convert(
+                "(declare-const x (_ BitVec 8))(assert (bvult ((_ extract 3 0) x) (bvadd #x1 #x2 #x3)))"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(
+            converted[0].term,
+            "(((<))  ((smt_extract)  3 0 x) (((+))  (((+))  (1::4 word) (2::4 word)) (3::4 word)))"
+        );
+        assert_eq!(converter.get_declared()[0].1, "8 word");
+        assert_eq!(converter.get_imports(), [WORD_THEORY]);
+
+        // Without a mapping of the sort, bit-vector variables are not typed
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        converter
+            .convert("(declare-const x (_ BitVec 8))".to_owned())
+            .unwrap();
+        assert!(converter.get_declared().is_empty());
+        assert!(converter.get_imports().is_empty());
+    }
+
+    #[test]
+    fn datatypes() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        let converted = converter
+            .convert(
+                "(declare-datatypes ((List 1) (Color 0)) ((par (T) ((nil) (cons (head T) (tail (List T))))) ((red) (green))))
+                 (declare-const l (List Int))(declare-const c Color)
+                 (assert (and ((_ is cons) l) (= (head l) 1) (is-red c) (= (as nil (List Int)) (tail l))))"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(
+            converter.get_type_declarations(),
+            ["datatype 'T List = is_nil: nil | is_cons: cons (head: \"'T\") (tail: \"(('T) List)\")\n  and Color = is_red: red | is_green: green"]
+        );
+        assert_eq!(
+            converter.get_declared(),
+            [
+                ("l".to_owned(), "((int) List)".to_owned()),
+                ("c".to_owned(), "Color".to_owned())
+            ]
+        );
+        assert_eq!(
+            converted[0].term,
+            "((\\<and>)  ((\\<and>)  ((\\<and>)  (is_cons l) ((=)  (head l) (1::int))) (is_red c)) ((=)  (nil::((int) List)) (tail l)))"
+        );
+        assert_eq!(converted[0].vars, ["l".to_owned(), "c".to_owned()].into());
+
+        // Repeated declarations in the model are skipped
+        let model = converter
+            .convert_model(
+                "(declare-datatypes ((Color 0)) (((red) (green))))(define-fun c () Color green)"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(model[0].term, "c = green");
+        assert_eq!(converter.get_type_declarations().len(), 1);
+    }
+
+    #[test]
+    fn match_cases() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        let converted = converter
+            .convert(
+                "(declare-datatypes ((List 0)) (((nil) (cons (head Int) (tail List)))))
+                 (assert (= x (match l ((nil 0) ((cons h t) (+ h y))))))
+                 (assert (match l ((nil true) (other (= other l)))))"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(
+            converted[0].term,
+            "((=)  x (case l of nil \\<Rightarrow> (0::int) | cons h t \\<Rightarrow> ((+)  h y)))"
+        );
+        assert_eq!(
+            converted[0].vars,
+            ["x".to_owned(), "l".to_owned(), "y".to_owned()].into()
+        );
+        assert_eq!(
+            converted[1].term,
+            "(case l of nil \\<Rightarrow> true | other \\<Rightarrow> ((=)  other l))"
+        );
+
+        let unknown = Converter::new(SPEC.to_owned())
+            .unwrap()
+            .convert("(assert (match l (((cons h t) h))))".to_owned());
+        assert!(matches!(unknown, Err(Error::Unsupported(s, _)) if s == "constructor cons"));
+    }
+
+    #[test]
+    fn unsupported_location() {
+        let formula = "(declare-const x Int)\n(assert (= x 0))\n(push 1)\n  (assert (and (= x 1) (= (foo x) 2)))\n(check-sat)";
+        for normalize in [false, true] {
+            let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+            converter.normalize = normalize;
+            let e = converter.convert(formula.to_owned()).unwrap_err();
+            let Error::Unsupported(op, context) = &e else {
+                panic!("Unexpected error {}", e);
+            };
+            assert_eq!(op, "foo");
+            assert_eq!(context.term.as_deref(), Some("(foo x)"));
+            assert_eq!(context.assertion, Some(1));
+            assert_eq!(e.position(), Some(SourcePosition { line: 4, column: 3 }));
+            assert_eq!(
+                e.to_string(),
+                "Unsupported SMT-LIB function: foo in (foo x) (assertion 1 at 4:3)"
+            );
+        }
+    }
+
+    #[test]
+    fn simplified_terms() {
+        let mut converter = Converter::builtin(BuiltinSpec::QfSlia).unwrap();
+        converter.simplify(true);
+        let converted = converter
+            .convert(
+                "(declare-const x Int)(declare-const s String)
+                 (assert (and (< 0 (* 2 3)) (= (str.len (str.++ \"ab\" \"c\")) x)))
+                 (assert (or (= s \"a\") (not true)))"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(converted[0].term, "((=)  (3::int) x)");
+        assert_eq!(converted[1].term, "((=)  s [(chr 97)])");
+    }
+
+    #[test]
+    fn substituted_model() {
+        let mut converter = Converter::builtin(BuiltinSpec::QfSlia).unwrap();
+        converter.simplify(true);
+        let mut formula = converter
+            .convert(
+                "(declare-const x Int)(declare-fun f (Int) Int)
+                 (assert (< x (f x)))(assert (forall ((x Int)) (>= (f x) x)))"
+                    .to_owned(),
+            )
+            .unwrap();
+        converter
+            .convert_model("(define-fun x () Int 2)(define-fun f ((y Int)) Int (+ y 1))".to_owned())
+            .unwrap();
+        converter.substitute_model(&mut formula).unwrap();
+        assert_eq!(
+            formula[0].term,
+            "((<)  (2::int) ((\\<lambda>(y::int). (((+)  y (1::int))::int)) (2::int)))"
+        );
+        // The bound variable is not substituted
+        assert_eq!(
+            formula[1].term,
+            "(\\<forall>(x::int). ((\\<ge>)  ((\\<lambda>(y::int). (((+)  y (1::int))::int)) x) x))"
+        );
+        assert_eq!(
+            converter.get_model_vars(),
+            ["x".to_owned(), "f".to_owned()].into()
+        );
+    }
+
+    #[test]
+    fn solver_aliases() {
+        let spec = SPEC.replacen(
+            '{',
+            r#"{ "aliases": { "seq.len": "my_len", "str.lenght": "str.len" },"#,
+            1,
+        );
+        let converter = Converter::new(spec).unwrap();
+        let aliases = converter.aliases();
+        // The spec overrides the default aliases
+        assert_eq!(aliases["seq.len"], "my_len");
+        assert_eq!(aliases["str.lenght"], "str.len");
+        assert_eq!(aliases["seq.++"], "str.++");
+        assert_eq!(
+            rewrite_aliases(
+                "(assert (= (str.lenght |seq.++|) (seq.len (seq.++ x \"seq.++\")))) ; seq.len",
+                &aliases
+            ),
+            "(assert (= (str.len |seq.++|) (my_len (str.++ x \"seq.++\")))) ; seq.len"
+        );
+
+        // Characters and sequences of characters are strings
+        let mut converter = Converter::builtin(BuiltinSpec::QfS).unwrap();
+        let model = converter
+            .convert_model(
+                "(define-fun s () String (str.++ (seq.unit (_ Char 97)) (as seq.empty String) (seq.unit (_ char #x62))))"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(
+            model[0].term,
+            "s = ((str_concat)  ((str_concat)  [(chr 97)] []) [(chr 98)])"
+        );
+        let mut converter = Converter::builtin(BuiltinSpec::QfS).unwrap();
+        assert!(matches!(
+            converter.convert("(declare-const x Int)(assert (= (seq.unit x) (seq.unit 1)))".to_owned()),
+            Err(Error::Unsupported(op, _)) if op == "seq.unit"
+        ));
+    }
+
+    #[test]
+    fn builtin_specs() {
+        let formula =
+            "(declare-const x String)(assert (str.in_re (str.++ x \"a\") (re.+ re.allchar)))";
+        let mut converter = Converter::builtin(BuiltinSpec::QfS).unwrap();
+        assert_eq!(
+            converter.convert(formula.to_owned()).unwrap()[0].term,
+            "((str_in_re)  ((str_concat)  x [(chr 97)]) ((re_concat)  re_allchar (re_star  re_allchar)))"
+        );
+        assert_eq!(
+            converter.get_declared(),
+            [("x".to_owned(), "uc_string".to_owned())]
+        );
+
+        let mut converter = Converter::builtin(BuiltinSpec::QfSlia).unwrap();
+        assert_eq!(
+            converter
+                .convert("(declare-const x String)(assert (<= 2 (str.len x)))".to_owned())
+                .unwrap()[0]
+                .term,
+            "((\\<le>)  (2::int) (str_len  x))"
+        );
+    }
+
+    #[test]
+    fn replace_functions() {
+        // The arguments keep the SMT-LIB order: the string, the pattern, and the replacement
+        let mut converter = Converter::builtin(BuiltinSpec::QfS).unwrap();
+        let formula = converter
+            .convert(
+                "(declare-const x String)(declare-const y String)
+                 (assert (= y (str.replace_all (str.replace_re x (re.+ (re.range \"0\" \"9\")) \"#\") \"a\" \"b\")))"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(
+            formula[0].term,
+            "((=)  y ((str_replace_all)  ((str_replace_re)  x ((re_concat)  ((re_range)  [(chr 48)] [(chr 57)]) \
+            (re_star  ((re_range)  [(chr 48)] [(chr 57)]))) [(chr 35)]) [(chr 97)] [(chr 98)]))"
+        );
+        let model = converter
+            .convert_model(
+                "(define-fun x () String \"a1\")(define-fun y () String (str.replace_re_all \"a1\" re.allchar \"b\"))"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(
+            model[1].term,
+            "y = ((str_replace_re_all)  [(chr 97),(chr 49)] re_allchar [(chr 98)])"
+        );
+    }
+
+    #[test]
+    fn coq_target() {
+        let mut converter = Converter::builtin_for(BuiltinSpec::QfSlia, TargetKind::Coq).unwrap();
+        let formula = converter
+            .convert(
+                "(declare-const x Int)(declare-const s String)(declare-fun f (Int) Bool)
+                 (assert (< 0 x 5))(assert (distinct s \"a\" \"b\"))(assert (let ((y (+ x 1))) (f y)))"
+                    .to_owned(),
+            )
+            .unwrap();
+        let terms: Vec<_> = formula.iter().map(|c| c.term.as_str()).collect();
+        assert_eq!(
+            terms,
+            [
+                "(andb ((Z.ltb)  0%Z x) ((Z.ltb)  x 5%Z)) = true",
+                "(andb (negb (String.eqb s \"a\"%string)) (andb (negb (String.eqb s \"b\"%string)) (negb (String.eqb \"a\"%string \"b\"%string)))) = true",
+                "(let y := ((Z.add)  x 1%Z) in (f y)) = true"
+            ]
+        );
+        assert_eq!(
+            converter.get_declared(),
+            [
+                ("x".to_owned(), "Z".to_owned()),
+                ("s".to_owned(), "string".to_owned()),
+                ("f".to_owned(), "Z -> bool".to_owned())
+            ]
+        );
+        let model = converter
+            .convert_model(
+                "(define-fun x () Int (- 2))(define-fun f ((z Int)) Bool (> z 0))".to_owned(),
+            )
+            .unwrap();
+        assert_eq!(model[0].term, "x = (-2)%Z");
+        assert_eq!(
+            model[1].term,
+            "f = (fun (z : Z) => (((Z.gtb)  z 0%Z) : bool))"
+        );
+
+        let mut converter = Converter::builtin_for(BuiltinSpec::QfSlia, TargetKind::Coq).unwrap();
+        assert!(matches!(
+            converter.convert("(assert (forall ((x Int)) (> x 0)))".to_owned()),
+            Err(Error::Unsupported(op, _)) if op == "quantifiers in Coq output"
+        ));
+    }
+
+    #[test]
+    fn string_conversions() {
+        // The Coq spec maps none of the conversions, so only ground applications and str.is_digit convert
+        let mut converter = Converter::builtin_for(BuiltinSpec::QfSlia, TargetKind::Coq).unwrap();
+        let formula = converter
+            .convert(
+                "(declare-const x Int)(declare-const s String)
+                 (assert (= x (str.to_int \"42\") (str.to_code \"*\")))
+                 (assert (= s (str.++ (str.from_int (+ 4 2)) (str.from_code 97))))
+                 (assert (str.is_digit s))"
+                    .to_owned(),
+            )
+            .unwrap();
+        let terms: Vec<_> = formula.iter().map(|c| c.term.as_str()).collect();
+        assert_eq!(
+            terms[..2],
+            [
+                "(andb ((Z.eqb)  x 42%Z) ((Z.eqb)  42%Z 42%Z)) = true",
+                "((String.eqb)  s ((String.append)  \"6\"%string \"a\"%string)) = true",
+            ]
+        );
+        // The digits "0" to "9"
+        assert!(terms[2].starts_with("((orb)  ((orb)  ((orb)  ((orb)  ((orb)  ((orb)  ((orb)  ((orb)  ((orb)  ((String.eqb)  s \"0\"%string) ((String.eqb)  s \"1\"%string))"));
+        assert!(terms[2].ends_with("((String.eqb)  s \"9\"%string)) = true"));
+
+        let mut converter = Converter::builtin_for(BuiltinSpec::QfSlia, TargetKind::Coq).unwrap();
+        assert!(matches!(
+            converter.convert("(declare-const x Int)(assert (= (str.from_int x) \"1\"))".to_owned()),
+            Err(Error::Unsupported(op, _)) if op == "str.from_int"
+        ));
+    }
+
+    #[test]
+    fn lean_target() {
+        let mut converter = Converter::builtin_for(BuiltinSpec::QfSlia, TargetKind::Lean).unwrap();
+        let formula = converter
+            .convert(
+                "(declare-const x Int)(declare-const s String)
+                 (assert (< 0 x 5))(assert (let ((a (div x 2)) (b s)) (= (str.len b) a)))"
+                    .to_owned(),
+            )
+            .unwrap();
+        let terms: Vec<_> = formula.iter().map(|c| c.term.as_str()).collect();
+        assert_eq!(
+            terms,
+            [
+                "((decide ((0 : Int) < x)) && (decide (x < (5 : Int)))) = true",
+                "(let (a, b) := (((Int.ediv)  x (2 : Int)), s); ((Int.ofNat (String.length b)) == a)) = true"
+            ]
+        );
+        let model = converter
+            .convert_model(
+                "(define-fun x () Int (- 2))(define-fun s () String \"\\u{e9}\")".to_owned(),
+            )
+            .unwrap();
+        assert_eq!(model[0].term, "x = (-2 : Int)");
+        assert_eq!(model[1].term, "s = \"\\u00e9\"");
+        assert_eq!(
+            converter.get_declared()[0],
+            ("x".to_owned(), "Int".to_owned())
+        );
+    }
+
+    #[test]
+    fn recursive_functions() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        let converted = converter
+            .convert(
+                "(define-fun-rec f ((n Int)) Int (ite (= n 0) 0 (+ n (f (+ n 1)))))\
+                 (declare-const x Int)(assert (= (f x) 1))"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(converted[0].term, "((=)  (f x) (1::int))");
+        assert_eq!(
+            converter.get_definitions(),
+            ["function f :: \"int \\<Rightarrow> int\" where\n  \
+              \"f n = (if ((=)  n (0::int)) then (0::int) else ((+)  n (f ((+)  n (1::int)))))\"\n  \
+              by pat_completeness auto\ntermination sorry"]
+        );
+        assert!(!converter.get_vars_used().contains("f"));
+
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        converter.termination_measure(Some("\\<lambda>n. nat n".to_owned()));
+        // smt2parser expects the function declarations of define-funs-rec without enclosing parentheses
+        converter
+            .convert(
+                "(define-funs-rec (ev ((n Int)) Bool od ((n Int)) Bool) ((od n) (ev n)))"
+                    .to_owned(),
+            )
+            .unwrap();
+        let definition = &converter.get_definitions()[0];
+        assert!(definition.starts_with("function ev :: \"int \\<Rightarrow> bool\"\n  and od ::"));
+        assert!(definition.contains("\"ev n = (od n)\"\n| \"od n = (ev n)\""));
+        assert!(
+            definition.ends_with("termination by (relation \"measure (\\<lambda>n. nat n)\") auto")
+        );
+
+        assert!(matches!(
+            Converter::new(SPEC.to_owned())
+                .unwrap()
+                .convert("(declare-const x Int)(define-fun-rec f ((n Int)) Int x)".to_owned()),
+            Err(Error::Unsupported(..))
+        ));
+    }
+
+    #[test]
+    fn uninterpreted_sorts_and_functions() {
+        let formula =
+            "(declare-sort U 0)(declare-fun f (U Int) U)(declare-const x U)(declare-const y U)
+                       (assert (distinct (f x 1) y))";
+        let model = "(declare-fun U!val!0 () U)(declare-fun U!val!1 () U)
+                     (define-fun x () U U!val!0)(define-fun y () U U!val!1)
+                     (define-fun f ((a U) (b Int)) U U!val!0)";
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        let converted = converter.convert(formula.to_owned()).unwrap();
+        converter.convert_model(model.to_owned()).unwrap();
+        assert_eq!(converted[0].term, "((\\<noteq>)  (f x (1::int)) y)");
+        assert!(converted[0].vars.contains("f"));
+        assert_eq!(converter.get_type_declarations(), ["typedecl U"]);
+        assert_eq!(
+            converter.get_declared()[0],
+            (
+                "f".to_owned(),
+                "U \\<Rightarrow> int \\<Rightarrow> U".to_owned()
+            )
+        );
+        assert_eq!(
+            converter.get_element_axioms(),
+            ["distinct [U!val!0, U!val!1]"]
+        );
+
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        converter.uninterpreted_sorts(UninterpretedSorts::TypeVariables);
+        converter.convert(formula.to_owned()).unwrap();
+        assert!(converter.get_type_declarations().is_empty());
+        assert_eq!(
+            converter.get_declared()[1],
+            ("x".to_owned(), "'U".to_owned())
+        );
+    }
+
+    #[test]
+    fn variables_per_command() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        let converted = converter
+            .convert("(assert (= x 1))(assert (= (+ x y) z))".to_owned())
+            .unwrap();
+        assert_eq!(converted[0].vars, ["x".to_owned()].into());
+        assert_eq!(
+            converted[1].vars,
+            ["x".to_owned(), "y".to_owned(), "z".to_owned()].into()
+        );
+    }
+
+    #[test]
+    fn uninterpreted_functions() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        converter.uninterpret(&["+".to_owned()]);
+        let converted = converter
+            .convert("(assert (= x (+ 1 2 3)))".to_owned())
+            .unwrap();
+        assert_eq!(
+            converted.into_iter().map(|c| c.term).collect::<Vec<_>>(),
+            vec!["((=)  x (smt_uninterp__2b_ (1::int) (2::int) (3::int)))"]
+        );
+        assert!(converter.get_uninterpreted_used().contains("+"));
+        assert!(!converter.get_vars_used().contains("smt_uninterp__2b_"));
+    }
+
+    #[test]
+    fn uninterpreted_functions_distinct() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        converter.uninterpret(&["+".to_owned(), "-".to_owned()]);
+        let converted = converter
+            .convert("(assert (= (+ x 1) (- x 1)))".to_owned())
+            .unwrap();
+        assert_eq!(
+            converted[0].term,
+            "((=)  (smt_uninterp__2b_ x (1::int)) (smt_uninterp__2d_ x (1::int)))"
+        );
+        assert_eq!(
+            converter.get_uninterpreted_used(),
+            ["+".to_owned(), "-".to_owned()].into()
+        );
+    }
+
+    #[test]
+    fn rename_symbols() {
+        let isabelle = TargetKind::Isabelle.target();
+        assert_eq!(isabelle.identifier("x"), "x");
+        assert_eq!(isabelle.identifier("x_1'"), "x_1'");
+        assert_eq!(isabelle.identifier("x!0"), "smt_x_21_0");
+        assert_eq!(isabelle.identifier("a.b"), "smt_a_2e_b");
+        assert_eq!(isabelle.identifier("hello world"), "smt_hello_20_world");
+        assert_eq!(isabelle.identifier("value"), "smt_value");
+        assert_eq!(isabelle.identifier("_x"), "smt__5f_x");
+        // Names of renamed symbols cannot clash with symbols keeping their name
+        assert_eq!(isabelle.identifier("smt_value"), "smt_smt_5f_value");
+        // Reserved names depend on the target
+        assert_eq!(TargetKind::Coq.target().identifier("value"), "value");
+        assert_eq!(TargetKind::Coq.target().identifier("fix"), "smt_fix");
+
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        let converted = converter
+            .convert(
+                "(declare-fun fun () Int)(declare-fun a.b (Int) Int)\
+                (assert (forall ((value Int)) (= (a.b value) fun)))"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(
+            converted[0].term,
+            "(\\<forall>(smt_value::int). ((=)  (smt_a_2e_b smt_value) smt_fun))"
+        );
+        // The converter keeps the symbols of the variables
+        assert_eq!(
+            converted[0].vars,
+            ["a.b".to_owned(), "fun".to_owned()].into()
+        );
+        let model = converter
+            .convert_model("(define-fun fun () Int 0)".to_owned())
+            .unwrap();
+        assert_eq!(model[0].term, "smt_fun = (0::int)");
+    }
+
+    #[test]
+    fn quoted_symbols() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        let converted = converter
+            .convert(
+                "(declare-const |hello world| Int)(declare-fun |f| (Int) Int)\
+                (assert (= (f |hello world|) 1))"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(converted[0].term, "((=)  (f smt_hello_20_world) (1::int))");
+        assert_eq!(converter.get_declared()[0].0, "hello world");
+        let model = converter
+            .convert_model(
+                "(define-fun |hello world| () Int 0)(define-fun f ((|a b| Int)) Int (+ |a b| 1))"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(model[0].term, "smt_hello_20_world = (0::int)");
+        assert_eq!(
+            model[1].term,
+            "f = (\\<lambda>(smt_a_20_b::int). (((+)  smt_a_20_b (1::int))::int))"
+        );
+        // The quoted symbols are the same variables as in the formula, `|f|` being `f`
+        assert_eq!(converter.get_vars_used(), converter.get_vars_defined());
+    }
+
+    #[test]
+    fn let_bindings() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        let converted = converter
+            .convert("(assert (let ((a!1 (+ x 1))) (= a!1 y)))".to_owned())
+            .unwrap();
+        assert_eq!(
+            converted[0].term,
+            "(let smt_a_21_1 = ((+)  x (1::int)) in ((=)  smt_a_21_1 y))"
+        );
+        assert_eq!(converted[0].vars, ["x".to_owned(), "y".to_owned()].into());
+        assert!(converter.get_simps().contains("Let_def"));
+
+        // Bindings are parallel and shadow outer bindings
+        assert_eq!(
+            convert("(assert (let ((x y) (y x)) (let ((x (+ x y))) (= x y))))"),
+            vec!["(let (x, y) = (y, x) in (let x = ((+)  x y) in ((=)  x y)))"]
+        );
+    }
+
+    #[test]
+    fn quantifiers() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        let converted = converter
+            .convert(
+                "(assert (forall ((x Int) (b Bool)) (exists ((y Int)) (= (+ x y) z))))".to_owned(),
+            )
+            .unwrap();
+        assert_eq!(
+            converted[0].term,
+            "(\\<forall>(x::int) (b::bool). (\\<exists>(y::int). ((=)  ((+)  x y) z)))"
+        );
+        assert_eq!(converted[0].vars, ["z".to_owned()].into());
+
+        let unsupported = Converter::new(SPEC.to_owned())
+            .unwrap()
+            .convert("(assert (forall ((x RegLan)) (= x x)))".to_owned());
+        assert!(matches!(unsupported, Err(Error::Unsupported(s, _)) if s == "sort RegLan"));
+    }
+
+    #[test]
+    fn attributes() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        let converted = converter
+            .convert(
+                "(assert (! (= x 1) :named first))(assert (forall ((y Int)) (! (= y y) :pattern (y))))"
+                    .to_owned(),
+            )
+            .unwrap();
+        assert_eq!(converted[0].term, "((=)  x (1::int))");
+        assert_eq!(converted[0].name.as_deref(), Some("first"));
+        assert_eq!(converted[1].term, "(\\<forall>(y::int). ((=)  y y))");
+        assert_eq!(converted[1].name, None);
+    }
+
+    #[test]
+    fn indexed_identifiers() {
+        let spec = SPEC.replace(
+            r#""Ints": {"#,
+            r#""Strings": {
+                "re.loop": { "mapsto": "re_loop", "chainable": false },
+                "re.none": { "mapsto": "re_none", "chainable": false }
+            },
+            "Ints": {"#,
+        );
+        let mut converter = Converter::new(spec).unwrap();
+        let converted = converter
+            .convert("(assert (= r ((_ re.loop 2 5) s)))(assert (= r (_ re.none 1)))".to_owned())
+            .unwrap();
+        assert_eq!(converted[0].term, "((=)  r ((re_loop)  2 5 s))");
+        assert_eq!(converted[1].term, "((=)  r (re_none 1))");
+    }
+
+    #[test]
+    fn derived_regex_operators() {
+        let spec = SPEC.replace(
+            r#""Ints": {"#,
+            r#""Strings": {
+                "str.to_re": { "mapsto": "to_re", "chainable": false },
+                "re.allchar": { "mapsto": "re_allchar", "chainable": false },
+                "re.*": { "mapsto": "star", "chainable": false },
+                "re.++": { "mapsto": "concat", "assoc": "left", "chainable": false },
+                "re.union": { "mapsto": "union", "assoc": "left", "chainable": false },
+                "re.inter": { "mapsto": "inter", "assoc": "left", "chainable": false },
+                "re.comp": { "mapsto": "comp", "chainable": false },
+                "re.loop": { "mapsto": "re_loop", "chainable": false }
+            },
+            "Ints": {"#,
+        );
+        let mut converter = Converter::new(spec).unwrap();
+        let converted = converter
+            .convert(
+                "(assert (= r (re.diff a b)))(assert (= r (re.opt a)))(assert (= r re.all))
+                 (assert (= r ((_ re.^ 3) a)))(assert (= r (re.loop a 1 2)))(assert (= r (re.loop a 2)))"
+                    .to_owned(),
+            )
+            .unwrap();
+        let terms: Vec<_> = converted.into_iter().map(|c| c.term).collect();
+        assert_eq!(
+            terms,
+            [
+                "((=)  r ((inter)  a (comp  b)))",
+                "((=)  r ((union)  (to_re  []) a))",
+                "((=)  r (star  re_allchar))",
+                "((=)  r ((re_loop)  3 3 a))",
+                "((=)  r ((re_loop)  1 2 a))",
+                "((=)  r ((concat)  ((re_loop)  2 2 a) (star  a)))"
+            ]
+        );
+    }
+
+    #[test]
+    fn template_mappings() {
+        let spec = SPEC.replace(
+            r#""Ints": {"#,
+            r#""Strings": {
+                "str.substr": { "mapsto": "smt_substr ?2 ?3 ?1", "chainable": false },
+                "re.loop": { "mapsto": "rep ?3 ?1 ?2", "chainable": false }
+            },
+            "Ints": {"#,
+        );
+        let mut converter = Converter::new(spec).unwrap();
+        let converted = converter
+            .convert(
+                "(assert (= s (str.substr t 1 x)))(assert (= r ((_ re.loop 2 5) a)))".to_owned(),
+            )
+            .unwrap();
+        assert_eq!(converted[0].term, "((=)  s (smt_substr (1::int) x t))");
+        assert_eq!(converted[1].term, "((=)  r (rep a 2 5))");
+    }
+
+    #[test]
+    fn char_literals() {
+        assert_eq!(
+            rewrite_char_literals("(assert (= x (_ char #x41)))(_ char #x1F600 )"),
+            "(assert (= x \"\\u{41}\"))\"\\u{1F600}\""
+        );
+        assert_eq!(rewrite_char_literals("(_ char #xZ)"), "(_ char #xZ)");
+        // Only the literals outside of string literals, quoted symbols, and comments are rewritten
+        let kept = "(define-fun s () String \"(_ char #x41)\")|(_ char #x41)|; (_ char #x41)\n";
+        assert_eq!(rewrite_char_literals(kept), kept);
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        converter
+            .convert_model("(define-fun s () String \"(_ char #x41)\")".to_owned())
+            .unwrap();
+        assert_eq!(
+            convert("(assert (= x (_ char #x41)))"),
+            vec!["((=)  x [(chr 65)])"]
+        );
+    }
+
+    #[test]
+    fn bit_literals() {
+        assert_eq!(convert("(assert (= x #x4F))"), vec!["((=)  x 79)"]);
+        assert_eq!(convert("(assert (= x #b0101))"), vec!["((=)  x 5)"]);
+
+        let spec = SPEC.replace(
+            r#""specs": {"#,
+            r#""sorts": { "BitVec": "word" },
+            "specs": {"#,
+        );
+        let mut converter = Converter::new(spec).unwrap();
+        let converted = converter
+            .convert("(assert (= x #x04F))(assert (= y #b101))".to_owned())
+            .unwrap();
+        assert_eq!(converted[0].term, "((=)  x (79::12 word))");
+        assert_eq!(converted[1].term, "((=)  y (5::3 word))");
+    }
+
+    #[test]
+    fn unroll_right_assoc() {
+        assert_eq!(
+            convert("(assert (=> a b c))"),
+            vec!["((\\<longrightarrow>)  a ((\\<longrightarrow>)  b c))"]
+        );
+        assert_eq!(
+            convert("(assert (=> a b c d))"),
+            vec!["((\\<longrightarrow>)  a ((\\<longrightarrow>)  b ((\\<longrightarrow>)  c d)))"]
+        );
+        assert_eq!(
+            convert("(assert (=> a b))"),
+            vec!["((\\<longrightarrow>)  a b)"]
+        );
+    }
+
+    #[test]
+    fn unroll_left_assoc() {
+        assert_eq!(
+            convert("(assert (and a b c d))"),
+            vec!["((\\<and>)  ((\\<and>)  ((\\<and>)  a b) c) d)"]
+        );
+    }
+
+    #[test]
+    fn chainable() {
+        assert_eq!(
+            convert("(assert (= a b c))"),
+            vec!["(((=)  a b) \\<and> ((=)  b c))"]
+        );
+        assert_eq!(convert("(assert (= a b))"), vec!["((=)  a b)"]);
+    }
+
+    #[test]
+    fn pairwise() {
+        assert_eq!(
+            convert("(assert (distinct a b c))"),
+            vec!["(((\\<noteq>)  a b) \\<and> ((\\<noteq>)  a c) \\<and> ((\\<noteq>)  b c))"]
+        );
+        assert_eq!(
+            convert("(assert (distinct a b))"),
+            vec!["((\\<noteq>)  a b)"]
+        );
+    }
+
+    #[test]
+    fn basic_unescapes() {
+        assert_eq!(
+            unicode_unescape("hello\\u{21}").unwrap(),
+            "hello!".to_owned()
+        );
+        assert_eq!(unicode_unescape("\\u{1f600}").unwrap(), "😀".to_owned());
+        // Without braces, the sequence has exactly four digits
+        assert_eq!(
+            unicode_unescape("\\u1f600").unwrap(),
+            "\u{1f60}0".to_owned()
+        );
+    }
+
+    #[test]
+    fn smt25_unescapes() {
+        let rewritten = rewrite_smtlib25("(assert (= s \"hello\\x21\\x6\\u{21}\"))");
+        assert_eq!(rewritten, "(assert (= s \"hello\\u{21}\\x6\\u{21}\"))");
+        assert_eq!(unicode_unescape("hello\\u{21}\\x6").unwrap(), "hello!\\x6");
+        // The backslash after an escaping backslash does not start an escape sequence
+        let escaped = "(assert (= s \"\\\\x41\\\\\\x41\"))";
+        assert_eq!(
+            rewrite_smtlib25(escaped),
+            "(assert (= s \"\\\\x41\\\\\\u{41}\"))"
+        );
+    }
+
+    #[test]
+    fn smt25_names() {
+        let input = "(assert (str.in.re (int.to.str (str.to-int x)) (str.to.re \"str.to.re\")))\n\
+            ; str.in.re\n(assert (= |str.to.re| re.nostr str.to.re.x))";
+        assert_eq!(
+            rewrite_smtlib25(input),
+            "(assert (str.in_re (str.from_int (str.to_int x)) (str.to_re \"str.to.re\")))\n\
+            ; str.in.re\n(assert (= |str.to.re| re.none str.to.re.x))"
+        );
+        // Doubled quotes do not end the literal
+        let quoted = "(assert (= s \"a\"\" str.to.re \\x41\"))";
+        assert_eq!(
+            rewrite_smtlib25(quoted),
+            "(assert (= s \"a\"\" str.to.re \\u{41}\"))"
+        );
+    }
+
+    #[test]
+    fn invalid_escape_sequence1() {
+        // Backslashes not starting an escape sequence are characters of their own
+        assert_eq!(unicode_unescape("\\u{}").unwrap(), "\\u{}");
+        assert_eq!(unicode_unescape("a\\nb\\").unwrap(), "a\\nb\\");
+        // Braced sequences have up to five digits and end at code point 2FFFF
+        assert_eq!(
+            unicode_unescape("\\u{000041}\\u{30000}").unwrap(),
+            "\\u{000041}\\u{30000}"
+        );
+    }
+
+    #[test]
+    fn tooshort_escape_sequence() {
+        assert_eq!(unicode_unescape("\\u12").unwrap(), "\\u12");
+    }
+
+    #[test]
+    fn nonhex_escape_sequence() {
+        assert_eq!(unicode_unescape("\\u{12g}").unwrap(), "\\u{12g}");
+    }
+
+    #[test]
+    fn smt25_invalid() {
+        assert_eq!(unicode_unescape("\\xFG").unwrap(), "\\xFG");
+        assert_eq!(unicode_unescape("\\x41").unwrap(), "\\x41");
+    }
+
+    #[test]
+    fn surrogate_escape_sequence() {
+        let error = unicode_unescape("a\\ud800\"").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Error: Invalid escape sequence \\ud800 in the string literal \"a\\ud800\"\"\": \
+            the code point D800 is not a character"
+        );
+    }
+
+    #[test]
+    fn doubled_quotes() {
+        let mut converter = Converter::new(SPEC.to_owned()).unwrap();
+        let converted = converter
+            .convert("(declare-const s String)(assert (= s \"say \"\"hi\"\"\"))".to_owned())
+            .unwrap();
+        let quote = u32::from('"');
+        assert!(converted[0].term.contains(&format!(
+            ",(chr {}),(chr 104),(chr 105),(chr {})]",
+            quote, quote
+        )));
     }
 }
@@ -0,0 +1,177 @@
+//! The Coq target of the converter.
+//!
+//! SMT-LIB terms are converted to Boolean Coq terms with the functions of the Coq standard library a Coq spec maps to,
+//! and assertions to `t = true`. Integers are `Z`, strings are the 8-bit strings of `Coq.Strings.String`.
+
+use std::io::{self, Write};
+
+use crate::convert::{BuiltinSpec, Target};
+use crate::error::Error;
+use crate::lemma::{self, Lemma, Theory};
+use crate::{coq, spec};
+
+/// The keywords of Gallina and the vernacular, and the Booleans the assertions compare to
+const RESERVED_NAMES: &[&str] = &[
+    "Definition",
+    "Lemma",
+    "Prop",
+    "Proof",
+    "Qed",
+    "Require",
+    "SProp",
+    "Set",
+    "Theorem",
+    "Type",
+    "as",
+    "at",
+    "cofix",
+    "else",
+    "end",
+    "exists",
+    "false",
+    "fix",
+    "for",
+    "forall",
+    "fun",
+    "if",
+    "in",
+    "is",
+    "let",
+    "match",
+    "mod",
+    "return",
+    "struct",
+    "then",
+    "true",
+    "where",
+    "with",
+];
+
+/// Coq, checked with `coqc`, see [crate::coq]
+#[derive(Default)]
+pub struct Coq;
+
+impl Target for Coq {
+    fn name(&self) -> &'static str {
+        "Coq"
+    }
+
+    fn spec_files(&self) -> &'static [&'static str] {
+        &spec::COQ_SPEC_FILES
+    }
+
+    fn reserved_names(&self) -> &'static [&'static str] {
+        RESERVED_NAMES
+    }
+
+    /// The Coq spec maps to the Coq standard library and covers both logics.
+    fn builtin_spec(&self, _spec: BuiltinSpec) -> &'static str {
+        include_str!("../specs/coq/QF_SLIA.json")
+    }
+
+    fn assertion(&self, term: String) -> String {
+        format!("{} = true", term)
+    }
+
+    fn function_type(&self, types: &[String]) -> String {
+        types.join(" -> ")
+    }
+
+    fn conjunction(&self, terms: &[String]) -> String {
+        terms
+            .iter()
+            .rev()
+            .cloned()
+            .reduce(|c, t| format!("(andb {} {})", t, c))
+            .unwrap_or_else(|| "true".to_owned())
+    }
+
+    fn annotate(&self, term: &str, ty: &str) -> String {
+        format!("({} : {})", term, ty)
+    }
+
+    fn lambda(&self, params: &[String], body: &str) -> String {
+        format!("(fun {} => {})", params.join(" "), body)
+    }
+
+    fn let_in(&self, names: &[String], values: &[String], body: &str) -> String {
+        if names.len() == 1 {
+            format!("(let {} := {} in {})", names[0], values[0], body)
+        } else {
+            format!(
+                "(let '({}) := ({}) in {})",
+                names.join(", "),
+                values.join(", "),
+                body
+            )
+        }
+    }
+
+    fn int_literal(&self, value: &str) -> String {
+        if value.starts_with('-') {
+            format!("({})%Z", value)
+        } else {
+            format!("{}%Z", value)
+        }
+    }
+
+    fn string_literal(&self, s: &str) -> Result<String, Error> {
+        coq_string(s)
+    }
+
+    fn default_method(&self) -> &'static str {
+        lemma::COQ_DEFAULT_METHOD
+    }
+
+    fn file_name(&self) -> &'static str {
+        "Validation.v"
+    }
+
+    fn validation_theory(&self, lemma: &Lemma, imports: &[String]) -> Theory {
+        coq::coq_theory(lemma, imports)
+    }
+
+    fn write_lemma(&self, lemma: &Lemma, w: &mut dyn Write) -> io::Result<()> {
+        lemma.write_coq(w)
+    }
+
+    fn write_theory(&self, theory: &Theory, w: &mut dyn Write) -> io::Result<()> {
+        theory.write_coq(w)
+    }
+}
+
+/// Returns the Coq string literal of the string.
+/// Coq strings are sequences of 8-bit characters, so characters beyond `\u{ff}` are not supported.
+/// Strings of printable ASCII characters are written as literals, others character by character.
+fn coq_string(s: &str) -> Result<String, Error> {
+    if s.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+        return Ok(format!("\"{}\"%string", s.replace('"', "\"\"")));
+    }
+    let mut res = String::from("EmptyString");
+    for c in s.chars().rev() {
+        let code = u32::from(c);
+        if code > 0xff {
+            return Err(Error::unsupported(format!(
+                "character \\u{{{:x}}} in Coq output",
+                code
+            )));
+        }
+        res = format!("(String (Ascii.ascii_of_nat {}) {})", code, res);
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::coq_string;
+
+    #[test]
+    fn string_literals() {
+        assert_eq!(coq_string("a\"b").unwrap(), "\"a\"\"b\"%string");
+        assert_eq!(
+            coq_string("\n").unwrap(),
+            "(String (Ascii.ascii_of_nat 10) EmptyString)"
+        );
+        assert!(coq_string("\u{100}").is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! The Isabelle/HOL target of the converter.
+//!
+//! SMT-LIB terms are converted to Isabelle/HOL terms with the functions of the Isabelle SMT theories the spec maps to.
+//! This is the only target translating the constructs the spec cannot map, see [Target::full_translation].
+
+use std::io::{self, Write};
+
+use itertools::Itertools;
+
+use crate::convert::{BuiltinSpec, Target};
+use crate::error::Error;
+use crate::lemma::{self, Lemma, Theory};
+use crate::spec;
+
+/// The keywords of the theory syntax and of HOL terms, and HOL constants commonly used as variable names
+const RESERVED_NAMES: &[&str] = &[
+    "ALL",
+    "EX",
+    "False",
+    "GREATEST",
+    "LEAST",
+    "O",
+    "SOME",
+    "THE",
+    "True",
+    "UNIV",
+    "and",
+    "assumes",
+    "begin",
+    "by",
+    "case",
+    "datatype",
+    "definition",
+    "div",
+    "dvd",
+    "else",
+    "end",
+    "fixes",
+    "for",
+    "fun",
+    "function",
+    "if",
+    "imports",
+    "in",
+    "is",
+    "lemma",
+    "let",
+    "mod",
+    "o",
+    "obtains",
+    "of",
+    "shows",
+    "then",
+    "theorem",
+    "theory",
+    "typedecl",
+    "undefined",
+    "value",
+    "where",
+];
+
+/// Isabelle/HOL, the language of the Isabelle SMT theories
+pub struct Isabelle;
+
+impl Target for Isabelle {
+    fn name(&self) -> &'static str {
+        "Isabelle/HOL"
+    }
+
+    fn spec_files(&self) -> &'static [&'static str] {
+        &spec::SPEC_FILES
+    }
+
+    fn reserved_names(&self) -> &'static [&'static str] {
+        RESERVED_NAMES
+    }
+
+    fn builtin_spec(&self, spec: BuiltinSpec) -> &'static str {
+        spec.json()
+    }
+
+    fn full_translation(&self) -> bool {
+        true
+    }
+
+    fn assertion(&self, term: String) -> String {
+        term
+    }
+
+    fn function_type(&self, types: &[String]) -> String {
+        types.join(" \\<Rightarrow> ")
+    }
+
+    fn conjunction(&self, terms: &[String]) -> String {
+        format!("({})", terms.join(" \\<and> "))
+    }
+
+    fn annotate(&self, term: &str, ty: &str) -> String {
+        format!("({}::{})", term, ty)
+    }
+
+    fn lambda(&self, params: &[String], body: &str) -> String {
+        format!("(\\<lambda>{}. {})", params.join(" "), body)
+    }
+
+    fn let_in(&self, names: &[String], values: &[String], body: &str) -> String {
+        if names.len() == 1 {
+            format!("(let {} = {} in {})", names[0], values[0], body)
+        } else {
+            format!(
+                "(let ({}) = ({}) in {})",
+                names.join(", "),
+                values.join(", "),
+                body
+            )
+        }
+    }
+
+    fn int_literal(&self, value: &str) -> String {
+        format!("({}::int)", value)
+    }
+
+    /// Strings are lists of characters of the Isabelle SMT theories, e.g. `[(chr 97),(chr 98)]`.
+    fn string_literal(&self, s: &str) -> Result<String, Error> {
+        let chars = s.chars().map(|c| format!("(chr {})", u32::from(c)));
+        Ok(format!("[{}]", chars.format(",")))
+    }
+
+    fn quantifier(&self, forall: bool, vars: &[String], body: &str) -> Result<String, Error> {
+        let binder = if forall { "\\<forall>" } else { "\\<exists>" };
+        Ok(format!("({}{}. {})", binder, vars.join(" "), body))
+    }
+
+    fn case(&self, scrutinee: &str, cases: &[(String, String)]) -> Result<String, Error> {
+        let cases = cases
+            .iter()
+            .map(|(pattern, body)| format!("{} \\<Rightarrow> {}", pattern, body));
+        Ok(format!("(case {} of {})", scrutinee, cases.format(" | ")))
+    }
+
+    fn update(&self, function: &str, index: &str, value: &str) -> Result<String, Error> {
+        Ok(format!("({}({} := {}))", function, index, value))
+    }
+
+    fn default_method(&self) -> &'static str {
+        lemma::DEFAULT_METHOD
+    }
+
+    fn file_name(&self) -> &'static str {
+        "Validation.thy"
+    }
+
+    fn validation_theory(&self, lemma: &Lemma, imports: &[String]) -> Theory {
+        crate::checker::validation_theory(lemma, imports)
+    }
+
+    fn write_lemma(&self, lemma: &Lemma, w: &mut dyn Write) -> io::Result<()> {
+        lemma.write_isabelle(w)
+    }
+
+    fn write_theory(&self, theory: &Theory, w: &mut dyn Write) -> io::Result<()> {
+        theory.write_isabelle(w)
+    }
+}
@@ -0,0 +1,176 @@
+//! The Lean 4 target of the converter.
+//!
+//! SMT-LIB terms are converted to Lean terms of type `Bool` with the functions of the Lean core library a Lean spec maps to,
+//! and assertions to `t = true`. Integers are `Int`, strings are `String`; no further libraries are needed.
+
+use std::io::{self, Write};
+
+use crate::convert::{BuiltinSpec, Target};
+use crate::error::Error;
+use crate::lemma::{self, Lemma, Theory};
+use crate::{lean, spec};
+
+/// The keywords of Lean 4, and the Booleans the assertions compare to
+const RESERVED_NAMES: &[&str] = &[
+    "Prop",
+    "Sort",
+    "Type",
+    "abbrev",
+    "at",
+    "by",
+    "calc",
+    "def",
+    "do",
+    "else",
+    "end",
+    "example",
+    "false",
+    "for",
+    "from",
+    "fun",
+    "have",
+    "if",
+    "import",
+    "in",
+    "inductive",
+    "instance",
+    "let",
+    "match",
+    "namespace",
+    "open",
+    "return",
+    "section",
+    "show",
+    "structure",
+    "then",
+    "theorem",
+    "true",
+    "variable",
+    "where",
+    "with",
+];
+
+/// Lean 4, checked with `lean`, see [crate::lean]
+#[derive(Default)]
+pub struct Lean;
+
+impl Target for Lean {
+    fn name(&self) -> &'static str {
+        "Lean"
+    }
+
+    fn spec_files(&self) -> &'static [&'static str] {
+        &spec::LEAN_SPEC_FILES
+    }
+
+    fn reserved_names(&self) -> &'static [&'static str] {
+        RESERVED_NAMES
+    }
+
+    /// The Lean spec maps to the Lean core library and covers both logics.
+    fn builtin_spec(&self, _spec: BuiltinSpec) -> &'static str {
+        include_str!("../specs/lean/QF_SLIA.json")
+    }
+
+    fn assertion(&self, term: String) -> String {
+        format!("{} = true", term)
+    }
+
+    fn function_type(&self, types: &[String]) -> String {
+        types.join(" → ")
+    }
+
+    fn conjunction(&self, terms: &[String]) -> String {
+        if terms.is_empty() {
+            "true".to_owned()
+        } else {
+            format!("({})", terms.join(" && "))
+        }
+    }
+
+    fn annotate(&self, term: &str, ty: &str) -> String {
+        format!("({} : {})", term, ty)
+    }
+
+    fn lambda(&self, params: &[String], body: &str) -> String {
+        format!("(fun {} => {})", params.join(" "), body)
+    }
+
+    fn let_in(&self, names: &[String], values: &[String], body: &str) -> String {
+        if names.len() == 1 {
+            format!("(let {} := {}; {})", names[0], values[0], body)
+        } else {
+            format!(
+                "(let ({}) := ({}); {})",
+                names.join(", "),
+                values.join(", "),
+                body
+            )
+        }
+    }
+
+    fn int_literal(&self, value: &str) -> String {
+        format!("({} : Int)", value)
+    }
+
+    fn string_literal(&self, s: &str) -> Result<String, Error> {
+        lean_string(s)
+    }
+
+    fn default_method(&self) -> &'static str {
+        lemma::LEAN_DEFAULT_METHOD
+    }
+
+    fn file_name(&self) -> &'static str {
+        "Validation.lean"
+    }
+
+    fn validation_theory(&self, lemma: &Lemma, imports: &[String]) -> Theory {
+        lean::lean_theory(lemma, imports)
+    }
+
+    fn write_lemma(&self, lemma: &Lemma, w: &mut dyn Write) -> io::Result<()> {
+        lemma.write_lean(w)
+    }
+
+    fn write_theory(&self, theory: &Theory, w: &mut dyn Write) -> io::Result<()> {
+        theory.write_lean(w)
+    }
+}
+
+/// Returns the Lean string literal of the string.
+/// Characters other than printable ASCII are escaped as `\uHHHH`,
+/// so characters beyond the Basic Multilingual Plane are not supported.
+fn lean_string(s: &str) -> Result<String, Error> {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                res.push('\\');
+                res.push(c);
+            }
+            c if c.is_ascii_graphic() || c == ' ' => res.push(c),
+            c if u32::from(c) <= 0xffff => res.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => {
+                return Err(Error::unsupported(format!(
+                    "character \\u{{{:x}}} in Lean output",
+                    u32::from(c)
+                )))
+            }
+        }
+    }
+    res.push('"');
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lean_string;
+
+    #[test]
+    fn string_literals() {
+        assert_eq!(lean_string("a\"b\\").unwrap(), "\"a\\\"b\\\\\"");
+        assert_eq!(lean_string("\n\u{e9}").unwrap(), "\"\\u000a\\u00e9\"");
+        assert!(lean_string("\u{1f600}").is_err());
+    }
+}
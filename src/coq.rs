@@ -0,0 +1,96 @@
+//! Checking lemmas with the Coq proof assistant.
+//!
+//! With [TargetKind::Coq](crate::convert::TargetKind::Coq), the converter maps the formula with a Coq spec
+//! (`coq_spec.json` in the theory root, or the builtin one) to Boolean Coq terms, and assertions to `t = true`.
+//! The lemma is written to a file `Validation.v` and compiled with `coqc`, which runs its proof script.
+//! Since the premises assign values to all variables, the default script substitutes them and computes the conclusions.
+
+use std::path::Path;
+
+use itertools::Itertools;
+
+use crate::checker::{CheckResult, UnknownReason};
+use crate::convert_coq::Coq;
+use crate::error::Error;
+use crate::lemma::{Lemma, Theory};
+use crate::prover::{ProcessChecker, Prover};
+
+/// The logical path the theory root is bound to, so that its modules are imported as `SMT.Module`
+pub const LOAD_PATH: &str = "SMT";
+
+/// Creates the Coq file `Validation` containing the lemma to check, requiring the given modules.
+pub fn coq_theory(lemma: &Lemma, imports: &[String]) -> Theory {
+    let mut theory = Theory::new("Validation", false);
+    for import in imports.iter().unique() {
+        theory.add_theory_import(import);
+    }
+    theory.add_lemma(lemma.clone());
+    theory
+}
+
+/// Checks lemmas by compiling them with `coqc`, one process per lemma
+pub type CoqChecker = ProcessChecker<Coq>;
+
+impl Prover for Coq {
+    fn executable(&self) -> &'static str {
+        "coqc"
+    }
+
+    /// Binds the theory root to [LOAD_PATH] and compiles the file in its directory.
+    fn command_line(&self, theory_root: &str, file: &Path) -> Vec<String> {
+        vec![
+            "coqc".to_owned(),
+            "-Q".to_owned(),
+            theory_root.to_owned(),
+            LOAD_PATH.to_owned(),
+            file.display().to_string(),
+        ]
+    }
+
+    /// Classifies a failed compilation from the output of `coqc`.
+    /// A conclusion that computes to `false` cannot be unified with `true`, which refutes the lemma.
+    /// Errors in the statement, such as ill-typed terms, are errors of the spec rather than failed proofs.
+    fn classify_failure(&self, output: &str) -> Result<CheckResult, Error> {
+        if output.contains("Unable to unify \"true\" with \"false\"")
+            || output.contains("Unable to unify \"false\" with \"true\"")
+        {
+            log::debug!("Lemma is invalid: {}", output);
+            return Ok(CheckResult::FailedInvalid);
+        }
+        if output.contains("Syntax error")
+            || output.contains("was not found in the current environment")
+            || output.contains("has type")
+        {
+            log::error!("coqc rejected the validation file:\n{}", output);
+            return Err(Error::Other(
+                "coqc rejected the validation file (see logs for details)".to_owned(),
+            ));
+        }
+        log::debug!("Proof could not be finished: {}", output);
+        Ok(CheckResult::FailedUnknown(UnknownReason::from_output(
+            output,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_coqc_failures() {
+        let invalid = "File \"./Validation.v\", line 3, characters 43-54:\nError:\nIn environment\nUnable to unify \"true\" with \"false\".";
+        assert_eq!(
+            Coq.classify_failure(invalid).unwrap(),
+            CheckResult::FailedInvalid
+        );
+        assert!(Coq
+            .classify_failure("Error: The reference foo was not found in the current environment.")
+            .is_err());
+        assert_eq!(
+            Coq.classify_failure("Error: Attempt to save an incomplete proof")
+                .unwrap(),
+            CheckResult::FailedUnknown(UnknownReason::TacticFailure)
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Rewriting of derived SMT-LIB operators in terms of more basic ones.
+//!
+//! The spec does not have to map every operator of a theory. For example, the regular expression
+//! operators `re.diff` and `re.opt` are definable with `re.inter`, `re.comp`, and `re.union`.
+//! If the spec does not map such an operator, the converter rewrites its applications with [desugar]
+//! and converts the result instead. The same applies to the [SEQUENCE_FUNCTIONS] solvers use for strings.
+
+use smt2parser::concrete::{Constant, QualIdentifier, Symbol, Term};
+use smt2parser::visitors::{Identifier, Index};
+
+use crate::eval::MAX_CODE_POINT;
+use crate::normalize::app;
+
+/// The functions on sequences of characters that z3 uses for strings in models and that are rewritten to strings:
+/// `(seq.unit c)` is the string of the character `c`, a character `(_ Char n)` is the string of the code point `n`,
+/// and `seq.empty` is the empty string. The other sequence functions are aliases, see [crate::spec::DEFAULT_ALIASES].
+pub(crate) const SEQUENCE_FUNCTIONS: [&str; 3] = ["seq.unit", "Char", "seq.empty"];
+
+/// Rewrites operators written in SMT-LIB 2.5 syntax to their SMT-LIB 2.6 form.
+/// In SMT-LIB 2.5, the bounds of `re.loop` are arguments: `(re.loop r 2 5)` is `((_ re.loop 2 5) r)`,
+/// and `(re.loop r 2)` matches at least two repetitions of `r`.
+pub(crate) fn rewrite_legacy(op: &str, indices: &[Index], args: &[Term]) -> Option<Term> {
+    match (op, indices, args) {
+        ("re.loop", [], [r, lower, upper]) => Some(indexed_app(
+            "re.loop",
+            vec![numeral_index(lower)?, numeral_index(upper)?],
+            vec![r.clone()],
+        )),
+        ("re.loop", [], [r, lower]) => Some(app(
+            "re.++",
+            vec![
+                indexed_app("re.^", vec![numeral_index(lower)?], vec![r.clone()]),
+                app("re.*", vec![r.clone()]),
+            ],
+        )),
+        _ => None,
+    }
+}
+
+/// Rewrites an application of an operator the spec does not map to an equivalent term, if it is a derived operator.
+pub(crate) fn desugar(op: &str, indices: &[Index], args: &[Term]) -> Option<Term> {
+    match (op, indices, args) {
+        ("re.diff", [], [a, b]) => Some(app(
+            "re.inter",
+            vec![a.clone(), app("re.comp", vec![b.clone()])],
+        )),
+        ("re.opt", [], [a]) => Some(app(
+            "re.union",
+            vec![app("str.to_re", vec![string("")]), a.clone()],
+        )),
+        ("re.+", [], [a]) => Some(app("re.++", vec![a.clone(), app("re.*", vec![a.clone()])])),
+        ("re.all", [], []) => Some(app("re.*", vec![identifier("re.allchar")])),
+        ("re.^", [n], [a]) => Some(indexed_app(
+            "re.loop",
+            vec![n.clone(), n.clone()],
+            vec![a.clone()],
+        )),
+        ("seq.unit", [], [c]) if is_char(c) => Some(c.clone()),
+        ("seq.empty", [], []) => Some(string("")),
+        ("Char", [Index::Numeral(n)], []) => {
+            let code = n.to_string().parse::<u32>().ok()?;
+            (code <= MAX_CODE_POINT).then(|| string(&format!("\\u{{{:x}}}", code)))
+        }
+        // A digit is one of the ten strings "0" to "9"
+        ("str.is_digit", [], [s]) => Some(app(
+            "or",
+            ('0'..='9')
+                .map(|d| app("=", vec![s.clone(), string(&d.to_string())]))
+                .collect(),
+        )),
+        _ => None,
+    }
+}
+
+/// Returns whether the term is a character literal, `(_ Char n)` or `(_ char #xAB)`, which is read as a string literal.
+fn is_char(term: &Term) -> bool {
+    match term {
+        Term::Constant(Constant::String(_)) => true,
+        Term::QualIdentifier(QualIdentifier::Simple {
+            identifier: Identifier::Indexed { symbol, .. },
+        }) => symbol.0 == "Char",
+        _ => false,
+    }
+}
+
+/// Returns the index for a numeral argument.
+fn numeral_index(term: &Term) -> Option<Index> {
+    match term {
+        Term::Constant(Constant::Numeral(n)) => Some(Index::Numeral(n.clone())),
+        _ => None,
+    }
+}
+
+fn string(s: &str) -> Term {
+    Term::Constant(Constant::String(s.to_owned()))
+}
+
+fn identifier(symbol: &str) -> Term {
+    Term::QualIdentifier(QualIdentifier::Simple {
+        identifier: Identifier::Simple {
+            symbol: Symbol(symbol.to_owned()),
+        },
+    })
+}
+
+/// Builds an application of the indexed function with the given name.
+fn indexed_app(op: &str, indices: Vec<Index>, arguments: Vec<Term>) -> Term {
+    Term::Application {
+        qual_identifier: QualIdentifier::Simple {
+            identifier: Identifier::Indexed {
+                symbol: Symbol(op.to_owned()),
+                indices,
+            },
+        },
+        arguments,
+    }
+}
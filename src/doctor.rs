@@ -0,0 +1,272 @@
+//! The environment check of `smtmv doctor`.
+//!
+//! Validations fail late and often obscurely if Isabelle is missing, of an unsupported release, or has no
+//! heap image of the `smt` session, or if the theory root or its spec are broken. The doctor checks these
+//! requirements one after another and reports each step with the error and its remediation, skipping the steps
+//! that depend on a failed one. Finally, it validates a trivial model end to end with the chosen checker.
+
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::checker::CheckerKind;
+use crate::convert::{BuiltinSpec, Converter};
+use crate::error::Error;
+use crate::validation::{self, ValidationOptions, ValidationResult};
+use crate::{heap, isabelle_env, spec};
+
+/// The formula validated at the end of the check, which the model [ROUND_TRIP_MODEL] satisfies
+const ROUND_TRIP_FORMULA: &str = "(declare-const x Int)(assert (> x 0))";
+const ROUND_TRIP_MODEL: &str = "(define-fun x () Int 1)";
+
+/// The outcome of a step of the environment check
+#[derive(Debug)]
+pub enum Status {
+    /// The requirement is met, with details such as the path or version found
+    Passed(String),
+    Failed(Error),
+    /// The step was not run, for the given reason
+    Skipped(String),
+}
+
+/// A step of the environment check and its outcome
+#[derive(Debug)]
+pub struct Step {
+    pub name: &'static str,
+    pub status: Status,
+}
+
+impl Step {
+    fn new(name: &'static str, outcome: Result<String, Error>) -> Self {
+        let status = match outcome {
+            Ok(details) => Status::Passed(details),
+            Err(e) => Status::Failed(e),
+        };
+        Self { name, status }
+    }
+
+    fn skipped(name: &'static str, reason: &str) -> Self {
+        Self {
+            name,
+            status: Status::Skipped(reason.to_owned()),
+        }
+    }
+
+    pub fn failed(&self) -> bool {
+        matches!(self.status, Status::Failed(_))
+    }
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.status {
+            Status::Passed(details) => write!(f, "[ ok ] {}: {}", self.name, details),
+            Status::Failed(e) => write!(f, "[FAIL] {}: {}", self.name, e),
+            Status::Skipped(reason) => write!(f, "[skip] {}: {}", self.name, reason),
+        }
+    }
+}
+
+/// Checks the environment of validations with the checker in the theory roots, the first being the one
+/// containing the Isabelle SMT theories, with the spec of the theory roots or the built-in one.
+/// The Isabelle installation and the heap image are only checked for the Isabelle checkers.
+pub fn check(
+    theory_roots: &[PathBuf],
+    checker: CheckerKind,
+    builtin_spec: Option<BuiltinSpec>,
+) -> Vec<Step> {
+    let mut steps = vec![];
+    let isabelle = checker.target() == crate::convert::TargetKind::Isabelle;
+    if isabelle {
+        steps.extend(check_isabelle());
+    }
+    let root = Step::new(
+        "theory root",
+        match theory_roots.iter().find(|r| !r.is_dir()) {
+            Some(r) => Err(Error::Other(format!("{} is not a directory", r.display()))),
+            None if theory_roots.is_empty() => Err(Error::Other(
+                "no theory root given with -T, and none found in $ISABELLE_SMT or ~/isabelle_smt"
+                    .to_owned(),
+            )),
+            None => Ok(theory_roots
+                .iter()
+                .map(|r| r.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")),
+        },
+    );
+    let root_found = !root.failed();
+    steps.push(root);
+    steps.push(if root_found {
+        Step::new("spec", check_spec(theory_roots, checker, builtin_spec))
+    } else {
+        Step::skipped("spec", "the theory root was not found")
+    });
+
+    if isabelle {
+        let installed = !steps[0].failed();
+        steps.push(if installed && root_found {
+            let session_dirs: Vec<String> = theory_roots
+                .iter()
+                .map(|r| r.display().to_string())
+                .collect();
+            Step::new(
+                "heap image",
+                heap::build(&session_dirs, false)
+                    .and_then(|()| heap::check(&session_dirs))
+                    .map(|()| format!("session {} is built", heap::SESSION)),
+            )
+        } else if installed {
+            Step::skipped("heap image", "the theory root was not found")
+        } else {
+            Step::skipped("heap image", "Isabelle was not found")
+        });
+    }
+
+    steps.push(if steps.iter().any(Step::failed) {
+        Step::skipped("round trip", "a previous step failed")
+    } else {
+        Step::new(
+            "round trip",
+            round_trip(theory_roots, checker, builtin_spec),
+        )
+    });
+    steps
+}
+
+/// Checks the Isabelle installation and its release.
+fn check_isabelle() -> Vec<Step> {
+    let installation = Step::new(
+        "Isabelle installation",
+        isabelle_env::executable().map(|e| e.display().to_string()),
+    );
+    if installation.failed() {
+        return vec![
+            installation,
+            Step::skipped("Isabelle version", "Isabelle was not found"),
+        ];
+    }
+    let version = Step::new(
+        "Isabelle version",
+        isabelle_env::check_version().map(|v| match v {
+            Some(v) => v.to_string(),
+            None => "unknown (e.g. a repository snapshot), assuming it is supported".to_owned(),
+        }),
+    );
+    vec![installation, version]
+}
+
+/// Loads the spec of the theory roots (or the built-in one) for the language of the checker,
+/// and checks that it fits the theories. Returns the spec file.
+fn check_spec(
+    theory_roots: &[PathBuf],
+    checker: CheckerKind,
+    builtin_spec: Option<BuiltinSpec>,
+) -> Result<String, Error> {
+    let target = checker.target();
+    let (mut converter, name) = match builtin_spec {
+        Some(builtin) => (
+            Converter::builtin_for(builtin, target)?,
+            format!("built-in spec for {:?}", builtin),
+        ),
+        None => {
+            let file = spec::find_target_spec_file(&theory_roots[0], target);
+            if !file.is_file() {
+                return Err(Error::Other(format!("{} does not exist", file.display())));
+            }
+            let mut converter = Converter::from_spec_file(&file)?;
+            converter.target(target);
+            (converter, file.display().to_string())
+        }
+    };
+    converter.check_theory_root(&theory_roots[0])?;
+    for root in &theory_roots[1..] {
+        converter.add_theory_root(root)?;
+    }
+    Ok(name)
+}
+
+/// Validates a model of a trivial formula with the checker, which has to find it valid.
+fn round_trip(
+    theory_roots: &[PathBuf],
+    checker: CheckerKind,
+    builtin_spec: Option<BuiltinSpec>,
+) -> Result<String, Error> {
+    let options = ValidationOptions {
+        checker,
+        target: checker.target(),
+        builtin_spec,
+        theory_roots: theory_roots[1..].to_vec(),
+        ..Default::default()
+    };
+    let start = Instant::now();
+    let result = validation::validate(
+        ROUND_TRIP_MODEL.to_owned(),
+        ROUND_TRIP_FORMULA.to_owned(),
+        &theory_roots[0],
+        &options,
+    )?;
+    match result {
+        ValidationResult::Valid => Ok(format!(
+            "the {:?} checker proved a trivial lemma in {:.1}s",
+            checker,
+            start.elapsed().as_secs_f64()
+        )),
+        ValidationResult::Unknown(reason) => Err(Error::Other(format!(
+            "the {:?} checker could not prove a trivial lemma ({})",
+            checker, reason
+        ))),
+        ValidationResult::Invalid => Err(Error::Other(format!(
+            "the {:?} checker refuted a trivial lemma",
+            checker
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_steps() {
+        let passed = Step::new("Isabelle version", Ok("Isabelle2024".to_owned()));
+        assert_eq!(passed.to_string(), "[ ok ] Isabelle version: Isabelle2024");
+        let failed = Step::new(
+            "Isabelle installation",
+            Err(Error::IsabelleNotFound("set ISABELLE_HOME".to_owned())),
+        );
+        assert!(failed.failed());
+        assert_eq!(
+            failed.to_string(),
+            "[FAIL] Isabelle installation: Isabelle not found: set ISABELLE_HOME"
+        );
+        let skipped = Step::skipped("heap image", "the theory root was not found");
+        assert!(!skipped.failed());
+        assert_eq!(
+            skipped.to_string(),
+            "[skip] heap image: the theory root was not found"
+        );
+    }
+
+    #[test]
+    fn missing_spec() {
+        let root = tempfile::tempdir().unwrap();
+        let steps = check(&[root.path().to_owned()], CheckerKind::Coq, None);
+        let names: Vec<&str> = steps.iter().map(|s| s.name).collect();
+        assert_eq!(names, ["theory root", "spec", "round trip"]);
+        assert!(matches!(steps[0].status, Status::Passed(_)));
+        assert!(steps[1].failed());
+        assert!(steps[1]
+            .to_string()
+            .contains("coq_spec.json does not exist"));
+        assert!(matches!(steps[2].status, Status::Skipped(_)));
+
+        let steps = check(
+            &[root.path().join("missing")],
+            CheckerKind::Coq,
+            Some(BuiltinSpec::QfSlia),
+        );
+        assert!(steps[0].failed());
+        assert!(matches!(steps[1].status, Status::Skipped(_)));
+    }
+}
@@ -0,0 +1,190 @@
+//! Differential testing of several SMT solvers on the same formula.
+//!
+//! All solvers run concurrently on the formula. The models of those answering `sat` are then validated
+//! one after another, reusing one checker, and the answers and verdicts are compared:
+//! a model that is invalid, or a solver answering `unsat` while another finds a valid model, is a disagreement.
+
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::error::Error;
+use crate::solver::{self, Solver, SolverAnswer};
+use crate::validation::{self, ValidationOptions, ValidationResult};
+
+/// A solver to run, with the executable to use instead of the one on the `PATH`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SolverConfig {
+    pub solver: Solver,
+    pub executable: Option<PathBuf>,
+}
+
+/// The outcome of running a solver and validating its model
+#[derive(Debug)]
+pub struct SolverRun {
+    pub solver: Solver,
+    /// The solver's answer, or why it could not be obtained
+    pub answer: Result<SolverAnswer, Error>,
+    /// The validation of the model, if the solver answered `sat`
+    pub validation: Option<Result<ValidationResult, Error>>,
+}
+
+impl SolverRun {
+    /// Returns the verdict of the validation, or `-` if the model was not validated.
+    pub fn verdict(&self) -> &'static str {
+        match &self.validation {
+            None => "-",
+            Some(Ok(r)) => r.verdict(),
+            Some(Err(_)) => "error",
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        matches!(self.validation, Some(Ok(ValidationResult::Valid)))
+    }
+}
+
+/// A disagreement between the solvers, or between a solver and the validation of its model
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Disagreement {
+    /// The solver answered `sat` with an invalid model
+    InvalidModel(Solver),
+    /// The first solver answered `sat` and the second `unsat`; true iff the model of the first is valid
+    Conflict(Solver, Solver, bool),
+}
+
+impl Display for Disagreement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Disagreement::InvalidModel(s) => write!(f, "{} answered sat with an invalid model", s),
+            Disagreement::Conflict(sat, unsat, true) => write!(
+                f,
+                "{} answered unsat, but the model of {} is valid",
+                unsat, sat
+            ),
+            Disagreement::Conflict(sat, unsat, false) => {
+                write!(f, "{} answered sat and {} answered unsat", sat, unsat)
+            }
+        }
+    }
+}
+
+/// Runs the solvers on the formula and validates the models they find, in the order of the solvers.
+pub fn cross_check(
+    solvers: &[SolverConfig],
+    formula: &str,
+    theory_path: &Path,
+    options: &ValidationOptions,
+) -> Vec<SolverRun> {
+    let answers: Vec<Result<SolverAnswer, Error>> = thread::scope(|s| {
+        let handles: Vec<_> = solvers
+            .iter()
+            .map(|c| {
+                s.spawn(|| {
+                    solver::solve(
+                        c.solver,
+                        c.executable.as_deref(),
+                        formula,
+                        options.check_index,
+                    )
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("Solver thread panicked"))
+            .collect()
+    });
+    let mut checker = None;
+    solvers
+        .iter()
+        .zip(answers)
+        .map(|(config, answer)| {
+            let validation = match &answer {
+                Ok(SolverAnswer::Sat(model)) => {
+                    log::info!("Validating the model of {}", config.solver);
+                    let (result, _) = validation::validate_reusing(
+                        model.clone(),
+                        formula.to_owned(),
+                        theory_path,
+                        options,
+                        &mut checker,
+                    );
+                    Some(result)
+                }
+                _ => None,
+            };
+            SolverRun {
+                solver: config.solver,
+                answer,
+                validation,
+            }
+        })
+        .collect()
+}
+
+/// Returns the disagreements among the runs.
+/// A solver answering `sat` with an invalid model is wrong regardless of the other answers.
+/// Otherwise, each pair of solvers answering `sat` and `unsat` disagrees.
+pub fn disagreements(runs: &[SolverRun]) -> Vec<Disagreement> {
+    let mut res = vec![];
+    let answered = |answer: fn(&SolverAnswer) -> bool| {
+        runs.iter()
+            .filter(move |r| r.answer.as_ref().is_ok_and(answer))
+    };
+    for sat in answered(|a| matches!(a, SolverAnswer::Sat(_))) {
+        if matches!(sat.validation, Some(Ok(ValidationResult::Invalid))) {
+            res.push(Disagreement::InvalidModel(sat.solver));
+            continue;
+        }
+        for unsat in answered(|a| *a == SolverAnswer::Unsat) {
+            res.push(Disagreement::Conflict(
+                sat.solver,
+                unsat.solver,
+                sat.is_valid(),
+            ));
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(
+        solver: Solver,
+        answer: SolverAnswer,
+        validation: Option<ValidationResult>,
+    ) -> SolverRun {
+        SolverRun {
+            solver,
+            answer: Ok(answer),
+            validation: validation.map(Ok),
+        }
+    }
+
+    #[test]
+    fn find_disagreements() {
+        let sat = || SolverAnswer::Sat(String::new());
+        let runs = [
+            run(Solver::Z3, sat(), Some(ValidationResult::Valid)),
+            run(Solver::Cvc5, SolverAnswer::Unsat, None),
+            run(Solver::Yices, sat(), Some(ValidationResult::Invalid)),
+            run(Solver::Bitwuzla, SolverAnswer::Unknown, None),
+        ];
+        assert_eq!(
+            disagreements(&runs),
+            [
+                Disagreement::Conflict(Solver::Z3, Solver::Cvc5, true),
+                Disagreement::InvalidModel(Solver::Yices),
+            ]
+        );
+        assert_eq!(
+            disagreements(&runs)[0].to_string(),
+            "cvc5 answered unsat, but the model of z3 is valid"
+        );
+        assert!(disagreements(&runs[..1]).is_empty());
+        assert_eq!(runs[1].verdict(), "-");
+    }
+}
@@ -1,27 +1,188 @@
 use std::fmt::{Display, Formatter};
 
 /// Error type
-#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, thiserror::Error)]
 pub enum Error {
     /// The SMT-LIB function is not supported by the Isabelle SMT theories.
-    Unsupported(String),
+    /// The context tells where the function occurs, as far as known.
+    #[error("Unsupported SMT-LIB function: {0}{1}")]
+    Unsupported(String, Box<UnsupportedContext>),
     /// Error while parsing the model.
-    ParseError(smt2parser::Error),
-    /// Error while checking the lemma in Isabelle.
-    IsabelleError,
+    #[error("Parse error: {0}")]
+    ParseError(#[source] smt2parser::Error),
+    /// Isabelle failed in the given phase.
+    #[error("{0}")]
+    IsabelleError(IsabelleFailure),
+    /// No Isabelle installation was found.
+    #[error("Isabelle not found: {0}")]
+    IsabelleNotFound(String),
+    /// The installed Isabelle release is not supported by this tool.
+    #[error("Unsupported Isabelle release: {0}; select a supported installation with --isabelle-path or ISABELLE_HOME")]
+    UnsupportedIsabelle(String),
+    /// The heap image of the Isabelle session is missing or outdated.
+    #[error("Isabelle heap image: {0}, rebuild it with 'smtmv build-heap'")]
+    HeapImage(String),
+    /// The SMT-LIB function (first) is mapped by a theory outside the logic (second) declared with `set-logic`.
+    #[error("operator {0} not in logic {1}")]
+    NotInLogic(String, String),
+    /// The spec cannot be read or does not follow the schema of specs.
+    #[error("Invalid spec: {0}")]
+    InvalidSpec(String),
+    /// The spec, the theories, and this tool are not compatible with each other.
+    #[error("Incompatible versions: {0}")]
+    IncompatibleVersion(String),
     /// Other error.
+    #[error("Error: {0}")]
     Other(String),
 }
 
-impl Display for Error {
+/// The phase in which Isabelle failed.
+/// Checks that do not finish in time are not failures but [unknown](crate::UnknownReason::Timeout) results.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IsabelleFailure {
+    /// The Isabelle process, server, or session could not be started
+    Startup,
+    /// The heap image of the session could not be built
+    Build,
+    /// Isabelle failed while checking the validation theory, for another reason than a failed proof
+    Proof,
+    /// The connection to the Isabelle server was lost while checking a theory
+    Connection,
+}
+
+impl Display for IsabelleFailure {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Error::Unsupported(s) => write!(f, "Unsupported SMT-LIB function: {}", s),
-            Error::ParseError(e) => write!(f, "Parse error: {}", e),
-            Error::Other(s) => write!(f, "Error: {}", s),
-            Error::IsabelleError => {
+            IsabelleFailure::Startup => {
+                write!(f, "Isabelle could not be started (see logs for details)")
+            }
+            IsabelleFailure::Build => write!(
+                f,
+                "Isabelle failed to build the session (see logs for details)"
+            ),
+            IsabelleFailure::Proof => {
                 write!(f, "Isabelle failed to check proof (see logs for details)")
             }
+            IsabelleFailure::Connection => write!(
+                f,
+                "The connection to the Isabelle server failed (see logs for details)"
+            ),
+        }
+    }
+}
+
+/// Where an unsupported SMT-LIB function occurs in the input
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UnsupportedContext {
+    /// The innermost application of the function
+    pub term: Option<String>,
+    /// The index of the assertion among the assertions of the input, counting from 0
+    pub assertion: Option<usize>,
+    /// The position of the assertion in the input
+    pub position: Option<SourcePosition>,
+}
+
+impl Display for UnsupportedContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(term) = &self.term {
+            write!(f, " in {}", term)?;
+        }
+        if let Some(assertion) = self.assertion {
+            write!(f, " (assertion {}", assertion)?;
+            if let Some(position) = self.position {
+                write!(f, " at {}", position)?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// Who has to act on an error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// The formula, the model, or the arguments are malformed or not supported
+    Input,
+    /// The spec is invalid or does not fit the theories or this tool
+    Spec,
+    /// Isabelle is missing, misconfigured, or failed
+    Isabelle,
+}
+
+/// A position in the SMT-LIB input, counting lines and columns from 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourcePosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for SourcePosition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+impl Error {
+    /// Returns the error for an unsupported SMT-LIB function, without context.
+    pub(crate) fn unsupported(op: impl Into<String>) -> Self {
+        Error::Unsupported(op.into(), Box::default())
+    }
+
+    /// Returns whether the error is caused by the input, the spec, or Isabelle.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::InvalidSpec(_) | Error::IncompatibleVersion(_) => ErrorCategory::Spec,
+            Error::IsabelleError(_)
+            | Error::IsabelleNotFound(_)
+            | Error::UnsupportedIsabelle(_)
+            | Error::HeapImage(_) => ErrorCategory::Isabelle,
+            Error::Unsupported(..)
+            | Error::ParseError(_)
+            | Error::NotInLogic(..)
+            | Error::Other(_) => ErrorCategory::Input,
         }
     }
+
+    /// Returns the position in the input the error refers to, if known.
+    pub fn position(&self) -> Option<SourcePosition> {
+        match self {
+            Error::ParseError(
+                smt2parser::Error::SyntaxError(p, _) | smt2parser::Error::ParsingError(p, _),
+            ) => Some(SourcePosition {
+                line: p.line,
+                column: p.column,
+            }),
+            Error::Unsupported(_, context) => context.position,
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smt2parser::{concrete, CommandStream};
+
+    #[test]
+    fn error_details() {
+        let e = CommandStream::new(
+            "(assert true)\n(assert (".as_bytes(),
+            concrete::SyntaxBuilder,
+            None,
+        )
+        .find_map(|c| c.err())
+        .map(Error::ParseError)
+        .unwrap();
+        assert_eq!(e.category(), ErrorCategory::Input);
+        assert_eq!(e.position().map(|p| p.line), Some(2));
+        assert!(std::error::Error::source(&e).is_some());
+
+        let e = Error::IsabelleError(IsabelleFailure::Build);
+        assert_eq!(e.category(), ErrorCategory::Isabelle);
+        let e = Error::UnsupportedIsabelle("Isabelle2021".to_owned());
+        assert_eq!(e.category(), ErrorCategory::Isabelle);
+        assert!(e.to_string().contains("--isabelle-path"));
+        assert_eq!(e.position(), None);
+    }
 }
@@ -0,0 +1,755 @@
+//! Evaluation of formulas under ground models, without Isabelle.
+//!
+//! Most models assign a literal to every variable. The formula can then be decided by evaluating its assertions
+//! with the SMT-LIB semantics of the Core, Ints, and Strings theories, which takes microseconds instead of starting Isabelle.
+//! Regular membership constraints are decided with the [regular expressions](crate::reglan) of the Strings theory.
+//! Terms outside this fragment, e.g. quantifiers, sequences, or integers exceeding 128 bits,
+//! cannot be evaluated; the lemma is then checked by Isabelle as usual.
+
+use std::collections::HashMap;
+
+use smt2parser::concrete::{Command, Constant, QualIdentifier, Term};
+use smt2parser::visitors::{Identifier, Index};
+use smt2parser::{concrete, CommandStream};
+
+use crate::convert;
+use crate::reglan::Regex;
+use crate::scopes;
+
+/// The largest code point of an SMT-LIB string character
+pub(crate) const MAX_CODE_POINT: u32 = 0x2FFFF;
+
+/// The maximal nesting of function applications, guarding against functions defined in terms of themselves
+const MAX_DEPTH: usize = 100;
+
+/// The outcome of evaluating a formula under a model
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Evaluation {
+    /// All assertions evaluate to true
+    Valid,
+    /// The assertion with the given index (counting from 0) evaluates to false
+    Invalid(usize),
+    /// Some assertion could not be evaluated, for the given reason
+    Undecided(String),
+}
+
+/// A value of an evaluated term
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Value {
+    Bool(bool),
+    Int(i128),
+    /// A string as its code points, which may include surrogates
+    String(Vec<u32>),
+    RegLan(Regex),
+}
+
+/// The result of evaluating a term, or why it cannot be evaluated
+type Eval<T> = Result<T, String>;
+
+/// A function defined with `define-fun` in the formula or the model
+struct Function {
+    parameters: Vec<String>,
+    body: Term,
+}
+
+/// Evaluates the assertions live at the `check-sat` with the given index (the last one if None)
+/// under the model, given as a sequence of `define-fun` commands.
+/// Unlike the checkers, the evaluation follows the SMT-LIB semantics directly instead of the Isabelle theories.
+pub fn evaluate(formula: &str, model: &str, check_index: Option<usize>) -> Evaluation {
+    let parse = |input: &str| {
+        CommandStream::new(
+            convert::preprocess(input).as_bytes(),
+            concrete::SyntaxBuilder,
+            None,
+        )
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+    };
+    let commands = match parse(formula).and_then(|commands| {
+        scopes::live_commands(commands, check_index).map_err(|e| e.to_string())
+    }) {
+        Ok(c) => c,
+        Err(e) => return Evaluation::Undecided(e),
+    };
+    let model = match parse(model) {
+        Ok(m) => m,
+        Err(e) => return Evaluation::Undecided(e),
+    };
+
+    let mut evaluator = Evaluator::default();
+    for command in model.iter().chain(&commands) {
+        if let Command::DefineFun { sig, term } = command {
+            evaluator.functions.insert(
+                sig.name.0.clone(),
+                Function {
+                    parameters: sig.parameters.iter().map(|(p, _)| p.0.clone()).collect(),
+                    body: term.clone(),
+                },
+            );
+        }
+    }
+    let mut undecided = None;
+    let assertions = commands.iter().filter_map(|c| match c {
+        Command::Assert { term } => Some(term),
+        _ => None,
+    });
+    for (index, assertion) in assertions.enumerate() {
+        match evaluator.eval(assertion, &mut vec![]) {
+            Ok(Value::Bool(true)) => (),
+            Ok(Value::Bool(false)) => return Evaluation::Invalid(index),
+            Ok(v) => {
+                undecided
+                    .get_or_insert_with(|| format!("assertion {} is not Boolean: {:?}", index, v));
+            }
+            Err(reason) => {
+                undecided.get_or_insert(reason);
+            }
+        }
+    }
+    match undecided {
+        Some(reason) => Evaluation::Undecided(reason),
+        None => Evaluation::Valid,
+    }
+}
+
+#[derive(Default)]
+struct Evaluator {
+    functions: HashMap<String, Function>,
+    depth: usize,
+}
+
+impl Evaluator {
+    /// Evaluates the term with the values of the bound variables in `env`, innermost last.
+    fn eval(&mut self, term: &Term, env: &mut Vec<(String, Value)>) -> Eval<Value> {
+        match term {
+            Term::Constant(c) => constant(c),
+            Term::QualIdentifier(identifier) => {
+                let (op, indices) = name(identifier)?;
+                self.apply(&op, &indices, &[], env)
+            }
+            Term::Application {
+                qual_identifier,
+                arguments,
+            } => {
+                let (op, indices) = name(qual_identifier)?;
+                self.apply(&op, &indices, arguments, env)
+            }
+            Term::Let { var_bindings, term } => {
+                // All variables are bound in parallel
+                let mut values = vec![];
+                for (symbol, t) in var_bindings {
+                    values.push((symbol.0.clone(), self.eval(t, env)?));
+                }
+                let len = env.len();
+                env.extend(values);
+                let value = self.eval(term, env);
+                env.truncate(len);
+                value
+            }
+            Term::Attributes { term, .. } => self.eval(term, env),
+            Term::Forall { .. } | Term::Exists { .. } => {
+                Err("quantifiers are not evaluated".to_owned())
+            }
+            Term::Match { .. } => Err("match is not evaluated".to_owned()),
+        }
+    }
+
+    /// Evaluates the application of the function, with the given indices, to the arguments.
+    fn apply(
+        &mut self,
+        op: &str,
+        indices: &[u32],
+        args: &[Term],
+        env: &mut Vec<(String, Value)>,
+    ) -> Eval<Value> {
+        if args.is_empty() && indices.is_empty() {
+            if let Some((_, v)) = env.iter().rev().find(|(n, _)| n == op) {
+                return Ok(v.clone());
+            }
+        }
+        // Connectives are evaluated lazily, so that a single false conjunct decides the conjunction
+        match op {
+            "and" => return self.connective(args, env, false, |_| false),
+            "or" => return self.connective(args, env, true, |_| false),
+            "=>" => return self.connective(args, env, true, |i| i + 1 < args.len()),
+            "ite" if args.len() == 3 => {
+                let branch = if bool(&self.eval(&args[0], env)?)? {
+                    &args[1]
+                } else {
+                    &args[2]
+                };
+                return self.eval(branch, env);
+            }
+            _ => (),
+        }
+        let mut values = vec![];
+        for a in args {
+            values.push(self.eval(a, env)?);
+        }
+        if let Some(v) = builtin(op, indices, &values) {
+            return v;
+        }
+        let Some(function) = self.functions.get(op) else {
+            return Err(format!("no value for {}", op));
+        };
+        if function.parameters.len() != values.len() {
+            return Err(format!("wrong number of arguments of {}", op));
+        }
+        if self.depth >= MAX_DEPTH {
+            return Err(format!("too deeply nested applications of {}", op));
+        }
+        let body = function.body.clone();
+        let mut bound: Vec<(String, Value)> =
+            function.parameters.iter().cloned().zip(values).collect();
+        self.depth += 1;
+        let value = self.eval(&body, &mut bound);
+        self.depth -= 1;
+        value
+    }
+
+    /// Evaluates a disjunction (or, if `decisive` is false, a conjunction) of the arguments,
+    /// stopping at the first argument with the decisive value.
+    /// `negated` tells which arguments are negated, e.g. the premises of an implication.
+    fn connective(
+        &mut self,
+        args: &[Term],
+        env: &mut Vec<(String, Value)>,
+        decisive: bool,
+        negated: impl Fn(usize) -> bool,
+    ) -> Eval<Value> {
+        let mut undecided = None;
+        for (i, a) in args.iter().enumerate() {
+            match self.eval(a, env).and_then(|v| bool(&v)) {
+                Ok(b) if (b != negated(i)) == decisive => return Ok(Value::Bool(decisive)),
+                Ok(_) => (),
+                Err(e) => {
+                    undecided.get_or_insert(e);
+                }
+            }
+        }
+        match undecided {
+            Some(e) => Err(e),
+            None => Ok(Value::Bool(!decisive)),
+        }
+    }
+}
+
+/// Returns the name and the numeral indices of the identifier, which must not be sorted.
+fn name(identifier: &QualIdentifier) -> Eval<(String, Vec<u32>)> {
+    let not_evaluated = || format!("{} is not evaluated", identifier);
+    match identifier {
+        QualIdentifier::Simple {
+            identifier: Identifier::Simple { symbol },
+        } => Ok((symbol.0.clone(), vec![])),
+        QualIdentifier::Simple {
+            identifier: Identifier::Indexed { symbol, indices },
+        } => {
+            let indices = indices
+                .iter()
+                .map(|i| match i {
+                    Index::Numeral(n) => n.to_string().parse().map_err(|_| not_evaluated()),
+                    Index::Symbol(_) => Err(not_evaluated()),
+                })
+                .collect::<Eval<_>>()?;
+            Ok((symbol.0.clone(), indices))
+        }
+        QualIdentifier::Sorted { .. } => Err(not_evaluated()),
+    }
+}
+
+fn constant(c: &Constant) -> Eval<Value> {
+    match c {
+        Constant::Numeral(n) => n
+            .to_string()
+            .parse()
+            .map(Value::Int)
+            .map_err(|_| format!("numeral {} is too large", n)),
+        Constant::String(s) => Ok(Value::String(unescape(s))),
+        c => Err(format!("constant {} is not evaluated", c)),
+    }
+}
+
+/// Returns the code points of an SMT-LIB string literal, resolving the `\u` escape sequences.
+/// Backslashes not starting a valid escape sequence are kept as they are.
+fn unescape(s: &str) -> Vec<u32> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut res = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        if let Some((code, len)) = escape_sequence(&chars[i..]) {
+            res.push(code);
+            i += len;
+        } else {
+            res.push(chars[i] as u32);
+            i += 1;
+        }
+    }
+    res
+}
+
+/// Returns the code point and the length of the escape sequence at the start of `chars`, if any:
+/// `\ud₃d₂d₁d₀` or `\u{d₀}` to `\u{d₄d₃d₂d₁d₀}` with hexadecimal digits, up to code point 2FFFF.
+pub(crate) fn escape_sequence(chars: &[char]) -> Option<(u32, usize)> {
+    let rest = chars.strip_prefix(&['\\', 'u'])?;
+    let hex = |digits: &[char]| {
+        let digits: String = digits.iter().collect();
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .filter(|_| digits.chars().all(|c| c.is_ascii_hexdigit()))
+    };
+    if let Some(braced) = rest.strip_prefix(&['{']) {
+        let close = braced.iter().position(|&c| c == '}')?;
+        let code =
+            hex(&braced[..close]).filter(|&c| (1..=5).contains(&close) && c <= MAX_CODE_POINT)?;
+        Some((code, close + 4))
+    } else {
+        Some((hex(rest.get(..4)?)?, 6))
+    }
+}
+
+fn bool(v: &Value) -> Eval<bool> {
+    match v {
+        Value::Bool(b) => Ok(*b),
+        v => Err(format!("expected a Boolean, got {:?}", v)),
+    }
+}
+
+fn int(v: &Value) -> Eval<i128> {
+    match v {
+        Value::Int(i) => Ok(*i),
+        v => Err(format!("expected an integer, got {:?}", v)),
+    }
+}
+
+fn string(v: &Value) -> Eval<&[u32]> {
+    match v {
+        Value::String(s) => Ok(s),
+        v => Err(format!("expected a string, got {:?}", v)),
+    }
+}
+
+fn reglan(v: &Value) -> Eval<&Regex> {
+    match v {
+        Value::RegLan(r) => Ok(r),
+        v => Err(format!("expected a regular expression, got {:?}", v)),
+    }
+}
+
+fn overflow<T>(v: Option<T>) -> Eval<T> {
+    v.ok_or_else(|| "integer overflow".to_owned())
+}
+
+/// Returns the position of the first occurrence of `needle` in `s` at or after `from`.
+fn find(s: &[u32], needle: &[u32], from: usize) -> Option<usize> {
+    if needle.is_empty() {
+        return (from <= s.len()).then_some(from);
+    }
+    s.get(from..)?
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|i| i + from)
+}
+
+/// Converts an SMT-LIB string index to an index into `s`, if it is in range.
+fn index(s: &[u32], i: i128) -> Option<usize> {
+    usize::try_from(i).ok().filter(|&i| i < s.len())
+}
+
+/// Applies the built-in function with the given indices to the values, or returns None if the function is not built in.
+fn builtin(op: &str, indices: &[u32], args: &[Value]) -> Option<Eval<Value>> {
+    if !indices.is_empty() && !matches!(op, "re.loop" | "re.^" | "Char") {
+        return Some(Err(format!("indexed {} is not evaluated", op)));
+    }
+    let ints = || args.iter().map(int).collect::<Eval<Vec<_>>>();
+    let strings = || args.iter().map(string).collect::<Eval<Vec<_>>>();
+    // Chainable comparisons hold for all adjacent pairs
+    let chain = |cmp: fn(&i128, &i128) -> bool| {
+        ints().map(|v| Value::Bool(v.windows(2).all(|p| cmp(&p[0], &p[1]))))
+    };
+    let unary = |f: fn(&Value) -> Eval<Value>| match args {
+        [a] => f(a),
+        _ => Err(format!("{} expects one argument", op)),
+    };
+    let binary = |f: fn(&Value, &Value) -> Eval<Value>| match args {
+        [a, b] => f(a, b),
+        _ => Err(format!("{} expects two arguments", op)),
+    };
+    let ternary = |f: fn(&Value, &Value, &Value) -> Eval<Value>| match args {
+        [a, b, c] => f(a, b, c),
+        _ => Err(format!("{} expects three arguments", op)),
+    };
+    Some(match op {
+        "true" if args.is_empty() => Ok(Value::Bool(true)),
+        "false" if args.is_empty() => Ok(Value::Bool(false)),
+        "not" => unary(|a| Ok(Value::Bool(!bool(a)?))),
+        "xor" => args
+            .iter()
+            .map(bool)
+            .try_fold(false, |acc, b| Ok(acc != b?))
+            .map(Value::Bool),
+        // Equal regular expressions may differ syntactically
+        "=" | "distinct" if args.iter().any(|a| matches!(a, Value::RegLan(_))) => {
+            Err("equality of regular expressions is not evaluated".to_owned())
+        }
+        "=" => Ok(Value::Bool(args.windows(2).all(|p| p[0] == p[1]))),
+        "distinct" => Ok(Value::Bool(
+            args.iter()
+                .enumerate()
+                .all(|(i, a)| args[i + 1..].iter().all(|b| a != b)),
+        )),
+
+        // Ints
+        "+" => ints()
+            .and_then(|v| overflow(v.into_iter().try_fold(0i128, i128::checked_add)))
+            .map(Value::Int),
+        "*" => ints()
+            .and_then(|v| overflow(v.into_iter().try_fold(1i128, i128::checked_mul)))
+            .map(Value::Int),
+        "-" => ints()
+            .and_then(|v| match v.split_first() {
+                Some((a, [])) => overflow(a.checked_neg()),
+                Some((a, rest)) => overflow(rest.iter().try_fold(*a, |acc, b| acc.checked_sub(*b))),
+                None => Err("- expects arguments".to_owned()),
+            })
+            .map(Value::Int),
+        "div" | "mod" => ints().and_then(|v| match v.as_slice() {
+            // Division by zero is unspecified in SMT-LIB, so any value is possible
+            [_, 0] => Err("division by zero".to_owned()),
+            [a, b] if op == "div" => overflow(a.checked_div_euclid(*b)).map(Value::Int),
+            [a, b] => overflow(a.checked_rem_euclid(*b)).map(Value::Int),
+            _ => Err(format!("{} expects two arguments", op)),
+        }),
+        "abs" => unary(|a| overflow(int(a)?.checked_abs()).map(Value::Int)),
+        "<" => chain(i128::lt),
+        "<=" => chain(i128::le),
+        ">" => chain(i128::gt),
+        ">=" => chain(i128::ge),
+
+        // Strings
+        "str.++" => strings().map(|v| Value::String(v.concat())),
+        "str.len" => unary(|s| Ok(Value::Int(string(s)?.len() as i128))),
+        "str.<" | "str.<=" => strings().map(|v| {
+            Value::Bool(v.windows(2).all(|p| {
+                if op == "str.<" {
+                    p[0] < p[1]
+                } else {
+                    p[0] <= p[1]
+                }
+            }))
+        }),
+        "str.at" => binary(|s, i| {
+            let s = string(s)?;
+            Ok(Value::String(
+                index(s, int(i)?).map_or(vec![], |i| vec![s[i]]),
+            ))
+        }),
+        "str.substr" => ternary(|s, i, n| {
+            let (s, n) = (string(s)?, int(n)?);
+            Ok(Value::String(match index(s, int(i)?) {
+                Some(i) if n > 0 => {
+                    let end =
+                        usize::try_from(n).map_or(s.len(), |n| s.len().min(i.saturating_add(n)));
+                    s[i..end].to_vec()
+                }
+                _ => vec![],
+            }))
+        }),
+        "str.prefixof" => binary(|p, s| Ok(Value::Bool(string(s)?.starts_with(string(p)?)))),
+        "str.suffixof" => binary(|p, s| Ok(Value::Bool(string(s)?.ends_with(string(p)?)))),
+        "str.contains" => binary(|s, t| Ok(Value::Bool(find(string(s)?, string(t)?, 0).is_some()))),
+        "str.indexof" => ternary(|s, t, i| {
+            let (s, t, i) = (string(s)?, string(t)?, int(i)?);
+            let found = usize::try_from(i).ok().and_then(|i| find(s, t, i));
+            Ok(Value::Int(found.map_or(-1, |i| i as i128)))
+        }),
+        "str.replace" => ternary(|s, t, u| {
+            let (s, t, u) = (string(s)?, string(t)?, string(u)?);
+            Ok(Value::String(match find(s, t, 0) {
+                Some(i) => [&s[..i], u, &s[i + t.len()..]].concat(),
+                None => s.to_vec(),
+            }))
+        }),
+        "str.replace_all" => ternary(|s, t, u| {
+            let (s, t, u) = (string(s)?, string(t)?, string(u)?);
+            if t.is_empty() {
+                return Ok(Value::String(s.to_vec()));
+            }
+            let mut res = vec![];
+            let mut start = 0;
+            while let Some(i) = find(s, t, start) {
+                res.extend_from_slice(&s[start..i]);
+                res.extend_from_slice(u);
+                start = i + t.len();
+            }
+            res.extend_from_slice(&s[start..]);
+            Ok(Value::String(res))
+        }),
+        "str.replace_re" => ternary(|s, r, t| {
+            let (s, t) = (string(s)?, string(t)?);
+            Ok(Value::String(match reglan(r)?.find(s, 0, false)? {
+                Some((i, j)) => [&s[..i], t, &s[j..]].concat(),
+                None => s.to_vec(),
+            }))
+        }),
+        "str.replace_re_all" => ternary(|s, r, t| {
+            let (s, r, t) = (string(s)?, reglan(r)?, string(t)?);
+            // Empty matches are not replaced, otherwise the replacement would not end
+            let mut res = vec![];
+            let mut start = 0;
+            while let Some((i, j)) = r.find(s, start, true)? {
+                res.extend_from_slice(&s[start..i]);
+                res.extend_from_slice(t);
+                start = j;
+            }
+            res.extend_from_slice(&s[start..]);
+            Ok(Value::String(res))
+        }),
+        // Characters of z3, see desugar::SEQUENCE_FUNCTIONS
+        "Char" => match (indices, args) {
+            ([c], []) if *c <= MAX_CODE_POINT => Ok(Value::String(vec![*c])),
+            _ => Err(format!("invalid character {:?}", indices)),
+        },
+        "seq.unit" => unary(|c| match string(c)? {
+            [c] => Ok(Value::String(vec![*c])),
+            _ => Err("seq.unit of a string that is not a character".to_owned()),
+        }),
+        "str.is_digit" => unary(|s| {
+            Ok(Value::Bool(
+                matches!(string(s)?, [c] if (0x30..=0x39).contains(c)),
+            ))
+        }),
+        "str.to_code" => unary(|s| {
+            Ok(Value::Int(match string(s)? {
+                [c] => *c as i128,
+                _ => -1,
+            }))
+        }),
+        "str.from_code" => unary(|n| {
+            let n = int(n)?;
+            Ok(Value::String(match u32::try_from(n) {
+                Ok(c) if c <= MAX_CODE_POINT => vec![c],
+                _ => vec![],
+            }))
+        }),
+        "str.to_int" => unary(|s| {
+            let s = string(s)?;
+            if s.is_empty() || !s.iter().all(|c| (0x30..=0x39).contains(c)) {
+                return Ok(Value::Int(-1));
+            }
+            let digits: String = s.iter().filter_map(|&c| char::from_u32(c)).collect();
+            digits
+                .parse()
+                .map(Value::Int)
+                .map_err(|_| "integer overflow".to_owned())
+        }),
+        "str.from_int" => unary(|n| {
+            let n = int(n)?;
+            Ok(Value::String(if n < 0 {
+                vec![]
+            } else {
+                n.to_string().chars().map(|c| c as u32).collect()
+            }))
+        }),
+
+        // Regular expressions
+        "str.to_re" => unary(|s| Ok(Value::RegLan(Regex::string(string(s)?)))),
+        "str.in_re" => binary(|s, r| reglan(r)?.matches(string(s)?).map(Value::Bool)),
+        "re.none" if args.is_empty() => Ok(Value::RegLan(Regex::None)),
+        "re.all" if args.is_empty() => Ok(Value::RegLan(Regex::all())),
+        "re.allchar" if args.is_empty() => Ok(Value::RegLan(Regex::all_char())),
+        "re.++" | "re.union" | "re.inter" | "re.diff" => {
+            let Ok(rs) = args.iter().map(reglan).collect::<Eval<Vec<_>>>() else {
+                return Some(Err(format!("{} expects regular expressions", op)));
+            };
+            let mut rs = rs.into_iter().cloned();
+            Ok(Value::RegLan(match op {
+                "re.++" => rs.rev().fold(Regex::Epsilon, |r, a| Regex::concat(a, r)),
+                "re.union" => Regex::union(rs),
+                "re.inter" => Regex::inter(rs),
+                _ => match rs.next() {
+                    Some(first) => Regex::inter(std::iter::once(first).chain(rs.map(Regex::comp))),
+                    None => Regex::None,
+                },
+            }))
+        }
+        "re.*" => unary(|r| Ok(Value::RegLan(Regex::repeat(reglan(r)?.clone(), 0, None)))),
+        "re.+" => unary(|r| Ok(Value::RegLan(Regex::repeat(reglan(r)?.clone(), 1, None)))),
+        "re.opt" => unary(|r| Ok(Value::RegLan(Regex::repeat(reglan(r)?.clone(), 0, Some(1))))),
+        "re.comp" => unary(|r| Ok(Value::RegLan(Regex::comp(reglan(r)?.clone())))),
+        "re.range" => binary(|a, b| Ok(Value::RegLan(Regex::range(string(a)?, string(b)?)))),
+        "re.loop" | "re.^" => {
+            // SMT-LIB 2.5 passes the bounds of re.loop as arguments, with an optional upper bound
+            let bounds = match (indices, args) {
+                ([min, max], [r]) if op == "re.loop" => Ok((r, *min, Some(*max))),
+                ([n], [r]) => Ok((r, *n, Some(*n))),
+                ([], [r, min, rest @ ..]) if op == "re.loop" && rest.len() <= 1 => {
+                    let bound = |v| {
+                        int(v).and_then(|i| {
+                            u32::try_from(i).map_err(|_| format!("invalid bound {} of re.loop", i))
+                        })
+                    };
+                    bound(min).and_then(|min| {
+                        let max = rest.first().map(bound).transpose()?;
+                        Ok((r, min, max))
+                    })
+                }
+                _ => Err(format!("invalid application of {}", op)),
+            };
+            bounds.and_then(|(r, min, max)| {
+                Ok(Value::RegLan(Regex::repeat(reglan(r)?.clone(), min, max)))
+            })
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(term: &str) -> Eval<Value> {
+        let formula = format!("(assert (= r {}))", term);
+        let commands: Vec<Command> =
+            CommandStream::new(formula.as_bytes(), concrete::SyntaxBuilder, None)
+                .collect::<Result<_, _>>()
+                .unwrap();
+        let Command::Assert {
+            term: Term::Application { arguments, .. },
+        } = &commands[0]
+        else {
+            unreachable!()
+        };
+        Evaluator::default().eval(&arguments[1], &mut vec![])
+    }
+
+    fn text(s: &str) -> Eval<Value> {
+        Ok(Value::String(s.chars().map(|c| c as u32).collect()))
+    }
+
+    #[test]
+    fn evaluate_terms() {
+        assert_eq!(eval("(div (- 7) 2)"), Ok(Value::Int(-4)));
+        assert_eq!(eval("(mod (- 7) 2)"), Ok(Value::Int(1)));
+        assert_eq!(eval("(- 1 2 3)"), Ok(Value::Int(-4)));
+        assert_eq!(eval("(< 1 2 2)"), Ok(Value::Bool(false)));
+        assert!(eval("(div 1 0)").is_err());
+        assert!(eval("(* 170141183460469231731687303715884105727 2)").is_err());
+        assert_eq!(
+            eval("(let ((x 1) (y 2)) (let ((x y) (y x)) (- x y)))"),
+            Ok(Value::Int(1))
+        );
+        assert_eq!(eval("(and false (= x 1))"), Ok(Value::Bool(false)));
+        assert!(eval("(and true (= x 1))").is_err());
+        assert_eq!(eval("(=> false x)"), Ok(Value::Bool(true)));
+        assert_eq!(eval("(=> true true false)"), Ok(Value::Bool(false)));
+        assert_eq!(eval("(xor true true true)"), Ok(Value::Bool(true)));
+
+        assert_eq!(eval("(str.substr \"abcde\" 1 3)"), text("bcd"));
+        assert_eq!(eval("(str.substr \"abcde\" 3 10)"), text("de"));
+        assert_eq!(eval("(str.substr \"abcde\" 5 1)"), text(""));
+        assert_eq!(eval("(str.at \"abc\" (- 1))"), text(""));
+        assert_eq!(eval("(str.indexof \"abab\" \"b\" 2)"), Ok(Value::Int(3)));
+        assert_eq!(eval("(str.indexof \"ab\" \"\" 2)"), Ok(Value::Int(2)));
+        assert_eq!(eval("(str.indexof \"ab\" \"\" 3)"), Ok(Value::Int(-1)));
+        assert_eq!(eval("(str.replace \"abab\" \"b\" \"c\")"), text("acab"));
+        assert_eq!(eval("(str.replace \"ab\" \"\" \"c\")"), text("cab"));
+        assert_eq!(eval("(str.replace_all \"abab\" \"b\" \"c\")"), text("acac"));
+        assert_eq!(eval("(str.to_int \"012\")"), Ok(Value::Int(12)));
+        assert_eq!(eval("(str.to_int \"-1\")"), Ok(Value::Int(-1)));
+        assert_eq!(eval("(str.from_int 42)"), text("42"));
+        assert_eq!(
+            eval("(str.len \"\\u{1F600}\\u0041\\u{30000}\")"),
+            Ok(Value::Int(11))
+        );
+        assert_eq!(
+            eval("(str.to_code \"\\u{2FFFF}\")"),
+            Ok(Value::Int(0x2FFFF))
+        );
+        assert_eq!(eval("(str.< \"ab\" \"b\")"), Ok(Value::Bool(true)));
+        assert_eq!(eval("(str.prefixof \"ab\" \"abc\")"), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn evaluate_regex_membership() {
+        let in_re = |s: &str, r: &str| eval(&format!("(str.in_re \"{}\" {})", s, r));
+        let id = "(re.++ (re.union (re.range \"a\" \"z\") (str.to_re \"_\")) (re.* (re.range \"0\" \"z\")))";
+        assert_eq!(in_re("x_1", id), Ok(Value::Bool(true)));
+        assert_eq!(in_re("1x", id), Ok(Value::Bool(false)));
+        assert_eq!(
+            in_re("abab", "((_ re.loop 1 2) (str.to_re \"ab\"))"),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            in_re("ababab", "((_ re.^ 2) (str.to_re \"ab\"))"),
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            in_re("ababab", "(re.loop (str.to_re \"ab\") 2)"),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            in_re(
+                "abc",
+                "(re.diff (re.+ re.allchar) (re.++ re.all (str.to_re \"c\")))"
+            ),
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            in_re("\\u{1F600}", "(re.inter (re.comp re.none) re.allchar)"),
+            Ok(Value::Bool(true))
+        );
+        assert!(eval("(= re.all (re.comp re.none))").is_err());
+    }
+
+    #[test]
+    fn evaluate_regex_replacement() {
+        let digits = "(re.+ (re.range \"0\" \"9\"))";
+        let replace = |op: &str, s: &str, r: &str| eval(&format!("({} \"{}\" {} \"#\")", op, s, r));
+        // The leftmost match is replaced, and the shortest one of those
+        assert_eq!(replace("str.replace_re", "ab12c3", digits), text("ab#2c3"));
+        assert_eq!(replace("str.replace_re", "abc", digits), text("abc"));
+        assert_eq!(
+            replace("str.replace_re_all", "ab12c3", digits),
+            text("ab##c#")
+        );
+        // The empty string matches before the first character, but is not replaced by str.replace_re_all
+        assert_eq!(
+            replace("str.replace_re", "ab", "(re.* re.allchar)"),
+            text("#ab")
+        );
+        assert_eq!(
+            replace("str.replace_re_all", "ab", "(re.* re.allchar)"),
+            text("##")
+        );
+        assert_eq!(
+            replace("str.replace_re_all", "aXbX", "(re.opt (str.to_re \"X\"))"),
+            text("a#b#")
+        );
+    }
+
+    #[test]
+    fn evaluate_formulas() {
+        let formula = "(declare-fun x () String)(declare-fun f (Int) Int)\
+            (define-fun g ((y Int)) Int (+ (f y) 1))\
+            (assert (= (str.len x) 2))(push 1)(assert (= (g 1) 3))(check-sat)(pop 1)(assert (= x \"ab\"))(check-sat)";
+        let model =
+            "(define-fun x () String \"ab\")(define-fun f ((x!0 Int)) Int (ite (= x!0 1) 2 0))";
+        assert_eq!(evaluate(formula, model, None), Evaluation::Valid);
+        assert_eq!(evaluate(formula, model, Some(0)), Evaluation::Valid);
+        let model = "(define-fun x () String \"ac\")(define-fun f ((x!0 Int)) Int 0)";
+        assert_eq!(evaluate(formula, model, Some(0)), Evaluation::Invalid(1));
+        assert_eq!(evaluate(formula, model, None), Evaluation::Invalid(1));
+        assert_eq!(
+            evaluate(formula, "(define-fun x () String \"ab\")", Some(0)),
+            Evaluation::Undecided("no value for f".to_owned())
+        );
+        assert!(matches!(
+            evaluate("(assert (forall ((y Int)) (> y 0)))", "", None),
+            Evaluation::Undecided(_)
+        ));
+        assert!(matches!(
+            evaluate("(assert x)", "(define-fun x () Bool x)", None),
+            Evaluation::Undecided(_)
+        ));
+    }
+}
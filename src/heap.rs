@@ -0,0 +1,174 @@
+//! The heap image of the `smt` session.
+//!
+//! The batch checker runs `isabelle process -l smt`, which loads the prebuilt heap image of the session
+//! instead of building it. A missing heap, or one built before the theories changed, makes every check fail
+//! with an obscure Isabelle error, so the heap is checked before validating and rebuilt with `smtmv build-heap`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+use crate::error::{Error, IsabelleFailure};
+use crate::isabelle_env;
+
+/// The session whose heap image the validation theories are checked with
+pub const SESSION: &str = "smt";
+
+/// The files of a session directory that the heap image depends on
+fn is_session_source(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "thy" || e == "ML")
+        || path
+            .file_name()
+            .is_some_and(|n| n == "ROOT" || n == "ROOTS")
+}
+
+/// Returns the command line building the heap image of [SESSION] from the session directories.
+/// With `clean`, the heap is rebuilt even if Isabelle considers it up to date.
+pub fn build_command(session_dirs: &[String], clean: bool) -> Vec<String> {
+    let mut cmd = vec!["isabelle".to_owned(), "build".to_owned(), "-b".to_owned()];
+    if clean {
+        cmd.push("-c".to_owned());
+    }
+    for d in session_dirs {
+        cmd.extend(["-d".to_owned(), d.clone()]);
+    }
+    cmd.push(SESSION.to_owned());
+    cmd
+}
+
+/// Builds the heap image of [SESSION], showing the output of Isabelle.
+pub fn build(session_dirs: &[String], clean: bool) -> Result<(), Error> {
+    let cmd = build_command(session_dirs, clean);
+    log::info!("Running {}", cmd.join(" "));
+    let status = Command::new(isabelle_env::executable()?)
+        .args(&cmd[1..])
+        .stdin(Stdio::null())
+        .status()
+        .map_err(|e| Error::Other(format!("Could not run isabelle: {}", e)))?;
+    if status.success() {
+        Ok(())
+    } else {
+        log::error!("Building the heap image failed with {}", status);
+        Err(Error::IsabelleError(IsabelleFailure::Build))
+    }
+}
+
+/// Checks that the heap image of [SESSION] exists and is newer than the theories of the session directories.
+/// If the heap directories cannot be determined from Isabelle's settings, the check is skipped.
+pub fn check(session_dirs: &[String]) -> Result<(), Error> {
+    let Some(candidates) = heap_paths() else {
+        log::debug!("Could not locate the Isabelle heaps, skipping the heap check");
+        return Ok(());
+    };
+    let Some(built) = candidates
+        .iter()
+        .find_map(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+    else {
+        return Err(Error::HeapImage(format!(
+            "the heap image of session {} is missing",
+            SESSION
+        )));
+    };
+    match newest_source(session_dirs) {
+        Some((path, modified)) if modified > built => Err(Error::HeapImage(format!(
+            "the heap image of session {} is older than {}",
+            SESSION,
+            path.display()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the possible locations of the heap image, the user's heaps first.
+/// Returns None if Isabelle's settings cannot be read.
+fn heap_paths() -> Option<Vec<PathBuf>> {
+    let output = Command::new(isabelle_env::executable().ok()?)
+        .args([
+            "getenv",
+            "-b",
+            "ML_IDENTIFIER",
+            "ISABELLE_HEAPS",
+            "ISABELLE_HEAPS_SYSTEM",
+        ])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines().map(str::trim);
+    let ml_identifier = lines.next().filter(|l| !l.is_empty())?;
+    Some(
+        lines
+            .filter(|l| !l.is_empty())
+            .map(|heaps| Path::new(heaps).join(ml_identifier).join(SESSION))
+            .collect(),
+    )
+}
+
+/// Returns the most recently modified source file below the session directories, with its modification time.
+fn newest_source(session_dirs: &[String]) -> Option<(PathBuf, SystemTime)> {
+    session_sources(session_dirs)
+        .into_iter()
+        .filter_map(|path| {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            Some((path, modified))
+        })
+        .max_by_key(|(_, modified)| *modified)
+}
+
+/// Returns the source files below the session directories that the heap image depends on, in sorted order.
+pub(crate) fn session_sources(session_dirs: &[String]) -> Vec<PathBuf> {
+    let mut sources = vec![];
+    let mut dirs: Vec<PathBuf> = session_dirs.iter().map(PathBuf::from).collect();
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.is_dir() {
+                dirs.push(path);
+            } else if is_session_source(&path) {
+                sources.push(path);
+            }
+        }
+    }
+    sources.sort();
+    sources
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn build_heap_command() {
+        assert_eq!(
+            build_command(&["/th".to_owned(), "/ext".to_owned()], true).join(" "),
+            "isabelle build -b -c -d /th -d /ext smt"
+        );
+    }
+
+    #[test]
+    fn newest_theory_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        let old = SystemTime::now() - Duration::from_secs(60);
+        for (file, modified) in [
+            ("ROOT", old),
+            ("sub/Strings.thy", SystemTime::now()),
+            ("spec.json", SystemTime::now() + Duration::from_secs(60)),
+        ] {
+            let path = dir.path().join(file);
+            fs::write(&path, "").unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+        let (path, _) = newest_source(&[dir.path().display().to_string()]).unwrap();
+        assert_eq!(path, dir.path().join("sub/Strings.thy"));
+    }
+}
@@ -0,0 +1,323 @@
+//! The intermediate representation of converted terms.
+//!
+//! The converter resolves SMT-LIB terms with the spec to [Term]s, which use the names and types of the target language
+//! but leave its syntax open. A [Target] then pretty-prints the terms, so passes over the converted terms,
+//! such as substitutions and simplifications, are written once for all targets.
+
+use std::collections::HashMap;
+
+use crate::convert::Target;
+use crate::error::Error;
+use crate::spec;
+
+/// A converted assertion or definition
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Assertion(Term),
+    /// The definition of the named constant or function by its value
+    Definition(String, Term),
+}
+
+impl Command {
+    /// Replaces the free variables by their values, see [Term::substitute].
+    pub fn substitute(self, values: &HashMap<String, Term>) -> Self {
+        match self {
+            Command::Assertion(t) => Command::Assertion(t.substitute(values)),
+            Command::Definition(name, t) => Command::Definition(name, t.substitute(values)),
+        }
+    }
+
+    /// Returns whether the command has no free variables; a definition has at least the defined one.
+    pub fn is_ground(&self) -> bool {
+        match self {
+            Command::Assertion(t) => !t.any(&|t| matches!(t, Term::Var(_))),
+            Command::Definition(..) => false,
+        }
+    }
+
+    /// Pretty-prints the command as a statement of the target, e.g. `f = (\<lambda>x. x)`.
+    pub fn print(&self, target: &dyn Target) -> Result<String, Error> {
+        Ok(match self {
+            Command::Assertion(t) => target.assertion(t.print(target)?),
+            Command::Definition(name, t) => {
+                format!("{} = {}", target.identifier(name), t.print(target)?)
+            }
+        })
+    }
+}
+
+/// A converted term
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Term {
+    /// A free variable of the formula, i.e. a declared constant or function
+    Var(String),
+    /// Any other name of the target language, such as a bound variable, a constructor, or a function of the spec
+    Name(String),
+    /// An integer literal, given by its decimal value, e.g. `-5`
+    Int(String),
+    /// A string literal, unescaped
+    Str(String),
+    /// The application of the function the spec maps the SMT-LIB function `op` to
+    Apply {
+        op: String,
+        function: String,
+        args: Vec<Term>,
+    },
+    /// The spec template of the SMT-LIB function `op` instantiated with the arguments, see [spec::instantiate]
+    Template {
+        op: String,
+        template: String,
+        args: Vec<Term>,
+    },
+    /// The curried application of a term, e.g. of an uninterpreted function or an array
+    Call(Box<Term>, Vec<Term>),
+    /// The term annotated with a type of the target language
+    Annotated(Box<Term>, String),
+    Ite(Box<Term>, Box<Term>, Box<Term>),
+    /// Binds the names to the values in parallel
+    Let {
+        names: Vec<String>,
+        values: Vec<Term>,
+        body: Box<Term>,
+    },
+    /// An anonymous function of the parameters, which are annotated with their types if known
+    Lambda {
+        params: Vec<(String, Option<String>)>,
+        body: Box<Term>,
+    },
+    Conjunction(Vec<Term>),
+    /// A universal (existential if not `forall`) quantification over the typed variables
+    Quantifier {
+        forall: bool,
+        vars: Vec<(String, String)>,
+        body: Box<Term>,
+    },
+    /// A case distinction; a pattern is a constructor followed by its variables, or a single variable
+    Case {
+        scrutinee: Box<Term>,
+        cases: Vec<(Vec<String>, Term)>,
+    },
+    /// The function updated to map the index to the value, e.g. an array `store`
+    Update {
+        function: Box<Term>,
+        index: Box<Term>,
+        value: Box<Term>,
+    },
+}
+
+impl Term {
+    /// Returns the term annotated with the type.
+    pub fn annotated(self, ty: impl Into<String>) -> Self {
+        Term::Annotated(Box::new(self), ty.into())
+    }
+
+    /// Returns the curried application of the function to the arguments.
+    pub fn call(function: Term, args: Vec<Term>) -> Self {
+        Term::Call(Box::new(function), args)
+    }
+
+    /// Returns the term with `f` applied to its immediate subterms.
+    pub fn map_subterms(self, mut f: impl FnMut(Term) -> Term) -> Self {
+        let mut boxed = |t: Box<Term>| Box::new(f(*t));
+        match self {
+            Term::Apply { op, function, args } => Term::Apply {
+                op,
+                function,
+                args: args.into_iter().map(&mut f).collect(),
+            },
+            Term::Template { op, template, args } => Term::Template {
+                op,
+                template,
+                args: args.into_iter().map(&mut f).collect(),
+            },
+            Term::Call(function, args) => {
+                let function = boxed(function);
+                Term::Call(function, args.into_iter().map(&mut f).collect())
+            }
+            Term::Annotated(term, ty) => Term::Annotated(boxed(term), ty),
+            Term::Ite(c, a, b) => Term::Ite(boxed(c), boxed(a), boxed(b)),
+            Term::Let {
+                names,
+                values,
+                body,
+            } => Term::Let {
+                names,
+                values: values.into_iter().map(&mut f).collect(),
+                body: Box::new(f(*body)),
+            },
+            Term::Lambda { params, body } => Term::Lambda {
+                params,
+                body: boxed(body),
+            },
+            Term::Conjunction(terms) => Term::Conjunction(terms.into_iter().map(f).collect()),
+            Term::Quantifier { forall, vars, body } => Term::Quantifier {
+                forall,
+                vars,
+                body: boxed(body),
+            },
+            Term::Case { scrutinee, cases } => Term::Case {
+                scrutinee: boxed(scrutinee),
+                cases: cases
+                    .into_iter()
+                    .map(|(pattern, body)| (pattern, f(body)))
+                    .collect(),
+            },
+            Term::Update {
+                function,
+                index,
+                value,
+            } => Term::Update {
+                function: boxed(function),
+                index: boxed(index),
+                value: boxed(value),
+            },
+            t @ (Term::Var(_) | Term::Name(_) | Term::Int(_) | Term::Str(_)) => t,
+        }
+    }
+
+    /// Returns the immediate subterms of the term.
+    pub fn subterms(&self) -> Vec<&Term> {
+        match self {
+            Term::Apply { args, .. } | Term::Template { args, .. } | Term::Conjunction(args) => {
+                args.iter().collect()
+            }
+            Term::Call(function, args) => [&**function].into_iter().chain(args).collect(),
+            Term::Annotated(t, _)
+            | Term::Lambda { body: t, .. }
+            | Term::Quantifier { body: t, .. } => vec![t],
+            Term::Ite(c, a, b) => vec![c, a, b],
+            Term::Let { values, body, .. } => values.iter().chain([&**body]).collect(),
+            Term::Case { scrutinee, cases } => [&**scrutinee]
+                .into_iter()
+                .chain(cases.iter().map(|(_, body)| body))
+                .collect(),
+            Term::Update {
+                function,
+                index,
+                value,
+            } => vec![function, index, value],
+            Term::Var(_) | Term::Name(_) | Term::Int(_) | Term::Str(_) => vec![],
+        }
+    }
+
+    /// Returns whether the term or any of its subterms satisfies the predicate.
+    pub fn any(&self, predicate: &impl Fn(&Term) -> bool) -> bool {
+        predicate(self) || self.subterms().into_iter().any(|t| t.any(predicate))
+    }
+
+    /// Replaces the free variables by their values.
+    /// Bound variables are never [Term::Var]s, so they are left unchanged.
+    pub fn substitute(self, values: &HashMap<String, Term>) -> Self {
+        match self {
+            Term::Var(name) => match values.get(&name) {
+                Some(value) => value.clone(),
+                None => Term::Var(name),
+            },
+            t => t.map_subterms(|t| t.substitute(values)),
+        }
+    }
+
+    /// Pretty-prints the term in the syntax of the target.
+    /// Free variables are renamed to identifiers of the target, see [Target::identifier].
+    /// Fails if the target cannot express the term, e.g. a string literal with characters it does not support.
+    pub fn print(&self, target: &dyn Target) -> Result<String, Error> {
+        let print_all = |terms: &[Term]| -> Result<Vec<String>, Error> {
+            terms.iter().map(|t| t.print(target)).collect()
+        };
+        Ok(match self {
+            Term::Var(name) => target.identifier(name),
+            Term::Name(name) => name.clone(),
+            Term::Int(value) => target.int_literal(value),
+            Term::Str(s) => target.string_literal(s)?,
+            Term::Apply { function, args, .. } => target.application(function, &print_all(args)?),
+            Term::Template { template, args, .. } => {
+                format!("({})", spec::instantiate(template, &print_all(args)?)?)
+            }
+            Term::Call(function, args) => target.call(&function.print(target)?, &print_all(args)?),
+            Term::Annotated(term, ty) => target.annotate(&term.print(target)?, ty),
+            Term::Ite(c, a, b) => {
+                target.ite(&c.print(target)?, &a.print(target)?, &b.print(target)?)
+            }
+            Term::Let {
+                names,
+                values,
+                body,
+            } => target.let_in(names, &print_all(values)?, &body.print(target)?),
+            Term::Lambda { params, body } => {
+                let params: Vec<String> = params
+                    .iter()
+                    .map(|(name, ty)| match ty {
+                        Some(ty) => target.annotate(name, ty),
+                        None => name.clone(),
+                    })
+                    .collect();
+                target.lambda(&params, &body.print(target)?)
+            }
+            Term::Conjunction(terms) => target.conjunction(&print_all(terms)?),
+            Term::Quantifier { forall, vars, body } => {
+                let vars: Vec<String> = vars
+                    .iter()
+                    .map(|(name, ty)| target.annotate(name, ty))
+                    .collect();
+                target.quantifier(*forall, &vars, &body.print(target)?)?
+            }
+            Term::Case { scrutinee, cases } => {
+                let mut printed = vec![];
+                for (pattern, body) in cases {
+                    printed.push((pattern.join(" "), body.print(target)?));
+                }
+                target.case(&scrutinee.print(target)?, &printed)?
+            }
+            Term::Update {
+                function,
+                index,
+                value,
+            } => target.update(
+                &function.print(target)?,
+                &index.print(target)?,
+                &value.print(target)?,
+            )?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Term;
+    use crate::convert::TargetKind;
+
+    #[test]
+    fn print_for_targets() {
+        let term = Term::Let {
+            names: vec!["a".to_owned()],
+            values: vec![Term::Int("-2".to_owned())],
+            body: Box::new(Term::Apply {
+                op: "<".to_owned(),
+                function: "<".to_owned(),
+                args: vec![Term::Name("a".to_owned()), Term::Var("x".to_owned())],
+            }),
+        };
+        assert_eq!(
+            term.print(TargetKind::Isabelle.target()).unwrap(),
+            "(let a = (-2::int) in ((<)  a x))"
+        );
+        assert_eq!(
+            term.print(TargetKind::Coq.target()).unwrap(),
+            "(let a := (-2)%Z in ((<)  a x))"
+        );
+
+        let quantified = Term::Quantifier {
+            forall: true,
+            vars: vec![("y".to_owned(), "int".to_owned())],
+            body: Box::new(Term::call(
+                Term::Var("f".to_owned()),
+                vec![Term::Name("y".to_owned())],
+            )),
+        };
+        assert_eq!(
+            quantified.print(TargetKind::Isabelle.target()).unwrap(),
+            "(\\<forall>(y::int). (f y))"
+        );
+        assert!(quantified.print(TargetKind::Lean.target()).is_err());
+    }
+}
@@ -0,0 +1,434 @@
+//! The Isabelle installation used to check lemmas.
+//!
+//! Option names and session arguments change between Isabelle releases.
+//! Instead of letting such incompatibilities surface as failures of the Isabelle process,
+//! the installed version is detected up front and unsupported versions are rejected with a clear message.
+//!
+//! The installation is the one given explicitly with [use_installation], or else the first found
+//! in `$ISABELLE_HOME`, on the `PATH`, or in a well-known location such as `~/Isabelle2024`.
+//! Its `bin` directory is put in front of the `PATH` by [setup], so that the Isabelle server is started from it as well.
+//!
+//! The heaps of the Isabelle processes are sized by the settings of the installation, see [memory_limit_env].
+
+use std::env;
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::error::{Error, IsabelleFailure};
+
+/// The oldest supported Isabelle release (inclusive).
+pub const MIN_ISABELLE_VERSION: IsabelleVersion = IsabelleVersion::new(2022, 0);
+/// The newest supported Isabelle release (inclusive).
+pub const MAX_ISABELLE_VERSION: IsabelleVersion = IsabelleVersion::new(2025, u32::MAX);
+
+/// The version of an Isabelle release, such as `Isabelle2021-1` or `Isabelle2024`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IsabelleVersion {
+    year: u32,
+    /// The number of the follow-up release within a year, as in `Isabelle2021-1`
+    revision: u32,
+}
+
+impl IsabelleVersion {
+    pub const fn new(year: u32, revision: u32) -> Self {
+        Self { year, revision }
+    }
+
+    /// Parses the output of `isabelle version`, e.g. `Isabelle2023` or `Isabelle2021-1: December 2021`.
+    /// Release candidates such as `Isabelle2024-RC2` are treated as the release.
+    /// Returns None for repository snapshots and other output without a release name.
+    pub fn parse(s: &str) -> Option<Self> {
+        let name = s.split_whitespace().next()?.trim_end_matches(':');
+        let rest = name.strip_prefix("Isabelle")?;
+        let (year, suffix) = match rest.split_once('-') {
+            Some((year, suffix)) => (year, Some(suffix)),
+            None => (rest, None),
+        };
+        let year = year.parse().ok()?;
+        let revision = match suffix {
+            Some(s) if s.starts_with("RC") => 0,
+            Some(s) => s.parse().ok()?,
+            None => 0,
+        };
+        Some(Self::new(year, revision))
+    }
+
+    /// Returns true iff this tool supports the release.
+    pub fn is_supported(&self) -> bool {
+        *self >= MIN_ISABELLE_VERSION && *self <= MAX_ISABELLE_VERSION
+    }
+}
+
+impl Display for IsabelleVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Isabelle{}", self.year)?;
+        if self.revision > 0 {
+            write!(f, "-{}", self.revision)?;
+        }
+        Ok(())
+    }
+}
+
+/// The `isabelle` executable of the installation in use
+static EXECUTABLE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Uses the Isabelle installation at the path, which is either the `isabelle` executable or the installation directory.
+/// Must be called before anything runs Isabelle; later calls have no effect.
+pub fn use_installation(path: &Path) -> Result<&'static Path, Error> {
+    let executable = if path.is_dir() {
+        executable_in(path).ok_or_else(|| {
+            Error::IsabelleNotFound(format!("{} contains no bin/isabelle", path.display()))
+        })?
+    } else if path.is_file() {
+        path.to_path_buf()
+    } else {
+        return Err(Error::IsabelleNotFound(format!(
+            "{} does not exist",
+            path.display()
+        )));
+    };
+    Ok(install(executable))
+}
+
+/// Returns the `isabelle` executable, locating the installation on first use.
+pub fn executable() -> Result<&'static Path, Error> {
+    if let Some(executable) = EXECUTABLE.get() {
+        return Ok(executable);
+    }
+    let executable = locate().ok_or_else(|| {
+        Error::IsabelleNotFound(
+            "put the isabelle executable on the PATH, set ISABELLE_HOME to the installation, or pass --isabelle-path"
+                .to_owned(),
+        )
+    })?;
+    Ok(install(executable))
+}
+
+/// Makes the executable the one in use.
+fn install(executable: PathBuf) -> &'static Path {
+    let executable = EXECUTABLE.get_or_init(|| executable);
+    log::debug!("Using Isabelle at {}", executable.display());
+    executable
+}
+
+/// Locates the installation in use (see [executable]) and puts its `bin` directory in front of the `PATH`,
+/// since the Isabelle server is started as the `isabelle` on the `PATH`.
+/// This changes the environment of the process, so it has to be called before any other thread is started;
+/// the checkers never change the environment themselves.
+pub fn setup() -> Result<&'static Path, Error> {
+    let executable = executable()?;
+    if let Some(bin) = executable.parent().filter(|b| !b.as_os_str().is_empty()) {
+        let mut path = OsString::from(bin);
+        if let Some(old) = env::var_os("PATH") {
+            path.push(":");
+            path.push(old);
+        }
+        env::set_var("PATH", path);
+    }
+    Ok(executable)
+}
+
+/// Returns the `isabelle` executable of the installation directory, if it has one.
+fn executable_in(home: &Path) -> Option<PathBuf> {
+    let executable = home.join("bin").join("isabelle");
+    executable.is_file().then_some(executable)
+}
+
+/// Finds an installation in `$ISABELLE_HOME`, on the `PATH`, or in a well-known location, in this order.
+fn locate() -> Option<PathBuf> {
+    if let Some(executable) =
+        env::var_os("ISABELLE_HOME").and_then(|h| executable_in(Path::new(&h)))
+    {
+        return Some(executable);
+    }
+    if let Some(executable) = env::var_os("PATH")
+        .iter()
+        .flat_map(env::split_paths)
+        .map(|d| d.join("isabelle"))
+        .find(|e| e.is_file())
+    {
+        return Some(executable);
+    }
+    let mut dirs = vec![
+        PathBuf::from("/opt"),
+        PathBuf::from("/usr/local"),
+        PathBuf::from("/Applications"),
+    ];
+    if let Some(home) = env::var_os("HOME") {
+        dirs.insert(0, PathBuf::from(home));
+    }
+    installations_in(&dirs).into_iter().next().map(|(_, e)| e)
+}
+
+/// Returns the installations named after their release (e.g. `Isabelle2024` or `Isabelle2024.app`) in the directories,
+/// the supported ones first and the newest first among them.
+fn installations_in(dirs: &[PathBuf]) -> Vec<(IsabelleVersion, PathBuf)> {
+    let mut res = vec![];
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|e| e.path()) {
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let release = name.trim_end_matches(".app");
+            let Some(version) = IsabelleVersion::parse(release) else {
+                continue;
+            };
+            // Application bundles on macOS keep the installation in Contents/Resources before Isabelle2022
+            let executable = executable_in(&path)
+                .or_else(|| executable_in(&path.join("Contents/Resources").join(release)));
+            if let Some(executable) = executable {
+                res.push((version, executable));
+            }
+        }
+    }
+    res.sort_by_key(|(v, _)| (std::cmp::Reverse(v.is_supported()), std::cmp::Reverse(*v)));
+    res
+}
+
+/// Returns a description of the supported Isabelle releases for error messages.
+pub fn supported_versions() -> String {
+    format!(
+        "Isabelle{} to Isabelle{}",
+        MIN_ISABELLE_VERSION.year, MAX_ISABELLE_VERSION.year
+    )
+}
+
+/// Detects the version of the `isabelle` executable in use, see [executable].
+/// Returns None if the version could not be determined.
+pub fn detect_version() -> Option<IsabelleVersion> {
+    let executable = match executable() {
+        Ok(e) => e,
+        Err(e) => {
+            log::warn!("{}", e);
+            return None;
+        }
+    };
+    let output = match Command::new(executable).arg("version").output() {
+        Ok(o) if o.status.success() => o,
+        Ok(o) => {
+            log::warn!(
+                "Could not determine the Isabelle version: {}",
+                String::from_utf8_lossy(&o.stderr).trim()
+            );
+            return None;
+        }
+        Err(e) => {
+            log::warn!("Could not run 'isabelle version': {}", e);
+            return None;
+        }
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = IsabelleVersion::parse(&stdout);
+    if version.is_none() {
+        log::warn!("Unrecognized Isabelle version '{}'", stdout.trim());
+    }
+    version
+}
+
+/// Checks that Isabelle is installed and supported.
+/// If the version cannot be determined (e.g. for repository snapshots), the check is skipped.
+pub fn check_version() -> Result<Option<IsabelleVersion>, Error> {
+    executable()?;
+    match detect_version() {
+        Some(v) if !v.is_supported() => Err(Error::UnsupportedIsabelle(format!(
+            "{} (supported: {})",
+            v,
+            supported_versions()
+        ))),
+        Some(v) => {
+            log::debug!("Using {}", v);
+            Ok(Some(v))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The setting with the options of the Poly/ML process running the prover
+const ML_OPTIONS: &str = "ML_OPTIONS";
+/// The setting with the options of the Java process running an Isabelle tool, such as `isabelle process`
+const JAVA_OPTIONS: &str = "ISABELLE_TOOL_JAVA_OPTIONS";
+
+/// The values of [ML_OPTIONS] and [JAVA_OPTIONS] in the settings of the installation in use
+static TOOL_OPTIONS: OnceLock<(String, String)> = OnceLock::new();
+
+/// Returns the settings limiting the heaps of an Isabelle process to `limit_mb` megabytes in total,
+/// as `NAME=VALUE` arguments of `isabelle env`, which keeps the process from reading the settings files again.
+/// A quarter of the limit is the maximum Java heap (`-Xmx`) and the rest the maximum ML heap (`--maxheap`);
+/// they are appended to the options of the settings, so they override any limits there.
+pub fn memory_limit_env(limit_mb: u64) -> Result<[String; 2], Error> {
+    let (ml_options, java_options) = tool_options()?;
+    Ok(limit_memory(ml_options, java_options, limit_mb))
+}
+
+/// Appends the heap limits to the ML and Java options, see [memory_limit_env].
+fn limit_memory(ml_options: &str, java_options: &str, limit_mb: u64) -> [String; 2] {
+    let java_mb = (limit_mb / 4).max(1);
+    let ml_mb = limit_mb.saturating_sub(java_mb).max(1);
+    let append = |options: &str, limit: String| match options.trim() {
+        "" => limit,
+        options => format!("{} {}", options, limit),
+    };
+    [
+        format!(
+            "{}={}",
+            ML_OPTIONS,
+            append(ml_options, format!("--maxheap {}M", ml_mb))
+        ),
+        format!(
+            "{}={}",
+            JAVA_OPTIONS,
+            append(java_options, format!("-Xmx{}m", java_mb))
+        ),
+    ]
+}
+
+/// Returns the values of [ML_OPTIONS] and [JAVA_OPTIONS], reading them with `isabelle getenv` on first use.
+fn tool_options() -> Result<&'static (String, String), Error> {
+    if let Some(options) = TOOL_OPTIONS.get() {
+        return Ok(options);
+    }
+    let executable = executable()?;
+    let output = Command::new(executable)
+        .args(["getenv", "-b", ML_OPTIONS, JAVA_OPTIONS])
+        .output()
+        .map_err(|e| {
+            Error::IsabelleNotFound(format!("could not run {}: {}", executable.display(), e))
+        })?;
+    if !output.status.success() {
+        log::error!(
+            "Could not read the Isabelle settings: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return Err(Error::IsabelleError(IsabelleFailure::Startup));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut values = stdout.lines().map(str::to_owned);
+    let options = (
+        values.next().unwrap_or_default(),
+        values.next().unwrap_or_default(),
+    );
+    log::debug!("Isabelle heap settings: {:?}", options);
+    Ok(TOOL_OPTIONS.get_or_init(|| options))
+}
+
+/// Returns the name of the system option Isabelle rejected, if the output reports one.
+/// This happens if an option was renamed or removed in the installed release.
+pub fn rejected_option(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let (_, rest) = line
+            .split_once("Unknown system option")
+            .or_else(|| line.split_once("Unknown option"))?;
+        Some(
+            rest.trim()
+                .trim_matches(|c| c == '"' || c == ':')
+                .to_owned(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_isabelle_versions() {
+        assert_eq!(
+            IsabelleVersion::parse("Isabelle2023\n"),
+            Some(IsabelleVersion::new(2023, 0))
+        );
+        assert_eq!(
+            IsabelleVersion::parse("Isabelle2021-1: December 2021"),
+            Some(IsabelleVersion::new(2021, 1))
+        );
+        assert_eq!(
+            IsabelleVersion::parse("Isabelle2024-RC1"),
+            Some(IsabelleVersion::new(2024, 0))
+        );
+        assert_eq!(IsabelleVersion::parse("repository snapshot"), None);
+        assert_eq!(IsabelleVersion::new(2021, 1).to_string(), "Isabelle2021-1");
+    }
+
+    #[test]
+    fn supported_isabelle_versions() {
+        assert!(!IsabelleVersion::new(2021, 1).is_supported());
+        assert!(IsabelleVersion::new(2022, 0).is_supported());
+        assert!(IsabelleVersion::new(2025, 0).is_supported());
+        assert!(!IsabelleVersion::new(2026, 0).is_supported());
+    }
+
+    #[test]
+    fn find_installations() {
+        let dir = tempfile::tempdir().unwrap();
+        for release in [
+            "Isabelle2019",
+            "Isabelle2023",
+            "Isabelle2024",
+            "Isabelle2024-notes",
+        ] {
+            let bin = dir.path().join(release).join("bin");
+            std::fs::create_dir_all(&bin).unwrap();
+            std::fs::write(bin.join("isabelle"), "").unwrap();
+        }
+        let bin = dir
+            .path()
+            .join("Isabelle2021.app/Contents/Resources/Isabelle2021/bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(bin.join("isabelle"), "").unwrap();
+        std::fs::create_dir(dir.path().join("Isabelle2025")).unwrap();
+        let found: Vec<_> = installations_in(&[dir.path().to_path_buf()])
+            .into_iter()
+            .map(|(v, e)| (v.to_string(), e))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "Isabelle2024".to_owned(),
+                    dir.path().join("Isabelle2024/bin/isabelle")
+                ),
+                (
+                    "Isabelle2023".to_owned(),
+                    dir.path().join("Isabelle2023/bin/isabelle")
+                ),
+                ("Isabelle2021".to_owned(), bin.join("isabelle")),
+                (
+                    "Isabelle2019".to_owned(),
+                    dir.path().join("Isabelle2019/bin/isabelle")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn detect_rejected_options() {
+        assert_eq!(
+            rejected_option("*** Unknown system option \"build_pide_reports\"").unwrap(),
+            "build_pide_reports"
+        );
+        assert_eq!(rejected_option("*** Failed to finish proof"), None);
+    }
+
+    #[test]
+    fn limit_heaps() {
+        assert_eq!(
+            limit_memory("--minheap 500", "-Djava.awt.headless=true -Xmx4g", 2000),
+            [
+                "ML_OPTIONS=--minheap 500 --maxheap 1500M",
+                "ISABELLE_TOOL_JAVA_OPTIONS=-Djava.awt.headless=true -Xmx4g -Xmx500m",
+            ]
+        );
+        assert_eq!(
+            limit_memory("", " ", 1),
+            [
+                "ML_OPTIONS=--maxheap 1M",
+                "ISABELLE_TOOL_JAVA_OPTIONS=-Xmx1m"
+            ]
+        );
+    }
+}
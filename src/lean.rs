@@ -0,0 +1,110 @@
+//! Checking lemmas with the Lean 4 theorem prover.
+//!
+//! With [TargetKind::Lean](crate::convert::TargetKind::Lean), the converter maps the formula with a Lean spec
+//! (`lean_spec.json` in the theory root, or the builtin one) to Boolean Lean terms, and assertions to `t = true`.
+//! The lemma is written as a theorem to a file `Validation.lean`, which is elaborated with `lean`.
+//! If the theory root is a Lake package, `lean` runs in its environment (`lake env lean`), so the theorem can import its modules.
+
+use std::path::Path;
+
+use itertools::Itertools;
+
+use crate::checker::{CheckResult, UnknownReason};
+use crate::convert_lean::Lean;
+use crate::error::Error;
+use crate::lemma::{Lemma, Theory};
+use crate::prover::{ProcessChecker, Prover};
+
+/// The configuration files of a Lake package
+const LAKE_FILES: [&str; 2] = ["lakefile.lean", "lakefile.toml"];
+
+/// Creates the Lean file `Validation` containing the theorem to check, importing the given modules.
+pub fn lean_theory(lemma: &Lemma, imports: &[String]) -> Theory {
+    let mut theory = Theory::new("Validation", false);
+    for import in imports.iter().unique() {
+        theory.add_theory_import(import);
+    }
+    theory.add_lemma(lemma.clone());
+    theory
+}
+
+/// Checks lemmas by elaborating them with `lean`, one process per lemma
+pub type LeanChecker = ProcessChecker<Lean>;
+
+impl Prover for Lean {
+    fn executable(&self) -> &'static str {
+        "lean"
+    }
+
+    /// Elaborates the file with `lean`, in the environment of the theory root if it is a Lake package.
+    fn command_line(&self, theory_root: &str, file: &Path) -> Vec<String> {
+        let root = Path::new(theory_root);
+        let mut cmd = if LAKE_FILES.iter().any(|f| root.join(f).exists()) {
+            vec!["lake".to_owned(), "env".to_owned(), "lean".to_owned()]
+        } else {
+            vec!["lean".to_owned()]
+        };
+        cmd.push(file.display().to_string());
+        cmd
+    }
+
+    fn runs_in_theory_root(&self) -> bool {
+        true
+    }
+
+    /// Classifies a failed elaboration from the output of `lean`.
+    /// `decide` reports a conclusion that evaluates to false, which refutes the lemma.
+    /// Errors in the statement, such as unknown identifiers, are errors of the spec rather than failed proofs.
+    fn classify_failure(&self, output: &str) -> Result<CheckResult, Error> {
+        if output.contains("proved that the proposition") && output.contains("is false") {
+            log::debug!("Lemma is invalid: {}", output);
+            return Ok(CheckResult::FailedInvalid);
+        }
+        if output.contains("unknown identifier")
+            || output.contains("unknown constant")
+            || output.contains("type mismatch")
+            || output.contains("unexpected token")
+        {
+            log::error!("lean rejected the validation file:\n{}", output);
+            return Err(Error::Other(
+                "lean rejected the validation file (see logs for details)".to_owned(),
+            ));
+        }
+        if output.contains("maximum recursion depth") {
+            return Ok(CheckResult::FailedUnknown(UnknownReason::ResourceLimit));
+        }
+        if output.contains("(deterministic) timeout") {
+            return Ok(CheckResult::FailedUnknown(UnknownReason::Timeout));
+        }
+        log::debug!("Proof could not be finished: {}", output);
+        Ok(CheckResult::FailedUnknown(UnknownReason::from_output(
+            output,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_lean_failures() {
+        let invalid = "Validation.lean:3:0: error: tactic 'decide' proved that the proposition\n  Int.ofNat 3 < 2 = true\nis false";
+        assert_eq!(
+            Lean.classify_failure(invalid).unwrap(),
+            CheckResult::FailedInvalid
+        );
+        assert!(Lean
+            .classify_failure("error: unknown identifier 'String.foo'")
+            .is_err());
+        assert_eq!(
+            Lean.classify_failure("error: maximum recursion depth has been reached")
+                .unwrap(),
+            CheckResult::FailedUnknown(UnknownReason::ResourceLimit)
+        );
+        assert_eq!(
+            Lean.classify_failure("error: unsolved goals").unwrap(),
+            CheckResult::FailedUnknown(UnknownReason::TacticFailure)
+        );
+    }
+}
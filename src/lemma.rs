@@ -1,13 +1,54 @@
 use std::collections::HashSet;
+use std::io::{self, Write};
 
 use itertools::Itertools;
 
+/// The default proof method
+pub const DEFAULT_METHOD: &str = "simp";
+/// The default proof script of Coq lemmas, which computes the conclusions under the substituted premises
+pub const COQ_DEFAULT_METHOD: &str = "intros; subst; repeat split; vm_compute; reflexivity";
+/// The default tactic of Lean theorems, which decides the conclusions under the substituted premises
+pub const LEAN_DEFAULT_METHOD: &str = "subst_vars; decide";
+/// The proof method of ground lemmas, which evaluates the conclusions with the code generator
+pub const EVAL_METHOD: &str = "eval";
+/// Proof methods that accept the simplifier rules of the lemma as `simp add:` modifier
+const SIMP_METHODS: [&str; 6] = ["simp", "auto", "force", "fastforce", "clarsimp", "slowsimp"];
+
+/// A tool run on a lemma instead of proving it, see [Lemma::with_query]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Query {
+    /// Search for a counterexample with Nitpick
+    Nitpick,
+    /// Search for a proof with Sledgehammer
+    Sledgehammer,
+}
+
+/// How the proof method of a lemma is written in Isabelle
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProofBody {
+    /// `apply(method)` followed by `done`
+    #[default]
+    Apply,
+    /// A terminal `by method`, e.g. `by eval`
+    By,
+}
+
 #[derive(Default, Clone, Debug)]
 pub struct Lemma {
     name: String,
     premises: Vec<String>,
     conclusions: Vec<String>,
     simps: HashSet<String>,
+    /// The variables fixed at a type, with their Isabelle/HOL types
+    fixes: Vec<(String, String)>,
+    /// The proof method, [DEFAULT_METHOD] if not set
+    method: Option<String>,
+    /// How the method is written in the proof
+    body: ProofBody,
+    /// Run this tool instead of proving the lemma
+    query: Option<Query>,
+    /// Isabelle commands the lemma depends on, such as datatype declarations, written before it in the theory
+    declarations: Vec<String>,
 }
 
 impl Lemma {
@@ -20,87 +61,295 @@ impl Lemma {
         }
     }
 
-    pub fn add_premise(&mut self, premise: &str) -> &mut Self {
-        self.premises.push(premise.to_owned());
+    pub fn add_premise(&mut self, premise: impl Into<String>) -> &mut Self {
+        self.premises.push(premise.into());
         self
     }
 
-    pub fn add_premises(&mut self, premises: &[String]) -> &mut Self {
+    pub fn add_premises<I, S>(&mut self, premises: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
         for p in premises {
             self.add_premise(p);
         }
         self
     }
 
-    pub fn add_conclusion(&mut self, conclusion: &str) -> &mut Self {
-        self.conclusions.push(conclusion.to_owned());
+    pub fn add_conclusion(&mut self, conclusion: impl Into<String>) -> &mut Self {
+        self.conclusions.push(conclusion.into());
         self
     }
 
-    pub fn add_conclusions(&mut self, conclusions: &[String]) -> &mut Self {
+    pub fn add_conclusions<I, S>(&mut self, conclusions: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
         for c in conclusions {
             self.add_conclusion(c);
         }
         self
     }
 
-    #[allow(unstable_name_collisions)]
+    /// Fixes the type of a variable, instead of letting Isabelle infer it.
+    pub fn add_fix(&mut self, var: impl Into<String>, ty: impl Into<String>) -> &mut Self {
+        self.fixes.push((var.into(), ty.into()));
+        self
+    }
+
+    /// Adds an Isabelle command the lemma depends on, e.g. a `datatype` declaration.
+    pub fn add_declaration(&mut self, declaration: impl Into<String>) -> &mut Self {
+        self.declarations.push(declaration.into());
+        self
+    }
+
+    /// Adds a rule the simplifier uses to prove the lemma.
+    pub fn add_simp(&mut self, simp: impl Into<String>) -> &mut Self {
+        self.simps.insert(simp.into());
+        self
+    }
+
+    pub fn fixes(&self) -> &[(String, String)] {
+        &self.fixes
+    }
+
+    pub fn declarations(&self) -> &[String] {
+        &self.declarations
+    }
+
+    pub fn premises(&self) -> &[String] {
+        &self.premises
+    }
+
+    pub fn conclusions(&self) -> &[String] {
+        &self.conclusions
+    }
+
+    /// Sets the proof method, e.g. `auto`, `eval`, or any Isabelle method expression.
+    /// The simplifier-based methods (`simp`, `auto`, `force`, ...) are passed the simplifier rules of the lemma;
+    /// other methods are used as given.
+    pub fn set_method(&mut self, method: impl Into<String>) -> &mut Self {
+        self.method = Some(method.into());
+        self
+    }
+
+    /// Sets how the proof method is written in Isabelle.
+    pub fn set_proof_body(&mut self, body: ProofBody) -> &mut Self {
+        self.body = body;
+        self
+    }
+
+    pub fn proof_body(&self) -> ProofBody {
+        self.body
+    }
+
+    /// Returns the proof method used to prove the lemma, including the simplifier rules if the method takes them.
+    pub fn tactic(&self) -> String {
+        let method = self.method.as_deref().unwrap_or(DEFAULT_METHOD).trim();
+        let simps = self.simps.iter().sorted().join(" ");
+        if method == "simp" {
+            format!("simp add: {}", simps)
+        } else if SIMP_METHODS.contains(&method) {
+            format!("{} simp add: {}", method, simps)
+        } else {
+            method.to_owned()
+        }
+    }
+
+    /// Returns the proof method if it was set with [Lemma::set_method].
+    pub fn method(&self) -> Option<&str> {
+        self.method.as_deref()
+    }
+
+    /// Returns a copy of the lemma that is not proven, but on which the given tool is run.
+    /// Checking the copy succeeds whatever the tool finds; the findings are reported in its output.
+    pub fn with_query(&self, query: Query) -> Lemma {
+        Lemma {
+            query: Some(query),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a copy of the lemma without the given premises.
+    /// Without any premises left, the proof method no longer uses `assms`, which Isabelle would not know.
+    pub fn without_premises(&self, premises: &[String]) -> Lemma {
+        let mut lemma = Lemma {
+            premises: self
+                .premises
+                .iter()
+                .filter(|p| !premises.contains(p))
+                .cloned()
+                .collect(),
+            ..self.clone()
+        };
+        if lemma.premises.is_empty() {
+            lemma.simps.remove("assms");
+        }
+        lemma
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the number of conclusions of the lemma.
+    pub fn num_conclusions(&self) -> usize {
+        self.conclusions.len()
+    }
+
     pub fn to_isabelle(&self) -> String {
-        let template = "
-lemma ?name: assumes ?model shows \"?formula\"
-    apply(simp add: ?simps)
-    done
-";
-
-        let premises = self
-            .clone()
-            .premises
-            .into_iter()
-            .map(|p| format!("\"{}\"", p))
-            .intersperse(" and ".to_string())
-            .collect::<String>();
-
-        let conclusion: String = self
-            .clone()
-            .conclusions
-            .into_iter()
-            .intersperse(" \\<and> ".to_string())
-            .collect();
-
-        let simps: String = self
-            .simps
-            .iter()
-            .cloned()
-            .intersperse(" ".to_string())
-            .collect();
-
-        template
-            .replace("?name", &self.name)
-            .replace("?model", &premises)
-            .replace("?formula", &conclusion)
-            .replace("?simps", &simps)
-    }
-
-    fn split_conclusion(self) -> Vec<Lemma> {
-        let mut builders = vec![];
-
-        for (i, con) in self.conclusions.iter().enumerate() {
-            let name = format!("{}_{}", self.name.clone(), i);
-            let mut sl = Lemma::new(&name);
-            sl.add_premises(&self.premises).add_conclusion(con);
-
-            builders.push(sl);
-        }
-        builders
+        let mut buf = vec![];
+        self.write_isabelle(&mut buf)
+            .expect("Writing to a buffer does not fail");
+        String::from_utf8(buf).expect("Lemma is valid UTF-8")
+    }
+
+    /// Writes the lemma in Isabelle syntax without building the whole text in memory.
+    pub fn write_isabelle(&self, w: &mut dyn Write) -> io::Result<()> {
+        write!(w, "\nlemma {}: ", self.name)?;
+        for (i, (var, ty)) in self.fixes.iter().enumerate() {
+            let keyword = if i == 0 { "fixes" } else { "and" };
+            write!(w, "{} {} :: \"{}\" ", keyword, var, ty)?;
+        }
+        // A lemma without premises, e.g. of a model substituted into the formula, has no `assumes` clause
+        for (i, p) in self.premises.iter().enumerate() {
+            let keyword = if i == 0 { "assumes" } else { " and" };
+            write!(w, "{} \"{}\"", keyword, p)?;
+        }
+        if !self.premises.is_empty() {
+            write!(w, " ")?;
+        }
+        write!(w, "shows \"")?;
+        for (i, c) in self.conclusions.iter().enumerate() {
+            if i > 0 {
+                write!(w, " \\<and> ")?;
+            }
+            write!(w, "{}", c)?;
+        }
+        writeln!(w, "\"")?;
+        match self.query {
+            Some(Query::Nitpick) => return writeln!(w, "    nitpick\n    oops"),
+            Some(Query::Sledgehammer) => return writeln!(w, "    sledgehammer\n    oops"),
+            None => (),
+        }
+        let tactic = self.tactic();
+        match self.body {
+            ProofBody::Apply => {
+                writeln!(w, "    apply({})", tactic)?;
+                writeln!(w, "    done")
+            }
+            // Method expressions with arguments are parenthesized
+            ProofBody::By if tactic.contains(char::is_whitespace) => {
+                writeln!(w, "    by ({})", tactic)
+            }
+            ProofBody::By => writeln!(w, "    by {}", tactic),
+        }
+    }
+
+    pub fn to_coq(&self) -> String {
+        let mut buf = vec![];
+        self.write_coq(&mut buf)
+            .expect("Writing to a buffer does not fail");
+        String::from_utf8(buf).expect("Lemma is valid UTF-8")
+    }
+
+    /// Writes the lemma in Coq syntax, with the premises as implications.
+    /// The proof script is the method set with [Lemma::set_method], or [COQ_DEFAULT_METHOD];
+    /// simplifier rules and queries only apply to Isabelle and are ignored.
+    pub fn write_coq(&self, w: &mut dyn Write) -> io::Result<()> {
+        write!(w, "\nLemma {} : ", self.name)?;
+        if !self.fixes.is_empty() {
+            write!(w, "forall")?;
+            for (var, ty) in &self.fixes {
+                write!(w, " ({} : {})", var, ty)?;
+            }
+            write!(w, ", ")?;
+        }
+        for p in &self.premises {
+            write!(w, "({}) -> ", p)?;
+        }
+        if self.conclusions.is_empty() {
+            write!(w, "True")?;
+        }
+        for (i, c) in self.conclusions.iter().enumerate() {
+            if i > 0 {
+                write!(w, " /\\ ")?;
+            }
+            write!(w, "({})", c)?;
+        }
+        writeln!(w, ".")?;
+        let method = self.method.as_deref().unwrap_or(COQ_DEFAULT_METHOD).trim();
+        writeln!(w, "Proof. {}. Qed.", method.trim_end_matches('.'))
+    }
+
+    pub fn to_lean(&self) -> String {
+        let mut buf = vec![];
+        self.write_lean(&mut buf)
+            .expect("Writing to a buffer does not fail");
+        String::from_utf8(buf).expect("Lemma is valid UTF-8")
+    }
+
+    /// Writes the lemma as a Lean 4 theorem, with the variables and the (anonymous) premises as parameters.
+    /// The proof is the tactic set with [Lemma::set_method], or [LEAN_DEFAULT_METHOD];
+    /// simplifier rules and queries only apply to Isabelle and are ignored.
+    pub fn write_lean(&self, w: &mut dyn Write) -> io::Result<()> {
+        write!(w, "\ntheorem {}", self.name)?;
+        for (var, ty) in &self.fixes {
+            write!(w, " ({} : {})", var, ty)?;
+        }
+        for p in &self.premises {
+            write!(w, " (_ : {})", p)?;
+        }
+        write!(w, " : ")?;
+        if self.conclusions.is_empty() {
+            write!(w, "True")?;
+        }
+        for (i, c) in self.conclusions.iter().enumerate() {
+            if i > 0 {
+                write!(w, " ∧ ")?;
+            }
+            write!(w, "({})", c)?;
+        }
+        let method = self.method.as_deref().unwrap_or(LEAN_DEFAULT_METHOD).trim();
+        writeln!(w, " := by\n  {}", method)
+    }
+
+    /// Splits the lemma into one lemma per conclusion, named `{name}_{i}` for the i-th conclusion.
+    pub fn split_conclusion(&self) -> impl ExactSizeIterator<Item = Lemma> + '_ {
+        self.chunk_conclusions(1)
+    }
+
+    /// Splits the lemma into lemmas with the same premises and at most `size` of the conclusions each.
+    /// The conjunction of the resulting lemmas is equivalent to this lemma.
+    /// The lemmas are created lazily, so only one chunk needs to be in memory at a time.
+    pub fn chunk_conclusions(&self, size: usize) -> impl ExactSizeIterator<Item = Lemma> + '_ {
+        self.conclusions
+            .chunks(size.max(1))
+            .enumerate()
+            .map(move |(i, cons)| {
+                let name = format!("{}_{}", self.name, i);
+                let mut sl = Lemma::new(&name);
+                sl.add_premises(&self.premises).add_conclusions(cons);
+                sl.fixes.clone_from(&self.fixes);
+                sl.declarations.clone_from(&self.declarations);
+                sl.simps.clone_from(&self.simps);
+                sl.method.clone_from(&self.method);
+                sl.body = self.body;
+                sl
+            })
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Debug)]
 pub struct Theory {
     name: String,
     imports: Vec<String>,
+    /// The declarations of all lemmas, each written once before the lemmas
+    declarations: Vec<String>,
     split_lemmata: bool,
-    lemmata: Vec<String>,
+    lemmata: Vec<Lemma>,
 }
 
 impl Theory {
@@ -117,41 +366,256 @@ impl Theory {
         self
     }
 
+    /// Splits the lemmas of the theory, and those added later, into one lemma per conclusion.
+    /// A failed proof then names the conclusion that could not be proven.
+    pub fn split(&mut self) -> &mut Self {
+        self.split_lemmata = true;
+        let lemmata = std::mem::take(&mut self.lemmata);
+        self.lemmata = lemmata.iter().flat_map(Lemma::split_conclusion).collect();
+        self
+    }
+
     pub fn add_lemma(&mut self, builder: Lemma) {
-        if self.split_lemmata {
-            for lem in builder.split_conclusion() {
-                self.lemmata.push(lem.to_isabelle());
+        for declaration in &builder.declarations {
+            if !self.declarations.contains(declaration) {
+                self.declarations.push(declaration.clone());
             }
+        }
+        if self.split_lemmata {
+            self.lemmata.extend(builder.split_conclusion());
         } else {
-            self.lemmata.push(builder.to_isabelle())
+            self.lemmata.push(builder)
         }
     }
 
     pub fn to_isabelle(&self) -> String {
-        let mut theory = String::new();
+        let mut buf = vec![];
+        self.write_isabelle(&mut buf)
+            .expect("Writing to a buffer does not fail");
+        String::from_utf8(buf).expect("Theory is valid UTF-8")
+    }
 
+    /// Writes the theory in Isabelle syntax, lemma by lemma.
+    pub fn write_isabelle(&self, w: &mut dyn Write) -> io::Result<()> {
         // Header
-        theory += &format!("theory {}\n", self.name);
+        writeln!(w, "theory {}", self.name)?;
         // Imports
-        theory += "\timports ";
+        write!(w, "\timports ")?;
         if self.imports.is_empty() {
-            theory += " Main\n";
+            writeln!(w, " Main")?;
         } else {
             for i in &self.imports {
-                theory += i;
-                theory += " ";
+                write!(w, "{} ", i)?;
             }
-            theory += "\n";
+            writeln!(w)?;
+        }
+        writeln!(w, "begin\n")?;
+
+        for declaration in &self.declarations {
+            writeln!(w, "{}\n", declaration)?;
         }
-        theory += "begin\n\n";
 
         // Lemmata
         for lemma in &self.lemmata {
-            theory += lemma;
-            theory += "\n";
+            lemma.write_isabelle(w)?;
+            writeln!(w)?;
         }
 
-        theory += "end\n";
-        theory
+        writeln!(w, "end")
+    }
+
+    pub fn to_coq(&self) -> String {
+        let mut buf = vec![];
+        self.write_coq(&mut buf)
+            .expect("Writing to a buffer does not fail");
+        String::from_utf8(buf).expect("Theory is valid UTF-8")
+    }
+
+    pub fn to_lean(&self) -> String {
+        let mut buf = vec![];
+        self.write_lean(&mut buf)
+            .expect("Writing to a buffer does not fail");
+        String::from_utf8(buf).expect("Theory is valid UTF-8")
+    }
+
+    /// Writes the theory as a Lean 4 file, importing the imports.
+    pub fn write_lean(&self, w: &mut dyn Write) -> io::Result<()> {
+        for i in &self.imports {
+            writeln!(w, "import {}", i)?;
+        }
+        writeln!(w, "-- {}", self.name)?;
+        for declaration in &self.declarations {
+            writeln!(w, "\n{}", declaration)?;
+        }
+        for lemma in &self.lemmata {
+            lemma.write_lean(w)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the theory as a Coq file, requiring the imports after the standard libraries the Coq specs map to.
+    pub fn write_coq(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(w, "(* {} *)", self.name)?;
+        writeln!(w, "From Coq Require Import ZArith String Ascii Bool.")?;
+        for i in &self.imports {
+            writeln!(w, "Require Import {}.", i)?;
+        }
+        for declaration in &self.declarations {
+            writeln!(w, "\n{}", declaration)?;
+        }
+        for lemma in &self.lemmata {
+            lemma.write_coq(w)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Lemma, ProofBody, Query, Theory, EVAL_METHOD};
+
+    #[test]
+    fn chunk_conclusions() {
+        let mut lemma = Lemma::new("l");
+        lemma.add_premise("x = 1").add_conclusions(&[
+            "a".to_owned(),
+            "b".to_owned(),
+            "c".to_owned(),
+        ]);
+        let chunks: Vec<_> = lemma.chunk_conclusions(2).collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].name, "l_0");
+        assert_eq!(chunks[0].conclusions, vec!["a", "b"]);
+        assert_eq!(chunks[1].conclusions, vec!["c"]);
+        assert_eq!(chunks[1].premises, vec!["x = 1"]);
+        lemma.set_method("auto");
+        assert_eq!(
+            lemma.chunk_conclusions(2).next().unwrap().tactic(),
+            "auto simp add: assms"
+        );
+        assert_eq!(lemma.split_conclusion().len(), 3);
+
+        lemma.set_proof_body(ProofBody::By);
+        let mut theory = Theory::new("T", false);
+        theory.add_lemma(lemma);
+        let text = theory.split().to_isabelle();
+        assert!(text.contains("lemma l_2: assumes \"x = 1\" shows \"c\""));
+        assert_eq!(text.matches("by (auto simp add: assms)").count(), 3);
+    }
+
+    #[test]
+    fn declarations_precede_lemmas() {
+        let mut lemma = Lemma::new("l");
+        lemma
+            .add_declaration("datatype Color = is_red: red | is_green: green")
+            .add_conclusion("a")
+            .add_conclusion("b");
+        let mut theory = Theory::new("T", true);
+        theory.add_lemma(lemma);
+        let text = theory.to_isabelle();
+        assert_eq!(text.matches("datatype Color").count(), 1);
+        assert!(text.find("datatype").unwrap() < text.find("lemma l_0").unwrap());
+    }
+
+    #[test]
+    fn fixed_types() {
+        let mut lemma = Lemma::new("l");
+        lemma
+            .add_fix("x", "int")
+            .add_fix("p", "int \\<Rightarrow> bool")
+            .add_premise("x = 1")
+            .add_conclusion("p x");
+        assert!(lemma.to_isabelle().starts_with(
+            "\nlemma l: fixes x :: \"int\" and p :: \"int \\<Rightarrow> bool\" assumes \"x = 1\" shows"
+        ));
+        assert_eq!(lemma.chunk_conclusions(1).next().unwrap().fixes().len(), 2);
+    }
+
+    #[test]
+    fn proof_methods() {
+        let mut lemma = Lemma::new("l");
+        lemma.add_simp("Let_def");
+        assert_eq!(lemma.tactic(), "simp add: Let_def assms");
+        lemma.set_method("force");
+        assert_eq!(lemma.tactic(), "force simp add: Let_def assms");
+        lemma.set_method("eval");
+        assert_eq!(lemma.tactic(), "eval");
+        lemma.set_method("(simp add: assms, arith)");
+        assert_eq!(lemma.tactic(), "(simp add: assms, arith)");
+    }
+
+    #[test]
+    fn query_lemmas() {
+        let mut lemma = Lemma::new("l");
+        lemma.add_premise("x = 1").add_conclusion("x > 1");
+        let nitpick = lemma.with_query(Query::Nitpick).to_isabelle();
+        assert!(nitpick.ends_with("    nitpick\n    oops\n"));
+        assert!(!nitpick.contains("apply"));
+        assert!(lemma
+            .with_query(Query::Sledgehammer)
+            .to_isabelle()
+            .ends_with("    sledgehammer\n    oops\n"));
+        assert!(lemma.to_isabelle().ends_with("    done\n"));
+    }
+
+    #[test]
+    fn lemma_to_coq() {
+        let mut lemma = Lemma::new("l");
+        lemma
+            .add_fix("x", "Z")
+            .add_fix("y", "Z")
+            .add_premises(["x = 1%Z", "y = 2%Z"])
+            .add_conclusions(["Z.ltb x y = true", "Z.gtb y 0%Z = true"]);
+        assert_eq!(
+            lemma.to_coq(),
+            "\nLemma l : forall (x : Z) (y : Z), (x = 1%Z) -> (y = 2%Z) -> (Z.ltb x y = true) /\\ (Z.gtb y 0%Z = true).\nProof. intros; subst; repeat split; vm_compute; reflexivity. Qed.\n"
+        );
+        let mut theory = Theory::new("Validation", false);
+        theory.add_theory_import("SMT.Strings");
+        theory.add_lemma(lemma.set_method("auto.").clone());
+        let text = theory.to_coq();
+        assert!(text.contains("Require Import SMT.Strings.\n"));
+        assert!(text.ends_with("Proof. auto. Qed.\n"));
+    }
+
+    #[test]
+    fn lemma_to_lean() {
+        let mut lemma = Lemma::new("l");
+        lemma
+            .add_fix("x", "Int")
+            .add_premise("x = (1 : Int)")
+            .add_conclusions(["decide (x < 2) = true", "(x == 1) = true"]);
+        assert_eq!(
+            lemma.to_lean(),
+            "\ntheorem l (x : Int) (_ : x = (1 : Int)) : (decide (x < 2) = true) ∧ ((x == 1) = true) := by\n  subst_vars; decide\n"
+        );
+        let mut theory = Theory::new("Validation", false);
+        theory.add_theory_import("Smt.Strings");
+        theory.add_lemma(lemma);
+        assert!(theory.to_lean().starts_with("import Smt.Strings\n"));
+    }
+
+    #[test]
+    fn lemma_to_isabelle() {
+        let mut lemma = Lemma::new("l");
+        lemma
+            .add_premises(["x = 1", "y = 2"])
+            .add_conclusions(["x < y", "y > 0"]);
+        assert_eq!(
+            lemma.to_isabelle(),
+            "\nlemma l: assumes \"x = 1\" and \"y = 2\" shows \"x < y \\<and> y > 0\"\n    apply(simp add: assms)\n    done\n"
+        );
+
+        let mut ground = Lemma::new("g");
+        ground.add_conclusion("(1::int) < 2");
+        assert!(ground
+            .to_isabelle()
+            .starts_with("\nlemma g: shows \"(1::int) < 2\""));
+        ground.set_method(EVAL_METHOD).set_proof_body(ProofBody::By);
+        assert!(ground.to_isabelle().ends_with("\"\n    by eval\n"));
+        ground.set_method("auto");
+        assert!(ground
+            .to_isabelle()
+            .ends_with("\n    by (auto simp add: assms)\n"));
     }
 }
@@ -1 +1,100 @@
+//! SMT model validation using Isabelle/HOL.
+//!
+//! Converts an SMT-LIB formula and a model produced by an SMT solver to an Isabelle/HOL lemma
+//! stating that the model satisfies the formula, and checks the lemma with Isabelle.
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! let formula = "(declare-const x Int)(assert (> x 0))".to_owned();
+//! let model = "sat\n((define-fun x () Int 1))".to_owned();
+//! match smtmv::validate(model, formula, Path::new("/path/to/isabelle_smt")) {
+//!     Ok(smtmv::ValidationResult::Valid) => println!("valid"),
+//!     Ok(result) => println!("{:?}", result),
+//!     Err(e) => eprintln!("{}", e),
+//! }
+//! ```
+//!
+//! Other verification backends implement [LemmaChecker] and are registered in the [ValidationOptions]:
+//!
+//! ```no_run
+//! use smtmv::{CheckResult, CustomChecker, Error, Lemma, LemmaChecker, ValidationOptions};
+//!
+//! struct MyProver;
+//!
+//! impl LemmaChecker for MyProver {
+//!     fn check(&mut self, lemma: &Lemma) -> Result<CheckResult, Error> {
+//!         // Translate lemma.premises() and lemma.conclusions() and run the prover
+//!         Ok(CheckResult::OK)
+//!     }
+//! }
+//!
+//! let mut options = ValidationOptions::default();
+//! options.register_checker(CustomChecker::new("my-prover", |_timeout| Ok(Box::new(MyProver))));
+//! # let (model, formula) = (String::new(), String::new());
+//! let result = smtmv::validate_with_options(model, formula, "/path/to/isabelle_smt".as_ref(), &options);
+//! ```
 
+pub mod batch;
+pub mod cache;
+pub mod cancel;
+pub mod certificate;
+pub mod checker;
+pub mod convert;
+pub mod convert_coq;
+pub mod convert_isabelle;
+pub mod convert_lean;
+pub mod coq;
+mod desugar;
+pub mod doctor;
+pub mod drivers;
+pub mod error;
+pub mod eval;
+pub mod heap;
+pub mod ir;
+pub mod isabelle_env;
+pub mod lean;
+pub mod lemma;
+mod normalize;
+pub mod pipeline;
+pub mod prover;
+mod reglan;
+mod repro;
+mod scopes;
+pub mod serve;
+pub mod shrink;
+mod simplify;
+pub mod solver;
+mod solver_output;
+mod sorts;
+mod spec;
+pub mod spec_check;
+mod transcript;
+pub mod validation;
+
+use std::path::Path;
+
+pub use cancel::Cancellation;
+pub use checker::{CheckResult, CustomChecker, LemmaChecker, UnknownReason};
+pub use error::{Error, ErrorCategory, IsabelleFailure, SourcePosition, UnsupportedContext};
+pub use lemma::{Lemma, Theory};
+pub use validation::{ValidationOptions, ValidationResult};
+
+/// Validates the model against the formula with the default [ValidationOptions].
+/// `theory_path` is the root of the Isabelle SMT theories, containing the `spec.json`.
+/// See [validate_with_options] for details on the result.
+pub fn validate(
+    smt_model: String,
+    smt_formula: String,
+    theory_path: &Path,
+) -> Result<ValidationResult, Error> {
+    validate_with_options(
+        smt_model,
+        smt_formula,
+        theory_path,
+        &ValidationOptions::default(),
+    )
+}
+
+pub use validation::validate as validate_with_options;
+pub use validation::validate_async;
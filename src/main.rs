@@ -1,25 +1,74 @@
-mod checker;
-mod convert;
-mod error;
-mod lemma;
-mod validation;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use env_logger::{Builder, Target};
+use log::LevelFilter;
+use smtmv::batch;
+use smtmv::checker::{CheckerKind, PROGRESS_TARGET};
+use smtmv::convert::{BuiltinSpec, TargetKind, UninterpretedSorts};
+use smtmv::drivers::{self, SolverConfig};
+use smtmv::pipeline::Stage;
+use smtmv::serve;
+use smtmv::shrink;
+use smtmv::solver::{self, Solver, SolverAnswer};
+use smtmv::spec_check::{self, Severity};
+use smtmv::validation::{self, PartialModel};
+use smtmv::ErrorCategory;
 
-use clap::{command, ArgGroup, Parser};
-use env_logger::Builder;
-
-use std::fs::{self, File};
+use std::env;
+use std::fs;
 use std::io::Write;
 use std::io::{self, BufReader, Read};
-use std::path::PathBuf;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::str::FromStr;
+use std::time::Duration;
+
+/// The exit code if the model is valid, or if the theory was only written with `--emit-only`
+const EXIT_VALID: i32 = 0;
+/// The exit code if the model is invalid
+const EXIT_INVALID: i32 = 1;
+/// The exit code if the validity of the model could not be determined
+const EXIT_UNKNOWN: i32 = 2;
+/// The exit code if the lemma is false, but only with uninterpreted functions, so the model may still be valid
+const EXIT_UNINTERPRETED: i32 = 3;
+/// The exit code for invalid arguments and inputs that cannot be read or parsed
+const EXIT_INPUT_ERROR: i32 = 10;
+/// The exit code if the spec is invalid or not compatible with this tool or the theories
+const EXIT_SPEC_ERROR: i32 = 11;
+/// The exit code if Isabelle failed
+const EXIT_ISABELLE_ERROR: i32 = 12;
+/// The exit code if the solver could not be run or gave no answer
+const EXIT_SOLVER_ERROR: i32 = 13;
+/// The exit code if a check was aborted because of the timeout
+const EXIT_TIMEOUT: i32 = 124;
+
+/// The formula path that stands for stdin
+const STDIN_PATH: &str = "-";
+
+/// The environment variable naming the theory root to use if none is given
+const DEFAULT_THEORY_ROOT_VAR: &str = "ISABELLE_SMT";
+
+/// The format of the validation result printed to stdout
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// A single word: valid, invalid, or unknown
+    Text,
+    /// A JSON object with the verdict and the details of the validation
+    Json,
+}
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
-#[clap(group(ArgGroup::new("models").required(true).args(&["stdin", "model"])))]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
+#[clap(group(ArgGroup::new("models").args(&["stdin", "model", "combined"])))]
 struct Cli {
-    /// Path to file containing the SMT formula
-    smt: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to file containing the SMT formula, or '-' to read it from stdin (with --model or --combined);
+    /// a directory or glob pattern validates each formula file 'foo.smt2' with the model 'foo.model'
+    #[arg(required = true)]
+    smt: Option<String>,
 
     /// Path to file containing the model (must not be used with --stdin)
     #[arg(long)]
@@ -29,58 +78,1176 @@ struct Cli {
     #[arg(long)]
     stdin: bool,
 
-    /// Path to the root of the theory directory
-    #[arg(short = 'T', required = true)]
-    throot: String,
+    /// The SMT file contains the script followed by the solver's answer and model
+    #[arg(long)]
+    combined: bool,
+
+    #[command(flatten)]
+    checker_args: CheckerArgs,
+
+    #[command(flatten)]
+    options: Options,
+
+    /// Output format of the result
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// The Isabelle installation directory or isabelle executable to use instead of the one found automatically
+    #[arg(long, global = true, value_name = "PATH")]
+    isabelle_path: Option<PathBuf>,
+
+    /// Log more details to stderr: -v for the steps of the validation, -vv for debugging, -vvv for tracing
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only log errors, without warnings and progress messages
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand)]
+enum Command {
+    /// Validate all formula/model pairs listed in a manifest, reusing one Isabelle session
+    Batch {
+        /// CSV file with 'formula,model' lines, or JSON array of {"formula", "model"} objects
+        manifest: PathBuf,
+
+        #[command(flatten)]
+        checker_args: CheckerArgs,
+
+        #[command(flatten)]
+        options: Options,
+
+        /// Number of pairs to validate concurrently, each worker with its own Isabelle session
+        #[arg(long, short = 'j', default_value_t = 1)]
+        jobs: usize,
+
+        /// Output format of the results, one line per pair
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    /// Run an SMT solver on the formula and validate the model it finds
+    SolveAndCheck {
+        /// Path to file containing the SMT formula
+        smt: String,
+
+        /// The solver to run
+        #[arg(long, value_enum)]
+        solver: Solver,
+
+        /// The solver's executable, if it is not on the PATH under its usual name
+        #[arg(long, value_name = "PATH")]
+        solver_path: Option<PathBuf>,
+
+        #[command(flatten)]
+        checker_args: CheckerArgs,
+
+        #[command(flatten)]
+        options: Options,
+
+        /// Output format of the result
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    /// Run several SMT solvers on the formula, validate their models, and report disagreements
+    Diff {
+        /// Path to file containing the SMT formula
+        smt: String,
+
+        /// Comma-separated solvers to run
+        #[arg(long, value_enum, value_delimiter = ',', num_args = 1.., required = true)]
+        solver: Vec<Solver>,
+
+        /// The executable of a solver that is not on the PATH under its usual name, e.g. z3=/opt/z3/bin/z3 (can be repeated)
+        #[arg(long, value_name = "SOLVER=PATH", value_parser = parse_solver_path)]
+        solver_path: Vec<(Solver, PathBuf)>,
+
+        #[command(flatten)]
+        checker_args: CheckerArgs,
+
+        #[command(flatten)]
+        options: Options,
+
+        /// Output format of the results
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    /// Shrink an invalid or unknown validation to a minimal formula and model with the same verdict
+    Shrink {
+        /// Path to file containing the SMT formula
+        smt: String,
+
+        /// Path to file containing the model
+        #[arg(long)]
+        model: String,
+
+        /// Write the shrunk formula and model to formula.smt2 and model.smt2 in this directory instead of printing them
+        #[arg(long, value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        checker_args: CheckerArgs,
+
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Validate requests read as JSON lines from stdin or a unix socket, keeping the Isabelle session warm between them
+    Serve {
+        /// Listen on this unix socket instead of reading stdin and writing stdout; connections are served one after another
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+
+        #[command(flatten)]
+        checker_args: CheckerArgs,
+
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Build the heap image of the smt session, which the batch checker needs
+    BuildHeap {
+        /// Path to the root of the theory directory; repeat to add the sessions of further roots
+        #[arg(short = 'T')]
+        throot: Vec<String>,
+
+        /// Rebuild the heap even if Isabelle considers it up to date
+        #[arg(long)]
+        clean: bool,
+    },
+    /// Check the setup step by step: Isabelle, the theory root and its spec, the heap image, and a trivial validation
+    Doctor {
+        /// Path to the root of the theory directory; repeat to add the sessions of further roots
+        #[arg(short = 'T')]
+        throot: Vec<String>,
+
+        /// The checker to validate the trivial model with
+        #[arg(long, value_enum, default_value_t = CheckerKind::Batch)]
+        checker: CheckerKind,
+
+        /// Use the spec shipped for this logic instead of the spec.json of the theory root
+        #[arg(long, value_enum, value_name = "LOGIC")]
+        builtin_spec: Option<BuiltinSpec>,
+    },
+    /// Work with the spec of a theory root
+    Spec {
+        #[command(subcommand)]
+        command: SpecCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum SpecCommand {
+    /// Check the spec.json for schema violations, unknown keys, duplicate mappings, and names the theories do not define
+    Check {
+        /// Path to the root of the theory directory
+        #[arg(short = 'T')]
+        throot: String,
+
+        /// The spec to check instead of the spec.json in the theory root
+        #[arg(long, value_name = "FILE")]
+        spec: Option<PathBuf>,
+    },
+}
+
+/// The theory roots and the checker, shared by the commands that validate models
+#[derive(Args)]
+struct CheckerArgs {
+    /// Path to the root of the theory directory; repeat to merge the specs of further roots, later ones overriding
+    #[arg(short = 'T', required_unless_present = "builtin_spec")]
+    throot: Vec<String>,
+
+    /// The checker to use; the server keeps the theories loaded between checks, coq and lean check the lemma converted to Coq or Lean 4
+    /// [default: batch, or server for the batch, diff, shrink, and serve commands]
+    #[arg(long, value_enum)]
+    checker: Option<CheckerKind>,
+}
+
+impl CheckerArgs {
+    /// Returns the checker to use, or the default of the command if none is given.
+    fn checker(&self, default: CheckerKind) -> CheckerKind {
+        self.checker.unwrap_or(default)
+    }
+}
+
+/// Options shared by single and batch validation
+#[derive(Args)]
+struct Options {
+    /// Comma-separated SMT-LIB functions to treat as uninterpreted instead of using their spec mapping
+    #[arg(long, value_delimiter = ',')]
+    uninterpret: Vec<String>,
+
+    /// Only validate assertions that mention a variable assigned by the model or no variable; the verdict is then at best unknown
+    #[arg(long)]
+    slice: bool,
+
+    /// Normalize assertions (negation normal form, ite-lifting, flattening) before generating the lemma
+    #[arg(long)]
+    normalize: bool,
+
+    /// Simplify the converted terms (fold ground arithmetic, string literals, and Boolean constants) before generating the lemma
+    #[arg(long)]
+    simplify: bool,
+
+    /// Substitute the model's values into the formula instead of assuming the model, so the lemma is ground
+    #[arg(long)]
+    substitute_model: bool,
+
+    /// Skip assertions using unsupported SMT-LIB functions; the verdict is then at best unknown
+    #[arg(long)]
+    lenient: bool,
+
+    /// Check the assertions in independent lemmas of at most this many conjuncts each
+    #[arg(long)]
+    chunk_size: Option<usize>,
+
+    /// Check each assertion in a lemma of its own and report which ones could not be proven
+    #[arg(long, conflicts_with = "chunk_size")]
+    split_conclusions: bool,
+
+    /// Write a self-contained reproduction bundle to this directory if the model is not found valid
+    #[arg(long)]
+    repro_dir: Option<PathBuf>,
+
+    /// Keep the files of checks that do not prove the lemma (Unknown, Invalid, or Error) and print where they are
+    #[arg(long)]
+    keep_temp: bool,
+
+    /// Additional Isabelle theory to import into the validation theory (can be repeated)
+    #[arg(long = "import", value_name = "THEORY")]
+    imports: Vec<String>,
+
+    /// Store proof certificates in this directory and skip proving lemmas that already have one
+    #[arg(long)]
+    certificates: Option<PathBuf>,
+
+    /// Prove all lemmas again, ignoring existing certificates
+    #[arg(long, requires = "certificates")]
+    recheck: bool,
+
+    /// Cache the verdicts in $XDG_CACHE_HOME/smtmv and skip validations that were done before with the same inputs
+    #[arg(long, overrides_with = "no_cache")]
+    cache: bool,
+
+    /// Do not use the verdict cache (overrides an earlier --cache)
+    #[arg(long, overrides_with = "cache")]
+    no_cache: bool,
+
+    /// Abort checking a lemma after this many seconds; the result is then unknown (exit code 124)
+    #[arg(long, value_name = "SECS")]
+    timeout: Option<u64>,
+
+    /// Limit the heaps of the Isabelle process to this many megabytes in total (batch checker); checks exceeding it are unknown
+    #[arg(long, value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+    memory_limit: Option<u64>,
+
+    /// If the model cannot be shown valid, search for a counterexample with Nitpick and print it
+    #[arg(long)]
+    counterexample: bool,
+
+    /// If the model is valid, report the assignments it does not need, checking the lemma again without them
+    #[arg(long)]
+    model_core: bool,
+
+    /// The proof method, e.g. simp, auto, force, eval, or any Isabelle method expression;
+    /// a Coq or Lean tactic with the coq or lean checker [default: simp, eval for ground lemmas, or a tactic computing the conclusions for Coq and Lean]
+    #[arg(long, value_name = "METHOD")]
+    tactic: Option<String>,
+
+    /// Retry unproven lemmas with stronger proof methods (simp, auto, fastforce, eval, Sledgehammer)
+    #[arg(long)]
+    escalate: bool,
+
+    /// Represent sorts declared with declare-sort as declared types or as type variables
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = UninterpretedSorts::Typedecl)]
+    uninterpreted_sorts: UninterpretedSorts,
+
+    /// How variables the model does not assign are treated: the model is invalid, valid iff the formula holds for all of their values, or completed with default values
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = PartialModel::Invalid)]
+    partial_model: PartialModel,
+
+    /// Fail with the differences if the model assigns variables the formula does not declare,
+    /// or leaves variables of the formula unassigned (unless accepted by --partial-model)
+    #[arg(long)]
+    strict_model: bool,
+
+    /// Read the formula and the model as SMT-LIB 2.5: accept the old names of string functions (e.g. str.to.int)
+    /// and \xAB escape sequences in string literals
+    #[arg(long)]
+    smtlib25: bool,
+
+    /// Validate against the assertions live at the N-th check-sat (counting from 0) instead of the last one
+    #[arg(long, value_name = "N")]
+    check_index: Option<usize>,
+
+    /// The measure proving termination of functions defined with define-fun-rec; otherwise termination is assumed
+    #[arg(long, value_name = "TERM")]
+    termination_measure: Option<String>,
+
+    /// Use the spec shipped for this logic instead of the spec.json of the theory root
+    #[arg(long, value_enum, value_name = "LOGIC")]
+    builtin_spec: Option<BuiltinSpec>,
+
+    /// Use the spec in this file instead of the spec.json of the theory root
+    #[arg(long, value_name = "FILE", conflicts_with = "builtin_spec")]
+    spec: Option<PathBuf>,
+
+    /// Write a transcript of the theories, commands, output, and timings of the checks to this file
+    /// (in batch mode, to <N>.log in this directory for the N-th model, counting from 0)
+    #[arg(long, value_name = "PATH")]
+    log_isabelle: Option<PathBuf>,
+
+    /// Write the generated theory to this file or directory without running Isabelle
+    #[arg(long, value_name = "PATH")]
+    emit_only: Option<PathBuf>,
+
+    /// Always check the lemma with Isabelle, even if evaluating the formula under the model decides the verdict
+    #[arg(long)]
+    no_eval: bool,
+
+    /// Comma-separated stages to decide the verdict with, each tried only if the previous ones leave it unknown
+    /// [default: eval,<checker>]
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "no_eval")]
+    pipeline: Vec<Stage>,
+}
+
+impl Options {
+    fn into_validation_options(
+        self,
+        theory_roots: Vec<PathBuf>,
+        checker: CheckerKind,
+        format: OutputFormat,
+    ) -> validation::ValidationOptions {
+        validation::ValidationOptions {
+            uninterpreted: self.uninterpret,
+            slice: self.slice,
+            normalize: self.normalize,
+            simplify: self.simplify,
+            substitute_model: self.substitute_model,
+            lenient: self.lenient,
+            chunk_size: self.chunk_size,
+            split_conclusions: self.split_conclusions,
+            repro_dir: self.repro_dir,
+            keep_temp: self.keep_temp,
+            imports: self.imports,
+            theory_roots,
+            builtin_spec: self.builtin_spec,
+            spec_file: self.spec,
+            check_index: self.check_index,
+            termination_measure: self.termination_measure,
+            certificate_dir: self.certificates,
+            recheck: self.recheck,
+            cache_dir: if self.cache && !self.no_cache {
+                cache_dir()
+            } else {
+                None
+            },
+            detailed_report: format == OutputFormat::Json,
+            checker,
+            custom_checker: None,
+            cancellation: None,
+            timeout: self.timeout.map(Duration::from_secs),
+            memory_limit: self.memory_limit,
+            counterexample: self.counterexample,
+            model_core: self.model_core,
+            method: self.tactic,
+            escalate: self.escalate,
+            emit_only: self.emit_only,
+            log_isabelle: self.log_isabelle,
+            // Pipelines checking the lemma with another prover generate the lemma in its language
+            target: self
+                .pipeline
+                .iter()
+                .filter_map(|s| s.checker_kind())
+                .chain(self.pipeline.is_empty().then_some(checker))
+                .map(CheckerKind::target)
+                .find(|t| *t != TargetKind::Isabelle)
+                .unwrap_or_default(),
+            pipeline: if self.pipeline.is_empty() {
+                let eval = (!self.no_eval).then_some(Stage::Eval);
+                eval.into_iter().chain([checker.into()]).collect()
+            } else {
+                self.pipeline
+            },
+            uninterpreted_sorts: self.uninterpreted_sorts,
+            partial_model: self.partial_model,
+            strict_model: self.strict_model,
+            smtlib25: self.smtlib25,
+        }
+    }
+}
+
+/// Returns the absolute path to the theory root.
+fn theory_root(throot: &str) -> PathBuf {
+    let th_path = PathBuf::from_str(throot).unwrap();
+    match fs::canonicalize(&th_path) {
+        Ok(path) => path,
+        Err(e) => {
+            log::error!("Invalid theory root {}: {}", th_path.display(), e);
+            exit(EXIT_INPUT_ERROR);
+        }
+    }
+}
+
+/// Returns the absolute paths to the first theory root and to the additional ones.
+/// Without any theory root, the theories are expected in the [DEFAULT_THEORY_ROOT_VAR] directory or in `~/isabelle_smt`.
+fn theory_roots(throots: &[String]) -> (PathBuf, Vec<PathBuf>) {
+    let mut roots = throots.iter().map(|r| theory_root(r));
+    let first = roots.next().unwrap_or_else(default_theory_root);
+    (first, roots.collect())
+}
+
+/// Returns the directory of the verdict cache, or None with a warning if there is no cache directory.
+fn cache_dir() -> Option<PathBuf> {
+    let dir = smtmv::cache::default_dir();
+    if dir.is_none() {
+        log::warn!("Neither $XDG_CACHE_HOME nor $HOME is set, not caching verdicts");
+    }
+    dir
+}
+
+/// Returns the standard location of the companion Isabelle theories, whether it exists or not.
+fn default_theory_root_path() -> Option<PathBuf> {
+    std::env::var_os(DEFAULT_THEORY_ROOT_VAR)
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join("isabelle_smt")))
+}
+
+/// Returns the standard location of the companion Isabelle theories.
+fn default_theory_root() -> PathBuf {
+    match default_theory_root_path().and_then(|r| fs::canonicalize(r).ok()) {
+        Some(root) => root,
+        None => {
+            log::error!(
+                "No theory root given with -T, and none found in ${} or ~/isabelle_smt",
+                DEFAULT_THEORY_ROOT_VAR
+            );
+            exit(EXIT_INPUT_ERROR);
+        }
+    }
 }
 
 fn main() {
-    init_logger();
-    let cli = Cli::parse();
-    let raw_model = if cli.stdin {
-        let mut stdin = io::stdin();
-        let mut lines = String::new();
-        stdin
-            .read_to_string(&mut lines)
-            .expect("Failed to read model from stdin");
-        lines
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) if e.use_stderr() => {
+            // Usage errors must not be mistaken for a verdict
+            let _ = e.print();
+            exit(EXIT_INPUT_ERROR);
+        }
+        Err(e) => e.exit(),
+    };
+    init_logger(cli.verbose, cli.quiet);
+    if let Some(path) = &cli.isabelle_path {
+        if let Err(e) = smtmv::isabelle_env::use_installation(path) {
+            log::error!("{}", e);
+            exit(EXIT_INPUT_ERROR);
+        }
+    }
+    // Before any thread is started, since it changes the environment; commands needing Isabelle report a missing one
+    if let Err(e) = smtmv::isabelle_env::setup() {
+        log::debug!("{}", e);
+    }
+    match cli.command {
+        Some(Command::Batch {
+            manifest,
+            checker_args,
+            options,
+            jobs,
+            format,
+        }) => {
+            let checker = checker_args.checker(CheckerKind::Server);
+            run_batch(
+                &manifest,
+                &checker_args.throot,
+                options,
+                checker,
+                jobs,
+                format,
+            );
+            return;
+        }
+        Some(Command::SolveAndCheck {
+            smt,
+            solver,
+            solver_path,
+            checker_args,
+            options,
+            format,
+        }) => {
+            run_solve_and_check(
+                &smt,
+                solver,
+                solver_path.as_deref(),
+                &checker_args.throot,
+                options,
+                checker_args.checker(CheckerKind::Batch),
+                format,
+            );
+            return;
+        }
+        Some(Command::Diff {
+            smt,
+            solver,
+            solver_path,
+            checker_args,
+            options,
+            format,
+        }) => {
+            let solvers: Vec<SolverConfig> = solver
+                .into_iter()
+                .map(|s| SolverConfig {
+                    solver: s,
+                    executable: solver_path
+                        .iter()
+                        .find(|(p, _)| *p == s)
+                        .map(|(_, path)| path.clone()),
+                })
+                .collect();
+            let checker = checker_args.checker(CheckerKind::Server);
+            run_diff(
+                &smt,
+                &solvers,
+                &checker_args.throot,
+                options,
+                checker,
+                format,
+            );
+            return;
+        }
+        Some(Command::Shrink {
+            smt,
+            model,
+            output_dir,
+            checker_args,
+            options,
+        }) => {
+            run_shrink(
+                &smt,
+                &model,
+                output_dir.as_deref(),
+                &checker_args.throot,
+                options,
+                checker_args.checker(CheckerKind::Server),
+            );
+            return;
+        }
+        Some(Command::Serve {
+            socket,
+            checker_args,
+            options,
+        }) => {
+            let checker = checker_args.checker(CheckerKind::Server);
+            run_serve(socket.as_deref(), &checker_args.throot, options, checker);
+            return;
+        }
+        Some(Command::BuildHeap { throot, clean }) => {
+            let (th_path, theory_roots) = theory_roots(&throot);
+            let session_dirs: Vec<String> = std::iter::once(th_path)
+                .chain(theory_roots)
+                .map(|r| r.display().to_string())
+                .collect();
+            if let Err(e) = smtmv::heap::build(&session_dirs, clean) {
+                log::error!("{}", e);
+                exit(EXIT_ISABELLE_ERROR);
+            }
+            return;
+        }
+        Some(Command::Doctor {
+            throot,
+            checker,
+            builtin_spec,
+        }) => {
+            run_doctor(&throot, checker, builtin_spec);
+            return;
+        }
+        Some(Command::Spec {
+            command: SpecCommand::Check { throot, spec },
+        }) => {
+            run_spec_check(&throot, spec);
+            return;
+        }
+        None => (),
+    }
+    let smt = cli.smt.unwrap();
+    if smt != STDIN_PATH && (Path::new(&smt).is_dir() || batch::is_pattern(&smt)) {
+        if cli.stdin || cli.model.is_some() || cli.combined {
+            log::error!("The models of a directory or glob pattern are found by their names, do not use --stdin, --model, or --combined");
+            exit(EXIT_INPUT_ERROR);
+        }
+        let checker = cli.checker_args.checker(CheckerKind::Batch);
+        run_suite(
+            &smt,
+            &cli.checker_args.throot,
+            cli.options,
+            checker,
+            cli.format,
+        );
+        return;
+    }
+    let checker = cli.checker_args.checker(CheckerKind::Batch);
+    let (th_path, theory_roots) = theory_roots(&cli.checker_args.throot);
+
+    let mut fm_str = if smt == STDIN_PATH {
+        if cli.stdin {
+            log::error!(
+                "The formula and the model cannot both be read from stdin, use --combined instead"
+            );
+            exit(EXIT_INPUT_ERROR);
+        }
+        read_input("the formula from stdin", read_stdin())
+    } else {
+        read_input(&smt, fs::read_to_string(&smt))
+    };
+
+    let raw_model = if cli.combined {
+        match validation::split_combined(&fm_str) {
+            Ok((script, model)) => {
+                fm_str = script;
+                model
+            }
+            Err(e) => {
+                log::error!("Error: {}", e);
+                exit(EXIT_INPUT_ERROR);
+            }
+        }
+    } else if cli.stdin {
+        read_input("the model from stdin", read_stdin())
     } else if let Some(m) = cli.model {
-        fs::read_to_string(m).unwrap()
+        read_input(&m, fs::read_to_string(&m))
     } else {
-        log::error!("No model");
-        exit(-1);
+        log::error!("No model, use --model, --stdin, or --combined");
+        exit(EXIT_INPUT_ERROR);
     };
 
     log::trace!("Received model: '{}'", raw_model);
 
-    let th_path = PathBuf::from_str(&cli.throot).unwrap();
-    // Make absolute
-    let th_path = fs::canonicalize(th_path).unwrap();
-
-    let mut fm_str = String::new();
-    BufReader::new(File::open(cli.smt).unwrap())
-        .read_to_string(&mut fm_str)
-        .expect("Failed to read formula");
+    let options = cli
+        .options
+        .into_validation_options(theory_roots, checker, cli.format);
 
     log::info!("Starting validation");
-    match validation::validate(raw_model, fm_str, &th_path) {
-        Ok(validation::ValidationResult::Valid) => println!("valid"),
-        Ok(validation::ValidationResult::Invalid) => println!("invalid"),
-        Ok(validation::ValidationResult::Unknown) => println!("unknown"),
-        Err(error::Error::Unsupported(e)) => {
-            log::warn!("Unsupported: {}", e);
-            print!("unknown")
+    let (result, report) = validation::validate_with_report(raw_model, fm_str, &th_path, &options);
+    if cli.format == OutputFormat::Json {
+        print_json(&result, &report);
+    } else {
+        match &result {
+            Ok(validation::ValidationResult::Unknown(smtmv::UnknownReason::NotChecked)) => (),
+            Ok(validation::ValidationResult::Unknown(reason)) => {
+                log::info!("Unknown: {}", reason);
+                println!("unknown")
+            }
+            Ok(r) => println!("{}", r.verdict()),
+            Err(e) => log::error!("Error: {}", e),
+        }
+        if let Some(counterexample) = &report.counterexample {
+            for (var, value) in &counterexample.assignment {
+                println!("  {} = {}", var, value);
+            }
+        }
+        if !report.completed_vars.is_empty() {
+            println!("  completed: {}", report.completed_vars.join(", "));
+        }
+        if let Some(unused) = report.unused_assignments.as_ref().filter(|u| !u.is_empty()) {
+            println!("  unused: {}", unused.join(", "));
+        }
+        if !report.internal_symbols.is_empty() {
+            println!("  solver-internal: {}", report.internal_symbols.join(", "));
+        }
+    }
+    exit(exit_code(&result));
+}
+
+/// Reads all of stdin.
+fn read_stdin() -> io::Result<String> {
+    let mut content = String::new();
+    io::stdin().read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// Returns the content that was read, or exits with [EXIT_INPUT_ERROR] if it could not be read.
+fn read_input(name: &str, content: io::Result<String>) -> String {
+    match content {
+        Ok(content) => content,
+        Err(e) => {
+            log::error!("Could not read {}: {}", name, e);
+            exit(EXIT_INPUT_ERROR);
+        }
+    }
+}
+
+/// Returns the exit code for the result of a validation.
+fn exit_code(result: &Result<validation::ValidationResult, smtmv::Error>) -> i32 {
+    match result {
+        Ok(validation::ValidationResult::Valid) => EXIT_VALID,
+        Ok(validation::ValidationResult::Invalid) => EXIT_INVALID,
+        Ok(validation::ValidationResult::Unknown(smtmv::UnknownReason::NotChecked)) => EXIT_VALID,
+        Ok(validation::ValidationResult::Unknown(smtmv::UnknownReason::Timeout)) => EXIT_TIMEOUT,
+        Ok(validation::ValidationResult::Unknown(smtmv::UnknownReason::Uninterpreted(_))) => {
+            EXIT_UNINTERPRETED
+        }
+        Ok(validation::ValidationResult::Unknown(_)) => EXIT_UNKNOWN,
+        Err(e) => match e.category() {
+            ErrorCategory::Input => EXIT_INPUT_ERROR,
+            ErrorCategory::Spec => EXIT_SPEC_ERROR,
+            ErrorCategory::Isabelle => EXIT_ISABELLE_ERROR,
+        },
+    }
+}
+
+/// Returns the exit code of a batch given the exit codes of two of its pairs.
+/// Errors take precedence over invalid models, invalid models over timeouts,
+/// timeouts over models refuted with uninterpreted functions, and those over unknown results.
+fn worse_exit_code(a: i32, b: i32) -> i32 {
+    let rank = |code| match code {
+        EXIT_VALID => 0,
+        EXIT_UNKNOWN => 1,
+        EXIT_UNINTERPRETED => 2,
+        EXIT_TIMEOUT => 3,
+        EXIT_INVALID => 4,
+        error => 5 + error,
+    };
+    if rank(a) >= rank(b) {
+        a
+    } else {
+        b
+    }
+}
+
+/// Validates all pairs of the manifest and prints one line per pair.
+/// Exits with [EXIT_INPUT_ERROR] if the manifest cannot be read,
+/// and otherwise with the exit code of the worst result, see [worse_exit_code].
+fn run_batch(
+    manifest: &Path,
+    throots: &[String],
+    options: Options,
+    checker: CheckerKind,
+    workers: usize,
+    format: OutputFormat,
+) {
+    let jobs = match batch::read_manifest(manifest) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            log::error!("Error: {}", e);
+            exit(EXIT_INPUT_ERROR);
+        }
+    };
+    let (code, _) = validate_jobs(&jobs, throots, options, checker, workers, format);
+    exit(code);
+}
+
+/// Validates the formula files in a directory or matching a glob pattern, each with the model named after it.
+/// Prints one line per pair like [run_batch], followed by a summary of the results.
+/// Formula files without a model are reported but do not fail the run.
+fn run_suite(
+    path: &str,
+    throots: &[String],
+    options: Options,
+    checker: CheckerKind,
+    format: OutputFormat,
+) {
+    let (jobs, missing) = match batch::discover_jobs(path) {
+        Ok(found) => found,
+        Err(e) => {
+            log::error!("Error: {}", e);
+            exit(EXIT_INPUT_ERROR);
+        }
+    };
+    for formula in &missing {
+        log::warn!("No model for {}, skipping it", formula.display());
+    }
+    let (code, mut summary) = validate_jobs(&jobs, throots, options, checker, 1, format);
+    summary.missing_model = missing.len();
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::json!({ "summary": summary })),
+        OutputFormat::Text => {
+            println!();
+            for (name, count) in [
+                ("valid", summary.valid),
+                ("invalid", summary.invalid),
+                ("unknown", summary.unknown),
+                ("error", summary.error),
+                ("missing model", summary.missing_model),
+                ("total", summary.total()),
+            ] {
+                println!("{:<14}{:>6}", name, count);
+            }
+            if !summary.stages.is_empty() {
+                println!();
+                println!(
+                    "{:<14}{:>6}{:>9}{:>10}",
+                    "stage", "runs", "decided", "time (s)"
+                );
+                for (stage, s) in &summary.stages {
+                    println!(
+                        "{:<14}{:>6}{:>9}{:>10.1}",
+                        stage.to_string(),
+                        s.runs,
+                        s.decided,
+                        s.time_ms as f64 / 1000.0
+                    );
+                }
+            }
+        }
+    }
+    exit(code);
+}
+
+/// Validates the jobs and prints one line per pair.
+/// Returns the exit code of the worst result, see [worse_exit_code], and the number of pairs with each result.
+fn validate_jobs(
+    jobs: &[batch::BatchJob],
+    throots: &[String],
+    options: Options,
+    checker: CheckerKind,
+    workers: usize,
+    format: OutputFormat,
+) -> (i32, batch::BatchSummary) {
+    let (th_path, theory_roots) = theory_roots(throots);
+    let options = options.into_validation_options(theory_roots, checker, format);
+    let mut code = EXIT_VALID;
+    let mut summary = batch::BatchSummary::default();
+    batch::validate_batch(jobs, &th_path, &options, workers, |job, result, report| {
+        code = worse_exit_code(code, exit_code(&result));
+        summary.record(&result, &report);
+        match format {
+            OutputFormat::Json => {
+                let mut json = report_json(&result, &report);
+                json["formula"] = job.formula.display().to_string().into();
+                json["model"] = job.model.display().to_string().into();
+                println!("{}", json);
+            }
+            OutputFormat::Text => {
+                let verdict = match &result {
+                    Ok(r) => r.verdict(),
+                    Err(e) => {
+                        log::error!("Could not validate {}: {}", job.model.display(), e);
+                        "error"
+                    }
+                };
+                println!(
+                    "{}\t{}\t{}",
+                    job.formula.display(),
+                    job.model.display(),
+                    verdict
+                );
+            }
+        }
+    });
+    (code, summary)
+}
+
+/// Runs the solver on the formula and validates the model it finds.
+/// Prints the solver's answer and the verdict of the validation, and exits with the exit code of the validation.
+/// Exits with [EXIT_SOLVER_ERROR] if the solver fails, and with [EXIT_UNKNOWN] if it finds no model.
+fn run_solve_and_check(
+    smt: &str,
+    solver: Solver,
+    solver_path: Option<&Path>,
+    throots: &[String],
+    options: Options,
+    checker: CheckerKind,
+    format: OutputFormat,
+) {
+    let (th_path, theory_roots) = theory_roots(throots);
+    let formula = read_input(smt, fs::read_to_string(smt));
+    let answer = match solver::solve(solver, solver_path, &formula, options.check_index) {
+        Ok(answer) => answer,
+        Err(e) => {
+            log::error!("{}", e);
+            exit(EXIT_SOLVER_ERROR);
+        }
+    };
+    let SolverAnswer::Sat(model) = &answer else {
+        match format {
+            OutputFormat::Json => {
+                println!("{}", serde_json::json!({ "solver": answer.to_string() }))
+            }
+            OutputFormat::Text => println!("solver: {}", answer),
+        }
+        exit(EXIT_UNKNOWN);
+    };
+    let options = options.into_validation_options(theory_roots, checker, format);
+    let (result, report) =
+        validation::validate_with_report(model.clone(), formula, &th_path, &options);
+    match format {
+        OutputFormat::Json => {
+            let mut json = report_json(&result, &report);
+            json["solver"] = answer.to_string().into();
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            println!("solver: {}", answer);
+            match &result {
+                Ok(r) => println!("validation: {}", r.verdict()),
+                Err(e) => {
+                    log::error!("Error: {}", e);
+                    println!("validation: error");
+                }
+            }
+        }
+    }
+    exit(exit_code(&result));
+}
+
+/// Shrinks the formula and the model while the verdict stays the same and prints or writes the result.
+/// Exits with the exit code of the preserved verdict, which is [EXIT_VALID] if the model is valid and there is nothing to shrink.
+fn run_shrink(
+    smt: &str,
+    model: &str,
+    output_dir: Option<&Path>,
+    throots: &[String],
+    options: Options,
+    checker: CheckerKind,
+) {
+    let (th_path, theory_roots) = theory_roots(throots);
+    let formula = read_input(smt, fs::read_to_string(smt));
+    let model = read_input(model, fs::read_to_string(model));
+    let options = options.into_validation_options(theory_roots, checker, OutputFormat::Text);
+    let shrunk = match shrink::shrink(&formula, &model, &th_path, &options) {
+        Ok(shrunk) => shrunk,
+        Err(e) => {
+            log::error!("Error: {}", e);
+            exit(exit_code(&Err(e)));
+        }
+    };
+    if shrunk.result == validation::ValidationResult::Valid {
+        println!("valid");
+        exit(EXIT_VALID);
+    }
+    log::info!(
+        "Shrunk the formula from {} to {} commands and the model from {} to {} entries in {} validations",
+        shrunk.formula_commands.0,
+        shrunk.formula_commands.1,
+        shrunk.model_entries.0,
+        shrunk.model_entries.1,
+        shrunk.validations
+    );
+    match output_dir {
+        Some(dir) => {
+            let written = fs::create_dir_all(dir)
+                .and_then(|_| fs::write(dir.join("formula.smt2"), &shrunk.formula))
+                .and_then(|_| fs::write(dir.join("model.smt2"), &shrunk.model));
+            if let Err(e) = written {
+                log::error!("Could not write to {}: {}", dir.display(), e);
+                exit(EXIT_INPUT_ERROR);
+            }
+            println!("{}", shrunk.result.verdict());
+        }
+        None => {
+            println!("; formula ({})", shrunk.result.verdict());
+            print!("{}", shrunk.formula);
+            println!("; model");
+            print!("{}", shrunk.model);
+        }
+    }
+    exit(exit_code(&Ok(shrunk.result)));
+}
+
+/// Parses a solver executable given as `SOLVER=PATH`.
+fn parse_solver_path(s: &str) -> Result<(Solver, PathBuf), String> {
+    let (solver, path) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected SOLVER=PATH, got '{}'", s))?;
+    Ok((Solver::from_str(solver, true)?, PathBuf::from(path)))
+}
+
+/// Runs the solvers on the formula, validates their models, and prints one line per solver followed by the disagreements.
+/// Exits with [EXIT_INVALID] if there are disagreements, with [EXIT_SOLVER_ERROR] if a solver failed, and with [EXIT_VALID] otherwise.
+fn run_diff(
+    smt: &str,
+    solvers: &[SolverConfig],
+    throots: &[String],
+    options: Options,
+    checker: CheckerKind,
+    format: OutputFormat,
+) {
+    let (th_path, theory_roots) = theory_roots(throots);
+    let formula = read_input(smt, fs::read_to_string(smt));
+    let options = options.into_validation_options(theory_roots, checker, format);
+    let runs = drivers::cross_check(solvers, &formula, &th_path, &options);
+    let disagreements = drivers::disagreements(&runs);
+    let answer = |run: &drivers::SolverRun| match &run.answer {
+        Ok(a) => a.to_string(),
+        Err(e) => {
+            log::error!("{}", e);
+            "error".to_owned()
+        }
+    };
+    match format {
+        OutputFormat::Json => {
+            let runs: Vec<_> = runs
+                .iter()
+                .map(|r| serde_json::json!({ "solver": r.solver.to_string(), "answer": answer(r), "validation": r.verdict() }))
+                .collect();
+            let disagreements: Vec<_> = disagreements.iter().map(|d| d.to_string()).collect();
+            println!(
+                "{}",
+                serde_json::json!({ "runs": runs, "disagreements": disagreements })
+            );
+        }
+        OutputFormat::Text => {
+            for run in &runs {
+                println!("{}\t{}\t{}", run.solver, answer(run), run.verdict());
+            }
+            for disagreement in &disagreements {
+                println!("disagreement: {}", disagreement);
+            }
+        }
+    }
+    if !disagreements.is_empty() {
+        exit(EXIT_INVALID);
+    }
+    if runs.iter().any(|r| r.answer.is_err()) {
+        exit(EXIT_SOLVER_ERROR);
+    }
+}
+
+/// Answers validation requests until stdin ends, or forever on the socket.
+/// Replies are the JSON objects of `--format json` with the `id` of the request.
+fn run_serve(socket: Option<&Path>, throots: &[String], options: Options, checker: CheckerKind) {
+    let (th_path, theory_roots) = theory_roots(throots);
+    let options = options.into_validation_options(theory_roots, checker, OutputFormat::Text);
+    let mut pipeline = None;
+    let Some(socket) = socket else {
+        if let Err(e) = serve::serve(
+            BufReader::new(std::io::stdin()),
+            std::io::stdout(),
+            &th_path,
+            &options,
+            &mut pipeline,
+            report_json,
+        ) {
+            log::error!("Could not serve requests: {}", e);
+            exit(EXIT_INPUT_ERROR);
+        }
+        return;
+    };
+    // A socket left behind by an earlier daemon is replaced, any other file is not
+    if fs::symlink_metadata(socket).is_ok_and(|m| m.file_type().is_socket()) {
+        let _ = fs::remove_file(socket);
+    }
+    let listener = match UnixListener::bind(socket) {
+        Ok(l) => l,
+        Err(e) => {
+            log::error!("Could not listen on {}: {}", socket.display(), e);
+            exit(EXIT_INPUT_ERROR);
         }
+    };
+    log::info!(target: PROGRESS_TARGET, "Listening on {}", socket.display());
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| {
+            serve::serve(
+                BufReader::new(&stream),
+                &stream,
+                &th_path,
+                &options,
+                &mut pipeline,
+                report_json,
+            )
+        });
+        match result {
+            Ok(n) => log::info!("Answered {} requests of a connection", n),
+            Err(e) => log::warn!("Connection failed: {}", e),
+        }
+    }
+}
+
+/// Checks the environment step by step and exits with the code of the first failed step.
+/// Theory roots that do not exist are reported by the check rather than rejected.
+fn run_doctor(throot: &[String], checker: CheckerKind, builtin_spec: Option<BuiltinSpec>) {
+    let roots: Vec<PathBuf> = if throot.is_empty() {
+        default_theory_root_path().into_iter().collect()
+    } else {
+        throot.iter().map(PathBuf::from).collect()
+    };
+    let roots: Vec<PathBuf> = roots
+        .into_iter()
+        .map(|r| fs::canonicalize(&r).unwrap_or(r))
+        .collect();
+    let steps = smtmv::doctor::check(&roots, checker, builtin_spec);
+    for step in &steps {
+        println!("{}", step);
+    }
+    if let Some(smtmv::doctor::Status::Failed(e)) = steps
+        .into_iter()
+        .map(|s| s.status)
+        .find(|s| matches!(s, smtmv::doctor::Status::Failed(_)))
+    {
+        exit(exit_code(&Err(e)));
+    }
+}
+
+/// Checks the spec and prints one line per problem found.
+/// Exits with [EXIT_INPUT_ERROR] if the spec cannot be read and with [EXIT_SPEC_ERROR] if it has errors;
+/// warnings alone do not fail the check.
+fn run_spec_check(throot: &str, spec: Option<PathBuf>) {
+    let th_path = theory_root(throot);
+    let spec = spec.unwrap_or_else(|| spec_check::spec_file(&th_path));
+    let issues = match spec_check::check_spec(&spec, &th_path) {
+        Ok(issues) => issues,
         Err(e) => {
             log::error!("Error: {}", e);
-            exit(-1);
+            exit(EXIT_INPUT_ERROR);
         }
+    };
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    if issues.iter().any(|i| i.severity == Severity::Error) {
+        exit(EXIT_SPEC_ERROR);
+    }
+    if issues.is_empty() {
+        println!("ok");
     }
 }
 
-fn init_logger() {
-    let mut builder = Builder::from_default_env();
+/// Prints the result and the report as a single JSON object.
+fn print_json(
+    result: &Result<validation::ValidationResult, smtmv::Error>,
+    report: &validation::ValidationReport,
+) {
+    println!("{}", report_json(result, report));
+}
+
+/// Returns the report as a JSON object with the verdict added.
+fn report_json(
+    result: &Result<validation::ValidationResult, smtmv::Error>,
+    report: &validation::ValidationReport,
+) -> serde_json::Value {
+    let mut json = serde_json::to_value(report).expect("Report is serializable");
+    let (verdict, reason) = match result {
+        Ok(validation::ValidationResult::Unknown(reason)) => ("unknown", Some(reason.to_string())),
+        Ok(r) => (r.verdict(), None),
+        Err(e) => ("error", Some(e.to_string())),
+    };
+    json["verdict"] = verdict.into();
+    json["reason"] = reason.into();
+    if let Err(e) = result {
+        json["error_category"] = format!("{:?}", e.category()).to_lowercase().into();
+        if let Some(position) = e.position() {
+            json["position"] =
+                serde_json::json!({ "line": position.line, "column": position.column });
+        }
+    }
+    json
+}
+
+/// Logs to stderr, so that stdout only carries the results, e.g. the JSON output.
+/// Without -v or -q, RUST_LOG selects what is logged, and otherwise warnings and progress messages.
+fn init_logger(verbose: u8, quiet: bool) {
+    let mut builder = Builder::new();
     builder
-        .format(|buf, record| writeln!(buf, "[{}] {}", record.level(), record.args()))
-        .init();
+        .target(Target::Stderr)
+        .format(|buf, record| writeln!(buf, "[{}] {}", record.level(), record.args()));
+    match (quiet, verbose, env::var("RUST_LOG")) {
+        (true, ..) => builder.filter_level(LevelFilter::Error),
+        (false, 0, Ok(filters)) => builder.parse_filters(&filters),
+        (false, 0, Err(_)) => builder
+            .filter_level(LevelFilter::Warn)
+            .filter(Some(PROGRESS_TARGET), LevelFilter::Info),
+        (false, 1, _) => builder.filter_level(LevelFilter::Info),
+        (false, 2, _) => builder.filter_level(LevelFilter::Debug),
+        (false, _, _) => builder.filter_level(LevelFilter::Trace),
+    };
+    builder.init();
 }
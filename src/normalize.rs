@@ -0,0 +1,316 @@
+//! Boolean normalization of SMT-LIB assertions before conversion.
+//!
+//! The pipeline lifts `ite` out of term positions, pushes negations inward (negation normal form),
+//! and flattens nested conjunctions and disjunctions.
+//! Top-level conjunctions are split into separate assertions.
+//! The resulting goals are much easier for Isabelle's simplifier than the raw benchmark structure.
+
+use smt2parser::concrete::{Command, QualIdentifier, Symbol, Term};
+use smt2parser::visitors::Identifier;
+
+/// Normalizes all assertions of the given commands, splitting top-level conjunctions into separate assertions.
+/// Other commands are kept unchanged.
+pub fn normalize_commands(commands: Vec<Command>) -> Vec<Command> {
+    let mut normalized = vec![];
+    for c in commands {
+        match c {
+            Command::Assert { term } => {
+                for term in normalize(&term) {
+                    normalized.push(Command::Assert { term });
+                }
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+/// Normalizes a single assertion and returns its top-level conjuncts.
+pub fn normalize(term: &Term) -> Vec<Term> {
+    let term = lift_ite(term);
+    let term = nnf(&term, true);
+    let term = flatten(&term);
+    match as_application(&term, "and") {
+        Some(conjuncts) => conjuncts.to_vec(),
+        None => vec![term],
+    }
+}
+
+/// The Boolean connectives the normalization descends into.
+const CONNECTIVES: [&str; 5] = ["and", "or", "not", "=>", "xor"];
+
+/// Returns the name of the applied function if the term is an application of a simple identifier.
+fn head_symbol(term: &Term) -> Option<&str> {
+    match term {
+        Term::Application {
+            qual_identifier:
+                QualIdentifier::Simple {
+                    identifier: Identifier::Simple { symbol },
+                },
+            ..
+        } => Some(&symbol.0),
+        _ => None,
+    }
+}
+
+/// Returns the arguments if the term is an application of the given function.
+pub(crate) fn as_application<'a>(term: &'a Term, op: &str) -> Option<&'a [Term]> {
+    match term {
+        Term::Application { arguments, .. } if head_symbol(term) == Some(op) => Some(arguments),
+        _ => None,
+    }
+}
+
+/// Builds an application of the function with the given name.
+pub(crate) fn app(op: &str, arguments: Vec<Term>) -> Term {
+    Term::Application {
+        qual_identifier: QualIdentifier::Simple {
+            identifier: Identifier::Simple {
+                symbol: Symbol(op.to_owned()),
+            },
+        },
+        arguments,
+    }
+}
+
+fn not(term: Term) -> Term {
+    app("not", vec![term])
+}
+
+/// Returns true iff the term is a Boolean connective whose arguments are formulas.
+fn is_connective(term: &Term) -> bool {
+    match head_symbol(term) {
+        Some(op) => CONNECTIVES.contains(&op) || (op == "ite" && is_formula_ite(term)),
+        None => false,
+    }
+}
+
+/// Returns true iff the term is a well-formed `ite`.
+/// In formula position, such an `ite` is Boolean and treated as a connective.
+fn is_formula_ite(term: &Term) -> bool {
+    as_application(term, "ite").is_some_and(|args| args.len() == 3)
+}
+
+/// Lifts `ite` terms occurring inside atoms to the formula level:
+/// `P[(ite c a b)]` becomes `(and (or (not c) P[a]) (or c P[b]))`.
+fn lift_ite(term: &Term) -> Term {
+    match term {
+        Term::Application {
+            qual_identifier,
+            arguments,
+        } if is_connective(term) => Term::Application {
+            qual_identifier: qual_identifier.clone(),
+            arguments: arguments.iter().map(lift_ite).collect(),
+        },
+        Term::Application { .. } => match find_ite(term) {
+            Some((cond, then_branch, else_branch)) => {
+                let then_atom = replace_first_ite(term, &then_branch);
+                let else_atom = replace_first_ite(term, &else_branch);
+                app(
+                    "and",
+                    vec![
+                        app("or", vec![not(lift_ite(&cond)), lift_ite(&then_atom)]),
+                        app("or", vec![lift_ite(&cond), lift_ite(&else_atom)]),
+                    ],
+                )
+            }
+            None => term.clone(),
+        },
+        t => t.clone(),
+    }
+}
+
+/// Finds the first `ite` in argument position of an atom and returns its condition and branches.
+fn find_ite(term: &Term) -> Option<(Term, Term, Term)> {
+    match term {
+        Term::Application { arguments, .. } => {
+            for a in arguments {
+                if let Some([c, t, e]) = as_application(a, "ite") {
+                    return Some((c.clone(), t.clone(), e.clone()));
+                }
+                if let Some(found) = find_ite(a) {
+                    return Some(found);
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Replaces the first `ite` (in the order of [find_ite]) in argument position by the given branch.
+fn replace_first_ite(term: &Term, branch: &Term) -> Term {
+    fn replace(term: &Term, branch: &Term, done: &mut bool) -> Term {
+        match term {
+            Term::Application {
+                qual_identifier,
+                arguments,
+            } => {
+                let mut new_args = vec![];
+                for a in arguments {
+                    if *done {
+                        new_args.push(a.clone());
+                    } else if is_formula_ite(a) {
+                        *done = true;
+                        new_args.push(branch.clone());
+                    } else {
+                        new_args.push(replace(a, branch, done));
+                    }
+                }
+                Term::Application {
+                    qual_identifier: qual_identifier.clone(),
+                    arguments: new_args,
+                }
+            }
+            t => t.clone(),
+        }
+    }
+    replace(term, branch, &mut false)
+}
+
+/// Converts the term to negation normal form.
+/// If `positive` is false, the negation of the term is converted.
+fn nnf(term: &Term, positive: bool) -> Term {
+    let negate_if = |t: Term| if positive { t } else { not(t) };
+    match (head_symbol(term), term) {
+        (Some("not"), Term::Application { arguments, .. }) if arguments.len() == 1 => {
+            nnf(&arguments[0], !positive)
+        }
+        (Some(op @ ("and" | "or")), Term::Application { arguments, .. }) => {
+            let dual = match (op, positive) {
+                ("and", true) | ("or", false) => "and",
+                _ => "or",
+            };
+            app(dual, arguments.iter().map(|a| nnf(a, positive)).collect())
+        }
+        (Some("=>"), Term::Application { arguments, .. }) if arguments.len() >= 2 => {
+            // (=> a b c) is right-associative and equivalent to (or (not a) (not b) c)
+            let (conclusion, premises) = arguments.split_last().unwrap();
+            let mut disjuncts: Vec<Term> = premises.iter().map(|p| not(p.clone())).collect();
+            disjuncts.push(conclusion.clone());
+            nnf(&app("or", disjuncts), positive)
+        }
+        (Some("ite"), Term::Application { arguments, .. }) if arguments.len() == 3 => app(
+            "ite",
+            vec![
+                nnf(&arguments[0], true),
+                nnf(&arguments[1], positive),
+                nnf(&arguments[2], positive),
+            ],
+        ),
+        (_, Term::Forall { vars, term }) => {
+            let body = Box::new(nnf(term, positive));
+            if positive {
+                Term::Forall {
+                    vars: vars.clone(),
+                    term: body,
+                }
+            } else {
+                Term::Exists {
+                    vars: vars.clone(),
+                    term: body,
+                }
+            }
+        }
+        (_, Term::Exists { vars, term }) => {
+            let body = Box::new(nnf(term, positive));
+            if positive {
+                Term::Exists {
+                    vars: vars.clone(),
+                    term: body,
+                }
+            } else {
+                Term::Forall {
+                    vars: vars.clone(),
+                    term: body,
+                }
+            }
+        }
+        _ => negate_if(term.clone()),
+    }
+}
+
+/// Flattens nested applications of `and` and `or`.
+fn flatten(term: &Term) -> Term {
+    match (head_symbol(term), term) {
+        (Some(op @ ("and" | "or")), Term::Application { arguments, .. }) => {
+            let mut flat = vec![];
+            for a in arguments {
+                let a = flatten(a);
+                match as_application(&a, op) {
+                    Some(inner) => flat.extend(inner.iter().cloned()),
+                    None => flat.push(a),
+                }
+            }
+            if flat.len() == 1 {
+                flat.pop().unwrap()
+            } else {
+                app(op, flat)
+            }
+        }
+        (
+            Some(_),
+            Term::Application {
+                qual_identifier,
+                arguments,
+            },
+        ) if is_connective(term) => Term::Application {
+            qual_identifier: qual_identifier.clone(),
+            arguments: arguments.iter().map(flatten).collect(),
+        },
+        _ => term.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smt2parser::{concrete, CommandStream};
+
+    fn normalized(assertion: &str) -> Vec<String> {
+        let commands = CommandStream::new(assertion.as_bytes(), concrete::SyntaxBuilder, None)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        normalize_commands(commands)
+            .iter()
+            .map(|c| c.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn pushes_negations_inward() {
+        assert_eq!(
+            normalized("(assert (not (or a (not b) (=> c d))))"),
+            vec![
+                "(assert (not a))",
+                "(assert b)",
+                "(assert c)",
+                "(assert (not d))"
+            ]
+        );
+    }
+
+    #[test]
+    fn flattens_nested_junctions() {
+        assert_eq!(
+            normalized("(assert (or a (or b (and c (and d e)))))"),
+            vec!["(assert (or a b (and c d e)))"]
+        );
+    }
+
+    #[test]
+    fn lifts_ite_from_atoms() {
+        assert_eq!(
+            normalized("(assert (= x (ite c 1 2)))"),
+            vec!["(assert (or (not c) (= x 1)))", "(assert (or c (= x 2)))"]
+        );
+    }
+
+    #[test]
+    fn keeps_formula_ite() {
+        assert_eq!(
+            normalized("(assert (not (ite c a b)))"),
+            vec!["(assert (ite c (not a) (not b)))"]
+        );
+    }
+}
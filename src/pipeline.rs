@@ -0,0 +1,280 @@
+//! Deciding a validation with a sequence of increasingly expensive stages.
+//!
+//! Each stage either decides the verdict or leaves it unknown, in which case the next stage is tried.
+//! Evaluating the formula under the model takes microseconds but only decides ground models,
+//! while checking the lemma with Isabelle handles any formula the spec covers but takes seconds.
+//! The checkers of the Isabelle stages are created when a validation first reaches the stage,
+//! so an Isabelle server is only started if the cheaper stages leave some verdict unknown.
+//! The checkers import the theories the lemma needs when they are created, e.g. the reals,
+//! so they are created again for a validation that needs other imports.
+
+use std::fmt::{Display, Formatter};
+use std::time::Instant;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::cancel;
+use crate::checker::{CheckerKind, LemmaChecker, UnknownReason};
+use crate::error::Error;
+use crate::validation::ValidationResult;
+
+/// A stage of a [CheckerPipeline]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// Evaluate the formula under the model, see [crate::eval]
+    Eval,
+    /// Check the lemma with a new Isabelle process per lemma
+    #[value(alias = "isabelle")]
+    Batch,
+    /// Check the lemma in the session of an Isabelle server
+    Server,
+    /// Check the lemma converted to Coq with `coqc`
+    Coq,
+    /// Check the lemma converted to Lean 4 with `lean`
+    Lean,
+    /// Check the lemma with [crate::ValidationOptions::custom_checker]
+    #[value(skip)]
+    Custom,
+}
+
+impl Stage {
+    /// Returns the kind of checker checking the lemma in this stage, None for [Stage::Eval].
+    pub fn checker_kind(self) -> Option<CheckerKind> {
+        match self {
+            Stage::Eval => None,
+            Stage::Batch => Some(CheckerKind::Batch),
+            Stage::Server => Some(CheckerKind::Server),
+            Stage::Coq => Some(CheckerKind::Coq),
+            Stage::Lean => Some(CheckerKind::Lean),
+            Stage::Custom => Some(CheckerKind::Custom),
+        }
+    }
+}
+
+impl From<CheckerKind> for Stage {
+    fn from(kind: CheckerKind) -> Self {
+        match kind {
+            CheckerKind::Batch => Stage::Batch,
+            CheckerKind::Server => Stage::Server,
+            CheckerKind::Coq => Stage::Coq,
+            CheckerKind::Lean => Stage::Lean,
+            CheckerKind::Custom => Stage::Custom,
+        }
+    }
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Stage::Eval => "eval",
+            Stage::Batch => "batch",
+            Stage::Server => "server",
+            Stage::Coq => "coq",
+            Stage::Lean => "lean",
+            Stage::Custom => "custom",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The outcome of a stage in a single validation
+#[derive(Clone, Debug, Serialize)]
+pub struct StageReport {
+    pub stage: Stage,
+    /// `valid`, `invalid`, `unknown`, or `error`
+    pub verdict: &'static str,
+    pub time_ms: u64,
+}
+
+/// The outcomes of a stage over many validations
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StageStatistics {
+    /// The number of validations that reached the stage
+    pub runs: usize,
+    /// The number of validations the stage decided as valid or invalid
+    pub decided: usize,
+    pub time_ms: u64,
+}
+
+impl StageStatistics {
+    /// Counts the outcome of the stage in a validation.
+    pub fn record(&mut self, report: &StageReport) {
+        self.runs += 1;
+        if matches!(report.verdict, "valid" | "invalid") {
+            self.decided += 1;
+        }
+        self.time_ms += report.time_ms;
+    }
+}
+
+/// The stages of a validation, with the checkers of the Isabelle stages once they are created
+pub struct CheckerPipeline {
+    stages: Vec<(Stage, Option<Box<dyn LemmaChecker>>)>,
+    /// The additional theories the created checkers import
+    imports: Vec<String>,
+}
+
+impl CheckerPipeline {
+    /// Creates a pipeline of the stages, which are tried in the given order.
+    pub fn new(stages: &[Stage]) -> Self {
+        Self {
+            stages: stages.iter().map(|s| (*s, None)).collect(),
+            imports: vec![],
+        }
+    }
+
+    /// Creates a pipeline with a single Isabelle stage checking lemmas with the given checker.
+    /// The checker is taken to import no additional theories.
+    pub fn with_checker(stage: Stage, checker: Box<dyn LemmaChecker>) -> Self {
+        Self {
+            stages: vec![(stage, Some(checker))],
+            imports: vec![],
+        }
+    }
+
+    /// Returns the stages in the order they are tried.
+    pub fn stages(&self) -> Vec<Stage> {
+        self.stages.iter().map(|(s, _)| *s).collect()
+    }
+
+    /// Runs the stages in order until one decides the verdict, and returns the verdict with the outcome of each stage run.
+    /// `run_stage` runs a single stage, given the checker of the stage, or None for [Stage::Eval].
+    /// Missing checkers are created with `create` first; an error creating them is the result of the stage.
+    /// The checkers created for other additional theories than `imports` are dropped and created again.
+    /// The verdict is the first valid or invalid one, or the result of the last stage if no stage decides.
+    /// Once the validation is cancelled, no further stage is run and the verdict is [UnknownReason::Cancelled].
+    pub(crate) fn run(
+        &mut self,
+        imports: &[String],
+        mut create: impl FnMut(CheckerKind) -> Result<Box<dyn LemmaChecker>, Error>,
+        mut run_stage: impl FnMut(
+            Stage,
+            Option<&mut dyn LemmaChecker>,
+        ) -> Result<ValidationResult, Error>,
+    ) -> (Result<ValidationResult, Error>, Vec<StageReport>) {
+        if self.imports != imports {
+            for (stage, checker) in &mut self.stages {
+                if checker.take().is_some() {
+                    log::debug!(
+                        "Dropping the checker of the {} stage, which imports other theories",
+                        stage
+                    );
+                }
+            }
+            self.imports = imports.to_vec();
+        }
+        let mut result = Err(Error::Other("The pipeline has no stages".to_owned()));
+        let mut reports = vec![];
+        for (stage, checker) in &mut self.stages {
+            if cancel::is_cancelled() {
+                log::info!("The validation was cancelled before the {} stage", stage);
+                result = Ok(ValidationResult::Unknown(UnknownReason::Cancelled));
+                break;
+            }
+            let start = Instant::now();
+            result = match stage.checker_kind() {
+                None => run_stage(*stage, None),
+                Some(kind) => {
+                    if checker.is_none() {
+                        log::debug!("Creating the checker of the {} stage", stage);
+                        match create(kind) {
+                            Ok(c) => *checker = Some(c),
+                            Err(e) => result = Err(e),
+                        }
+                    }
+                    match checker {
+                        Some(c) => run_stage(*stage, Some(c.as_mut())),
+                        None => result,
+                    }
+                }
+            };
+            reports.push(StageReport {
+                stage: *stage,
+                verdict: result.as_ref().map_or("error", |r| r.verdict()),
+                time_ms: start.elapsed().as_millis() as u64,
+            });
+            match &result {
+                Ok(ValidationResult::Valid | ValidationResult::Invalid) => break,
+                Ok(ValidationResult::Unknown(reason)) => {
+                    log::info!("The {} stage left the verdict unknown ({})", stage, reason)
+                }
+                Err(e) => log::warn!("The {} stage failed: {}", stage, e),
+            }
+        }
+        (result, reports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::{CheckResult, UnknownReason};
+    use crate::lemma::Lemma;
+
+    /// Answers every lemma with the given result
+    struct FixedChecker(CheckResult);
+
+    impl LemmaChecker for FixedChecker {
+        fn check(&mut self, _lemma: &Lemma) -> Result<CheckResult, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn escalate_on_unknown() {
+        let mut pipeline = CheckerPipeline::new(&[Stage::Eval, Stage::Batch, Stage::Server]);
+        let mut created = vec![];
+        let mut run = |pipeline: &mut CheckerPipeline, eval: ValidationResult| {
+            pipeline.run(
+                &[],
+                |kind| {
+                    created.push(kind);
+                    let result = match kind {
+                        CheckerKind::Batch => CheckResult::FailedUnknown(UnknownReason::Timeout),
+                        _ => CheckResult::OK,
+                    };
+                    Ok(Box::new(FixedChecker(result)))
+                },
+                |_, checker| match checker {
+                    None => Ok(eval.clone()),
+                    Some(c) => match c.check(&Lemma::new("validation"))? {
+                        CheckResult::OK => Ok(ValidationResult::Valid),
+                        CheckResult::FailedInvalid => Ok(ValidationResult::Invalid),
+                        CheckResult::FailedUnknown(r) => Ok(ValidationResult::Unknown(r)),
+                    },
+                },
+            )
+        };
+
+        let (result, reports) = run(&mut pipeline, ValidationResult::Invalid);
+        assert_eq!(result.unwrap(), ValidationResult::Invalid);
+        assert_eq!(reports.len(), 1);
+
+        let undecided = ValidationResult::Unknown(UnknownReason::NotChecked);
+        let (result, reports) = run(&mut pipeline, undecided.clone());
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+        assert_eq!(
+            reports
+                .iter()
+                .map(|r| (r.stage, r.verdict))
+                .collect::<Vec<_>>(),
+            [
+                (Stage::Eval, "unknown"),
+                (Stage::Batch, "unknown"),
+                (Stage::Server, "valid")
+            ]
+        );
+        // The checkers are reused
+        let (result, _) = run(&mut pipeline, undecided);
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+        assert_eq!(created, [CheckerKind::Batch, CheckerKind::Server]);
+
+        let mut statistics = StageStatistics::default();
+        for r in &reports {
+            statistics.record(r);
+        }
+        assert_eq!((statistics.runs, statistics.decided), (3, 1));
+    }
+}
@@ -0,0 +1,140 @@
+//! Checking lemmas with a prover that is run once per lemma, such as `coqc` or `lean`.
+//!
+//! The lemma is written in the language of the prover to a file in a fresh directory, and the prover is run on it.
+//! The lemma is proven if the prover succeeds; otherwise its output tells whether the lemma was refuted.
+//! The provers only differ in their command lines and in how they report refuted lemmas, see [Prover].
+
+use std::path::Path;
+use std::time::Duration;
+
+use crate::checker::{interruption, keep_on_failure, run_command, CheckResult, LemmaChecker};
+use crate::convert::Target;
+use crate::error::Error;
+use crate::lemma::Lemma;
+
+/// A target language whose validation files are checked by running a prover on them
+pub trait Prover: Target {
+    /// The name of the prover in logs, e.g. `coqc`
+    fn executable(&self) -> &'static str;
+
+    /// Returns the command line checking the validation file, given the theory root.
+    /// The file is relative to the directory the prover runs in, see [Prover::runs_in_theory_root].
+    fn command_line(&self, theory_root: &str, file: &Path) -> Vec<String>;
+
+    /// Whether the prover runs in the theory root rather than in the directory of the validation file
+    fn runs_in_theory_root(&self) -> bool {
+        false
+    }
+
+    /// Classifies a failed check from the output of the prover.
+    /// Errors in the statement, e.g. unknown names, are errors of the spec rather than failed proofs.
+    fn classify_failure(&self, output: &str) -> Result<CheckResult, Error>;
+}
+
+/// Checks lemmas by running a prover on a validation file, one process per lemma
+pub struct ProcessChecker<P> {
+    prover: P,
+    /// The directory containing the modules of the prover the validation files import
+    theory_root: String,
+    /// Modules to import in addition to the standard library of the prover
+    imports: Vec<String>,
+    /// Kill the prover if it runs longer than this
+    timeout: Option<Duration>,
+    /// Keep the directories of checks that do not prove the lemma
+    keep_temp: bool,
+}
+
+impl<P: Prover + Default> ProcessChecker<P> {
+    pub fn new(theory_root: &str) -> Self {
+        Self {
+            prover: P::default(),
+            theory_root: theory_root.to_owned(),
+            imports: vec![],
+            timeout: None,
+            keep_temp: false,
+        }
+    }
+}
+
+impl<P: Prover> ProcessChecker<P> {
+    /// Keeps the directory containing the validation file if a check does not prove the lemma.
+    pub fn keep_temp(&mut self, keep: bool) -> &mut Self {
+        self.keep_temp = keep;
+        self
+    }
+
+    /// Kills the prover if a check takes longer than the given time.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Imports the given modules in the validation file.
+    pub fn import(&mut self, imports: &[String]) -> &mut Self {
+        self.imports.extend(imports.iter().cloned());
+        self
+    }
+
+    /// Returns the command line checking the validation file in the given directory.
+    pub fn command_line(&self, dir: &Path) -> Vec<String> {
+        let file = Path::new(self.prover.file_name());
+        if self.prover.runs_in_theory_root() {
+            self.prover.command_line(&self.theory_root, &dir.join(file))
+        } else {
+            self.prover.command_line(&self.theory_root, file)
+        }
+    }
+
+    /// Writes the validation file to the directory and runs the prover on it.
+    fn check_in(&self, lemma: &Lemma, dir: &Path) -> Result<CheckResult, Error> {
+        let path = dir.join(self.prover.file_name());
+        let theory = self.prover.validation_theory(lemma, &self.imports);
+        self.prover
+            .write_file(&theory, &path)
+            .map_err(|e| Error::Other(format!("Could not write {}: {}", path.display(), e)))?;
+
+        log::info!("Checking lemma with {}", self.prover.name());
+        let command_line = self.command_line(dir);
+        let mut command = tokio::process::Command::new(&command_line[0]);
+        command.args(&command_line[1..]);
+        if self.prover.runs_in_theory_root() {
+            command.current_dir(&self.theory_root);
+        } else {
+            command.current_dir(dir);
+        }
+        let output = match run_command(&mut command, self.timeout) {
+            Ok(Some(o)) => o,
+            Ok(None) => {
+                let reason = interruption(self.prover.executable());
+                return Ok(CheckResult::FailedUnknown(reason));
+            }
+            Err(e) => {
+                return Err(Error::Other(format!(
+                    "Could not run {}: {}",
+                    command_line[0], e
+                )))
+            }
+        };
+        if output.status.success() {
+            return Ok(CheckResult::OK);
+        }
+        let output = format!(
+            "{}\n{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        self.prover.classify_failure(&output)
+    }
+}
+
+impl<P: Prover> LemmaChecker for ProcessChecker<P> {
+    fn check(&mut self, lemma: &Lemma) -> Result<CheckResult, Error> {
+        let dir = tempfile::tempdir()
+            .map_err(|e| Error::Other(format!("Could not create a directory: {}", e)))?;
+        let result = self.check_in(lemma, dir.path());
+        if self.keep_temp {
+            keep_on_failure(dir, &result);
+        }
+        result
+    }
+}
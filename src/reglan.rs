@@ -0,0 +1,301 @@
+//! Regular expressions of the SMT-LIB string theory, for evaluating `str.in_re` and the `str.replace_re` functions.
+//!
+//! Membership is decided with Brzozowski derivatives: the derivative of a regular expression by a character
+//! denotes the suffixes of its words starting with that character, so a string matches iff the derivative by all of its
+//! characters, in order, accepts the empty string. Complement and intersection are handled like the other operators.
+//! The expressions are kept small by the simplifying constructors; expressions that grow too large are not evaluated.
+
+use crate::eval::MAX_CODE_POINT;
+
+/// The maximal number of nodes of a derivative
+const MAX_SIZE: usize = 10_000;
+
+/// A regular expression over code points
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Regex {
+    /// The empty language
+    None,
+    /// The language containing only the empty string
+    Epsilon,
+    /// The characters with a code point in the range, inclusive
+    Range(u32, u32),
+    Concat(Box<Regex>, Box<Regex>),
+    Union(Vec<Regex>),
+    Inter(Vec<Regex>),
+    Comp(Box<Regex>),
+    /// Between `min` and `max` (unbounded if None) repetitions
+    Loop(Box<Regex>, u32, Option<u32>),
+}
+
+impl Regex {
+    /// The language of all strings, `re.all`
+    pub(crate) fn all() -> Self {
+        Regex::Comp(Box::new(Regex::None))
+    }
+
+    /// The language of all single characters, `re.allchar`
+    pub(crate) fn all_char() -> Self {
+        Regex::Range(0, MAX_CODE_POINT)
+    }
+
+    /// The language containing only the string, `str.to_re`
+    pub(crate) fn string(s: &[u32]) -> Self {
+        s.iter()
+            .rev()
+            .fold(Regex::Epsilon, |r, &c| Regex::concat(Regex::Range(c, c), r))
+    }
+
+    /// The single characters between the characters of the strings, `re.range`.
+    /// The language is empty unless both strings are single characters.
+    pub(crate) fn range(from: &[u32], to: &[u32]) -> Self {
+        match (from, to) {
+            ([from], [to]) if from <= to => Regex::Range(*from, *to),
+            _ => Regex::None,
+        }
+    }
+
+    pub(crate) fn concat(a: Regex, b: Regex) -> Self {
+        match (a, b) {
+            (Regex::None, _) | (_, Regex::None) => Regex::None,
+            (Regex::Epsilon, r) | (r, Regex::Epsilon) => r,
+            // Concatenations are nested to the right, so that derivatives only look at the first element
+            (Regex::Concat(a1, a2), b) => Regex::concat(*a1, Regex::concat(*a2, b)),
+            (a, b) => Regex::Concat(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub(crate) fn union(items: impl IntoIterator<Item = Regex>) -> Self {
+        let mut res = vec![];
+        for r in items {
+            match r {
+                Regex::None => (),
+                Regex::Union(rs) => res.extend(rs),
+                r => res.push(r),
+            }
+        }
+        if res.contains(&Regex::all()) {
+            return Regex::all();
+        }
+        dedup(&mut res);
+        match res.len() {
+            0 => Regex::None,
+            1 => res.pop().unwrap(),
+            _ => Regex::Union(res),
+        }
+    }
+
+    pub(crate) fn inter(items: impl IntoIterator<Item = Regex>) -> Self {
+        let mut res = vec![];
+        for r in items {
+            match r {
+                Regex::Inter(rs) => res.extend(rs),
+                r if r == Regex::all() => (),
+                r => res.push(r),
+            }
+        }
+        if res.contains(&Regex::None) {
+            return Regex::None;
+        }
+        dedup(&mut res);
+        match res.len() {
+            0 => Regex::all(),
+            1 => res.pop().unwrap(),
+            _ => Regex::Inter(res),
+        }
+    }
+
+    pub(crate) fn comp(r: Regex) -> Self {
+        match r {
+            Regex::Comp(r) => *r,
+            r => Regex::Comp(Box::new(r)),
+        }
+    }
+
+    /// Between `min` and `max` (unbounded if None) repetitions, `re.loop`; `re.*`, `re.+`, and `re.opt` are special cases.
+    pub(crate) fn repeat(r: Regex, min: u32, max: Option<u32>) -> Self {
+        match (r, min, max) {
+            (_, _, Some(max)) if max < min => Regex::None,
+            (_, _, Some(0)) | (Regex::Epsilon, _, _) => Regex::Epsilon,
+            (Regex::None, 0, _) => Regex::Epsilon,
+            (Regex::None, _, _) => Regex::None,
+            (r, 1, Some(1)) => r,
+            (r, min, max) => Regex::Loop(Box::new(r), min, max),
+        }
+    }
+
+    /// Returns whether the language contains the empty string.
+    fn nullable(&self) -> bool {
+        match self {
+            Regex::None | Regex::Range(..) => false,
+            Regex::Epsilon => true,
+            Regex::Concat(a, b) => a.nullable() && b.nullable(),
+            Regex::Union(rs) => rs.iter().any(Regex::nullable),
+            Regex::Inter(rs) => rs.iter().all(Regex::nullable),
+            Regex::Comp(r) => !r.nullable(),
+            Regex::Loop(r, min, _) => *min == 0 || r.nullable(),
+        }
+    }
+
+    /// Returns the derivative by the character.
+    fn derivative(&self, c: u32) -> Regex {
+        match self {
+            Regex::None | Regex::Epsilon => Regex::None,
+            Regex::Range(from, to) if (*from..=*to).contains(&c) => Regex::Epsilon,
+            Regex::Range(..) => Regex::None,
+            Regex::Concat(a, b) => {
+                let first = Regex::concat(a.derivative(c), (**b).clone());
+                if a.nullable() {
+                    Regex::union([first, b.derivative(c)])
+                } else {
+                    first
+                }
+            }
+            Regex::Union(rs) => Regex::union(rs.iter().map(|r| r.derivative(c))),
+            Regex::Inter(rs) => Regex::inter(rs.iter().map(|r| r.derivative(c))),
+            Regex::Comp(r) => Regex::comp(r.derivative(c)),
+            // A word of r{min,max} starts with a non-empty word of r; if r is nullable,
+            // the empty words can be moved to the end, so the rest is a word of r{min-1,max-1}
+            Regex::Loop(r, min, max) => Regex::concat(
+                r.derivative(c),
+                Regex::repeat((**r).clone(), min.saturating_sub(1), max.map(|m| m - 1)),
+            ),
+        }
+    }
+
+    /// Returns the number of nodes of the expression.
+    fn size(&self) -> usize {
+        match self {
+            Regex::None | Regex::Epsilon | Regex::Range(..) => 1,
+            Regex::Concat(a, b) => 1 + a.size() + b.size(),
+            Regex::Union(rs) | Regex::Inter(rs) => 1 + rs.iter().map(Regex::size).sum::<usize>(),
+            Regex::Comp(r) | Regex::Loop(r, ..) => 1 + r.size(),
+        }
+    }
+
+    /// Returns whether the string is in the language, or an error if the derivatives grow too large.
+    pub(crate) fn matches(&self, s: &[u32]) -> Result<bool, String> {
+        let mut r = self.clone();
+        for &c in s {
+            if r == Regex::None {
+                return Ok(false);
+            }
+            r = r.derivative(c);
+            if r.size() > MAX_SIZE {
+                return Err("regular expression too large".to_owned());
+            }
+        }
+        Ok(r.nullable())
+    }
+
+    /// Returns the start and end of the leftmost shortest substring of `s` at or after `from` in the language,
+    /// which must not be empty if `non_empty`, as `str.replace_re` and `str.replace_re_all` replace it.
+    pub(crate) fn find(
+        &self,
+        s: &[u32],
+        from: usize,
+        non_empty: bool,
+    ) -> Result<Option<(usize, usize)>, String> {
+        for start in from..=s.len() {
+            if !non_empty && self.nullable() {
+                return Ok(Some((start, start)));
+            }
+            let mut r = self.clone();
+            for (end, &c) in s.iter().enumerate().skip(start) {
+                r = r.derivative(c);
+                if r == Regex::None {
+                    break;
+                }
+                if r.size() > MAX_SIZE {
+                    return Err("regular expression too large".to_owned());
+                }
+                if r.nullable() {
+                    return Ok(Some((start, end + 1)));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Removes duplicates, keeping the first occurrence.
+fn dedup(rs: &mut Vec<Regex>) {
+    let mut i = 0;
+    while i < rs.len() {
+        if rs[..i].contains(&rs[i]) {
+            rs.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(s: &str) -> Vec<u32> {
+        s.chars().map(|c| c as u32).collect()
+    }
+
+    #[test]
+    fn regex_membership() {
+        let ab = Regex::string(&s("ab"));
+        let digit = Regex::range(&s("0"), &s("9"));
+        // (ab)*[0-9]{2,3}
+        let r = Regex::concat(
+            Regex::repeat(ab.clone(), 0, None),
+            Regex::repeat(digit.clone(), 2, Some(3)),
+        );
+        for (word, expected) in [
+            ("12", true),
+            ("abab123", true),
+            ("ab1", false),
+            ("1234", false),
+            ("aba12", false),
+        ] {
+            assert_eq!(r.matches(&s(word)), Ok(expected), "{}", word);
+        }
+
+        // Strings of digits that are not all zeros and not longer than 4
+        let r = Regex::inter([
+            Regex::repeat(digit.clone(), 1, None),
+            Regex::comp(Regex::repeat(Regex::string(&s("0")), 0, None)),
+            Regex::repeat(Regex::all_char(), 0, Some(4)),
+        ]);
+        assert_eq!(r.matches(&s("0010")), Ok(true));
+        assert_eq!(r.matches(&s("000")), Ok(false));
+        assert_eq!(r.matches(&s("00001")), Ok(false));
+        assert_eq!(r.matches(&s("")), Ok(false));
+
+        assert_eq!(Regex::range(&s("b"), &s("a")), Regex::None);
+        assert_eq!(Regex::range(&s("ab"), &s("c")), Regex::None);
+        assert_eq!(Regex::repeat(ab.clone(), 3, Some(2)), Regex::None);
+        assert_eq!(Regex::all().matches(&[0x2FFFF, 0]), Ok(true));
+        assert_eq!(
+            Regex::union([Regex::None, ab.clone(), ab.clone()]),
+            ab.clone()
+        );
+        // (a|aa)* over long strings stays small
+        let a = Regex::string(&s("a"));
+        let r = Regex::repeat(
+            Regex::union([a.clone(), Regex::concat(a.clone(), a)]),
+            0,
+            None,
+        );
+        assert_eq!(r.matches(&s(&"a".repeat(200))), Ok(true));
+    }
+
+    #[test]
+    fn regex_find() {
+        // [0-9]+ finds the first digit only, and the empty language nothing
+        let digits = Regex::repeat(Regex::range(&s("0"), &s("9")), 1, None);
+        assert_eq!(digits.find(&s("ab12c3"), 0, false), Ok(Some((2, 3))));
+        assert_eq!(digits.find(&s("ab12c3"), 4, false), Ok(Some((5, 6))));
+        assert_eq!(Regex::None.find(&s("ab"), 0, false), Ok(None));
+        // An empty match is found at the start unless excluded
+        let any = Regex::repeat(Regex::all_char(), 0, None);
+        assert_eq!(any.find(&s("ab"), 1, false), Ok(Some((1, 1))));
+        assert_eq!(any.find(&s("ab"), 1, true), Ok(Some((1, 2))));
+        assert_eq!(any.find(&s("ab"), 2, true), Ok(None));
+    }
+}
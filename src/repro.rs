@@ -0,0 +1,289 @@
+//! Reproduction bundles for failed validations.
+//!
+//! A bundle is a directory containing everything needed to rerun a validation:
+//! the formula, the model, a snapshot of the spec, the generated theory, the exact Isabelle command line,
+//! and a `repro.sh` script tying them together, which reruns the validation with the options of the failed one.
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use crate::checker::{self, BatchChecker};
+use crate::lemma::Theory;
+use crate::pipeline::Stage;
+use crate::spec;
+use crate::validation::ValidationOptions;
+
+/// Writes a reproduction bundle to `dir`, creating the directory if needed.
+/// The first of the theory roots is the one containing the Isabelle SMT theories;
+/// the specs of additional roots are copied to `spec.1.json`, `spec.2.json`, and so on (keeping their extension).
+/// The generated theory is only included if the validation got far enough to generate it.
+/// The script reruns the validation with the given options, using the copied spec of the first root.
+pub fn write_bundle(
+    dir: &Path,
+    formula: &str,
+    model: &str,
+    theory_roots: &[&Path],
+    theory: Option<&Theory>,
+    options: &ValidationOptions,
+) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("formula.smt2"), formula)?;
+    fs::write(dir.join("model.smt2"), model)?;
+    // The snapshot of the spec the rerun uses, if the validation used a spec file
+    let mut snapshot = None;
+    for (i, theory_root) in theory_roots.iter().enumerate() {
+        let spec = match &options.spec_file {
+            Some(file) if i == 0 => file.clone(),
+            _ => spec::find_target_spec_file(theory_root, options.target),
+        };
+        let extension = spec.extension().unwrap_or_default().to_string_lossy();
+        let name = if i == 0 {
+            format!("spec.{}", extension)
+        } else {
+            format!("spec.{}.{}", i, extension)
+        };
+        if spec.exists() {
+            fs::copy(&spec, dir.join(&name))?;
+            if i == 0 && options.builtin_spec.is_none() {
+                snapshot = Some(name);
+            }
+        }
+    }
+
+    let roots: Vec<String> = theory_roots
+        .iter()
+        .map(|r| r.to_string_lossy().into_owned())
+        .collect();
+    let command = BatchChecker::command_line(&roots);
+    if let Some(theory) = theory {
+        let theory_dir = dir.join("theory");
+        fs::create_dir_all(&theory_dir)?;
+        checker::write_theory(theory, &theory_dir.join("Validation.thy"))?;
+        fs::write(dir.join("command.txt"), shell_join(&command) + "\n")?;
+    }
+
+    let script = dir.join("repro.sh");
+    let mut arguments = validation_arguments(options);
+    if let Some(snapshot) = snapshot {
+        arguments.extend(["--spec".to_owned(), snapshot]);
+    }
+    fs::write(
+        &script,
+        repro_script(&roots, theory.map(|_| &command), &arguments),
+    )?;
+    let mut permissions = fs::metadata(&script)?.permissions();
+    permissions.set_mode(0o755);
+    fs::set_permissions(&script, permissions)
+}
+
+/// Creates the script rerunning the validation with the given arguments and, if available, Isabelle on the generated theory.
+fn repro_script(
+    theory_roots: &[String],
+    isabelle_command: Option<&Vec<String>>,
+    arguments: &[String],
+) -> String {
+    let theory_root = &theory_roots[0];
+    let mut script = String::from("#!/bin/sh\n");
+    script += "# Reproduces a failed validation of smtmv.\n";
+    script += "# Set THEORY_ROOT to use a different copy of the Isabelle SMT theories.\n";
+    script += "set -e\n";
+    script += "cd \"$(dirname \"$0\")\"\n";
+    script += &format!(
+        "THEORY_ROOT=\"${{THEORY_ROOT:-{}}}\"\n\n",
+        shell_quote(theory_root)
+    );
+    if let Some(command) = isabelle_command {
+        // Replace the theory root by the variable so it can be overridden
+        let command = command
+            .iter()
+            .map(|a| {
+                if a == theory_root {
+                    "\"$THEORY_ROOT\"".to_owned()
+                } else {
+                    shell_quote(a)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        script += "# Check the generated theory with Isabelle\n";
+        script += &format!("(cd theory && {})\n\n", command);
+    }
+    script += "# Rerun the validation\n";
+    let mut rerun = String::from("smtmv -T \"$THEORY_ROOT\"");
+    for arg in theory_roots[1..]
+        .iter()
+        .flat_map(|r| ["-T".to_owned(), r.clone()])
+        .chain(arguments.iter().cloned())
+    {
+        rerun += &format!(" {}", shell_quote(&arg));
+    }
+    script += &format!("{} --model model.smt2 formula.smt2\n", rerun);
+    script
+}
+
+/// Returns the command line arguments of smtmv validating with the options.
+/// Options that do not change the verdict, such as the cache or the transcript, are left out,
+/// and so are custom checkers, which cannot be given on the command line.
+fn validation_arguments(options: &ValidationOptions) -> Vec<String> {
+    let mut args = vec![];
+    let mut flag = |enabled: bool, name: &str| {
+        if enabled {
+            args.push(format!("--{}", name));
+        }
+    };
+    flag(options.slice, "slice");
+    flag(options.normalize, "normalize");
+    flag(options.simplify, "simplify");
+    flag(options.substitute_model, "substitute-model");
+    flag(options.lenient, "lenient");
+    flag(options.split_conclusions, "split-conclusions");
+    flag(options.counterexample, "counterexample");
+    flag(options.model_core, "model-core");
+    flag(options.escalate, "escalate");
+    flag(options.strict_model, "strict-model");
+    flag(options.smtlib25, "smtlib25");
+    let mut option = |name: &str, value: String| {
+        args.push(format!("--{}", name));
+        args.push(value);
+    };
+    for op in &options.uninterpreted {
+        option("uninterpret", op.clone());
+    }
+    for import in &options.imports {
+        option("import", import.clone());
+    }
+    if let Some(size) = options.chunk_size {
+        option("chunk-size", size.to_string());
+    }
+    if let Some(timeout) = options.timeout {
+        // The command line takes whole seconds
+        let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+        option("timeout", secs.to_string());
+    }
+    if let Some(limit) = options.memory_limit {
+        option("memory-limit", limit.to_string());
+    }
+    if let Some(method) = &options.method {
+        option("tactic", method.clone());
+    }
+    if options.uninterpreted_sorts != Default::default() {
+        option(
+            "uninterpreted-sorts",
+            value_name(&options.uninterpreted_sorts),
+        );
+    }
+    if options.partial_model != Default::default() {
+        option("partial-model", value_name(&options.partial_model));
+    }
+    if let Some(index) = options.check_index {
+        option("check-index", index.to_string());
+    }
+    if let Some(measure) = &options.termination_measure {
+        option("termination-measure", measure.clone());
+    }
+    if let Some(spec) = &options.builtin_spec {
+        option("builtin-spec", value_name(spec));
+    }
+    let stages: Vec<String> = options
+        .stages()
+        .iter()
+        .filter(|s| **s != Stage::Custom)
+        .map(value_name)
+        .collect();
+    if !stages.is_empty() {
+        option("pipeline", stages.join(","));
+    }
+    args
+}
+
+/// Returns the name of the value on the command line.
+fn value_name(value: &impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_owned())
+        .unwrap_or_default()
+}
+
+/// Joins the arguments into a command line that can be pasted into a shell.
+fn shell_join(args: &[String]) -> String {
+    args.iter()
+        .map(|a| shell_quote(a))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quotes the argument for a POSIX shell if necessary.
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+".contains(c))
+    {
+        arg.to_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lemma::Lemma;
+
+    #[test]
+    fn quotes_shell_arguments() {
+        assert_eq!(shell_quote("-o"), "-o");
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn writes_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("spec.json"), "{}").unwrap();
+        let mut lemma = Lemma::new("validation");
+        lemma.add_premise("x = 1").add_conclusion("x > 0");
+
+        let bundle = dir.path().join("bundle");
+        write_bundle(
+            &bundle,
+            "(assert (> x 0))",
+            "(define-fun x () Int 1)",
+            &[root.path()],
+            Some(&checker::validation_theory(&lemma, &[])),
+            &ValidationOptions {
+                uninterpreted: vec!["+".to_owned()],
+                normalize: true,
+                chunk_size: Some(2),
+                timeout: Some(std::time::Duration::from_millis(1500)),
+                method: Some("auto simp".to_owned()),
+                pipeline: vec![Stage::Eval, Stage::Batch],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        for f in [
+            "formula.smt2",
+            "model.smt2",
+            "spec.json",
+            "theory/Validation.thy",
+            "command.txt",
+            "repro.sh",
+        ] {
+            assert!(bundle.join(f).exists(), "{} missing", f);
+        }
+        let script = fs::read_to_string(bundle.join("repro.sh")).unwrap();
+        assert!(script.contains("(cd theory && isabelle process -T Validation -d \"$THEORY_ROOT\""));
+        assert!(script.contains(
+            "smtmv -T \"$THEORY_ROOT\" --normalize --uninterpret + --chunk-size 2 --timeout 2 \
+            --tactic 'auto simp' --pipeline eval,batch --spec spec.json --model model.smt2 formula.smt2\n"
+        ));
+    }
+}
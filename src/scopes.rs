@@ -0,0 +1,140 @@
+//! Tracking of the assertion stack of scripts with `push`, `pop`, and several `check-sat` commands.
+//!
+//! A model answers a single `check-sat`, so only the declarations, definitions, and assertions
+//! that are live at that `check-sat` belong to the formula it satisfies.
+//! Commands in a scope are discarded when the scope is popped.
+
+use smt2parser::concrete::{Command, QualIdentifier, Symbol, Term};
+use smt2parser::visitors::Identifier;
+use smt2parser::Numeral;
+
+use crate::error::Error;
+
+/// Returns the commands live at the `check-sat` with the given index (counting from 0), or at the last `check-sat` if no index is given.
+/// The assumptions of a `check-sat-assuming` are returned as assertions.
+/// Scripts without any `check-sat` are returned unchanged.
+pub(crate) fn live_commands(
+    commands: Vec<Command>,
+    check_index: Option<usize>,
+) -> Result<Vec<Command>, Error> {
+    // The commands of each level of the assertion stack, starting with the global level
+    let mut stack: Vec<Vec<Command>> = vec![vec![]];
+    let mut checks: Vec<Vec<Command>> = vec![];
+    let mut rest = vec![];
+    for command in commands {
+        match command {
+            Command::Push { level } => {
+                for _ in 0..levels(&level)? {
+                    stack.push(vec![]);
+                }
+            }
+            Command::Pop { level } => {
+                let level = levels(&level)?;
+                if level >= stack.len() {
+                    return Err(Error::Other(format!(
+                        "Cannot pop {} levels from an assertion stack of {}",
+                        level,
+                        stack.len() - 1
+                    )));
+                }
+                stack.truncate(stack.len() - level);
+            }
+            Command::Reset | Command::ResetAssertions => stack = vec![vec![]],
+            Command::CheckSat => checks.push(stack.concat()),
+            Command::CheckSatAssuming { literals } => {
+                let mut live = stack.concat();
+                live.extend(literals.into_iter().map(|(symbol, positive)| {
+                    let literal = Term::QualIdentifier(QualIdentifier::Simple {
+                        identifier: Identifier::Simple { symbol },
+                    });
+                    let term = if positive {
+                        literal
+                    } else {
+                        Term::Application {
+                            qual_identifier: QualIdentifier::Simple {
+                                identifier: Identifier::Simple {
+                                    symbol: Symbol("not".to_owned()),
+                                },
+                            },
+                            arguments: vec![literal],
+                        }
+                    };
+                    Command::Assert { term }
+                }));
+                checks.push(live);
+            }
+            c => {
+                stack
+                    .last_mut()
+                    .expect("The global level is never popped")
+                    .push(c.clone());
+                rest.push(c);
+            }
+        }
+    }
+    if checks.is_empty() {
+        return match check_index {
+            None => Ok(rest),
+            Some(i) => Err(Error::Other(format!(
+                "No check-sat with index {}, the script has none",
+                i
+            ))),
+        };
+    }
+    let count = checks.len();
+    let index = check_index.unwrap_or(count - 1);
+    if index >= count {
+        return Err(Error::Other(format!(
+            "No check-sat with index {}, the script has {}",
+            index, count
+        )));
+    }
+    Ok(checks.swap_remove(index))
+}
+
+/// Returns the number of levels to push or pop.
+fn levels(level: &Numeral) -> Result<usize, Error> {
+    level
+        .to_string()
+        .parse()
+        .map_err(|_| Error::Other(format!("Invalid number of levels: {}", level)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smt2parser::{concrete, CommandStream};
+
+    fn live(script: &str, check_index: Option<usize>) -> Result<String, Error> {
+        let commands = CommandStream::new(script.as_bytes(), concrete::SyntaxBuilder, None)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let live = live_commands(commands, check_index)?;
+        Ok(live.iter().map(|c| c.to_string()).collect())
+    }
+
+    #[test]
+    fn scopes() {
+        let script = "(declare-const x Int)(assert (> x 0))(check-sat)\
+            (push 1)(declare-const y Int)(assert (< x y))(check-sat)(pop 1)\
+            (assert (< x 5))(check-sat-assuming (a (not b)))";
+        assert_eq!(
+            live(script, None).unwrap(),
+            "(declare-const x Int)(assert (> x 0))(assert (< x 5))(assert a)(assert (not b))"
+        );
+        assert_eq!(
+            live(script, Some(0)).unwrap(),
+            "(declare-const x Int)(assert (> x 0))"
+        );
+        assert_eq!(
+            live(script, Some(1)).unwrap(),
+            "(declare-const x Int)(assert (> x 0))(declare-const y Int)(assert (< x y))"
+        );
+        assert!(live(script, Some(3)).is_err());
+        assert!(live("(pop 1)", None).is_err());
+        assert_eq!(
+            live("(assert a)(push 2)(assert b)", None).unwrap(),
+            "(assert a)(assert b)"
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! The daemon mode of `smtmv serve`.
+//!
+//! Starting Isabelle takes far longer than checking a typical lemma, so a daemon validates all requests with the
+//! same checker pipeline, keeping the Isabelle session of the first request warm for the later ones.
+//! Requests and replies are JSON objects, one per line: a request has the `formula` and the `model` as SMT-LIB text
+//! and optionally an `id`, which is copied to its reply. Replies are sent in the order of the requests.
+//! A line `{"cancel": id}` cancels the requests with that id, whether they are being validated or still queued;
+//! their replies have the verdict unknown with the reason `cancelled`, and the cancellation itself is not answered.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::{mpsc, Mutex};
+
+use serde::Deserialize;
+
+use crate::cancel::Cancellation;
+use crate::error::Error;
+use crate::pipeline::CheckerPipeline;
+use crate::validation::{self, ValidationOptions, ValidationReport, ValidationResult};
+
+/// A request to validate a model
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Request {
+    /// Any JSON value identifying the request in its reply
+    #[serde(default)]
+    pub id: serde_json::Value,
+    pub formula: String,
+    pub model: String,
+}
+
+/// A request to cancel the requests with the given id
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Cancel {
+    pub cancel: serde_json::Value,
+}
+
+/// A line of the input that is answered, as read ahead of the validations
+enum Line {
+    Request(Request, Cancellation),
+    Invalid(serde_json::Error),
+}
+
+/// The ids of the requests read but not answered yet, in order, with the tokens cancelling them
+type Pending = Mutex<VecDeque<(serde_json::Value, Cancellation)>>;
+
+/// Validates the requests read from `input` line by line and writes a reply per request to `output`,
+/// until `input` ends. `reply` turns the result of a validation into the JSON object of the reply.
+/// A line that is not a request is answered with an error of the category `input`.
+/// The input is read on a thread of its own, so that cancellations take effect while a request is validated.
+/// The pipeline is kept for later calls, e.g. for the next connection to the daemon.
+/// Returns the number of requests answered.
+pub fn serve(
+    input: impl BufRead + Send,
+    mut output: impl Write,
+    theory_path: &Path,
+    options: &ValidationOptions,
+    pipeline: &mut Option<CheckerPipeline>,
+    reply: impl Fn(&Result<ValidationResult, Error>, &ValidationReport) -> serde_json::Value,
+) -> io::Result<usize> {
+    let pending = Pending::default();
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|scope| {
+        let reader = scope.spawn(|| read_lines(input, &pending, sender));
+        let mut answered = 0;
+        for line in receiver {
+            let json = match line {
+                Line::Request(request, cancellation) => {
+                    log::info!("Validating request {}", request.id);
+                    let options = ValidationOptions {
+                        cancellation: Some(cancellation),
+                        ..options.clone()
+                    };
+                    let (result, report) = validation::validate_reusing(
+                        request.model,
+                        request.formula,
+                        theory_path,
+                        &options,
+                        pipeline,
+                    );
+                    pending.lock().unwrap().pop_front();
+                    let mut json = reply(&result, &report);
+                    json["id"] = request.id;
+                    json
+                }
+                Line::Invalid(e) => serde_json::json!({
+                    "id": null,
+                    "verdict": "error",
+                    "reason": format!("Invalid request: {}", e),
+                    "error_category": "input",
+                }),
+            };
+            writeln!(output, "{}", json)?;
+            output.flush()?;
+            answered += 1;
+        }
+        reader.join().unwrap()?;
+        Ok(answered)
+    })
+}
+
+/// Reads the input line by line until it ends, cancels the pending requests as requested,
+/// and sends the other lines to be answered.
+fn read_lines(
+    input: impl BufRead,
+    pending: &Pending,
+    sender: mpsc::Sender<Line>,
+) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Ok(Cancel { cancel }) = serde_json::from_str(&line) {
+            let pending = pending.lock().unwrap();
+            let cancelled: Vec<_> = pending.iter().filter(|(id, _)| *id == cancel).collect();
+            log::info!("Cancelling {} requests with id {}", cancelled.len(), cancel);
+            for (_, cancellation) in cancelled {
+                cancellation.cancel();
+            }
+            continue;
+        }
+        let line = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let cancellation = Cancellation::new();
+                let mut pending = pending.lock().unwrap();
+                pending.push_back((request.id.clone(), cancellation.clone()));
+                Line::Request(request, cancellation)
+            }
+            Err(e) => Line::Invalid(e),
+        };
+        // The requests are no longer answered if the output failed
+        if sender.send(line).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::{self, CheckResult, CustomChecker, LemmaChecker};
+    use crate::convert::BuiltinSpec;
+    use crate::lemma::Lemma;
+    use crate::pipeline::Stage;
+
+    #[test]
+    fn answer_requests() {
+        let root = tempfile::tempdir().unwrap();
+        let options = ValidationOptions {
+            builtin_spec: Some(BuiltinSpec::QfSlia),
+            pipeline: vec![Stage::Eval],
+            ..Default::default()
+        };
+        let formula = "(declare-const x Int)(assert (> x 0))";
+        let requests = [
+            serde_json::json!({ "id": 1, "formula": formula, "model": "(define-fun x () Int 1)" })
+                .to_string(),
+            String::new(),
+            serde_json::json!({ "id": "b", "formula": formula, "model": "(define-fun x () Int 0)" })
+                .to_string(),
+            "{\"formula\": \"\"}".to_owned(),
+        ]
+        .join("\n");
+        let mut output = vec![];
+        let answered = serve(
+            requests.as_bytes(),
+            &mut output,
+            root.path(),
+            &options,
+            &mut None,
+            |result, _| serde_json::json!({ "verdict": result.as_ref().unwrap().verdict() }),
+        )
+        .unwrap();
+        assert_eq!(answered, 3);
+        let replies: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(
+            replies[0],
+            serde_json::json!({ "id": 1, "verdict": "valid" })
+        );
+        assert_eq!(
+            replies[1],
+            serde_json::json!({ "id": "b", "verdict": "invalid" })
+        );
+        assert_eq!(replies[2]["verdict"], "error");
+        assert!(replies[2]["reason"]
+            .as_str()
+            .unwrap()
+            .contains("missing field `model`"));
+    }
+
+    /// Checks lemmas by running `sleep`, which only finishes if it is killed
+    struct SleepChecker;
+
+    impl LemmaChecker for SleepChecker {
+        fn check(&mut self, _lemma: &Lemma) -> Result<CheckResult, Error> {
+            let mut command = tokio::process::Command::new("sleep");
+            command.arg("60");
+            match checker::run_command(&mut command, None).unwrap() {
+                Some(_) => Ok(CheckResult::OK),
+                None => Ok(CheckResult::FailedUnknown(checker::interruption("sleep"))),
+            }
+        }
+    }
+
+    #[test]
+    fn cancel_requests() {
+        let root = tempfile::tempdir().unwrap();
+        let mut options = ValidationOptions {
+            builtin_spec: Some(BuiltinSpec::QfSlia),
+            ..Default::default()
+        };
+        options.register_checker(CustomChecker::new("sleep", |_| Ok(Box::new(SleepChecker))));
+        let request = |id: u32| {
+            serde_json::json!({
+                "id": id,
+                "formula": "(declare-const x Int)(assert (> x 0))",
+                "model": "(define-fun x () Int 1)",
+            })
+        };
+        // The first request is cancelled while it is validated or queued, the second one while it is queued
+        let requests = [
+            request(1),
+            serde_json::json!({ "cancel": 1 }),
+            request(2),
+            serde_json::json!({ "cancel": 2 }),
+        ]
+        .map(|r| r.to_string())
+        .join("\n");
+        let mut output = vec![];
+        let answered = serve(
+            requests.as_bytes(),
+            &mut output,
+            root.path(),
+            &options,
+            &mut None,
+            |result, _| serde_json::json!({ "result": format!("{:?}", result.as_ref().unwrap()) }),
+        )
+        .unwrap();
+        assert_eq!(answered, 2);
+        let replies: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(
+            replies,
+            [1, 2].map(|id| serde_json::json!({ "id": id, "result": "Unknown(Cancelled)" }))
+        );
+    }
+}
@@ -0,0 +1,353 @@
+//! Shrinking a failed validation to a minimal failing core by delta debugging.
+//!
+//! Starting from a formula and a model the validation finds invalid or unknown, the shrinker splits top-level
+//! conjunctions into separate assertions and then repeatedly removes assertions, declarations, and definitions
+//! of the formula and entries of the model, keeping every removal after which the validation has the same outcome.
+//! Removals are tried in halving chunks, as in the `ddmin` algorithm, so large irrelevant parts are dropped
+//! with few validations. A declaration is only removed if no remaining command uses the declared symbol,
+//! and a model entry only if the formula does not use the variable it assigns,
+//! since an unassigned variable alone makes the model invalid.
+//! The result is 1-minimal: removing any single remaining command changes the outcome.
+
+use std::collections::HashSet;
+use std::mem::discriminant;
+use std::path::Path;
+
+use smt2parser::concrete::{Command, Term};
+use smt2parser::CommandStream;
+
+use crate::convert;
+use crate::error::Error;
+use crate::normalize;
+use crate::pipeline::CheckerPipeline;
+use crate::solver_output;
+use crate::validation::{self, ValidationOptions, ValidationReport, ValidationResult};
+
+/// The failing core of a validation
+#[derive(Clone, Debug)]
+pub struct Shrunk {
+    /// The remaining commands of the formula
+    pub formula: String,
+    /// The remaining `define-fun` commands of the model
+    pub model: String,
+    /// The outcome of the validation, which is that of the original formula and model
+    pub result: ValidationResult,
+    /// The number of commands of the formula before (not counting split conjunctions) and after shrinking
+    pub formula_commands: (usize, usize),
+    /// The number of model entries before and after shrinking
+    pub model_entries: (usize, usize),
+    /// The number of validations run while shrinking
+    pub validations: usize,
+}
+
+/// Shrinks the formula and the model while the validation keeps its outcome, see the [module documentation](self).
+/// If the model is valid, there is no failure to shrink and the result is the unchanged formula and model.
+/// Fails if the original validation fails.
+/// The checkers are kept for all validations, so the server checker reuses its Isabelle session.
+pub fn shrink(
+    smt_formula: &str,
+    smt_model: &str,
+    theory_path: &Path,
+    options: &ValidationOptions,
+) -> Result<Shrunk, Error> {
+    if options.emit_only.is_some() {
+        return Err(Error::Other(
+            "Cannot shrink a validation that only emits the theory".to_owned(),
+        ));
+    }
+    // The intermediate validations must not write bundles or transcripts
+    let options = ValidationOptions {
+        repro_dir: None,
+        log_isabelle: None,
+        ..options.clone()
+    };
+    let mut pipeline: Option<CheckerPipeline> = None;
+    shrink_with(smt_formula, smt_model, |formula, model| {
+        validation::validate_reusing(
+            model.to_owned(),
+            formula.to_owned(),
+            theory_path,
+            &options,
+            &mut pipeline,
+        )
+    })
+}
+
+/// Shrinks the formula and the model like [shrink], validating them with `validate`.
+fn shrink_with(
+    smt_formula: &str,
+    smt_model: &str,
+    mut validate: impl FnMut(&str, &str) -> (Result<ValidationResult, Error>, ValidationReport),
+) -> Result<Shrunk, Error> {
+    let mut validations = 0;
+    let mut run = |formula: &str, model: &str| {
+        validations += 1;
+        validate(formula, model)
+    };
+    let expected = run(smt_formula, smt_model).0?;
+    let model = parse(&solver_output::sanitize_model(smt_model, smt_formula))?;
+    let formula: Vec<Command> = parse(smt_formula)?;
+    let sizes = (formula.len(), model.len());
+    if expected == ValidationResult::Valid {
+        log::warn!("The model is valid, there is no failure to shrink");
+        return Ok(Shrunk {
+            formula: smt_formula.to_owned(),
+            model: smt_model.to_owned(),
+            result: expected,
+            formula_commands: (sizes.0, sizes.0),
+            model_entries: (sizes.1, sizes.1),
+            validations,
+        });
+    }
+    log::info!("Shrinking while the model is {}", expected.verdict());
+    let formula: Vec<Command> = formula.into_iter().flat_map(split_conjunctions).collect();
+
+    let mut formula_kept = vec![true; formula.len()];
+    let mut model_kept = vec![true; model.len()];
+    let mut outcome = expected.clone();
+    // Removing model entries may allow removing declarations, and the other way round
+    loop {
+        let mut progress = false;
+        progress |= minimize(&mut formula_kept, removable(&formula), |kept| {
+            if dangling(&formula, kept) {
+                return false;
+            }
+            let (result, _) = run(&render(&formula, kept), &render(&model, &model_kept));
+            keep_outcome(result, &expected, &mut outcome)
+        });
+        progress |= minimize(&mut model_kept, vec![true; model.len()], |kept| {
+            let (result, report) = run(&render(&formula, &formula_kept), &render(&model, kept));
+            report.undefined_vars.is_empty() && keep_outcome(result, &expected, &mut outcome)
+        });
+        if !progress {
+            break;
+        }
+    }
+
+    let count = |kept: &[bool]| kept.iter().filter(|k| **k).count();
+    Ok(Shrunk {
+        formula: render(&formula, &formula_kept),
+        model: render(&model, &model_kept),
+        result: outcome,
+        formula_commands: (sizes.0, count(&formula_kept)),
+        model_entries: (sizes.1, count(&model_kept)),
+        validations,
+    })
+}
+
+/// Returns whether the result is the expected outcome, i.e. the same verdict and, if unknown, the same kind of reason.
+/// The reason itself may change, e.g. the position of an unsupported function.
+fn keep_outcome(
+    result: Result<ValidationResult, Error>,
+    expected: &ValidationResult,
+    outcome: &mut ValidationResult,
+) -> bool {
+    let same = match (&result, expected) {
+        (Ok(ValidationResult::Unknown(r)), ValidationResult::Unknown(e)) => {
+            discriminant(r) == discriminant(e)
+        }
+        (Ok(r), e) => r == e,
+        (Err(_), _) => false,
+    };
+    if same {
+        *outcome = result.expect("Only successful validations keep the outcome");
+    }
+    same
+}
+
+/// Removes elements marked as removable from `kept` as long as `test` accepts the remaining ones.
+/// Chunks of the removable elements are removed at once, halving the chunk size until single elements are tried.
+/// Returns whether any element was removed.
+pub(crate) fn minimize(
+    kept: &mut [bool],
+    removable: Vec<bool>,
+    mut test: impl FnMut(&[bool]) -> bool,
+) -> bool {
+    let candidates = |kept: &[bool]| -> Vec<usize> {
+        (0..kept.len())
+            .filter(|i| kept[*i] && removable[*i])
+            .collect()
+    };
+    let mut removed = false;
+    let mut chunk_size = candidates(kept).len().div_ceil(2);
+    while chunk_size > 0 {
+        let mut progress = false;
+        let current = candidates(kept);
+        for chunk in current.chunks(chunk_size) {
+            let mut attempt = kept.to_vec();
+            for i in chunk {
+                attempt[*i] = false;
+            }
+            if test(&attempt) {
+                log::debug!("Removed {} command(s)", chunk.len());
+                kept.copy_from_slice(&attempt);
+                progress = true;
+            }
+        }
+        removed |= progress;
+        if chunk_size == 1 && !progress {
+            break;
+        }
+        // Single elements are tried again until none can be removed
+        chunk_size = if chunk_size == 1 { 1 } else { chunk_size / 2 };
+    }
+    removed
+}
+
+/// Returns which commands of the formula may be removed: assertions and the declarations of a single symbol,
+/// but not the commands structuring the script, such as `check-sat`, `push`, and `pop`.
+fn removable(commands: &[Command]) -> Vec<bool> {
+    commands
+        .iter()
+        .map(|c| matches!(c, Command::Assert { .. }) || declared_symbol(c).is_some())
+        .collect()
+}
+
+/// Returns the symbol the command declares or defines, if it declares a single one.
+fn declared_symbol(command: &Command) -> Option<&str> {
+    match command {
+        Command::DeclareConst { symbol, .. }
+        | Command::DeclareFun { symbol, .. }
+        | Command::DeclareSort { symbol, .. }
+        | Command::DefineSort { symbol, .. } => Some(&symbol.0),
+        Command::DefineFun { sig, .. } | Command::DefineFunRec { sig, .. } => Some(&sig.name.0),
+        _ => None,
+    }
+}
+
+/// Returns whether a kept command uses a symbol whose declaration was removed.
+fn dangling(commands: &[Command], kept: &[bool]) -> bool {
+    let used: HashSet<String> = commands
+        .iter()
+        .zip(kept)
+        .filter(|(_, k)| **k)
+        .flat_map(|(c, _)| symbols(&c.to_string()))
+        .collect();
+    commands
+        .iter()
+        .zip(kept)
+        .filter(|(_, k)| !**k)
+        .filter_map(|(c, _)| declared_symbol(c))
+        .any(|s| used.contains(s))
+}
+
+/// Returns the tokens of the printed command, which include all symbols it uses.
+/// Quoted symbols such as `|hello world|` are single tokens without the bars, as the parser reads them.
+fn symbols(command: &str) -> Vec<String> {
+    let delimiter = |c: char| c == '(' || c == ')' || c.is_whitespace();
+    let mut tokens = vec![];
+    let mut rest = command.trim_start_matches(delimiter);
+    while !rest.is_empty() {
+        let (token, after) = match rest.strip_prefix('|') {
+            Some(quoted) => quoted.split_once('|').unwrap_or((quoted, "")),
+            None => rest.split_at(rest.find(delimiter).unwrap_or(rest.len())),
+        };
+        tokens.push(token.to_owned());
+        rest = after.trim_start_matches(delimiter);
+    }
+    tokens
+}
+
+/// Splits an assertion of a conjunction into assertions of the conjuncts, which can then be removed separately.
+fn split_conjunctions(command: Command) -> Vec<Command> {
+    match command {
+        Command::Assert { term } => conjuncts(term)
+            .into_iter()
+            .map(|term| Command::Assert { term })
+            .collect(),
+        c => vec![c],
+    }
+}
+
+/// Returns the conjuncts of nested top-level conjunctions.
+fn conjuncts(term: Term) -> Vec<Term> {
+    match normalize::as_application(&term, "and") {
+        Some(arguments) => arguments.iter().cloned().flat_map(conjuncts).collect(),
+        None => vec![term],
+    }
+}
+
+/// Parses the commands of a formula or model.
+fn parse(input: &str) -> Result<Vec<Command>, Error> {
+    CommandStream::new(
+        convert::preprocess(input).as_bytes(),
+        smt2parser::concrete::SyntaxBuilder,
+        None,
+    )
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(Error::ParseError)
+}
+
+/// Prints the kept commands, one per line.
+fn render(commands: &[Command], kept: &[bool]) -> String {
+    commands
+        .iter()
+        .zip(kept)
+        .filter(|(_, k)| **k)
+        .map(|(c, _)| format!("{}\n", c))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::convert::BuiltinSpec;
+    use crate::pipeline::Stage;
+
+    #[test]
+    fn minimize_to_single_culprit() {
+        let mut kept = vec![true; 10];
+        let mut tests = 0;
+        let removed = minimize(&mut kept, vec![true; 10], |kept| {
+            tests += 1;
+            kept[6]
+        });
+        assert!(removed);
+        assert_eq!(kept.iter().positions(|k| *k).collect::<Vec<_>>(), vec![6]);
+        assert!(tests < 20);
+
+        // Elements that are not removable are kept
+        let mut kept = vec![true; 3];
+        minimize(&mut kept, vec![true, false, true], |_| true);
+        assert_eq!(kept, [false, true, false]);
+    }
+
+    #[test]
+    fn quoted_symbol_tokens() {
+        assert_eq!(
+            symbols("(assert (> |hello world| (f x)))"),
+            ["assert", ">", "hello world", "f", "x"]
+        );
+    }
+
+    #[test]
+    fn shrink_invalid_model() {
+        let options = ValidationOptions {
+            builtin_spec: Some(BuiltinSpec::QfSlia),
+            pipeline: vec![Stage::Eval],
+            ..Default::default()
+        };
+        let formula =
+            "(set-logic QF_SLIA)(declare-const x Int)(declare-const y Int)(declare-const s String)\
+            (assert (and (> x 0) (< y 5) (= (str.len s) 2)))(assert (> y x))(check-sat)";
+        let model =
+            "sat\n((define-fun x () Int 3)(define-fun y () Int 4)(define-fun s () String \"a\"))";
+        let shrunk = shrink(formula, model, Path::new("/nonexistent"), &options).unwrap();
+        assert_eq!(shrunk.result, ValidationResult::Invalid);
+        assert_eq!(
+            shrunk.formula,
+            "(set-logic QF_SLIA)\n(declare-const s String)\n(assert (= (str.len s) 2))\n(check-sat)\n"
+        );
+        assert_eq!(shrunk.model, "(define-fun s () String \"a\")\n");
+        assert_eq!(shrunk.formula_commands, (7, 4));
+        assert_eq!(shrunk.model_entries, (3, 1));
+
+        let valid =
+            "sat\n((define-fun x () Int 3)(define-fun y () Int 4)(define-fun s () String \"ab\"))";
+        let unchanged = shrink(formula, valid, Path::new("/nonexistent"), &options).unwrap();
+        assert_eq!(unchanged.result, ValidationResult::Valid);
+        assert_eq!(unchanged.model, valid);
+    }
+}
@@ -0,0 +1,317 @@
+//! Simplification of converted terms before generating the lemma.
+//!
+//! The simplifier folds ground integer arithmetic and comparisons, concatenations and lengths of string literals,
+//! conversions between strings, codes, and integers, and Boolean connectives with a constant operand, bottom-up. Folding follows the SMT-LIB semantics of the functions,
+//! which the spec is expected to map faithfully. The converted terms of the spec functions keep their SMT-LIB name,
+//! so the simplifier works for all targets; the results are literals or the terms the spec maps `true` and `false` to.
+//! Smaller lemmas leave less work to the proof method, and ground subterms no longer depend on its simplification rules.
+
+use crate::eval::MAX_CODE_POINT;
+use crate::ir::Term;
+
+/// Simplifies converted terms, see the [module documentation](self)
+pub(crate) struct Simplifier {
+    /// The names the spec maps `true` and `false` to; Booleans are only folded if both are mapped
+    booleans: Option<(String, String)>,
+}
+
+impl Simplifier {
+    pub(crate) fn new(true_name: Option<String>, false_name: Option<String>) -> Self {
+        Self {
+            booleans: true_name.zip(false_name),
+        }
+    }
+
+    /// Simplifies the term and its subterms.
+    pub(crate) fn simplify(&self, term: Term) -> Term {
+        match term.map_subterms(|t| self.simplify(t)) {
+            Term::Apply { op, function, args } => {
+                self.fold(&op, &args)
+                    .unwrap_or(Term::Apply { op, function, args })
+            }
+            Term::Template { op, template, args } => self
+                .fold(&op, &args)
+                .unwrap_or(Term::Template { op, template, args }),
+            Term::Ite(c, a, b) => match self.bool_value(&c) {
+                Some(true) => *a,
+                Some(false) => *b,
+                None => Term::Ite(c, a, b),
+            },
+            Term::Conjunction(terms) => self
+                .fold_connective(true, &terms)
+                .unwrap_or(Term::Conjunction(terms)),
+            t => t,
+        }
+    }
+
+    /// Returns the simplified application of the SMT-LIB function to the simplified arguments, if it simplifies.
+    pub(crate) fn fold(&self, op: &str, args: &[Term]) -> Option<Term> {
+        match op {
+            "and" => self.fold_connective(true, args),
+            "or" => self.fold_connective(false, args),
+            "not" => match args {
+                [a] => self.bool_term(!self.bool_value(a)?),
+                _ => None,
+            },
+            "=>" => match args {
+                [a, b] => match (self.bool_value(a), self.bool_value(b)) {
+                    (Some(false), _) | (_, Some(true)) => self.bool_term(true),
+                    (Some(true), _) => Some(b.clone()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            "=" | "distinct" => {
+                let equal = match args {
+                    [Term::Int(a), Term::Int(b)] => int_value(a)? == int_value(b)?,
+                    [Term::Str(a), Term::Str(b)] => a == b,
+                    [a, b] => {
+                        // Only the Booleans of the spec are known to be distinct
+                        let (a, b) = (self.bool_value(a)?, self.bool_value(b)?);
+                        a == b
+                    }
+                    _ => return None,
+                };
+                self.bool_term(equal == (op == "="))
+            }
+            "<" | "<=" | ">" | ">=" => {
+                let ints = int_values(args)?;
+                let holds = ints.windows(2).all(|p| match op {
+                    "<" => p[0] < p[1],
+                    "<=" => p[0] <= p[1],
+                    ">" => p[0] > p[1],
+                    _ => p[0] >= p[1],
+                });
+                self.bool_term(holds)
+            }
+            "+" | "*" | "-" | "div" | "mod" | "abs" => {
+                let ints = int_values(args)?;
+                let value = match (op, ints.as_slice()) {
+                    ("+", _) => ints.iter().try_fold(0i128, |a, b| a.checked_add(*b)),
+                    ("*", _) => ints.iter().try_fold(1i128, |a, b| a.checked_mul(*b)),
+                    ("-", [a]) => a.checked_neg(),
+                    ("-", [a, rest @ ..]) => rest.iter().try_fold(*a, |a, b| a.checked_sub(*b)),
+                    // Division by zero is unspecified in SMT-LIB, so it is left to the spec
+                    ("div" | "mod", [_, 0]) => None,
+                    ("div", [a, b]) => a.checked_div_euclid(*b),
+                    ("mod", [a, b]) => a.checked_rem_euclid(*b),
+                    ("abs", [a]) => a.checked_abs(),
+                    _ => None,
+                }?;
+                Some(Term::Int(value.to_string()))
+            }
+            "str.++" => {
+                let mut s = String::new();
+                for a in args {
+                    match a {
+                        Term::Str(a) => s += a,
+                        _ => return None,
+                    }
+                }
+                Some(Term::Str(s))
+            }
+            "str.len" => match args {
+                [Term::Str(s)] => Some(Term::Int(s.chars().count().to_string())),
+                _ => None,
+            },
+            "str.is_digit" => match args {
+                [Term::Str(s)] => self.bool_term(s.len() == 1 && is_digits(s)),
+                _ => None,
+            },
+            "str.to_code" => match args {
+                [Term::Str(s)] => {
+                    let mut chars = s.chars();
+                    let code = match (chars.next(), chars.next()) {
+                        (Some(c), None) => i128::from(u32::from(c)),
+                        _ => -1,
+                    };
+                    Some(Term::Int(code.to_string()))
+                }
+                _ => None,
+            },
+            "str.from_code" => match args {
+                [Term::Int(n)] => Some(Term::Str(match u32::try_from(int_value(n)?) {
+                    // Surrogates are code points of SMT-LIB strings, but not characters of literals
+                    Ok(c) if c <= MAX_CODE_POINT => char::from_u32(c)?.to_string(),
+                    _ => String::new(),
+                })),
+                _ => None,
+            },
+            "str.to_int" => match args {
+                [Term::Str(s)] if !is_digits(s) => Some(Term::Int("-1".to_owned())),
+                [Term::Str(s)] => Some(Term::Int(s.parse::<i128>().ok()?.to_string())),
+                _ => None,
+            },
+            "str.from_int" => match args {
+                [Term::Int(n)] => {
+                    let n = int_value(n)?;
+                    Some(Term::Str(if n < 0 { String::new() } else { n.to_string() }))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Simplifies a conjunction (if `and`) or disjunction with constant operands:
+    /// the neutral constant is dropped, and the absorbing one absorbs the whole term.
+    fn fold_connective(&self, and: bool, args: &[Term]) -> Option<Term> {
+        let mut rest = vec![];
+        for a in args {
+            match self.bool_value(a) {
+                Some(b) if b == and => (),
+                Some(_) => return self.bool_term(!and),
+                None => rest.push(a),
+            }
+        }
+        match rest.as_slice() {
+            [] => self.bool_term(and),
+            [a] => Some((*a).clone()),
+            // The remaining operands are kept, since the function of the spec may have a fixed arity
+            _ => None,
+        }
+    }
+
+    /// Returns the Boolean the term is, if it is a constant of the spec.
+    fn bool_value(&self, term: &Term) -> Option<bool> {
+        let (true_name, false_name) = self.booleans.as_ref()?;
+        match term {
+            Term::Name(n) if n == true_name => Some(true),
+            Term::Name(n) if n == false_name => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Returns the constant of the spec for the Boolean, if the spec maps the Booleans.
+    fn bool_term(&self, b: bool) -> Option<Term> {
+        let (true_name, false_name) = self.booleans.as_ref()?;
+        Some(Term::Name(if b { true_name } else { false_name }.clone()))
+    }
+}
+
+fn int_value(literal: &str) -> Option<i128> {
+    literal.parse().ok()
+}
+
+/// Returns whether the string is non-empty and consists of the decimal digits `0` to `9` only.
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Returns the values of the arguments if all are integer literals.
+fn int_values(args: &[Term]) -> Option<Vec<i128>> {
+    args.iter()
+        .map(|a| match a {
+            Term::Int(i) => int_value(i),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Simplifier;
+    use crate::ir::Term;
+
+    fn apply(op: &str, args: Vec<Term>) -> Term {
+        Term::Apply {
+            op: op.to_owned(),
+            function: op.to_owned(),
+            args,
+        }
+    }
+
+    fn int(i: i128) -> Term {
+        Term::Int(i.to_string())
+    }
+
+    #[test]
+    fn fold_constants() {
+        let simplifier = Simplifier::new(Some("True".to_owned()), Some("False".to_owned()));
+        let x = Term::Var("x".to_owned());
+        let t = Term::Name("True".to_owned());
+        let f = Term::Name("False".to_owned());
+
+        // (< x (+ 1 (* 2 3))) and (mod -7 2)
+        let sum = apply("+", vec![int(1), apply("*", vec![int(2), int(3)])]);
+        assert_eq!(
+            simplifier.simplify(apply("<", vec![x.clone(), sum])),
+            apply("<", vec![x.clone(), int(7)])
+        );
+        assert_eq!(
+            simplifier.simplify(apply("mod", vec![int(-7), int(2)])),
+            int(1)
+        );
+        assert_eq!(
+            simplifier.simplify(apply("div", vec![int(1), int(0)])),
+            apply("div", vec![int(1), int(0)])
+        );
+
+        let concat = apply(
+            "str.++",
+            vec![Term::Str("ab".to_owned()), Term::Str("c".to_owned())],
+        );
+        assert_eq!(simplifier.simplify(apply("str.len", vec![concat])), int(3));
+
+        // (and (= 1 1) x), (or x (> 1 2)), (=> false x), (not (distinct "a" "a"))
+        let equal = apply("=", vec![int(1), int(1)]);
+        assert_eq!(simplifier.simplify(apply("and", vec![equal, x.clone()])), x);
+        let greater = apply(">", vec![int(1), int(2)]);
+        assert_eq!(
+            simplifier.simplify(apply("or", vec![x.clone(), greater])),
+            x
+        );
+        assert_eq!(
+            simplifier.simplify(apply("=>", vec![f.clone(), x.clone()])),
+            t
+        );
+        let distinct = apply(
+            "distinct",
+            vec![Term::Str("a".to_owned()), Term::Str("a".to_owned())],
+        );
+        assert_eq!(simplifier.simplify(apply("not", vec![distinct])), t);
+        assert_eq!(
+            simplifier.simplify(Term::Conjunction(vec![x.clone(), f.clone()])),
+            f
+        );
+        assert_eq!(
+            simplifier.simplify(Term::Ite(
+                Box::new(t.clone()),
+                Box::new(int(1)),
+                Box::new(x.clone())
+            )),
+            int(1)
+        );
+
+        // str.to_int and str.to_code are -1 for strings that are not numerals or single characters
+        let str_apply =
+            |op: &str, s: &str| simplifier.simplify(apply(op, vec![Term::Str(s.to_owned())]));
+        assert_eq!(str_apply("str.to_int", "0042"), int(42));
+        assert_eq!(str_apply("str.to_int", "-1"), int(-1));
+        assert_eq!(str_apply("str.to_int", ""), int(-1));
+        assert_eq!(str_apply("str.to_code", "a"), int(97));
+        assert_eq!(str_apply("str.to_code", "ab"), int(-1));
+        assert_eq!(str_apply("str.is_digit", "7"), t);
+        assert_eq!(str_apply("str.is_digit", "77"), f);
+        let int_apply = |op: &str, i: i128| simplifier.simplify(apply(op, vec![int(i)]));
+        assert_eq!(int_apply("str.from_int", 42), Term::Str("42".to_owned()));
+        assert_eq!(int_apply("str.from_int", -3), Term::Str(String::new()));
+        assert_eq!(
+            int_apply("str.from_code", 0x1F600),
+            Term::Str("😀".to_owned())
+        );
+        assert_eq!(
+            int_apply("str.from_code", 0x30000),
+            Term::Str(String::new())
+        );
+        assert_eq!(
+            int_apply("str.from_code", 0xD800),
+            apply("str.from_code", vec![int(0xD800)])
+        );
+
+        // Without Booleans in the spec, only non-Boolean terms are folded
+        let simplifier = Simplifier::new(None, None);
+        let equal = apply("=", vec![int(1), int(1)]);
+        assert_eq!(simplifier.simplify(equal.clone()), equal);
+    }
+}
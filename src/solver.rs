@@ -0,0 +1,219 @@
+//! Running an SMT solver on a formula to obtain the model to validate.
+//!
+//! The script is extended to produce a model: models are enabled before the first command,
+//! and a `get-model` follows the `check-sat` whose model is validated, ending the script.
+
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use clap::ValueEnum;
+
+use crate::error::Error;
+use crate::solver_output;
+
+/// An SMT solver that can produce the model to validate
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Solver {
+    Z3,
+    Cvc5,
+    Yices,
+    Mathsat,
+    Bitwuzla,
+}
+
+impl Solver {
+    /// Returns the name of the solver's executable.
+    pub fn executable(&self) -> &'static str {
+        match self {
+            Solver::Z3 => "z3",
+            Solver::Cvc5 => "cvc5",
+            Solver::Yices => "yices-smt2",
+            Solver::Mathsat => "mathsat",
+            Solver::Bitwuzla => "bitwuzla",
+        }
+    }
+
+    /// Returns the arguments making the solver read the script from the given file.
+    fn arguments(&self, script: &Path) -> Vec<String> {
+        let script = script.display().to_string();
+        match self {
+            Solver::Z3 => vec!["-smt2".to_owned(), script],
+            Solver::Mathsat => vec!["-input=smt2".to_owned(), script],
+            _ => vec![script],
+        }
+    }
+}
+
+impl Display for Solver {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.executable())
+    }
+}
+
+/// The answer of a solver to the `check-sat` of a script
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SolverAnswer {
+    /// The formula is satisfiable; the output holds the model
+    Sat(String),
+    Unsat,
+    Unknown,
+}
+
+impl Display for SolverAnswer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SolverAnswer::Sat(_) => write!(f, "sat"),
+            SolverAnswer::Unsat => write!(f, "unsat"),
+            SolverAnswer::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Runs the solver on the script and returns its answer to the `check-sat` with the given index (counting from 0),
+/// or to the last one if no index is given.
+/// `executable` overrides the solver's executable on the `PATH`.
+pub fn solve(
+    solver: Solver,
+    executable: Option<&Path>,
+    script: &str,
+    check_index: Option<usize>,
+) -> Result<SolverAnswer, Error> {
+    let dir = tempfile::tempdir()
+        .map_err(|e| Error::Other(format!("Could not create a temporary directory: {}", e)))?;
+    let path = dir.path().join("formula.smt2");
+    fs::write(&path, model_script(script, check_index))
+        .map_err(|e| Error::Other(format!("Could not write {}: {}", path.display(), e)))?;
+    let executable = executable.unwrap_or_else(|| Path::new(solver.executable()));
+    log::info!("Running {}", executable.display());
+    let output = Command::new(executable)
+        .args(solver.arguments(&path))
+        .output()
+        .map_err(|e| Error::Other(format!("Could not run {}: {}", executable.display(), e)))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    log::trace!("Solver output: '{}'", stdout);
+    answer(&stdout, check_index).ok_or_else(|| {
+        Error::Other(format!(
+            "{} gave no answer to the check-sat: {}",
+            solver,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    })
+}
+
+/// Returns the script extended to produce the model of the `check-sat` with the given index, or of the last one.
+/// The commands after that `check-sat`, e.g. an `exit`, are dropped, so the `get-model` right after it is answered.
+/// Scripts without that `check-sat`, or which cannot be split into commands, are kept whole.
+fn model_script(script: &str, check_index: Option<usize>) -> String {
+    let mut res = String::from("(set-option :produce-models true)\n");
+    let commands = solver_output::sexprs(script).unwrap_or_default();
+    let checks: Vec<usize> = commands
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| is_check_sat(c))
+        .map(|(index, _)| index)
+        .collect();
+    let check = match check_index {
+        Some(i) => checks.get(i),
+        None => checks.last(),
+    };
+    match check {
+        Some(&check) => {
+            for command in &commands[..=check] {
+                res.push_str(command);
+                res.push('\n');
+            }
+        }
+        None => {
+            res.push_str(script);
+            res.push('\n');
+        }
+    }
+    res.push_str("(get-model)\n");
+    res
+}
+
+/// Returns whether the command is a `check-sat` or `check-sat-assuming`.
+fn is_check_sat(command: &str) -> bool {
+    let name = command
+        .strip_prefix('(')
+        .and_then(|c| solver_output::sexprs(c.strip_suffix(')')?))
+        .and_then(|items| items.first().copied());
+    matches!(name, Some("check-sat" | "check-sat-assuming"))
+}
+
+/// Returns the answer to the `check-sat` with the given index in the solver output, with the output up to the next answer.
+fn answer(output: &str, check_index: Option<usize>) -> Option<SolverAnswer> {
+    let items = solver_output::sexprs(output)?;
+    let answers: Vec<usize> = items
+        .iter()
+        .enumerate()
+        .filter(|(_, i)| matches!(**i, "sat" | "unsat" | "unknown"))
+        .map(|(index, _)| index)
+        .collect();
+    let index = *match check_index {
+        Some(i) => answers.get(i)?,
+        None => answers.last()?,
+    };
+    let end = answers
+        .iter()
+        .find(|&&a| a > index)
+        .copied()
+        .unwrap_or(items.len());
+    match items[index] {
+        "sat" => Some(SolverAnswer::Sat(items[index..end].join("\n"))),
+        "unsat" => Some(SolverAnswer::Unsat),
+        _ => Some(SolverAnswer::Unknown),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solver_answers() {
+        let output = "sat\n((define-fun x () Int 1))\nunsat\n(error \"no model\")\nsat\n((define-fun x () Int 2))";
+        assert_eq!(
+            answer(output, None),
+            Some(SolverAnswer::Sat(
+                "sat\n((define-fun x () Int 2))".to_owned()
+            ))
+        );
+        assert_eq!(
+            answer(output, Some(0)),
+            Some(SolverAnswer::Sat(
+                "sat\n((define-fun x () Int 1))".to_owned()
+            ))
+        );
+        assert_eq!(answer(output, Some(1)), Some(SolverAnswer::Unsat));
+        assert_eq!(answer(output, Some(3)), None);
+        assert_eq!(answer("(error \"unknown logic\")", None), None);
+        assert_eq!(
+            model_script("(check-sat)", None),
+            "(set-option :produce-models true)\n(check-sat)\n(get-model)\n"
+        );
+    }
+
+    #[test]
+    fn get_model_after_check_sat() {
+        assert_eq!(
+            model_script("(check-sat)(exit)", None),
+            "(set-option :produce-models true)\n(check-sat)\n(get-model)\n"
+        );
+        let script = "(declare-const x Int)\n(assert (> x 0))\n( check-sat )\n(get-model)\n(exit)";
+        assert_eq!(
+            model_script(script, None),
+            "(set-option :produce-models true)\n(declare-const x Int)\n(assert (> x 0))\n( check-sat )\n(get-model)\n"
+        );
+        let script = "(check-sat)\n(push 1)\n(assert (> x 1))\n(check-sat-assuming (p))\n(pop 1)";
+        assert_eq!(
+            model_script(script, Some(0)),
+            "(set-option :produce-models true)\n(check-sat)\n(get-model)\n"
+        );
+        assert!(model_script(script, None).ends_with("(check-sat-assuming (p))\n(get-model)\n"));
+        // Without the check-sat, the solver fails to answer
+        assert!(model_script(script, Some(2)).ends_with("(pop 1)\n(get-model)\n"));
+    }
+}
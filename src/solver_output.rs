@@ -0,0 +1,589 @@
+//! Normalization of the model output of SMT solvers.
+//!
+//! Solvers print models in different shapes. Z3, cvc5, and Bitwuzla answer `get-model` with a list of
+//! `define-fun` commands, wrapped in `(model ...)` by older versions. cvc5 defines functions as constants
+//! with `lambda` values. Yices prints its models as equalities `(= x 1)` and `(function ...)` tables,
+//! and MathSAT, like every solver answering `get-value`, prints pairs of terms and values `((x 1) (y 2))`.
+//! [sanitize_model] turns all of these into a plain list of `define-fun` commands the converter reads.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use smt2parser::visitors::Identifier;
+use smt2parser::{concrete, CommandStream};
+
+/// Converts the solver output to a list of `define-fun` commands.
+/// The formula provides the sorts of the constants and functions for outputs that do not state them.
+/// Output that is not part of a model, such as the answer `sat` or error messages, is dropped.
+/// If the output is not a list of S-expressions, it is returned without the answer.
+pub(crate) fn sanitize_model(output: &str, formula: &str) -> String {
+    let output = output.trim();
+    if output.matches("sat").count() > 1 {
+        log::warn!("Multiple 'sat' in model, did you provide two models?");
+    }
+    let Some(items) = sexprs(output) else {
+        log::warn!("Unbalanced parentheses in model");
+        return output
+            .strip_prefix("sat")
+            .unwrap_or(output)
+            .trim()
+            .to_owned();
+    };
+    let declarations = Declarations::of(formula);
+    let mut definitions = vec![];
+    for item in items {
+        definitions.extend(declarations.definitions(item));
+    }
+    definitions.join("\n")
+}
+
+/// Returns `define-fun` commands assigning the default value of their sort to the given constants and functions of the formula,
+/// with the names they assign: `false`, `0`, `0.0`, and `""` for Bool, Int, Real, and String.
+/// Functions map all arguments to the default value of their result sort.
+/// Names that are not declared in the formula or have another sort are left out.
+pub(crate) fn default_definitions(formula: &str, names: &[String]) -> Vec<(String, String)> {
+    let declarations = Declarations::of(formula);
+    names
+        .iter()
+        .filter_map(|name| {
+            let (parameters, sort) = declarations.get(name)?;
+            let value = match sort.as_str() {
+                "Bool" => "false",
+                "Int" => "0",
+                "Real" => "0.0",
+                "String" => "\"\"",
+                _ => return None,
+            };
+            let params = parameters
+                .iter()
+                .enumerate()
+                .map(|(i, s)| format!("(_x{} {})", i, s))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let definition = format!("(define-fun {} ({}) {} {})", name, params, sort, value);
+            Some((name.clone(), definition))
+        })
+        .collect()
+}
+
+/// Returns the names of the constants and functions declared in the formula.
+pub(crate) fn declared_symbols(formula: &str) -> HashSet<String> {
+    Declarations::of(formula).sorts.into_keys().collect()
+}
+
+/// Returns the symbols the model uses that look internal to the solver and are neither declared nor defined by it
+/// nor `known`, sorted: names containing `!`, such as the skolem constants `k!3` of z3, names starting with `@`,
+/// as cvc5 names its internal functions, and the `seq.` functions of z3.
+pub(crate) fn internal_symbols(model: &str, known: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut defined = HashSet::new();
+    let mut used = BTreeSet::new();
+    for command in CommandStream::new(model.as_bytes(), concrete::SyntaxBuilder, None).flatten() {
+        match command {
+            concrete::Command::DefineFun { sig, term }
+            | concrete::Command::DefineFunRec { sig, term } => {
+                let mut bound = sig.parameters.into_iter().map(|(s, _)| s.0).collect();
+                free_symbols(&term, &mut bound, &mut used);
+                defined.insert(sig.name.0);
+            }
+            concrete::Command::DeclareConst { symbol, .. }
+            | concrete::Command::DeclareFun { symbol, .. } => {
+                defined.insert(symbol.0);
+            }
+            _ => (),
+        }
+    }
+    used.into_iter()
+        .filter(|s| s.contains('!') || s.starts_with('@') || s.starts_with("seq."))
+        .filter(|s| !defined.contains(s) && !known(s))
+        .collect()
+}
+
+/// Adds the names of the constants and functions the term uses to `used`, except for the `bound` variables.
+fn free_symbols(term: &concrete::Term, bound: &mut Vec<String>, used: &mut BTreeSet<String>) {
+    fn bind(
+        symbols: Vec<String>,
+        term: &concrete::Term,
+        bound: &mut Vec<String>,
+        used: &mut BTreeSet<String>,
+    ) {
+        let len = bound.len();
+        bound.extend(symbols);
+        free_symbols(term, bound, used);
+        bound.truncate(len);
+    }
+    match term {
+        concrete::Term::Constant(_) => (),
+        concrete::Term::QualIdentifier(identifier) => {
+            let symbol = identifier_symbol(identifier);
+            if !bound.contains(&symbol) {
+                used.insert(symbol);
+            }
+        }
+        concrete::Term::Application {
+            qual_identifier,
+            arguments,
+        } => {
+            used.insert(identifier_symbol(qual_identifier));
+            for a in arguments {
+                free_symbols(a, bound, used);
+            }
+        }
+        concrete::Term::Let { var_bindings, term } => {
+            for (_, value) in var_bindings {
+                free_symbols(value, bound, used);
+            }
+            let symbols = var_bindings.iter().map(|(s, _)| s.0.clone()).collect();
+            bind(symbols, term, bound, used);
+        }
+        concrete::Term::Forall { vars, term } | concrete::Term::Exists { vars, term } => {
+            bind(
+                vars.iter().map(|(s, _)| s.0.clone()).collect(),
+                term,
+                bound,
+                used,
+            );
+        }
+        concrete::Term::Match { term, cases } => {
+            free_symbols(term, bound, used);
+            for (pattern, case) in cases {
+                bind(
+                    pattern.iter().map(|s| s.0.clone()).collect(),
+                    case,
+                    bound,
+                    used,
+                );
+            }
+        }
+        concrete::Term::Attributes { term, .. } => free_symbols(term, bound, used),
+    }
+}
+
+fn identifier_symbol(identifier: &concrete::QualIdentifier) -> String {
+    match identifier {
+        concrete::QualIdentifier::Simple { identifier }
+        | concrete::QualIdentifier::Sorted { identifier, .. } => match identifier {
+            Identifier::Simple { symbol } | Identifier::Indexed { symbol, .. } => symbol.0.clone(),
+        },
+    }
+}
+
+/// The sorts of the constants and functions declared in a formula
+struct Declarations {
+    /// The parameter sorts and the result sort of each function, by name
+    sorts: HashMap<String, (Vec<String>, String)>,
+}
+
+impl Declarations {
+    fn of(formula: &str) -> Self {
+        let sorts = CommandStream::new(formula.as_bytes(), concrete::SyntaxBuilder, None)
+            .flatten()
+            .filter_map(|c| match c {
+                concrete::Command::DeclareConst { symbol, sort } => {
+                    Some((symbol.0, (vec![], sort.to_string())))
+                }
+                concrete::Command::DeclareFun {
+                    symbol,
+                    parameters,
+                    sort,
+                } => Some((
+                    symbol.0,
+                    (
+                        parameters.iter().map(|s| s.to_string()).collect(),
+                        sort.to_string(),
+                    ),
+                )),
+                _ => None,
+            })
+            .collect();
+        Self { sorts }
+    }
+
+    fn get(&self, name: &str) -> Option<&(Vec<String>, String)> {
+        self.sorts.get(name.trim_matches('|'))
+    }
+
+    /// Returns the `define-fun` commands for a top-level item of the solver output.
+    fn definitions(&self, item: &str) -> Vec<String> {
+        let Some(elements) = list(item) else {
+            if item != "sat" {
+                log::warn!("Ignoring '{}' in the solver output", item);
+            }
+            return vec![];
+        };
+        match elements[..] {
+            // A model wrapped in '(model ...)'
+            ["model", ref definitions @ ..] => definitions
+                .iter()
+                .flat_map(|d| self.definitions(d))
+                .collect(),
+            ["define-fun", name, "()", sort, value] => {
+                vec![define_lambda(name, sort, value).unwrap_or_else(|| item.to_owned())]
+            }
+            ["define-fun" | "define-fun-rec" | "define-funs-rec" | "declare-sort"
+            | "declare-fun" | "declare-datatypes", ..] => vec![item.to_owned()],
+            // A Yices equality
+            ["=", name, value] => self.define(name, value).into_iter().collect(),
+            // A Yices function table
+            ["function", name, ref entries @ ..] => {
+                self.define_table(name, entries).into_iter().collect()
+            }
+            ["error", ..] => {
+                log::warn!("Ignoring output after the model: '{}'", item);
+                vec![]
+            }
+            _ => {
+                let pairs: Option<Vec<_>> = elements
+                    .iter()
+                    .map(|e| match list(e)?[..] {
+                        [term, value] => Some((term, value)),
+                        _ => None,
+                    })
+                    .collect();
+                match pairs {
+                    // Pairs of terms and values, answering 'get-value', or a MathSAT model
+                    Some(pairs) if !pairs.is_empty() => pairs
+                        .into_iter()
+                        .filter_map(|(term, value)| self.define(term, value))
+                        .collect(),
+                    // A list of definitions
+                    _ => elements.iter().flat_map(|d| self.definitions(d)).collect(),
+                }
+            }
+        }
+    }
+
+    /// Returns the definition of the constant or function with the given value, using the declared sort.
+    fn define(&self, name: &str, value: &str) -> Option<String> {
+        let Some((parameters, sort)) = self.get(name) else {
+            log::warn!(
+                "Ignoring the value of '{}', which is not a declared constant",
+                name
+            );
+            return None;
+        };
+        let value = normalize_value(value);
+        if let Some(lambda) = define_lambda(name, &format!("(-> {})", sort), &value) {
+            return Some(lambda);
+        }
+        if !parameters.is_empty() {
+            log::warn!(
+                "Ignoring the value of function '{}', which is not a lambda",
+                name
+            );
+            return None;
+        }
+        Some(format!("(define-fun {} () {} {})", name, sort, value))
+    }
+
+    /// Returns the definition of a function from a Yices function table such as
+    /// `(function f (type (-> int int)) (= (f 1) 2) (default 0))`.
+    fn define_table(&self, name: &str, entries: &[&str]) -> Option<String> {
+        let Some((parameters, sort)) = self.get(name) else {
+            log::warn!(
+                "Ignoring the table of '{}', which is not a declared function",
+                name
+            );
+            return None;
+        };
+        let params: Vec<String> = (0..parameters.len()).map(|i| format!("_x{}", i)).collect();
+        let mut body = None;
+        let mut cases = vec![];
+        for entry in entries {
+            match list(entry).as_deref() {
+                Some(["type", _]) => (),
+                Some(["default", value]) => body = Some(normalize_value(value)),
+                Some(["=", application, value]) => {
+                    let arguments = list(application).filter(|a| a.len() == params.len() + 1);
+                    let Some(arguments) = arguments else {
+                        log::warn!("Ignoring the entry '{}' of '{}'", entry, name);
+                        continue;
+                    };
+                    let condition = params
+                        .iter()
+                        .zip(&arguments[1..])
+                        .map(|(p, a)| format!("(= {} {})", p, normalize_value(a)))
+                        .collect::<Vec<_>>();
+                    let condition = match &condition[..] {
+                        [c] => c.clone(),
+                        cs => format!("(and {})", cs.join(" ")),
+                    };
+                    cases.push((condition, normalize_value(value)));
+                }
+                _ => log::warn!("Ignoring the entry '{}' of '{}'", entry, name),
+            }
+        }
+        // Without a default, the value of the last entry is used for all other arguments
+        let mut body = match body.or_else(|| cases.pop().map(|(_, v)| v)) {
+            Some(b) => b,
+            None => {
+                log::warn!("Ignoring the table of '{}', which has no entries", name);
+                return None;
+            }
+        };
+        for (condition, value) in cases.into_iter().rev() {
+            body = format!("(ite {} {} {})", condition, value, body);
+        }
+        let params = params
+            .iter()
+            .zip(parameters)
+            .map(|(p, s)| format!("({} {})", p, s))
+            .collect::<Vec<_>>()
+            .join(" ");
+        Some(format!(
+            "(define-fun {} ({}) {} {})",
+            name, params, sort, body
+        ))
+    }
+}
+
+/// Returns the definition of a function defined as a constant with a `lambda` value, as cvc5 prints them.
+/// `(define-fun f () (-> Int Int) (lambda ((x Int)) x))` defines `f` as `(define-fun f ((x Int)) Int x)`.
+/// Returns None if the value is not a lambda.
+fn define_lambda(name: &str, sort: &str, value: &str) -> Option<String> {
+    let [lambda, parameters, body] = list(value)?[..] else {
+        return None;
+    };
+    if lambda != "lambda" {
+        return None;
+    }
+    let result = *list(sort).filter(|s| s.first() == Some(&"->"))?.last()?;
+    Some(format!(
+        "(define-fun {} {} {} {})",
+        name, parameters, result, body
+    ))
+}
+
+/// Rewrites numerals in the syntax of Yices to SMT-LIB: `-1` becomes `(- 1)` and `1/2` becomes `(/ 1 2)`.
+fn normalize_value(value: &str) -> String {
+    let (negative, magnitude) = match value.strip_prefix('-') {
+        Some(m) => (true, m),
+        None => (false, value),
+    };
+    let is_numeral = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let magnitude = match magnitude.split_once('/') {
+        Some((n, d)) if is_numeral(n) && is_numeral(d) => format!("(/ {} {})", n, d),
+        None if is_numeral(magnitude) => magnitude.to_owned(),
+        _ => return value.to_owned(),
+    };
+    if negative {
+        format!("(- {})", magnitude)
+    } else {
+        magnitude
+    }
+}
+
+/// Returns the elements of a parenthesized list, or None if the S-expression is an atom.
+fn list(sexpr: &str) -> Option<Vec<&str>> {
+    sexprs(sexpr.strip_prefix('(')?.strip_suffix(')')?)
+}
+
+/// Splits the input into its top-level S-expressions and atoms, skipping comments.
+/// Returns None if the parentheses are unbalanced.
+pub(crate) fn sexprs(input: &str) -> Option<Vec<&str>> {
+    let mut res = vec![];
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        let end = match rest.chars().next()? {
+            '(' => matching_paren(rest)? + 1,
+            ';' => {
+                rest = rest.find('\n').map_or("", |i| &rest[i..]).trim_start();
+                continue;
+            }
+            ')' => return None,
+            '"' | '|' => quoted_len(rest)?,
+            _ => rest
+                .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                .unwrap_or(rest.len()),
+        };
+        res.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    Some(res)
+}
+
+/// Returns the byte length of the string literal or quoted symbol at the start of `s`, including its delimiters.
+/// Returns None if `s` does not start with one or it is not terminated.
+pub(crate) fn quoted_len(s: &str) -> Option<usize> {
+    match s.as_bytes().first()? {
+        b'"' => {
+            // A doubled quote is an escaped quote inside the literal
+            let mut end = 1;
+            loop {
+                end += s[end..].find('"')? + 1;
+                if !s[end..].starts_with('"') {
+                    return Some(end);
+                }
+                end += 1;
+            }
+        }
+        b'|' => Some(s[1..].find('|')? + 2),
+        _ => None,
+    }
+}
+
+/// Returns the byte index of the parenthesis closing the one at the start of `s`.
+/// Parentheses inside string literals, quoted symbols, and comments are ignored.
+pub(crate) fn matching_paren(s: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < s.len() {
+        match s.as_bytes()[i] {
+            b'(' => depth += 1,
+            b')' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            b'"' | b'|' => {
+                i += quoted_len(&s[i..])?;
+                continue;
+            }
+            b';' => i += s[i..].find('\n')?,
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_internal_symbols() {
+        let model = "(define-fun k!0 ((x!0 Int)) Int (ite (= x!0 1) 2 (@purify_3 x!0)))\
+            (define-fun s () String (let ((a!1 (seq.unit (_ Char 97)))) (seq.++ a!1 |str.in_re!12|)))\
+            (define-fun b () Bool (forall ((y!1 Int)) (seq.foo y!1)))";
+        assert_eq!(
+            internal_symbols(model, |s| s == "seq.unit"),
+            ["@purify_3", "seq.++", "seq.foo", "str.in_re!12"]
+        );
+    }
+
+    #[test]
+    fn test_sanitize_model_sat() {
+        let model = "sat\n((define-fun x () Int 1))".to_owned();
+        assert_eq!(
+            sanitize_model(&model, ""),
+            "(define-fun x () Int 1)".to_owned()
+        );
+    }
+
+    #[test]
+    fn test_sanitize_model_sat_old_z3() {
+        let model = "sat\n(model (define-fun x () Int 1))".to_owned();
+        assert_eq!(
+            sanitize_model(&model, ""),
+            "(define-fun x () Int 1)".to_owned()
+        );
+    }
+
+    #[test]
+    fn test_sanitize_model_trailing_output() {
+        let model = "sat\n((define-fun x () Int (- 1)))\n(error \"line 5\")".to_owned();
+        assert_eq!(
+            sanitize_model(&model, ""),
+            "(define-fun x () Int (- 1))".to_owned()
+        );
+    }
+
+    #[test]
+    fn test_sanitize_model_parens_in_literals() {
+        let model =
+            "sat\n((define-fun s () String \")\"\"(\")\n(define-fun |a)| () Int 1))".to_owned();
+        assert_eq!(
+            sanitize_model(&model, ""),
+            "(define-fun s () String \")\"\"(\")\n(define-fun |a)| () Int 1)".to_owned()
+        );
+    }
+
+    #[test]
+    fn test_sexprs_quoted() {
+        let input = "(a \"é)\"\"\" |b)|) ; c)\n\"x\"\"y\" |z w| (d ; )\n)";
+        assert_eq!(
+            sexprs(input).unwrap(),
+            ["(a \"é)\"\"\" |b)|)", "\"x\"\"y\"", "|z w|", "(d ; )\n)"]
+        );
+        assert_eq!(quoted_len("\"a\"\"\" b"), Some(5));
+        assert_eq!(quoted_len("|a"), None);
+        assert_eq!(matching_paren("(a \"b)"), None);
+    }
+
+    #[test]
+    fn test_sanitize_model_unwrapped() {
+        let model = "(define-fun x () Int 1)\n(define-fun y () Int 2)".to_owned();
+        assert_eq!(sanitize_model(&model, ""), model);
+    }
+
+    #[test]
+    fn test_sanitize_model_unbalanced() {
+        let model = "sat\n((define-fun x () Int 1)".to_owned();
+        assert_eq!(
+            sanitize_model(&model, ""),
+            "((define-fun x () Int 1)".to_owned()
+        );
+    }
+
+    #[test]
+    fn test_get_value_response() {
+        let formula = "(declare-const x Int)(declare-fun |s t| () String)(declare-fun f (Int) Int)";
+        let output = "sat\n((x (- 1)) (|s t| \"a)\"\"\"))\n(((f 1) 2))";
+        assert_eq!(
+            sanitize_model(output, formula),
+            "(define-fun x () Int (- 1))\n(define-fun |s t| () String \"a)\"\"\")"
+        );
+        let output = "sat\n((define-fun x () Int 1))\n((x 1))";
+        assert_eq!(
+            sanitize_model(output, formula),
+            "(define-fun x () Int 1)\n(define-fun x () Int 1)"
+        );
+    }
+
+    #[test]
+    fn test_solver_dialects() {
+        let formula = "(declare-const x Int)(declare-const r Real)(declare-fun f (Int Int) Int)";
+        // cvc5
+        assert_eq!(
+            sanitize_model(
+                "sat\n(\n(define-fun x () Int 1)\n(define-fun f () (-> Int Int Int) (lambda ((a Int) (b Int)) a))\n)",
+                formula
+            ),
+            "(define-fun x () Int 1)\n(define-fun f ((a Int) (b Int)) Int a)"
+        );
+        assert_eq!(
+            sanitize_model("((f (lambda ((a Int) (b Int)) b)))", formula),
+            "(define-fun f ((a Int) (b Int)) Int b)"
+        );
+        // Yices
+        assert_eq!(
+            sanitize_model(
+                "sat\n(= x -1)\n(= r 1/2)\n(function f\n (type (-> int int int))\n (= (f 1 2) -3)\n (= (f 0 0) 4)\n (default 0))",
+                formula
+            ),
+            "(define-fun x () Int (- 1))\n(define-fun r () Real (/ 1 2))\n\
+            (define-fun f ((_x0 Int) (_x1 Int)) Int (ite (and (= _x0 1) (= _x1 2)) (- 3) (ite (and (= _x0 0) (= _x1 0)) 4 0)))"
+        );
+        // MathSAT
+        assert_eq!(
+            sanitize_model("sat\n( (x (- 2)) (r (/ 1 3)) )", formula),
+            "(define-fun x () Int (- 2))\n(define-fun r () Real (/ 1 3))"
+        );
+        assert_eq!(sanitize_model("sat\n(model )", formula), "");
+    }
+
+    #[test]
+    fn test_default_definitions() {
+        let formula = "(declare-const b Bool)(declare-const s String)(declare-fun f (Int Int) Int)(declare-const a (Array Int Int))";
+        let names = ["b", "s", "f", "a", "undeclared"].map(str::to_owned);
+        assert_eq!(
+            default_definitions(formula, &names),
+            [
+                ("b", "(define-fun b () Bool false)"),
+                ("s", "(define-fun s () String \"\")"),
+                ("f", "(define-fun f ((_x0 Int) (_x1 Int)) Int 0)")
+            ]
+            .map(|(n, d)| (n.to_owned(), d.to_owned()))
+        );
+    }
+}
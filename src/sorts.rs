@@ -0,0 +1,333 @@
+//! Inference of the SMT-LIB sorts of terms.
+//!
+//! Some SMT-LIB functions are overloaded on the sorts of their arguments, e.g. `=` applies to strings,
+//! integers, and Booleans alike, while the Isabelle/HOL functions they map to may differ per sort.
+//! The converter infers the sorts of subterms to select sort-specific mappings from the spec
+//! and to coerce integers to reals in mixed arithmetic.
+
+use std::collections::{HashMap, HashSet};
+
+use smt2parser::concrete::{Command, Constant, QualIdentifier, Sort, Term};
+use smt2parser::visitors::{DatatypeDec, Identifier, Index};
+
+/// The SMT-LIB functions whose integer arguments are coerced to reals if any argument is real.
+pub(crate) const ARITHMETIC: [&str; 10] =
+    ["+", "-", "*", "/", "<", "<=", ">", ">=", "=", "distinct"];
+/// The Isabelle/HOL function embedding the integers into the reals.
+pub(crate) const INT_TO_REAL: &str = "real_of_int";
+
+pub(crate) const BOOL: &str = "Bool";
+pub(crate) const INT: &str = "Int";
+pub(crate) const REAL: &str = "Real";
+
+/// The sorts of the symbols declared by the input and of the variables bound at the current position.
+#[derive(Default)]
+pub(crate) struct SortContext {
+    /// The result sorts of the declared and defined constants and functions
+    symbols: HashMap<String, String>,
+    /// The symbols bound by enclosing binders, innermost last, with their sorts if known
+    scope: Vec<(String, Option<String>)>,
+    /// The constructors of the declared datatypes
+    constructors: HashSet<String>,
+}
+
+impl SortContext {
+    /// Records the sorts of the constants and functions declared or defined by the commands.
+    /// Only the first declaration of a symbol is recorded.
+    pub(crate) fn declare_commands(&mut self, commands: &[Command]) {
+        for c in commands {
+            match c {
+                Command::DeclareConst { symbol, sort }
+                | Command::DeclareFun { symbol, sort, .. } => self.declare(&symbol.0, sort),
+                Command::DefineFun { sig, .. } | Command::DefineFunRec { sig, .. } => {
+                    self.declare(&sig.name.0, &sig.result)
+                }
+                Command::DeclareDatatype { symbol, datatype } => {
+                    self.declare_datatype(&symbol.0, datatype)
+                }
+                Command::DeclareDatatypes { datatypes } => {
+                    for (symbol, _, datatype) in datatypes {
+                        self.declare_datatype(&symbol.0, datatype);
+                    }
+                }
+                Command::DefineFunsRec { funs } => {
+                    for (sig, _) in funs {
+                        self.declare(&sig.name.0, &sig.result);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn declare(&mut self, symbol: &str, sort: &Sort) {
+        self.symbols
+            .entry(symbol.to_owned())
+            .or_insert_with(|| sort.to_string());
+    }
+
+    /// Records the sorts of the constructors, selectors, and testers of a datatype.
+    /// The sorts of the constructors and selectors of parametric datatypes depend on the type arguments
+    /// and are not recorded.
+    fn declare_datatype(&mut self, name: &str, datatype: &DatatypeDec) {
+        for constructor in &datatype.constructors {
+            self.constructors.insert(constructor.symbol.0.clone());
+            self.symbols
+                .insert(format!("is-{}", constructor.symbol.0), BOOL.to_owned());
+            if datatype.parameters.is_empty() {
+                self.symbols
+                    .insert(constructor.symbol.0.clone(), name.to_owned());
+                for (selector, sort) in &constructor.selectors {
+                    self.declare(&selector.0, sort);
+                }
+            }
+        }
+    }
+
+    /// Enters the scope of a bound symbol of the given sort.
+    pub(crate) fn bind(&mut self, symbol: &str, sort: Option<String>) {
+        self.scope.push((symbol.to_owned(), sort));
+    }
+
+    /// Leaves the scopes of the `n` innermost bound symbols.
+    pub(crate) fn unbind(&mut self, n: usize) {
+        self.scope.truncate(self.scope.len() - n);
+    }
+
+    /// Infers the sort of the term in the current scope.
+    /// Returns None if the sort cannot be determined, e.g. for undeclared variables or unknown functions.
+    pub(crate) fn sort_of(&mut self, term: &Term) -> Option<String> {
+        match term {
+            Term::Constant(c) => Some(constant_sort(c)),
+            Term::QualIdentifier(identifier) => self.application_sort(identifier, &[]),
+            Term::Application {
+                qual_identifier,
+                arguments,
+            } => self.application_sort(qual_identifier, arguments),
+            Term::Let { var_bindings, term } => {
+                // The bound terms are typed in the enclosing scope
+                let sorts: Vec<_> = var_bindings.iter().map(|(_, t)| self.sort_of(t)).collect();
+                for ((s, _), sort) in var_bindings.iter().zip(sorts) {
+                    self.bind(&s.0, sort);
+                }
+                let sort = self.sort_of(term);
+                self.unbind(var_bindings.len());
+                sort
+            }
+            Term::Forall { .. } | Term::Exists { .. } => Some(BOOL.to_owned()),
+            Term::Match { term, cases } => {
+                let sort = self.sort_of(term);
+                let (pattern, body) = cases.first()?;
+                let vars = match pattern.as_slice() {
+                    [v] if !self.constructors.contains(&v.0) => {
+                        self.bind(&v.0, sort);
+                        1
+                    }
+                    [_, vars @ ..] => {
+                        for v in vars {
+                            self.bind(&v.0, None);
+                        }
+                        vars.len()
+                    }
+                    [] => 0,
+                };
+                let sort = self.sort_of(body);
+                self.unbind(vars);
+                sort
+            }
+            Term::Attributes { term, .. } => self.sort_of(term),
+        }
+    }
+
+    /// Infers the sort of a symbol applied to the given arguments (none for constants and variables).
+    fn application_sort(&mut self, identifier: &QualIdentifier, args: &[Term]) -> Option<String> {
+        let (symbol, indices) = match identifier {
+            QualIdentifier::Sorted { sort, .. } => return Some(sort.to_string()),
+            QualIdentifier::Simple {
+                identifier: Identifier::Simple { symbol },
+            } => (&symbol.0, &[][..]),
+            QualIdentifier::Simple {
+                identifier: Identifier::Indexed { symbol, indices },
+            } => (&symbol.0, &indices[..]),
+        };
+        if let Some((_, sort)) = self.scope.iter().rev().find(|(s, _)| s == symbol) {
+            return sort.clone();
+        }
+        if let Some(sort) = self.symbols.get(symbol) {
+            return Some(sort.clone());
+        }
+        if let Some(sort) = result_sort(symbol) {
+            return Some(sort.to_owned());
+        }
+        match symbol.as_str() {
+            "ite" => args
+                .get(1)
+                .and_then(|t| self.sort_of(t))
+                .or_else(|| args.get(2).and_then(|t| self.sort_of(t))),
+            "select" => {
+                let array = self.sort_of(args.first()?)?;
+                array_element_sort(&array)
+            }
+            "store" => self.sort_of(args.first()?),
+            "bvnot" | "bvneg" | "bvand" | "bvor" | "bvxor" | "bvnand" | "bvnor" | "bvxnor"
+            | "bvadd" | "bvsub" | "bvmul" | "bvudiv" | "bvurem" | "bvsdiv" | "bvsrem"
+            | "bvsmod" | "bvshl" | "bvlshr" | "bvashr" | "rotate_left" | "rotate_right"
+            | "repeat" => {
+                let sort = self.sort_of(args.first()?)?;
+                match (symbol.as_str(), indices) {
+                    ("repeat", [Index::Numeral(n)]) => Some(bit_vec_sort(
+                        bit_width(&sort)? * n.to_string().parse::<usize>().ok()?,
+                    )),
+                    _ => Some(sort),
+                }
+            }
+            "concat" => {
+                let mut width = 0;
+                for t in args {
+                    width += bit_width(&self.sort_of(t)?)?;
+                }
+                Some(bit_vec_sort(width))
+            }
+            "extract" => match indices {
+                [Index::Numeral(i), Index::Numeral(j)] => {
+                    let i: usize = i.to_string().parse().ok()?;
+                    let j: usize = j.to_string().parse().ok()?;
+                    Some(bit_vec_sort(i.checked_sub(j)? + 1))
+                }
+                _ => None,
+            },
+            "zero_extend" | "sign_extend" => match indices {
+                [Index::Numeral(n)] => {
+                    let width = bit_width(&self.sort_of(args.first()?)?)?;
+                    Some(bit_vec_sort(width + n.to_string().parse::<usize>().ok()?))
+                }
+                _ => None,
+            },
+            "+" | "-" | "*" => {
+                let sorts: Vec<_> = args.iter().filter_map(|t| self.sort_of(t)).collect();
+                if sorts.iter().any(|s| s == REAL) {
+                    Some(REAL.to_owned())
+                } else {
+                    sorts.into_iter().find(|s| s == INT)
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns the sort of a literal.
+/// Hexadecimal and binary literals are bit-vectors of the width of their digits.
+fn constant_sort(c: &Constant) -> String {
+    match c {
+        Constant::Numeral(_) => INT.to_owned(),
+        Constant::Decimal(_) => REAL.to_owned(),
+        Constant::Hexadecimal(h) => bit_vec_sort(4 * h.len()),
+        Constant::Binary(b) => bit_vec_sort(b.len()),
+        Constant::String(_) => "String".to_owned(),
+    }
+}
+
+/// Returns the bit-vector sort of the given width.
+fn bit_vec_sort(width: usize) -> String {
+    format!("(_ BitVec {})", width)
+}
+
+/// Returns the width of a bit-vector sort such as `(_ BitVec 8)`.
+fn bit_width(sort: &str) -> Option<usize> {
+    sort.strip_prefix("(_ BitVec ")?
+        .strip_suffix(')')?
+        .parse()
+        .ok()
+}
+
+/// Returns the element sort of an array sort such as `(Array Int (Array Int Bool))`.
+fn array_element_sort(sort: &str) -> Option<String> {
+    let parameters = sort.strip_prefix("(Array ")?.strip_suffix(')')?;
+    // The index sort ends at the first space outside of parentheses
+    let mut depth = 0;
+    for (i, c) in parameters.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ' ' if depth == 0 => return Some(parameters[i + 1..].to_owned()),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Returns the result sort of a function of the SMT-LIB theories that does not depend on the argument sorts.
+fn result_sort(op: &str) -> Option<&'static str> {
+    match op {
+        "true" | "false" | "is" | "not" | "=>" | "and" | "or" | "xor" | "=" | "distinct" | "<"
+        | "<=" | ">" | ">=" | "is_int" | "str.<" | "str.<=" | "str.in_re" | "str.prefixof"
+        | "str.suffixof" | "str.contains" | "str.is_digit" | "bvult" | "bvule" | "bvugt"
+        | "bvuge" | "bvslt" | "bvsle" | "bvsgt" | "bvsge" => Some(BOOL),
+        "bvcomp" => Some("(_ BitVec 1)"),
+        "div" | "mod" | "abs" | "to_int" | "str.len" | "str.indexof" | "str.to_int"
+        | "str.to_code" => Some(INT),
+        "/" | "to_real" => Some(REAL),
+        "str.++" | "str.at" | "str.substr" | "str.replace" | "str.replace_all"
+        | "str.replace_re" | "str.replace_re_all" | "str.from_int" | "str.from_code" => {
+            Some("String")
+        }
+        "str.to_re" | "re.none" | "re.all" | "re.allchar" | "re.++" | "re.union" | "re.inter"
+        | "re.*" | "re.+" | "re.opt" | "re.range" | "re.comp" | "re.diff" | "re.loop" | "re.^" => {
+            Some("RegLan")
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smt2parser::{concrete, CommandStream};
+
+    fn parse(input: &str) -> Vec<Command> {
+        CommandStream::new(input.as_bytes(), concrete::SyntaxBuilder, None)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    /// Returns the sorts of the asserted terms after processing the declarations.
+    fn assertion_sorts(input: &str) -> Vec<Option<String>> {
+        let commands = parse(input);
+        let mut context = SortContext::default();
+        context.declare_commands(&commands);
+        commands
+            .iter()
+            .filter_map(|c| match c {
+                Command::Assert { term } => Some(context.sort_of(term)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn infer_sorts() {
+        let sorts = assertion_sorts(
+            "(declare-const s String)(declare-fun f (Int) Real)
+             (assert (str.++ s \"a\"))(assert (+ 1 (f 2)))(assert (ite b 1 2))
+             (assert (let ((x s)) x))(assert (forall ((y Int)) (= y 1)))(assert (- 1 2))
+             (assert (g 1))(assert #x0F)(assert (let ((s 1)) (* s s)))
+             (declare-const a (Array (Array Int Int) (Array Int Bool)))(assert (select (store a b c) d))
+             (declare-const v (_ BitVec 8))(assert (concat ((_ extract 3 0) v) ((_ zero_extend 2) (bvadd v #xFF))))",
+        );
+        let expected = [
+            Some("String"),
+            Some("Real"),
+            Some("Int"),
+            Some("String"),
+            Some("Bool"),
+            Some("Int"),
+            None,
+            Some("(_ BitVec 8)"),
+            Some("Int"),
+            Some("(Array Int Bool)"),
+            Some("(_ BitVec 14)"),
+        ];
+        assert_eq!(sorts, expected.map(|s| s.map(|s| s.to_owned())));
+    }
+}
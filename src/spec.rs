@@ -0,0 +1,738 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::convert::TargetKind;
+use crate::error::Error;
+
+/// The SMT-LIB theories whose mappings are only active if the declared logic includes them.
+/// Mappings in theories with other names are always active.
+pub(crate) const SMT_LIB_THEORIES: [&str; 8] = [
+    "Core",
+    "Ints",
+    "Reals",
+    "Reals_Ints",
+    "ArraysEx",
+    "FixedSizeBitVectors",
+    "Strings",
+    "FloatingPoint",
+];
+
+/// The components of SMT-LIB logic names and the theories they include.
+/// Longer components come first, so that e.g. `LIRA` is not read as `LIA`.
+const LOGIC_COMPONENTS: [(&str, &[&str]); 15] = [
+    ("LIRA", &["Ints", "Reals", "Reals_Ints"]),
+    ("NIRA", &["Ints", "Reals", "Reals_Ints"]),
+    ("LIA", &["Ints"]),
+    ("NIA", &["Ints"]),
+    ("IDL", &["Ints"]),
+    ("LRA", &["Reals"]),
+    ("NRA", &["Reals"]),
+    ("RDL", &["Reals"]),
+    ("UF", &[]),
+    ("AX", &["ArraysEx"]),
+    ("BV", &["FixedSizeBitVectors"]),
+    ("DT", &[]),
+    ("FP", &["FloatingPoint"]),
+    ("S", &["Strings"]),
+    ("A", &["ArraysEx"]),
+];
+
+/// Returns the SMT-LIB theories included in the logic, e.g. `Core`, `Strings`, and `Ints` for `QF_SLIA`.
+/// Returns None for `ALL` and for logics whose name cannot be decomposed, which include all theories.
+pub(crate) fn logic_theories(logic: &str) -> Option<HashSet<&'static str>> {
+    if logic == "ALL" {
+        return None;
+    }
+    let mut rest = logic.strip_prefix("QF_").unwrap_or(logic);
+    let mut theories = HashSet::from(["Core"]);
+    while !rest.is_empty() {
+        let (component, included) = LOGIC_COMPONENTS.iter().find(|(c, _)| rest.starts_with(c))?;
+        theories.extend(included.iter());
+        rest = &rest[component.len()..];
+    }
+    Some(theories)
+}
+
+/// The names of the spec file in a theory root, in order of preference
+pub const SPEC_FILES: [&str; 4] = ["spec.json", "spec.toml", "spec.yaml", "spec.yml"];
+/// The names of the spec file mapping to Coq in a theory root, in order of preference
+pub const COQ_SPEC_FILES: [&str; 4] = [
+    "coq_spec.json",
+    "coq_spec.toml",
+    "coq_spec.yaml",
+    "coq_spec.yml",
+];
+/// The names of the spec file mapping to Lean in a theory root, in order of preference
+pub const LEAN_SPEC_FILES: [&str; 4] = [
+    "lean_spec.json",
+    "lean_spec.toml",
+    "lean_spec.yaml",
+    "lean_spec.yml",
+];
+
+/// The oldest spec format version this tool understands (inclusive).
+pub const MIN_SPEC_VERSION: Version = Version::new(0, 1);
+/// The newest spec format version this tool understands (inclusive).
+pub const MAX_SPEC_VERSION: Version = Version::new(1, 0);
+/// The SMT-LIB versions the converter can translate.
+pub const SUPPORTED_SMT_LIB_VERSIONS: [Version; 2] = [Version::new(2, 5), Version::new(2, 6)];
+/// The file in the theory root that declares the version of the Isabelle SMT theories.
+pub const THEORY_VERSION_FILE: &str = "VERSION";
+/// The Isabelle/HOL types of the SMT-LIB sorts that need no mapping in the spec.
+pub const DEFAULT_SORTS: [(&str, &str); 3] = [("Bool", "bool"), ("Int", "int"), ("Real", "real")];
+
+/// Names solvers use in models for SMT-LIB functions, with the SMT-LIB function, in addition to the aliases of a spec.
+/// z3 prints some string functions under their names for sequences, which are the same functions if the elements are
+/// characters; only those sequence functions that return strings, integers, or Booleans are aliased.
+pub const DEFAULT_ALIASES: [(&str, &str); 14] = [
+    ("str.in-re", "str.in_re"),
+    ("str.to-re", "str.to_re"),
+    ("seq.in.re", "str.in_re"),
+    ("seq.to.re", "str.to_re"),
+    ("seq.++", "str.++"),
+    ("seq.len", "str.len"),
+    ("seq.extract", "str.substr"),
+    ("seq.at", "str.at"),
+    ("seq.contains", "str.contains"),
+    ("seq.prefixof", "str.prefixof"),
+    ("seq.suffixof", "str.suffixof"),
+    ("seq.indexof", "str.indexof"),
+    ("seq.replace", "str.replace"),
+    ("seq.replace_all", "str.replace_all"),
+];
+
+/// A `major.minor` version number.
+/// Patch levels are accepted when parsing but do not affect compatibility.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    major: u32,
+    minor: u32,
+}
+
+impl Version {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+
+    /// Parses a version of the form `major[.minor[.patch]]`, with an optional leading `v`.
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        let invalid = || Error::Other(format!("Invalid version number '{}'", s));
+        let trimmed = s.trim();
+        let trimmed = trimmed.strip_prefix('v').unwrap_or(trimmed);
+        let mut parts = trimmed.split('.');
+        let major = match parts.next() {
+            Some(p) => p.parse::<u32>().map_err(|_| invalid())?,
+            None => return Err(invalid()),
+        };
+        let minor = match parts.next() {
+            Some(p) => p.parse::<u32>().map_err(|_| invalid())?,
+            None => 0,
+        };
+        for p in parts {
+            p.parse::<u32>().map_err(|_| invalid())?;
+        }
+        Ok(Self::new(major, minor))
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// The specification to map an SMT-LIB function to Isabelle/HOL using the Isabelle SMT theories.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Spec {
+    pub(crate) mapsto: Option<String>,
+    pub(crate) assoc: Option<String>,
+    pub(crate) chainable: bool,
+    /// Whether the SMT-LIB function is declared `pairwise` (like `distinct`).
+    /// The mapped function is then the binary relation that has to hold for all pairs of arguments.
+    #[serde(default)]
+    pub(crate) pairwise: bool,
+}
+
+impl Spec {
+    /// Returns true iff the mapping is a template with placeholders for the arguments, see [instantiate].
+    pub(crate) fn is_template(&self) -> bool {
+        self.mapsto.as_deref().is_some_and(|m| {
+            m.as_bytes()
+                .windows(2)
+                .any(|w| w[0] == b'?' && w[1].is_ascii_digit())
+        })
+    }
+
+    /// Returns true iff the SMT-LIB function is declared `left-assoc`.
+    pub(crate) fn is_left_assoc(&self) -> bool {
+        match &self.assoc {
+            Some(a) => a == "left",
+            None => false,
+        }
+    }
+
+    /// Returns true iff the SMT-LIB function is declared `left-assoc`.
+    pub(crate) fn is_right_assoc(&self) -> bool {
+        match &self.assoc {
+            Some(a) => a == "right",
+            None => false,
+        }
+    }
+}
+
+/// Instantiates a template mapping such as `smt_substr ?2 ?3 ?1`, replacing `?n` by the `n`-th argument.
+/// The indices of an indexed identifier come before the arguments.
+pub(crate) fn instantiate(template: &str, args: &[String]) -> Result<String, Error> {
+    let mut res = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '?' || !chars.peek().is_some_and(|d| d.is_ascii_digit()) {
+            res.push(c);
+            continue;
+        }
+        let mut number = String::new();
+        while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+            number.push(d);
+        }
+        let arg = number
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|i| args.get(i))
+            .ok_or_else(|| {
+                Error::Other(format!(
+                    "Placeholder ?{} in mapping '{}' does not refer to one of the {} arguments",
+                    number,
+                    template,
+                    args.len()
+                ))
+            })?;
+        res += arg;
+    }
+    Ok(res)
+}
+
+/// Mappings that only apply to benchmarks declaring a specific SMT-LIB logic with `set-logic`.
+/// They take precedence over the global mappings of the [SpecDef].
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub(crate) struct LogicSpec {
+    #[serde(default)]
+    pub(crate) specs: HashMap<String, HashMap<String, Spec>>,
+}
+
+/// The file format of a spec
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SpecFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl SpecFormat {
+    /// Returns the format of the spec file with the given path, detected by its extension.
+    /// Files with an unknown extension are read as JSON.
+    pub(crate) fn of(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => SpecFormat::Toml,
+            Some("yaml" | "yml") => SpecFormat::Yaml,
+            _ => SpecFormat::Json,
+        }
+    }
+
+    /// Parses the spec into a generic JSON value, regardless of its format.
+    pub(crate) fn parse_value(self, spec: &str) -> Result<serde_json::Value, String> {
+        match self {
+            SpecFormat::Json => serde_json::from_str(spec).map_err(|e| e.to_string()),
+            SpecFormat::Toml => toml::from_str(spec).map_err(|e| e.to_string()),
+            SpecFormat::Yaml => serde_yaml::from_str(spec).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+impl Display for SpecFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpecFormat::Json => write!(f, "JSON"),
+            SpecFormat::Toml => write!(f, "TOML"),
+            SpecFormat::Yaml => write!(f, "YAML"),
+        }
+    }
+}
+
+/// Returns the path to the spec file of the theory root.
+/// If the theory root contains none of the [SPEC_FILES], the path to its `spec.json` is returned.
+pub(crate) fn find_spec_file(theory_root: &Path) -> PathBuf {
+    find_target_spec_file(theory_root, TargetKind::Isabelle)
+}
+
+/// Returns the path to the spec file of the target in the theory root, see [TargetKind::spec_files].
+pub(crate) fn find_target_spec_file(theory_root: &Path, target: TargetKind) -> PathBuf {
+    let files = target.spec_files();
+    files
+        .iter()
+        .map(|f| theory_root.join(f))
+        .find(|p| p.exists())
+        .unwrap_or_else(|| theory_root.join(files[0]))
+}
+
+/// The specification to map SMT-LIB functions to Isabelle/HOL using the Isabelle SMT theories.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct SpecDef {
+    pub(crate) version: String,
+    #[serde(rename = "smt-lib-version")]
+    pub(crate) smt_lib_version: String,
+    pub(crate) specs: HashMap<String, HashMap<String, Spec>>,
+    /// Per-logic sections, keyed by the logic name (e.g. `QF_SLIA`).
+    #[serde(default)]
+    pub(crate) logics: HashMap<String, LogicSpec>,
+    /// Additional Isabelle theories to import into the validation theory (e.g. `My_Session.Helpers`).
+    #[serde(default)]
+    pub(crate) imports: Vec<String>,
+    /// The Isabelle/HOL types of SMT-LIB sorts, in addition to the [DEFAULT_SORTS].
+    #[serde(default)]
+    pub(crate) sorts: HashMap<String, String>,
+    /// Non-standard names of SMT-LIB functions, with the SMT-LIB function, in addition to the [DEFAULT_ALIASES]
+    #[serde(default)]
+    pub(crate) aliases: HashMap<String, String>,
+}
+
+impl SpecDef {
+    /// Parses a specification from its JSON representation.
+    pub(crate) fn from_json(spec_json: &str) -> Result<Self, Error> {
+        Self::parse(spec_json, SpecFormat::Json)
+    }
+
+    /// Parses a specification in the given format.
+    pub(crate) fn parse(spec: &str, format: SpecFormat) -> Result<Self, Error> {
+        let spec = match format {
+            SpecFormat::Json => serde_json::from_str(spec).map_err(|e| e.to_string()),
+            SpecFormat::Toml => toml::from_str(spec).map_err(|e| e.to_string()),
+            SpecFormat::Yaml => serde_yaml::from_str(spec).map_err(|e| e.to_string()),
+        };
+        spec.map_err(Error::InvalidSpec)
+    }
+
+    /// Reads and parses the spec file, detecting its format by the extension.
+    pub(crate) fn from_file(spec_file: &Path) -> Result<Self, Error> {
+        let spec = fs::read_to_string(spec_file).map_err(|e| {
+            Error::InvalidSpec(format!("could not read {}: {}", spec_file.display(), e))
+        })?;
+        Self::parse(&spec, SpecFormat::of(spec_file))
+    }
+
+    /// Merges another spec into this one, e.g. the spec of an additional theory root.
+    /// The mappings and sorts of the other spec override those of this one.
+    /// A function mapped by the other spec is removed from all theories of this spec,
+    /// so the override does not depend on the theory it is listed under.
+    pub(crate) fn merge(&mut self, other: SpecDef) {
+        Self::merge_specs(&mut self.specs, other.specs);
+        for (logic, section) in other.logics {
+            Self::merge_specs(
+                &mut self.logics.entry(logic).or_default().specs,
+                section.specs,
+            );
+        }
+        for import in other.imports {
+            if !self.imports.contains(&import) {
+                self.imports.push(import);
+            }
+        }
+        self.sorts.extend(other.sorts);
+        self.aliases.extend(other.aliases);
+    }
+
+    fn merge_specs(
+        specs: &mut HashMap<String, HashMap<String, Spec>>,
+        other: HashMap<String, HashMap<String, Spec>>,
+    ) {
+        for (theory, functions) in other {
+            for (op, spec) in functions {
+                for existing in specs.values_mut() {
+                    existing.remove(&op);
+                }
+                specs.entry(theory.clone()).or_default().insert(op, spec);
+            }
+        }
+    }
+
+    /// Returns the name of the Isabelle/HOL function to use for the given SMT-LIB function.
+    /// If a logic is given and the spec has a section for it, the mapping of that section is preferred.
+    pub(crate) fn get_spec(&self, op: &str, logic: Option<&str>) -> Option<(String, Spec)> {
+        self.get_active_spec(op, logic, &|_| true)
+    }
+
+    /// Returns the mapping of the given SMT-LIB function like [SpecDef::get_spec],
+    /// only considering the theories for which `active` holds.
+    pub(crate) fn get_active_spec(
+        &self,
+        op: &str,
+        logic: Option<&str>,
+        active: &dyn Fn(&str) -> bool,
+    ) -> Option<(String, Spec)> {
+        if let Some(section) = logic.and_then(|l| self.logics.get(l)) {
+            if let Some(found) = Self::find_spec(&section.specs, op, active) {
+                return Some(found);
+            }
+        }
+        Self::find_spec(&self.specs, op, active)
+    }
+
+    /// Returns the spec for the given SMT-LIB function applied to arguments of the given sort.
+    /// Sort-specific mappings are keyed by the function and the sort of its first argument, e.g. `=::String`.
+    pub(crate) fn get_sorted_spec(
+        &self,
+        op: &str,
+        sort: &str,
+        logic: Option<&str>,
+        active: &dyn Fn(&str) -> bool,
+    ) -> Option<(String, Spec)> {
+        self.get_active_spec(&format!("{}::{}", op, sort), logic, active)
+    }
+
+    /// Returns the SMT-LIB functions that have a sort-specific mapping in any section of the spec.
+    pub(crate) fn overloaded_ops(&self) -> HashSet<String> {
+        self.logics
+            .values()
+            .map(|l| &l.specs)
+            .chain([&self.specs])
+            .flat_map(|specs| specs.values().flat_map(|s| s.keys()))
+            .filter_map(|op| op.split_once("::").map(|(op, _)| op.to_owned()))
+            .collect()
+    }
+
+    fn find_spec(
+        specs: &HashMap<String, HashMap<String, Spec>>,
+        op: &str,
+        active: &dyn Fn(&str) -> bool,
+    ) -> Option<(String, Spec)> {
+        for (th, specs) in specs.iter().filter(|(th, _)| active(th)) {
+            if let Some(spec) = specs.get(op) {
+                return Some((th.clone(), spec.clone()));
+            }
+        }
+        None
+    }
+
+    /// Returns the Isabelle/HOL type of the given SMT-LIB sort.
+    /// Mappings in the spec take precedence over the [DEFAULT_SORTS].
+    pub(crate) fn get_sort(&self, sort: &str) -> Option<String> {
+        self.sorts.get(sort).cloned().or_else(|| {
+            DEFAULT_SORTS
+                .iter()
+                .find(|(s, _)| *s == sort)
+                .map(|(_, t)| t.to_string())
+        })
+    }
+
+    /// Returns true iff the spec has a section for the given logic.
+    pub(crate) fn has_logic(&self, logic: &str) -> bool {
+        self.logics.contains_key(logic)
+    }
+
+    /// Checks that the spec format and SMT-LIB version are supported by this tool.
+    pub(crate) fn check_supported(&self) -> Result<(), Error> {
+        let version = Version::parse(&self.version)?;
+        if version < MIN_SPEC_VERSION || version > MAX_SPEC_VERSION {
+            return Err(Error::IncompatibleVersion(format!(
+                "spec version {} is not supported (supported: {} to {})",
+                version, MIN_SPEC_VERSION, MAX_SPEC_VERSION
+            )));
+        }
+        let smt_lib = Version::parse(&self.smt_lib_version)?;
+        if !SUPPORTED_SMT_LIB_VERSIONS.contains(&smt_lib) {
+            return Err(Error::IncompatibleVersion(format!(
+                "SMT-LIB version {} is not supported (supported: {})",
+                smt_lib,
+                SUPPORTED_SMT_LIB_VERSIONS.map(|v| v.to_string()).join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that the spec was written for the theories in the given theory root.
+    /// The theory root declares its version in a [THEORY_VERSION_FILE].
+    /// If the theory root does not declare a version, the check is skipped.
+    pub(crate) fn check_theory_root(&self, theory_root: &Path) -> Result<(), Error> {
+        let version_file = theory_root.join(THEORY_VERSION_FILE);
+        let declared = match fs::read_to_string(&version_file) {
+            Ok(v) => v,
+            Err(_) => {
+                log::debug!(
+                    "No {} in theory root, skipping theory version check",
+                    THEORY_VERSION_FILE
+                );
+                return Ok(());
+            }
+        };
+        let theory_version = Version::parse(&declared)?;
+        let spec_version = Version::parse(&self.version)?;
+        if theory_version != spec_version {
+            return Err(Error::IncompatibleVersion(format!(
+                "spec version {} does not match the theory version {} declared in {}",
+                spec_version,
+                theory_version,
+                version_file.display()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec_def(version: &str, smt_lib_version: &str) -> SpecDef {
+        SpecDef {
+            version: version.to_owned(),
+            smt_lib_version: smt_lib_version.to_owned(),
+            specs: HashMap::new(),
+            logics: HashMap::new(),
+            imports: vec![],
+            sorts: HashMap::new(),
+            aliases: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn parse_versions() {
+        assert_eq!(Version::parse("0.1").unwrap(), Version::new(0, 1));
+        assert_eq!(Version::parse("v2.6.1").unwrap(), Version::new(2, 6));
+        assert_eq!(Version::parse("3").unwrap(), Version::new(3, 0));
+        assert!(Version::parse("two").is_err());
+        assert!(Version::parse("1.x").is_err());
+    }
+
+    #[test]
+    fn supported_versions() {
+        assert!(spec_def("0.1", "2.6").check_supported().is_ok());
+        assert!(spec_def("0.1.3", "2.5").check_supported().is_ok());
+        assert!(matches!(
+            spec_def("9.0", "2.6").check_supported(),
+            Err(Error::IncompatibleVersion(_))
+        ));
+        assert!(matches!(
+            spec_def("0.1", "3.0").check_supported(),
+            Err(Error::IncompatibleVersion(_))
+        ));
+    }
+
+    #[test]
+    fn logic_sections_take_precedence() {
+        let spec = SpecDef::from_json(
+            r#"{
+                "version": "0.1",
+                "smt-lib-version": "2.6",
+                "specs": {
+                    "Ints": { "+": { "mapsto": "plus", "assoc": "left", "chainable": false } },
+                    "Core": { "and": { "mapsto": "conj", "assoc": "left", "chainable": false } }
+                },
+                "logics": {
+                    "QF_LIA": {
+                        "specs": { "Ints": { "+": { "mapsto": "lia_plus", "chainable": false } } }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let mapsto = |op, logic| spec.get_spec(op, logic).and_then(|(_, s)| s.mapsto);
+        assert_eq!(mapsto("+", None).unwrap(), "plus");
+        assert_eq!(mapsto("+", Some("QF_S")).unwrap(), "plus");
+        assert_eq!(mapsto("+", Some("QF_LIA")).unwrap(), "lia_plus");
+        assert_eq!(mapsto("and", Some("QF_LIA")).unwrap(), "conj");
+        assert!(spec.has_logic("QF_LIA"));
+        assert!(!spec.has_logic("QF_S"));
+    }
+
+    #[test]
+    fn merge_specs() {
+        let mut spec = SpecDef::from_json(
+            r#"{
+                "version": "0.1",
+                "smt-lib-version": "2.6",
+                "specs": {
+                    "Strings": {
+                        "str.len": { "mapsto": "smt_len", "chainable": false },
+                        "str.++": { "mapsto": "smt_concat", "chainable": false }
+                    }
+                },
+                "imports": ["smt.Strings"],
+                "sorts": { "String": "string" }
+            }"#,
+        )
+        .unwrap();
+        let extension = SpecDef::from_json(
+            r#"{
+                "version": "0.1",
+                "smt-lib-version": "2.6",
+                "specs": {
+                    "Extensions": { "str.len": { "mapsto": "ext_len", "chainable": false } }
+                },
+                "logics": {
+                    "QF_S": { "specs": { "Extensions": { "str.++": { "mapsto": "ext_concat", "chainable": false } } } }
+                },
+                "imports": ["ext.Strings", "smt.Strings"],
+                "sorts": { "String": "ext_string" }
+            }"#,
+        )
+        .unwrap();
+        spec.merge(extension);
+        let mapsto = |op, logic| {
+            spec.get_spec(op, logic)
+                .map(|(th, s)| (th, s.mapsto.unwrap()))
+        };
+        assert_eq!(
+            mapsto("str.len", None).unwrap(),
+            ("Extensions".to_owned(), "ext_len".to_owned())
+        );
+        assert_eq!(mapsto("str.++", None).unwrap().1, "smt_concat");
+        assert_eq!(mapsto("str.++", Some("QF_S")).unwrap().1, "ext_concat");
+        assert_eq!(spec.imports, ["smt.Strings", "ext.Strings"]);
+        assert_eq!(spec.get_sort("String").unwrap(), "ext_string");
+    }
+
+    #[test]
+    fn spec_formats() {
+        let toml = r#"
+            version = "0.1"
+            smt-lib-version = "2.6"
+            imports = ["smt.Extra"]
+
+            # Strings
+            [specs.Strings]
+            "str.len" = { mapsto = "smt_len", chainable = false }
+            "str.++" = { mapsto = "smt_concat", assoc = "left", chainable = false }
+
+            [logics.QF_SLIA.specs.Ints]
+            "+" = { mapsto = "plus", chainable = false }
+        "#;
+        let yaml = r#"
+            version: "0.1"
+            smt-lib-version: "2.6"
+            imports: [smt.Extra]
+            # Strings
+            specs:
+              Strings:
+                str.len: { mapsto: smt_len, chainable: false }
+                str.++: { mapsto: smt_concat, assoc: left, chainable: false }
+            logics:
+              QF_SLIA:
+                specs:
+                  Ints:
+                    "+": { mapsto: plus, chainable: false }
+        "#;
+        for (spec, format) in [(toml, SpecFormat::Toml), (yaml, SpecFormat::Yaml)] {
+            let spec = SpecDef::parse(spec, format).unwrap();
+            let mapsto = |op, logic| spec.get_spec(op, logic).and_then(|(_, s)| s.mapsto);
+            assert_eq!(mapsto("str.len", None).unwrap(), "smt_len");
+            assert!(spec.get_spec("str.++", None).unwrap().1.is_left_assoc());
+            assert_eq!(mapsto("+", Some("QF_SLIA")).unwrap(), "plus");
+            assert_eq!(spec.imports, ["smt.Extra"]);
+            assert!(spec.check_supported().is_ok());
+        }
+        assert_eq!(SpecFormat::of(Path::new("a/spec.yml")), SpecFormat::Yaml);
+        assert_eq!(SpecFormat::of(Path::new("spec.json")), SpecFormat::Json);
+    }
+
+    #[test]
+    fn invalid_specs() {
+        use crate::error::ErrorCategory;
+        let e = SpecDef::parse("{\"version\": 1", SpecFormat::Json).unwrap_err();
+        assert!(matches!(e, Error::InvalidSpec(_)));
+        assert_eq!(e.category(), ErrorCategory::Spec);
+        let dir = tempfile::tempdir().unwrap();
+        let e = SpecDef::from_file(&dir.path().join("spec.json")).unwrap_err();
+        assert_eq!(e.category(), ErrorCategory::Spec);
+        assert!(e.to_string().starts_with("Invalid spec: could not read"));
+    }
+
+    #[test]
+    fn theories_of_logics() {
+        let theories = |logic| {
+            logic_theories(logic).map(|t| {
+                let mut t: Vec<_> = t.into_iter().collect();
+                t.sort();
+                t
+            })
+        };
+        assert_eq!(theories("QF_S").unwrap(), ["Core", "Strings"]);
+        assert_eq!(theories("QF_SLIA").unwrap(), ["Core", "Ints", "Strings"]);
+        assert_eq!(
+            theories("QF_AUFBVLIA").unwrap(),
+            ["ArraysEx", "Core", "FixedSizeBitVectors", "Ints"]
+        );
+        assert_eq!(
+            theories("UFLIRA").unwrap(),
+            ["Core", "Ints", "Reals", "Reals_Ints"]
+        );
+        assert_eq!(theories("ALL"), None);
+        assert_eq!(theories("QF_XYZ"), None);
+    }
+
+    #[test]
+    fn sorted_specs() {
+        let spec = SpecDef::from_json(
+            r#"{
+                "version": "0.1",
+                "smt-lib-version": "2.6",
+                "specs": {
+                    "Core": {
+                        "=": { "mapsto": "=", "chainable": true },
+                        "=::String": { "mapsto": "str_eq", "chainable": true }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let mapsto = |sort| {
+            spec.get_sorted_spec("=", sort, None, &|_| true)
+                .and_then(|(_, s)| s.mapsto)
+        };
+        assert_eq!(mapsto("String").unwrap(), "str_eq");
+        assert_eq!(mapsto("Int"), None);
+        assert_eq!(spec.overloaded_ops(), ["=".to_owned()].into());
+    }
+
+    #[test]
+    fn template_instantiation() {
+        let args = ["s".to_owned(), "i".to_owned(), "n".to_owned()];
+        assert_eq!(
+            instantiate("smt_substr ?2 ?3 ?1", &args).unwrap(),
+            "smt_substr i n s"
+        );
+        assert_eq!(instantiate("?1 ? ?1", &args).unwrap(), "s ? s");
+        assert!(instantiate("f ?4", &args).is_err());
+        assert!(instantiate("f ?0", &args).is_err());
+    }
+
+    #[test]
+    fn sort_mappings() {
+        let mut spec = spec_def("0.1", "2.6");
+        spec.sorts
+            .insert("String".to_owned(), "uc_string".to_owned());
+        spec.sorts.insert("Int".to_owned(), "integer".to_owned());
+        assert_eq!(spec.get_sort("String").unwrap(), "uc_string");
+        assert_eq!(spec.get_sort("Int").unwrap(), "integer");
+        assert_eq!(spec.get_sort("Bool").unwrap(), "bool");
+        assert_eq!(spec.get_sort("RegLan"), None);
+    }
+
+    #[test]
+    fn theory_root_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = spec_def("0.1", "2.6");
+        assert!(spec.check_theory_root(dir.path()).is_ok());
+        fs::write(dir.path().join(THEORY_VERSION_FILE), "0.1\n").unwrap();
+        assert!(spec.check_theory_root(dir.path()).is_ok());
+        fs::write(dir.path().join(THEORY_VERSION_FILE), "0.2\n").unwrap();
+        assert!(matches!(
+            spec.check_theory_root(dir.path()),
+            Err(Error::IncompatibleVersion(_))
+        ));
+    }
+}
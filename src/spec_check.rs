@@ -0,0 +1,341 @@
+//! Checks of a spec file for mistakes that would otherwise only surface when converting a formula.
+//!
+//! Besides the format itself, the check reports keys the tool does not know (usually typos, which are otherwise ignored),
+//! functions mapped in several theories (which one is used is unspecified), and mappings to names
+//! that do not occur in the theories of the theory root.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::error::Error;
+use crate::spec::{self, SpecDef, SpecFormat};
+
+/// The keys of the top-level object of a spec
+const SPEC_KEYS: [&str; 7] = [
+    "version",
+    "smt-lib-version",
+    "specs",
+    "logics",
+    "imports",
+    "sorts",
+    "aliases",
+];
+/// The keys of a per-logic section
+const LOGIC_KEYS: [&str; 1] = ["specs"];
+/// The keys of the mapping of a single function
+const MAPPING_KEYS: [&str; 4] = ["mapsto", "assoc", "chainable", "pairwise"];
+/// Names that may occur in a mapping without being defined by a theory
+const BUILTIN_NAMES: [&str; 9] = [
+    "if", "then", "else", "let", "in", "case", "of", "True", "False",
+];
+
+/// How serious a problem of a spec is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The spec is invalid or ambiguous
+    Error,
+    /// The spec is probably wrong, e.g. it maps to a name the theories do not define
+    Warning,
+}
+
+/// A problem found in a spec
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpecIssue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl SpecIssue {
+    fn error(message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+}
+
+impl Display for SpecIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+/// Returns the path to the spec file of the theory root: `spec.json`, `spec.toml`, or `spec.yaml`.
+pub fn spec_file(theory_root: &Path) -> std::path::PathBuf {
+    spec::find_spec_file(theory_root)
+}
+
+/// Checks the spec file against the theories in the theory root and returns the problems found.
+/// The format of the spec is detected by the extension of the file.
+/// Fails only if the spec file cannot be read.
+pub fn check_spec(spec_file: &Path, theory_root: &Path) -> Result<Vec<SpecIssue>, Error> {
+    let content = fs::read_to_string(spec_file)
+        .map_err(|e| Error::Other(format!("Could not read {}: {}", spec_file.display(), e)))?;
+    let format = SpecFormat::of(spec_file);
+    let value: Value = match format.parse_value(&content) {
+        Ok(v) => v,
+        Err(e) => return Ok(vec![SpecIssue::error(format!("Invalid {}: {}", format, e))]),
+    };
+    let mut issues = unknown_keys(&value);
+    let spec = match SpecDef::parse(&content, format) {
+        Ok(s) => s,
+        Err(e) => {
+            issues.push(SpecIssue::error(e.to_string()));
+            return Ok(issues);
+        }
+    };
+    if let Err(e) = spec.check_supported() {
+        issues.push(SpecIssue::error(e.to_string()));
+    }
+    issues.extend(duplicate_mappings(&spec));
+    let names = theory_names(theory_root);
+    if names.is_empty() {
+        issues.push(SpecIssue::warning(format!(
+            "No theories found in {}, not checking the mapped names",
+            theory_root.display()
+        )));
+    } else {
+        issues.extend(undefined_names(&spec, &names));
+    }
+    Ok(issues)
+}
+
+/// Reports the keys of the spec, its logic sections, and its mappings that the tool does not know.
+fn unknown_keys(value: &Value) -> Vec<SpecIssue> {
+    let mut issues = vec![];
+    report_unknown_keys(value, &SPEC_KEYS, "the spec", &mut issues);
+    if let Some(specs) = value.get("specs") {
+        report_unknown_mapping_keys(specs, "", &mut issues);
+    }
+    if let Some(logics) = value.get("logics").and_then(Value::as_object) {
+        for (logic, section) in logics {
+            report_unknown_keys(
+                section,
+                &LOGIC_KEYS,
+                &format!("logic {}", logic),
+                &mut issues,
+            );
+            if let Some(specs) = section.get("specs") {
+                report_unknown_mapping_keys(specs, &format!(" of logic {}", logic), &mut issues);
+            }
+        }
+    }
+    issues
+}
+
+/// Reports the unknown keys of the mappings in a `specs` object, which maps theories to functions to mappings.
+fn report_unknown_mapping_keys(specs: &Value, context: &str, issues: &mut Vec<SpecIssue>) {
+    let theories = specs.as_object().into_iter().flatten();
+    for (theory, functions) in theories {
+        for (op, mapping) in functions.as_object().into_iter().flatten() {
+            let context = format!("the mapping of '{}' in {}{}", op, theory, context);
+            report_unknown_keys(mapping, &MAPPING_KEYS, &context, issues);
+            if let Some(assoc) = mapping.get("assoc").and_then(Value::as_str) {
+                if assoc != "left" && assoc != "right" {
+                    issues.push(SpecIssue::error(format!(
+                        "Invalid assoc '{}' in {}, expected 'left' or 'right'",
+                        assoc, context
+                    )));
+                }
+            }
+        }
+    }
+}
+
+fn report_unknown_keys(value: &Value, known: &[&str], context: &str, issues: &mut Vec<SpecIssue>) {
+    for key in value.as_object().into_iter().flat_map(|o| o.keys()) {
+        if !known.contains(&key.as_str()) {
+            issues.push(SpecIssue::error(format!(
+                "Unknown key '{}' in {}",
+                key, context
+            )));
+        }
+    }
+}
+
+/// Reports functions mapped in more than one theory of the same section.
+fn duplicate_mappings(spec: &SpecDef) -> Vec<SpecIssue> {
+    let mut sections = vec![(String::new(), &spec.specs)];
+    for (logic, section) in spec.logics.iter() {
+        sections.push((format!(" of logic {}", logic), &section.specs));
+    }
+    let mut issues = vec![];
+    for (context, specs) in sections {
+        let mut theories: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (theory, functions) in specs {
+            for op in functions.keys() {
+                theories.entry(op).or_default().push(theory);
+            }
+        }
+        for (op, mut theories) in theories {
+            if theories.len() > 1 {
+                theories.sort();
+                issues.push(SpecIssue::error(format!(
+                    "'{}' is mapped in several theories{}: {}",
+                    op,
+                    context,
+                    theories.join(", ")
+                )));
+            }
+        }
+    }
+    issues
+}
+
+/// Reports mappings to names that do not occur in any of the theories.
+fn undefined_names(spec: &SpecDef, names: &HashSet<String>) -> Vec<SpecIssue> {
+    let sections = spec.logics.values().map(|l| &l.specs).chain([&spec.specs]);
+    let mut issues = vec![];
+    for specs in sections {
+        for (theory, functions) in specs {
+            for (op, mapping) in functions {
+                let Some(mapsto) = &mapping.mapsto else {
+                    continue;
+                };
+                for name in identifiers(mapsto) {
+                    if !names.contains(&name) && !BUILTIN_NAMES.contains(&name.as_str()) {
+                        issues.push(SpecIssue::warning(format!(
+                            "The mapping of '{}' in {} refers to '{}', which does not occur in the theories",
+                            op, theory, name
+                        )));
+                    }
+                }
+            }
+        }
+    }
+    issues.sort_by(|a, b| a.message.cmp(&b.message));
+    issues
+}
+
+/// Returns all names occurring in the theory files below the theory root.
+fn theory_names(theory_root: &Path) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let mut dirs = vec![theory_root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == "thy") {
+                if let Ok(text) = fs::read_to_string(&path) {
+                    names.extend(identifiers(&text));
+                }
+            }
+        }
+    }
+    names
+}
+
+/// Returns the Isabelle identifiers in the text.
+/// Symbols such as `\<and>` and placeholders such as `?1` are not identifiers.
+fn identifiers(text: &str) -> Vec<String> {
+    let mut res = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.peek() == Some(&'<') {
+            // Skip the symbol
+            for c in chars.by_ref() {
+                if c == '>' {
+                    break;
+                }
+            }
+        } else if c == '?' {
+            while chars.next_if(|c| c.is_ascii_alphanumeric()).is_some() {}
+        } else if c.is_ascii_alphabetic() {
+            let mut name = String::from(c);
+            while let Some(c) =
+                chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '\'' || *c == '.')
+            {
+                name.push(c);
+            }
+            res.push(name);
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '\'' {
+            // Skip the rest of numbers and other tokens that do not start with a letter
+            while chars
+                .next_if(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '\'')
+                .is_some()
+            {}
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_spec_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("Strings.thy"),
+            "theory Strings imports Main begin\nfun smt_substr :: \"...\" where\nend",
+        )
+        .unwrap();
+        let spec_file = dir.path().join("spec.json");
+        fs::write(
+            &spec_file,
+            r#"{
+                "version": "0.1",
+                "smt-lib-version": "2.6",
+                "specs": {
+                    "Strings": {
+                        "str.substr": { "mapsto": "smt_substr ?2 ?3 ?1", "chainable": false },
+                        "str.len": { "mapsto": "smt_len", "chainable": false, "asoc": "left" }
+                    },
+                    "Core": {
+                        "and": { "mapsto": "\\<and>", "chainable": false },
+                        "str.len": { "mapsto": "length", "chainable": false }
+                    }
+                },
+                "extra": true
+            }"#,
+        )
+        .unwrap();
+        let issues: Vec<String> = check_spec(&spec_file, dir.path())
+            .unwrap()
+            .iter()
+            .map(|i| i.to_string())
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                "error: Unknown key 'extra' in the spec",
+                "error: Unknown key 'asoc' in the mapping of 'str.len' in Strings",
+                "error: 'str.len' is mapped in several theories: Core, Strings",
+                "warning: The mapping of 'str.len' in Core refers to 'length', which does not occur in the theories",
+                "warning: The mapping of 'str.len' in Strings refers to 'smt_len', which does not occur in the theories",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_spec() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec_file = dir.path().join("spec.json");
+        fs::write(&spec_file, r#"{ "version": "0.1" }"#).unwrap();
+        let issues = check_spec(&spec_file, dir.path()).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Error);
+        fs::write(&spec_file, "{").unwrap();
+        assert!(check_spec(&spec_file, dir.path()).unwrap()[0]
+            .message
+            .starts_with("Invalid JSON"));
+    }
+}
@@ -0,0 +1,68 @@
+//! Transcripts of the interaction with the provers, see [crate::ValidationOptions::log_isabelle].
+//!
+//! A validation that keeps a transcript starts recording on its thread. The checkers then record what they send to
+//! the prover and what they get back: the validation theories, the command lines of the processes they run and
+//! their output, and the requests to the Isabelle server and its replies, each with the time since the start.
+//! Recording is per thread, since the checkers of a validation run on the thread validating the model,
+//! so the validations of a batch running in parallel keep separate transcripts.
+
+use std::cell::RefCell;
+use std::time::Instant;
+
+/// The entries recorded so far and when recording started
+struct Transcript {
+    start: Instant,
+    text: String,
+}
+
+thread_local! {
+    static TRANSCRIPT: RefCell<Option<Transcript>> = const { RefCell::new(None) };
+}
+
+/// Starts recording on this thread, discarding any entries recorded before.
+pub(crate) fn start() {
+    TRANSCRIPT.with(|t| {
+        *t.borrow_mut() = Some(Transcript {
+            start: Instant::now(),
+            text: String::new(),
+        })
+    });
+}
+
+/// Stops recording on this thread and returns the transcript, None if recording was not started.
+pub(crate) fn finish() -> Option<String> {
+    TRANSCRIPT.with(|t| t.borrow_mut().take().map(|t| t.text))
+}
+
+/// Records an entry with the given title if this thread is recording.
+/// The body is only produced if it is recorded.
+pub(crate) fn record(title: &str, body: impl FnOnce() -> String) {
+    TRANSCRIPT.with(|t| {
+        if let Some(transcript) = t.borrow_mut().as_mut() {
+            let elapsed = transcript.start.elapsed().as_secs_f64();
+            let body = body();
+            transcript.text += &format!("==== {} [{:.3}s] ====\n{}", title, elapsed, body);
+            if !body.ends_with('\n') {
+                transcript.text.push('\n');
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_while_started() {
+        record("Ignored", || unreachable!("not recording"));
+        start();
+        record("Command", || "isabelle process".to_owned());
+        record("Empty", String::new);
+        let transcript = finish().unwrap();
+        assert!(transcript.starts_with("==== Command ["));
+        assert!(transcript.contains("s] ====\nisabelle process\n==== Empty ["));
+        assert!(transcript.ends_with("s] ====\n\n"));
+        assert_eq!(finish(), None);
+    }
+}
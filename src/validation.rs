@@ -1,107 +1,2395 @@
-use std::collections::HashSet;
-use std::path::Path;
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use crate::checker::LemmaChecker;
+use serde::Serialize;
+
+use crate::cache::{self, ResultCache};
+use crate::checker::{LemmaChecker, UnknownReason};
 use crate::error::Error;
-use crate::{checker, convert, lemma};
+use crate::pipeline::{CheckerPipeline, Stage, StageReport};
+use crate::{
+    cancel, certificate, checker, convert, eval, heap, ir, isabelle_env, lemma, repro, shrink,
+    solver_output, spec, transcript,
+};
 
 /// Result of a validation
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ValidationResult {
     /// Model is valid
     Valid,
     /// Model is invalid
     Invalid,
-    /// Unable to determine validity
+    /// Unable to determine validity for the given reason
+    Unknown(UnknownReason),
+}
+
+/// How a model that does not assign all variables of the formula is validated
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PartialModel {
+    /// The model is invalid
+    #[default]
+    Invalid,
+    /// The model is valid iff the formula holds for all values of the unassigned variables
+    Forall,
+    /// The unassigned variables of sort Bool, Int, Real, and String are assigned `false`, `0`, `0.0`, and `""`,
+    /// and the completed model is validated; if variables of other sorts remain unassigned, the model is invalid
+    Default,
+}
+
+/// Options that control how a model is validated
+#[derive(Default, Clone, Debug)]
+pub struct ValidationOptions {
+    /// SMT-LIB functions to map to fresh uninterpreted constants instead of their spec mapping
+    pub uninterpreted: Vec<String>,
+    /// Only validate assertions mentioning at least one variable assigned by the model, and ground assertions.
+    /// The verdict is unknown rather than valid if any assertion is skipped.
+    pub slice: bool,
+    /// Normalize the Boolean structure of the assertions before generating the lemma
+    pub normalize: bool,
+    /// Simplify the converted terms, e.g. fold ground arithmetic, before generating the lemma
+    pub simplify: bool,
+    /// Substitute the values of the model into the formula instead of stating the model as premises of the lemma
+    pub substitute_model: bool,
+    /// Skip assertions using unsupported SMT-LIB functions instead of reporting the whole validation as unknown.
+    /// A model satisfying the other assertions is then unknown rather than valid.
+    pub lenient: bool,
+    /// Check the assertions in independent lemmas of at most this many conjuncts each
+    pub chunk_size: Option<usize>,
+    /// Check each assertion in a lemma of its own, so the status of each assertion is its own; overrides [Self::chunk_size]
+    pub split_conclusions: bool,
+    /// Write a reproduction bundle to this directory if the model is not found valid
+    pub repro_dir: Option<PathBuf>,
+    /// Keep the working directories of checks that do not prove their lemma, and log their paths
+    pub keep_temp: bool,
+    /// Additional Isabelle theories to import into the validation theory
+    pub imports: Vec<String>,
+    /// Validate the model against the assertions live at the `check-sat` with this index (counting from 0) instead of the last one
+    pub check_index: Option<usize>,
+    /// The measure proving the termination of recursive functions; termination is assumed if not set
+    pub termination_measure: Option<String>,
+    /// Use this built-in spec instead of the spec.json of the theory root
+    pub builtin_spec: Option<convert::BuiltinSpec>,
+    /// Use the spec in this file instead of the spec.json of the theory root; ignored with [Self::builtin_spec]
+    pub spec_file: Option<PathBuf>,
+    /// Additional theory roots whose specs are merged into the spec of the theory root, later roots overriding earlier ones
+    pub theory_roots: Vec<PathBuf>,
+    /// Store proof certificates in this directory and accept lemmas that already have one
+    pub certificate_dir: Option<PathBuf>,
+    /// Check lemmas again even if there is a certificate for them
+    pub recheck: bool,
+    /// Cache the verdicts in this directory and reuse the verdict of an identical earlier validation
+    pub cache_dir: Option<PathBuf>,
+    /// Include the generated lemma in the [ValidationReport]
+    pub detailed_report: bool,
+    /// The checker used to check the generated lemmas if [ValidationOptions::pipeline] is empty
+    pub checker: checker::CheckerKind,
+    /// The backend of [checker::CheckerKind::Custom] and [Stage::Custom]
+    pub custom_checker: Option<checker::CustomChecker>,
+    /// Abort checking a lemma after this time; the result is then unknown
+    pub timeout: Option<Duration>,
+    /// Limit the heaps of the Isabelle process to this many megabytes; checks exceeding it are unknown.
+    /// Only the batch checker enforces the limit, see [checker::BatchChecker::memory_limit].
+    pub memory_limit: Option<u64>,
+    /// Search for a counterexample with Nitpick if the lemma could not be proven
+    pub counterexample: bool,
+    /// After a valid verdict, report the assignments of the model the verdict does not depend on.
+    /// Lemmas that were checked are checked again without the model's assignments to find those the proof needs.
+    pub model_core: bool,
+    /// The proof method, [lemma::DEFAULT_METHOD] if not set
+    pub method: Option<String>,
+    /// Retry lemmas that could not be proven with stronger proof methods, see [checker::EscalatingChecker]
+    pub escalate: bool,
+    /// The language the lemma is generated in, which has to be the one of the checkers of the pipeline
+    pub target: convert::TargetKind,
+    /// How sorts declared with `declare-sort` are represented in Isabelle/HOL
+    pub uninterpreted_sorts: convert::UninterpretedSorts,
+    /// How variables the model does not assign are treated
+    pub partial_model: PartialModel,
+    /// Fail if the model assigns variables the formula does not declare,
+    /// or does not assign variables the formula uses and [ValidationOptions::partial_model] is [PartialModel::Invalid]
+    pub strict_model: bool,
+    /// Read the formula and the model as SMT-LIB 2.5, see [convert::rewrite_smtlib25]
+    pub smtlib25: bool,
+    /// The stages deciding the verdict, tried in order until one finds the model valid or invalid.
+    /// If empty, the lemma is only checked with [ValidationOptions::checker].
+    pub pipeline: Vec<Stage>,
+    /// Only write the validation theory to this file (or to `Validation.thy`, or `Validation.v` for Coq, in this directory)
+    /// and return [UnknownReason::NotChecked] instead of checking it
+    pub emit_only: Option<PathBuf>,
+    /// Write a transcript of the interaction with the provers to this file:
+    /// the validation theories, the command lines or server requests, the output or replies, and their timings
+    pub log_isabelle: Option<PathBuf>,
+    /// Cancelling this token interrupts the validation, which then returns [UnknownReason::Cancelled]
+    pub cancellation: Option<cancel::Cancellation>,
+}
+
+impl ValidationOptions {
+    /// Checks the lemmas with the given backend instead of Isabelle.
+    pub fn register_checker(&mut self, checker: checker::CustomChecker) -> &mut Self {
+        self.custom_checker = Some(checker);
+        self.checker = checker::CheckerKind::Custom;
+        self
+    }
+
+    /// Returns the stages of the validation, see [ValidationOptions::pipeline].
+    pub fn stages(&self) -> Vec<Stage> {
+        if self.pipeline.is_empty() {
+            vec![self.checker.into()]
+        } else {
+            self.pipeline.clone()
+        }
+    }
+}
+
+impl ValidationResult {
+    /// Returns the verdict as a single word: `valid`, `invalid`, or `unknown`.
+    pub fn verdict(&self) -> &'static str {
+        match self {
+            ValidationResult::Valid => "valid",
+            ValidationResult::Invalid => "invalid",
+            ValidationResult::Unknown(_) => "unknown",
+        }
+    }
+}
+
+/// Details of a validation, for machine-readable output
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct ValidationReport {
+    /// The variables used by the formula that the model does not assign
+    pub undefined_vars: Vec<String>,
+    /// The variables the model does not assign that were assigned a default value, see [PartialModel::Default]
+    pub completed_vars: Vec<String>,
+    /// The variables the model assigns that the formula does not declare, in order
+    pub stray_assignments: Vec<StrayAssignment>,
+    /// The symbols internal to the solver, such as the skolem constants `k!3` of z3, that the model uses
+    /// without defining them and that the spec does not map
+    pub internal_symbols: Vec<String>,
+    /// The SMT-LIB symbols of the variables renamed in the lemma, by their name in the lemma, see [convert::target_name]
+    pub renamed_vars: BTreeMap<String, String>,
+    /// The generated Isabelle/HOL lemma, only if [ValidationOptions::detailed_report] is set
+    pub lemma: Option<String>,
+    /// The status of each assertion of the formula, in order
+    pub assertions: Vec<AssertionReport>,
+    /// The time spent in each phase of the validation
+    pub timing: Timing,
+    /// The assignment falsifying the formula, only if [ValidationOptions::counterexample] is set and one was found
+    pub counterexample: Option<checker::Counterexample>,
+    /// The variables whose assignments the model does not need to be valid, in order,
+    /// only if [ValidationOptions::model_core] is set and the model is valid
+    pub unused_assignments: Option<Vec<String>>,
+    /// Whether the verdict was taken from the cache instead of validating the model again
+    pub cached: bool,
+    /// Whether the verdict was decided by evaluating the formula instead of checking the lemma with Isabelle
+    pub evaluated: bool,
+    /// The outcome of each stage of [ValidationOptions::pipeline] run, in order
+    pub stages: Vec<StageReport>,
+    /// The generated theory, kept for the reproduction bundle
+    #[serde(skip)]
+    theory: Option<lemma::Theory>,
+}
+
+/// A variable the model assigns that the formula does not declare, which is often a typo
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StrayAssignment {
+    /// The name of the variable in the model
+    pub var: String,
+    /// A variable of the formula the model does not assign with a similar name, see [similar_name]
+    pub similar: Option<String>,
+}
+
+impl Display for StrayAssignment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The model assigns {}, which the formula does not declare",
+            self.var
+        )?;
+        match &self.similar {
+            Some(similar) => write!(f, "; did you mean {}?", similar),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The status of a single assertion
+#[derive(Clone, Debug, Serialize)]
+pub struct AssertionReport {
+    /// The position of the assertion in the formula, starting at 0
+    pub index: usize,
+    /// The label given to the assertion with `:named`
+    pub name: Option<String>,
+    pub status: AssertionStatus,
+    /// The unsupported SMT-LIB function the assertion was skipped for, see [ValidationOptions::lenient]
+    pub unsupported: Option<String>,
+}
+
+/// The status of an assertion.
+/// Assertions are checked together with the others in the same lemma (see [ValidationOptions::chunk_size]),
+/// so the status is that of the lemma containing the assertion, unless [ValidationOptions::split_conclusions] is set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AssertionStatus {
+    /// The model satisfies the assertion
+    Valid,
+    /// The model violates the assertion (or another assertion checked in the same lemma)
+    Invalid,
+    /// Could not determine whether the model satisfies the assertion
     Unknown,
+    /// The assertion was skipped by slicing
+    Skipped,
+    /// The assertion was not checked, e.g. because the validation stopped early
+    Unchecked,
+}
+
+impl From<&checker::CheckResult> for AssertionStatus {
+    fn from(result: &checker::CheckResult) -> Self {
+        match result {
+            checker::CheckResult::OK => AssertionStatus::Valid,
+            checker::CheckResult::FailedInvalid => AssertionStatus::Invalid,
+            checker::CheckResult::FailedUnknown(_) => AssertionStatus::Unknown,
+        }
+    }
+}
+
+/// The time spent in each phase of a validation, in milliseconds
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct Timing {
+    /// Parsing and converting the formula and the model
+    pub conversion_ms: u64,
+    /// Checking the generated lemmas
+    pub checking_ms: u64,
+    pub total_ms: u64,
 }
 
 /// Validate model against formula.
 /// Returns `ValidationResult::Valid` if the model is valid, `ValidationResult::Invalid` if the model is invalid, and `ValidationResult::Unknown` if the validity cannot be determined.
+/// Unsupported SMT-LIB constructs are reported as `ValidationResult::Unknown`.
 /// Returns `Err` if the model or formula is not in valid SMT syntax.
 pub fn validate(
     smt_model: String,
     smt_formula: String,
     theory_path: &Path,
+    options: &ValidationOptions,
+) -> Result<ValidationResult, Error> {
+    validate_with_report(smt_model, smt_formula, theory_path, options).0
+}
+
+/// Validate model against formula like [validate], additionally returning the details of the validation.
+/// The report contains as much as was determined before the validation finished or failed.
+pub fn validate_with_report(
+    smt_model: String,
+    smt_formula: String,
+    theory_path: &Path,
+    options: &ValidationOptions,
+) -> (Result<ValidationResult, Error>, ValidationReport) {
+    validate_reusing(smt_model, smt_formula, theory_path, options, &mut None)
+}
+
+/// Validate model against formula like [validate], from async code.
+/// The validation runs on a blocking thread of the caller's tokio runtime, and the checkers run their processes
+/// and talk to the Isabelle server on that runtime instead of starting one of their own.
+/// The runtime needs its IO and time drivers, as enabled by `#[tokio::main]`.
+/// Dropping the future cancels the validation, and with it the [ValidationOptions::cancellation] token if one is given.
+pub async fn validate_async(
+    smt_model: String,
+    smt_formula: String,
+    theory_path: PathBuf,
+    mut options: ValidationOptions,
 ) -> Result<ValidationResult, Error> {
-    let smt_model = sanitize_model(&smt_model);
-    let spec_path = theory_path.join("spec.json");
-    log::debug!("Loading spec from {}", spec_path.display());
-    let mut converter = convert::Converter::from_spec_file(&spec_path)?;
+    let cancellation = options.cancellation.get_or_insert_with(Default::default);
+    let cancel_on_drop = cancel::CancelOnDrop::new(cancellation.clone());
+    let result = tokio::task::spawn_blocking(move || {
+        validate(smt_model, smt_formula, &theory_path, &options)
+    })
+    .await
+    .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+    cancel_on_drop.disarm();
+    result
+}
+
+/// Validate model against formula like [validate_with_report], using the given checker pipeline.
+/// If there is no pipeline yet, one is created as configured in the options and kept for later validations,
+/// along with the checkers it creates.
+pub(crate) fn validate_reusing(
+    smt_model: String,
+    smt_formula: String,
+    theory_path: &Path,
+    options: &ValidationOptions,
+    pipeline: &mut Option<CheckerPipeline>,
+) -> (Result<ValidationResult, Error>, ValidationReport) {
+    let start = Instant::now();
+    if options.log_isabelle.is_some() {
+        transcript::start();
+    }
+    let _cancellation = cancel::enter(options.cancellation.clone());
+    let inputs = options
+        .repro_dir
+        .as_ref()
+        .map(|_| (smt_formula.clone(), smt_model.clone()));
+    let mut report = ValidationReport::default();
+    // Theories that are only emitted are not checked, so there is no verdict to cache
+    let cache = match (&options.cache_dir, &options.emit_only) {
+        (Some(dir), None) => match ResultCache::open(dir) {
+            Ok(cache) => Some((
+                cache,
+                validation_key(&smt_formula, &smt_model, theory_path, options),
+            )),
+            Err(e) => {
+                log::warn!("Could not open cache directory {}: {}", dir.display(), e);
+                None
+            }
+        },
+        _ => None,
+    };
+    if let Some(result) = cache.as_ref().and_then(|(c, key)| c.lookup(key)) {
+        log::info!("Using the cached verdict '{}'", result.verdict());
+        report.cached = true;
+        report.timing.total_ms = start.elapsed().as_millis() as u64;
+        let result = Ok(result);
+        write_transcript(options, &result);
+        return (result, report);
+    }
+    let result = match validate_model(
+        smt_model,
+        smt_formula,
+        theory_path,
+        options,
+        pipeline,
+        &mut report,
+    ) {
+        Err(Error::Unsupported(op, context)) => {
+            let unsupported = format!("{}{}", op, context);
+            log::warn!("Unsupported: {}", unsupported);
+            Ok(ValidationResult::Unknown(UnknownReason::Unsupported(
+                unsupported,
+            )))
+        }
+        r => r,
+    };
+
+    if let (Some((cache, key)), Ok(result)) = (&cache, &result) {
+        if let Err(e) = cache.store(key, result) {
+            log::warn!("Could not cache the verdict: {}", e);
+        }
+    }
 
+    if let (Some(dir), Some((formula, model))) = (&options.repro_dir, inputs) {
+        if !matches!(result, Ok(ValidationResult::Valid)) {
+            let roots: Vec<&Path> = theory_roots(theory_path, options).collect();
+            let bundle = repro::write_bundle(
+                dir,
+                &formula,
+                &model,
+                &roots,
+                report.theory.as_ref(),
+                options,
+            );
+            match bundle {
+                Ok(()) => log::warn!("Wrote reproduction bundle to {}", dir.display()),
+                Err(e) => log::error!("Could not write reproduction bundle: {}", e),
+            }
+        }
+    }
+    report.timing.total_ms = start.elapsed().as_millis() as u64;
+    write_transcript(options, &result);
+    (result, report)
+}
+
+/// Records the result of the validation and writes the transcript to [ValidationOptions::log_isabelle].
+fn write_transcript(options: &ValidationOptions, result: &Result<ValidationResult, Error>) {
+    let Some(path) = &options.log_isabelle else {
+        return;
+    };
+    transcript::record("Result", || match result {
+        Ok(r) => format!("{:?}", r),
+        Err(e) => format!("Error: {}", e),
+    });
+    let text = transcript::finish().unwrap_or_default();
+    let written = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => fs::create_dir_all(dir),
+        _ => Ok(()),
+    }
+    .and_then(|()| fs::write(path, text));
+    match written {
+        Ok(()) => log::info!("Wrote the transcript to {}", path.display()),
+        Err(e) => log::error!(
+            "Could not write the transcript to {}: {}",
+            path.display(),
+            e
+        ),
+    }
+}
+
+/// Returns the key of the validation in the cache.
+/// Besides the formula and the model, it covers the version of smtmv, the specs, versions, and theories of the theory roots,
+/// and the options that change the lemma or the verdict.
+fn validation_key(
+    formula: &str,
+    model: &str,
+    theory_path: &Path,
+    options: &ValidationOptions,
+) -> String {
+    let mut specs = String::new();
+    for root in theory_roots(theory_path, options) {
+        if options.builtin_spec.is_none() || root != theory_path {
+            let spec_file = match &options.spec_file {
+                Some(file) if root == theory_path => file.clone(),
+                _ => spec::find_target_spec_file(root, options.target),
+            };
+            specs.push_str(&fs::read_to_string(spec_file).unwrap_or_default());
+        }
+        specs.push_str(
+            &fs::read_to_string(root.join(spec::THEORY_VERSION_FILE)).unwrap_or_default(),
+        );
+    }
+    if let Some(spec) = options.builtin_spec {
+        specs.push_str(spec.json_for(options.target));
+    }
+    let session_dirs: Vec<String> = theory_roots(theory_path, options)
+        .map(|r| r.display().to_string())
+        .collect();
+    let mut theories = String::new();
+    for source in heap::session_sources(&session_dirs) {
+        theories += &format!("{}\n", source.display());
+        theories += &fs::read_to_string(&source).unwrap_or_default();
+    }
+    let method = options
+        .method
+        .as_deref()
+        .unwrap_or(options.target.target().default_method());
+    let target = format!("{:?}", options.target);
+    let check_index = format!("{:?}", options.check_index);
+    let chunk_size = format!("{:?}", options.chunk_size);
+    let uninterpreted_sorts = format!("{:?}", options.uninterpreted_sorts);
+    let partial_model = format!("{:?}", options.partial_model);
+    let imports = options.imports.join("\n");
+    let uninterpreted = options.uninterpreted.join("\n");
+    let mut pipeline = options.stages().iter().join(",");
+    if let Some(custom) = &options.custom_checker {
+        pipeline = format!("{} with {}", pipeline, custom.name());
+    }
+    cache::cache_key(&[
+        ("version", env!("CARGO_PKG_VERSION")),
+        ("formula", formula),
+        ("model", model),
+        ("specs", &specs),
+        ("theories", &theories),
+        ("method", method),
+        ("escalate", if options.escalate { "true" } else { "false" }),
+        ("slice", if options.slice { "true" } else { "false" }),
+        (
+            "normalize",
+            if options.normalize { "true" } else { "false" },
+        ),
+        (
+            "split-conclusions",
+            if options.split_conclusions {
+                "true"
+            } else {
+                "false"
+            },
+        ),
+        ("chunk-size", &chunk_size),
+        (
+            "counterexample",
+            if options.counterexample {
+                "true"
+            } else {
+                "false"
+            },
+        ),
+        ("lenient", if options.lenient { "true" } else { "false" }),
+        ("simplify", if options.simplify { "true" } else { "false" }),
+        (
+            "substitute-model",
+            if options.substitute_model {
+                "true"
+            } else {
+                "false"
+            },
+        ),
+        ("pipeline", &pipeline),
+        ("target", &target),
+        ("imports", &imports),
+        ("uninterpreted", &uninterpreted),
+        ("uninterpreted-sorts", &uninterpreted_sorts),
+        ("partial-model", &partial_model),
+        (
+            "strict-model",
+            if options.strict_model {
+                "true"
+            } else {
+                "false"
+            },
+        ),
+        ("smtlib25", if options.smtlib25 { "true" } else { "false" }),
+        ("check-index", &check_index),
+        (
+            "termination-measure",
+            options.termination_measure.as_deref().unwrap_or(""),
+        ),
+    ])
+}
+
+/// Validates the model, recording the details in `report` as they become available.
+fn validate_model(
+    smt_model: String,
+    smt_formula: String,
+    theory_path: &Path,
+    options: &ValidationOptions,
+    pipeline: &mut Option<CheckerPipeline>,
+    report: &mut ValidationReport,
+) -> Result<ValidationResult, Error> {
+    let start = Instant::now();
+    let (smt_model, smt_formula) = if options.smtlib25 {
+        (
+            convert::rewrite_smtlib25(&smt_model),
+            convert::rewrite_smtlib25(&smt_formula),
+        )
+    } else {
+        (smt_model, smt_formula)
+    };
+    let mut converter = match options.builtin_spec {
+        Some(spec) => {
+            log::debug!("Using the built-in spec for {:?}", spec);
+            convert::Converter::builtin_for(spec, options.target)?
+        }
+        None => {
+            let spec_path = options
+                .spec_file
+                .clone()
+                .unwrap_or_else(|| spec::find_target_spec_file(theory_path, options.target));
+            log::debug!("Loading spec from {}", spec_path.display());
+            let mut converter = convert::Converter::from_spec_file(&spec_path)?;
+            converter.target(options.target);
+            converter
+        }
+    };
+    converter.check_theory_root(theory_path)?;
+    for root in &options.theory_roots {
+        log::debug!("Merging spec from {}", root.display());
+        converter.add_theory_root(root)?;
+    }
+    converter
+        .uninterpret(&options.uninterpreted)
+        .normalize(options.normalize)
+        .simplify(options.simplify)
+        .lenient(options.lenient)
+        .uninterpreted_sorts(options.uninterpreted_sorts)
+        .check_index(options.check_index)
+        .termination_measure(options.termination_measure.clone());
+
+    let declared = solver_output::declared_symbols(&smt_formula);
+    // Functions the formula declares are never aliases
+    let mut aliases = converter.aliases();
+    aliases.retain(|alias, _| !declared.contains(alias));
+    let smt_formula = convert::rewrite_aliases(&smt_formula, &aliases);
+    let smt_model = solver_output::sanitize_model(
+        &convert::rewrite_aliases(&smt_model, &aliases),
+        &smt_formula,
+    );
+    report.internal_symbols =
+        solver_output::internal_symbols(&smt_model, |s| declared.contains(s) || converter.knows(s));
+    if !report.internal_symbols.is_empty() {
+        log::warn!(
+            "The model uses symbols internal to the solver that the spec does not map: {}; \
+            if they are SMT-LIB functions under another name, add them to the \"aliases\" of the spec",
+            report.internal_symbols.join(", ")
+        );
+    }
+    // The formula and the model to complete if the model does not assign all variables
+    let completion_inputs = (options.partial_model == PartialModel::Default)
+        .then(|| (smt_formula.clone(), smt_model.clone()));
+
+    let stages = pipeline
+        .get_or_insert_with(|| CheckerPipeline::new(&options.stages()))
+        .stages();
+    // The formula and the model to evaluate before checking the lemma
+    let evaluation_inputs = (options.emit_only.is_none() && stages.contains(&Stage::Eval))
+        .then(|| (smt_formula.clone(), smt_model.clone()));
     // Conjunction of assertions converted to Isabelle
-    let formula = converter.convert(smt_formula)?;
+    let mut formula = converter.convert(smt_formula)?;
     log::info!("Converted formula");
     // Conjunction of equalities equivalent to the model, converted to Isabelle
-    let model = converter.convert(smt_model)?;
+    let model = converter.convert_model(smt_model)?;
     log::info!("Converted model");
+    if options.substitute_model {
+        converter.substitute_model(&mut formula)?;
+        log::info!("Substituted the model into the formula");
+    }
+    report.timing.conversion_ms = start.elapsed().as_millis() as u64;
+
+    let defined_vars = converter.get_vars_defined();
+    report.assertions = formula
+        .iter()
+        .enumerate()
+        .map(|(index, a)| AssertionReport {
+            index,
+            name: a.name.clone(),
+            status: if a.unsupported.is_some() {
+                AssertionStatus::Unknown
+            } else if !options.slice || is_relevant(a, &defined_vars) {
+                AssertionStatus::Unchecked
+            } else {
+                AssertionStatus::Skipped
+            },
+            unsupported: a.unsupported.clone(),
+        })
+        .collect();
+    let sliced = report
+        .assertions
+        .iter()
+        .filter(|a| a.status == AssertionStatus::Skipped)
+        .count();
+    // Assertions skipped for unsupported functions are not part of the lemma
+    let (formula, unsupported): (Vec<_>, Vec<_>) =
+        formula.into_iter().partition(|a| a.unsupported.is_none());
+    let unsupported: Vec<String> = unsupported
+        .into_iter()
+        .filter_map(|a| a.unsupported)
+        .collect();
+    let (formula, used_vars) = if options.slice {
+        let (checked, skipped) = slice_assertions(formula, &defined_vars);
+        if !skipped.is_empty() {
+            log::warn!(
+                "Skipped {} assertion(s) not mentioning any variable assigned by the model",
+                skipped.len()
+            );
+            for s in &skipped {
+                log::info!("Skipped assertion: {}", s.term);
+            }
+        }
+        let mut used_vars: HashSet<String> = checked.iter().flat_map(|a| a.vars.clone()).collect();
+        used_vars.extend(model.iter().flat_map(|m| m.vars.clone()));
+        (checked, used_vars)
+    } else {
+        (formula, converter.get_vars_used())
+    };
 
-    let undefined_vars: HashSet<String> = converter
-        .get_vars_used()
-        .difference(&converter.get_vars_defined())
+    let auxiliaries = converter.get_auxiliaries();
+    let undefined_vars: HashSet<String> = used_vars
+        .difference(&defined_vars)
+        .filter(|v| !auxiliaries.contains(*v))
         .cloned()
         .collect();
+    // A model completed with default values is compared to the formula when validating the completed model
+    if options.partial_model != PartialModel::Default || undefined_vars.is_empty() {
+        let unassigned: Vec<&String> = declared
+            .iter()
+            .filter(|v| !defined_vars.contains(*v))
+            .sorted()
+            .collect();
+        report.stray_assignments = stray_assignments(&model, &declared, &auxiliaries, &unassigned);
+        for stray in &report.stray_assignments {
+            log::warn!("{}", stray);
+        }
+        if options.strict_model {
+            let missing: Vec<&String> = match options.partial_model {
+                PartialModel::Invalid => undefined_vars.iter().sorted().collect(),
+                PartialModel::Forall | PartialModel::Default => vec![],
+            };
+            check_alignment(&report.stray_assignments, &missing)?;
+        }
+    }
     if !undefined_vars.is_empty() {
         log::info!("Model does not assign all variables: {:?}", undefined_vars);
-        return Ok(ValidationResult::Invalid);
+        report.undefined_vars = undefined_vars.into_iter().sorted().collect();
+        match options.partial_model {
+            PartialModel::Invalid => return Ok(ValidationResult::Invalid),
+            // The variables are fixed without premises, so the lemma holds for all of their values
+            PartialModel::Forall => log::info!(
+                "Validating the formula for all values of {}",
+                report.undefined_vars.join(", ")
+            ),
+            PartialModel::Default => {
+                let (smt_formula, mut smt_model) =
+                    completion_inputs.expect("the inputs are kept for completing the model");
+                let defaults =
+                    solver_output::default_definitions(&smt_formula, &report.undefined_vars);
+                if defaults.len() < report.undefined_vars.len() {
+                    log::info!("Not all unassigned variables have a sort with a default value");
+                    return Ok(ValidationResult::Invalid);
+                }
+                let (completed, definitions): (Vec<_>, Vec<_>) = defaults.into_iter().unzip();
+                log::warn!(
+                    "Assigning default values to {}, which the model does not assign",
+                    completed.join(", ")
+                );
+                for definition in definitions {
+                    smt_model.push('\n');
+                    smt_model.push_str(&definition);
+                }
+                report.undefined_vars.clear();
+                report.completed_vars = completed;
+                let options = ValidationOptions {
+                    partial_model: PartialModel::Invalid,
+                    ..options.clone()
+                };
+                return validate_model(
+                    smt_model,
+                    smt_formula,
+                    theory_path,
+                    &options,
+                    pipeline,
+                    report,
+                );
+            }
+        }
+    }
+
+    let uninterpreted = converter.get_uninterpreted_used();
+    if !uninterpreted.is_empty() {
+        log::warn!("Treating {:?} as uninterpreted", uninterpreted);
+    }
+
+    let target = options.target.target();
+    for var in used_vars.union(&defined_vars) {
+        let name = target.identifier(var);
+        if name != *var {
+            log::debug!("Renaming {} to {} in the lemma", var, name);
+            report.renamed_vars.insert(name, var.clone());
+        }
     }
 
     let mut lemma = lemma::Lemma::new("validation");
-    lemma.add_conclusions(&formula);
-    lemma.add_premises(&model);
+    let substituted = if options.substitute_model {
+        converter.get_model_vars()
+    } else {
+        HashSet::new()
+    };
+    for (var, ty) in converter.get_declared() {
+        // Substituted variables no longer occur in the lemma
+        if substituted.contains(var) {
+            continue;
+        }
+        if used_vars.contains(var) || defined_vars.contains(var) {
+            lemma.add_fix(target.identifier(var), ty);
+        }
+    }
+    for declaration in converter.get_type_declarations() {
+        lemma.add_declaration(declaration);
+    }
+    for definition in converter.get_definitions() {
+        lemma.add_declaration(definition);
+    }
+    let checks_assertions = !formula.is_empty();
+    let eval_obstacle = eval_obstacle(&converter, &formula);
+    let unused = options
+        .model_core
+        .then(|| unused_assignments(&formula, &model));
+    // The premises of the assignments the assertions may depend on, which the model core is searched among
+    let core_premises: Vec<(String, String)> = match &unused {
+        Some(unused) if !options.substitute_model => model
+            .iter()
+            .filter_map(|m| Some((assigned_var(m)?.to_owned(), m.term.clone())))
+            .filter(|(var, _)| !unused.contains(var))
+            .collect(),
+        _ => vec![],
+    };
+    let mut needless = vec![];
+    lemma.add_conclusions(formula.into_iter().map(|a| a.term));
+    if !options.substitute_model {
+        lemma.add_premises(model.into_iter().map(|m| m.term));
+    }
+    lemma.add_premises(converter.get_element_axioms());
+    for simp in converter.get_simps() {
+        lemma.add_simp(simp);
+    }
+    if let Some(method) = &options.method {
+        lemma.set_method(method.clone());
+    } else if options.target == convert::TargetKind::Isabelle {
+        match eval_obstacle {
+            None if lemma.fixes().is_empty() && lemma.premises().is_empty() => {
+                log::info!("The lemma is ground, proving it by evaluation");
+                lemma
+                    .set_method(lemma::EVAL_METHOD)
+                    .set_proof_body(lemma::ProofBody::By);
+            }
+            Some(obstacle) if options.substitute_model => {
+                log::info!("Not proving the lemma by evaluation: {}", obstacle)
+            }
+            _ => (),
+        }
+    }
+    let text = options.target.target().lemma_text(&lemma);
     log::info!("Generated lemma");
-    log::debug!("{}", lemma.to_isabelle());
-
-    let mut checker = checker::BatchChecker::new(theory_path.to_str().unwrap());
-    //let mut checker = checker::ClientChecker::start_server(theory_path.to_str().unwrap()).unwrap();
-
-    match checker.check(&lemma)? {
-        checker::CheckResult::OK => Ok(ValidationResult::Valid),
-        checker::CheckResult::FailedUnknown => Ok(ValidationResult::Unknown),
-        checker::CheckResult::FailedInvalid => Ok(ValidationResult::Invalid),
-    }
-}
-
-/// Extract model from SMT output and sanitizes it.
-/// If the models is not in valid SMT syntax, return None.
-fn sanitize_model(model: &str) -> String {
-    let mut model = model.trim().to_owned();
-    if model.matches("sat").count() > 1 {
-        log::warn!("Multiple 'sat' in model, did you provide two models?");
-    }
-    // Unwrap model from 'sat(...)'
-    if model.starts_with("sat\n(") {
-        model = model
-            .strip_prefix("sat")
-            .unwrap()
-            .trim()
-            .strip_prefix('(')
-            .unwrap()
-            .strip_suffix(')')
-            .unwrap()
-            .trim()
-            .to_owned()
-    }
-
-    // Remove additional 'model' prefix older z3 version produce
-    if model.starts_with("model") {
-        model = model.strip_prefix("model").unwrap().trim().to_owned();
+    log::debug!("{}", text);
+    if options.detailed_report {
+        report.lemma = Some(text);
+    }
+
+    let imports: Vec<String> = converter
+        .get_imports()
+        .into_iter()
+        .chain(options.imports.iter().cloned())
+        .collect();
+    // The reproduction bundles are Isabelle sessions
+    if options.repro_dir.is_some() && options.target == convert::TargetKind::Isabelle {
+        let mut theory = checker::validation_theory(&lemma, &imports);
+        if options.split_conclusions {
+            theory.split();
+        }
+        report.theory = Some(theory);
+    }
+    if let Some(path) = &options.emit_only {
+        let mut theory = options.target.target().validation_theory(&lemma, &imports);
+        if options.split_conclusions {
+            theory.split();
+        }
+        emit_theory(
+            options.target,
+            &theory,
+            path,
+            theory_roots(theory_path, options),
+        )?;
+        return Ok(ValidationResult::Unknown(UnknownReason::NotChecked));
+    }
+
+    let spec_version = converter.get_spec_version();
+    let start = Instant::now();
+    let pipeline = pipeline
+        .as_mut()
+        .expect("the pipeline is created before the conversion");
+    let (result, stages) = pipeline.run(
+        &imports,
+        |kind| create_checker(kind, theory_path, options, &imports),
+        |_, checker| match checker {
+            None => {
+                let (formula, model) = evaluation_inputs
+                    .as_ref()
+                    .expect("the inputs are kept for pipelines with an eval stage");
+                Ok(evaluate_model(formula, model, options.check_index, report))
+            }
+            Some(checker) => {
+                if !checks_assertions && !unsupported.is_empty() {
+                    log::warn!("All assertions were skipped");
+                    return Ok(ValidationResult::Unknown(UnknownReason::Unsupported(
+                        unsupported.join("; "),
+                    )));
+                }
+                // The reports of the checked assertions, which are the conclusions of the lemma
+                let mut checked: Vec<AssertionReport> = report
+                    .assertions
+                    .iter()
+                    .filter(|a| a.status == AssertionStatus::Unchecked)
+                    .cloned()
+                    .collect();
+                let mut checker: Box<dyn LemmaChecker + '_> = Box::new(checker);
+                // The escalation methods are Isabelle methods
+                if options.escalate && options.target == convert::TargetKind::Isabelle {
+                    checker = Box::new(checker::EscalatingChecker::new(checker));
+                }
+                // Certificates wrap the escalation, so a lemma proven by any method is accepted in later runs
+                if let Some(dir) = &options.certificate_dir {
+                    let store =
+                        certificate::CertificateStore::open(dir.clone(), spec_version, &imports)
+                            .map_err(|e| {
+                                Error::Other(format!(
+                                    "Could not open certificate directory {}: {}",
+                                    dir.display(),
+                                    e
+                                ))
+                            })?;
+                    checker = Box::new(certificate::CertifiedChecker::new(
+                        checker,
+                        store,
+                        options.recheck,
+                    ));
+                }
+                let result = if options.split_conclusions {
+                    check_split(&mut checker, &lemma, &mut checked)
+                } else {
+                    check_lemma(&mut checker, &lemma, options.chunk_size, &mut checked)
+                };
+                let result = match result {
+                    Ok(
+                        r @ (checker::CheckResult::FailedInvalid
+                        | checker::CheckResult::FailedUnknown(UnknownReason::TacticFailure)),
+                    ) if options.counterexample && uninterpreted.is_empty() => {
+                        match checker.find_counterexample(&lemma)? {
+                            Some(mut c) => {
+                                // Nitpick names the variables as the lemma does
+                                c.assignment = c
+                                    .assignment
+                                    .into_iter()
+                                    .map(|(var, value)| {
+                                        let symbol = report.renamed_vars.get(&var).cloned();
+                                        (symbol.unwrap_or(var), value)
+                                    })
+                                    .collect();
+                                log::info!("Nitpick found a counterexample:\n{}", c);
+                                report.counterexample = Some(c);
+                                Ok(checker::CheckResult::FailedInvalid)
+                            }
+                            None => Ok(r),
+                        }
+                    }
+                    r => r,
+                };
+                if matches!(result, Ok(checker::CheckResult::FailedInvalid))
+                    && !uninterpreted.is_empty()
+                {
+                    // See below, the model may still be valid
+                    for a in checked.iter_mut() {
+                        if a.status == AssertionStatus::Invalid {
+                            a.status = AssertionStatus::Unknown;
+                        }
+                    }
+                }
+                for a in checked {
+                    let index = a.index;
+                    report.assertions[index] = a;
+                }
+                if matches!(result, Ok(checker::CheckResult::OK)) && !core_premises.is_empty() {
+                    match needless_premises(&mut checker, &lemma, &core_premises) {
+                        Ok(n) => needless = n,
+                        Err(e) => log::warn!("Could not search for the model core: {}", e),
+                    }
+                }
+                match result? {
+                    checker::CheckResult::OK if !unsupported.is_empty() => {
+                        // The skipped assertions may still be violated
+                        log::info!(
+                            "The checked assertions hold, but {} assertion(s) were skipped",
+                            unsupported.len()
+                        );
+                        Ok(ValidationResult::Unknown(UnknownReason::Unsupported(
+                            unsupported.join("; "),
+                        )))
+                    }
+                    checker::CheckResult::OK if sliced > 0 => {
+                        // The sliced assertions may still be violated
+                        log::info!(
+                            "The checked assertions hold, but {} assertion(s) were sliced away",
+                            sliced
+                        );
+                        Ok(ValidationResult::Unknown(UnknownReason::Sliced(sliced)))
+                    }
+                    checker::CheckResult::OK => Ok(ValidationResult::Valid),
+                    checker::CheckResult::FailedUnknown(r) => Ok(ValidationResult::Unknown(r)),
+                    checker::CheckResult::FailedInvalid if !uninterpreted.is_empty() => {
+                        // The lemma may only be false because of the missing interpretations
+                        log::info!(
+                            "Lemma is false, but {:?} were uninterpreted, so the model may still be valid",
+                            uninterpreted
+                        );
+                        let mut uninterpreted: Vec<String> = uninterpreted.iter().cloned().collect();
+                        uninterpreted.sort();
+                        Ok(ValidationResult::Unknown(UnknownReason::Uninterpreted(
+                            uninterpreted,
+                        )))
+                    }
+                    checker::CheckResult::FailedInvalid => Ok(ValidationResult::Invalid),
+                }
+            }
+        },
+    );
+    report.timing.checking_ms = start.elapsed().as_millis() as u64;
+    report.stages = stages;
+    if let (Ok(ValidationResult::Valid), Some(mut unused)) = (&result, unused) {
+        unused.extend(needless);
+        unused.sort();
+        if !unused.is_empty() {
+            log::warn!(
+                "The model is valid without the assignments of {}",
+                unused.join(", ")
+            );
+        }
+        report.unused_assignments = Some(unused);
+    }
+    result
+}
+
+/// Creates the checker of a stage checking the lemma.
+/// For the Isabelle checkers, first checks that Isabelle and the session are available.
+/// Checkers of another language than [ValidationOptions::target] cannot check the lemma.
+fn create_checker(
+    kind: checker::CheckerKind,
+    theory_path: &Path,
+    options: &ValidationOptions,
+    imports: &[String],
+) -> Result<Box<dyn LemmaChecker>, Error> {
+    if kind == checker::CheckerKind::Custom {
+        let custom = options.custom_checker.as_ref().ok_or_else(|| {
+            Error::Other("The custom checker stage has no registered checker".to_owned())
+        })?;
+        log::debug!("Creating the {} checker", custom.name());
+        return custom.create(options.timeout);
+    }
+    if kind.target() != options.target {
+        return Err(Error::Other(format!(
+            "The {:?} checker cannot check lemmas generated for {:?}",
+            kind, options.target
+        )));
+    }
+    // The other provers are run in the theory root
+    if options.target != convert::TargetKind::Isabelle {
+        let roots = [theory_path.to_str().unwrap().to_owned()];
+        return kind.create(
+            &roots,
+            imports,
+            options.timeout,
+            options.keep_temp,
+            options.memory_limit,
+        );
+    }
+    isabelle_env::check_version()?;
+    let session_dirs: Vec<String> = theory_roots(theory_path, options)
+        .map(|r| r.to_str().unwrap().to_owned())
+        .collect();
+    // The server builds the session itself, the batch checker loads the prebuilt heap
+    if kind == checker::CheckerKind::Batch {
+        heap::check(&session_dirs)?;
+    }
+    kind.create(
+        &session_dirs,
+        imports,
+        options.timeout,
+        options.keep_temp,
+        options.memory_limit,
+    )
+}
+
+/// Decides the verdict by evaluating the formula under the model, see [eval].
+fn evaluate_model(
+    formula: &str,
+    model: &str,
+    check_index: Option<usize>,
+    report: &mut ValidationReport,
+) -> ValidationResult {
+    match eval::evaluate(formula, model, check_index) {
+        eval::Evaluation::Valid => {
+            log::info!("All assertions evaluate to true");
+            report.evaluated = true;
+            // Including those skipped by slicing or for unsupported functions
+            for a in &mut report.assertions {
+                a.status = AssertionStatus::Valid;
+            }
+            ValidationResult::Valid
+        }
+        eval::Evaluation::Invalid(index) => {
+            log::info!("Assertion {} evaluates to false", index);
+            report.evaluated = true;
+            ValidationResult::Invalid
+        }
+        eval::Evaluation::Undecided(reason) => {
+            log::info!("Could not evaluate the formula ({})", reason);
+            ValidationResult::Unknown(UnknownReason::NotEvaluated(reason))
+        }
+    }
+}
+
+/// Returns the theory root followed by the additional theory roots of the options.
+fn theory_roots<'a>(
+    theory_path: &'a Path,
+    options: &'a ValidationOptions,
+) -> impl Iterator<Item = &'a Path> {
+    std::iter::once(theory_path).chain(options.theory_roots.iter().map(|r| r.as_path()))
+}
+
+/// Writes the validation theory to `path`, or to `Validation.thy` (see [convert::Target::file_name]) in `path` if it is a directory.
+fn emit_theory<'a>(
+    target: convert::TargetKind,
+    theory: &lemma::Theory,
+    path: &Path,
+    theory_roots: impl Iterator<Item = &'a Path>,
+) -> Result<(), Error> {
+    let path = if path.is_dir() {
+        path.join(target.target().file_name())
+    } else {
+        path.to_owned()
     };
+    target
+        .target()
+        .write_file(theory, &path)
+        .map_err(|e| Error::Other(format!("Could not write {}: {}", path.display(), e)))?;
+    if target != convert::TargetKind::Isabelle {
+        log::info!(
+            "Wrote {} file to {}",
+            target.target().name(),
+            path.display()
+        );
+        return Ok(());
+    }
+    log::info!(
+        "Wrote theory to {}, open it with: isabelle jedit {} -l smt {}",
+        path.display(),
+        theory_roots
+            .map(|r| format!("-d {}", r.display()))
+            .join(" "),
+        path.display()
+    );
+    if path.file_stem().is_some_and(|s| s != "Validation") {
+        log::warn!("Isabelle only loads the theory from a file named Validation.thy");
+    }
+    Ok(())
+}
+
+/// Checks the lemma, split into chunks of at most `chunk_size` conclusions if given.
+/// `assertions` are the reports of the conclusions, whose status is set to the result of the lemma or chunk containing them.
+fn check_lemma(
+    checker: &mut impl LemmaChecker,
+    lemma: &lemma::Lemma,
+    chunk_size: Option<usize>,
+    assertions: &mut [AssertionReport],
+) -> Result<checker::CheckResult, Error> {
+    match chunk_size {
+        Some(size) if lemma.num_conclusions() > size => check_lemmas(
+            checker,
+            lemma
+                .chunk_conclusions(size)
+                .zip(assertions.chunks_mut(size)),
+        ),
+        _ => {
+            let result = checker.check(lemma)?;
+            record_result(&result, assertions);
+            Ok(result)
+        }
+    }
+}
+
+/// Sets the status of the assertions checked in a lemma and reports the named ones if the lemma could not be proven.
+fn record_result(result: &checker::CheckResult, assertions: &mut [AssertionReport]) {
+    for a in assertions.iter_mut() {
+        a.status = result.into();
+    }
+    let names = assertions.iter().filter_map(|a| a.name.as_ref()).join(", ");
+    if names.is_empty() {
+        return;
+    }
+    match result {
+        checker::CheckResult::OK => (),
+        checker::CheckResult::FailedInvalid => {
+            log::warn!("The model violates one of the named assertions: {}", names)
+        }
+        checker::CheckResult::FailedUnknown(_) => {
+            log::warn!("Could not check the named assertions: {}", names)
+        }
+    }
+}
+
+/// Checks each conclusion of the lemma in a lemma of its own, see [LemmaChecker::check_conclusions].
+/// `assertions` are the reports of the conclusions, whose status is set to the result of their own lemma.
+/// The result is invalid if any conclusion is, and otherwise unknown if any conclusion could not be checked.
+fn check_split(
+    checker: &mut impl LemmaChecker,
+    lemma: &lemma::Lemma,
+    assertions: &mut [AssertionReport],
+) -> Result<checker::CheckResult, Error> {
+    let results = checker.check_conclusions(lemma)?;
+    let mut result = checker::CheckResult::OK;
+    for ((conclusion, r), a) in lemma
+        .split_conclusion()
+        .zip(results)
+        .zip(assertions.iter_mut())
+    {
+        let assertion = match &a.name {
+            Some(name) => format!("assertion {} ({})", a.index, name),
+            None => format!("assertion {}", a.index),
+        };
+        match &r {
+            checker::CheckResult::OK => (),
+            checker::CheckResult::FailedInvalid => log::warn!(
+                "Lemma {} failed: the model violates {}",
+                conclusion.name(),
+                assertion
+            ),
+            checker::CheckResult::FailedUnknown(reason) => log::warn!(
+                "Lemma {} failed: could not check {} ({})",
+                conclusion.name(),
+                assertion,
+                reason
+            ),
+        }
+        a.status = (&r).into();
+        result = match (result, r) {
+            (checker::CheckResult::FailedInvalid, _) | (_, checker::CheckResult::FailedInvalid) => {
+                checker::CheckResult::FailedInvalid
+            }
+            (unknown @ checker::CheckResult::FailedUnknown(_), _) | (_, unknown) => unknown,
+        };
+    }
+    Ok(result)
+}
+
+/// Checks the lemmas one after another and combines their results.
+/// Each lemma is dropped after it was checked, so a lazy iterator keeps only one lemma in memory.
+/// Stops at the first invalid lemma; otherwise the result is unknown if any lemma could not be checked.
+fn check_lemmas<'a>(
+    checker: &mut impl LemmaChecker,
+    lemmas: impl ExactSizeIterator<Item = (lemma::Lemma, &'a mut [AssertionReport])>,
+) -> Result<checker::CheckResult, Error> {
+    let mut result = checker::CheckResult::OK;
+    let total = lemmas.len();
+    for (i, (lemma, assertions)) in lemmas.enumerate() {
+        log::info!("Checking chunk {}/{}", i + 1, total);
+        let chunk_result = checker.check(&lemma)?;
+        record_result(&chunk_result, assertions);
+        match chunk_result {
+            checker::CheckResult::OK => (),
+            checker::CheckResult::FailedInvalid => return Ok(checker::CheckResult::FailedInvalid),
+            unknown @ checker::CheckResult::FailedUnknown(_) => {
+                if matches!(result, checker::CheckResult::OK) {
+                    result = unknown;
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Returns why the conclusions cannot be proven by Isabelle's `eval` method, None if they are ground and executable.
+/// Evaluation runs the code generated from the definitions of the spec's functions, so it needs code equations for them,
+/// which the Isabelle SMT theories provide, and it does not stop if the evaluated functions do not terminate.
+/// Recursive functions of the formula are therefore not evaluated, since their termination may only be assumed.
+fn eval_obstacle(
+    converter: &convert::Converter,
+    formula: &[convert::ConvertedTerm],
+) -> Option<&'static str> {
+    let commands: Vec<_> = formula.iter().filter_map(|a| a.command.as_ref()).collect();
+    if commands.len() < formula.len() || !commands.iter().all(|c| c.is_ground()) {
+        return Some("the conclusions have free variables");
+    }
+    let quantified = commands.iter().any(|c| match c {
+        ir::Command::Assertion(t) | ir::Command::Definition(_, t) => {
+            t.any(&|t| matches!(t, ir::Term::Quantifier { .. }))
+        }
+    });
+    if quantified {
+        return Some("quantifiers are not executable");
+    }
+    if !converter.get_uninterpreted_used().is_empty() {
+        return Some("uninterpreted functions are not executable");
+    }
+    if converter
+        .get_type_declarations()
+        .iter()
+        .any(|d| d.starts_with("typedecl"))
+    {
+        return Some("declared sorts are not executable");
+    }
+    if !converter.get_definitions().is_empty() {
+        return Some("recursive functions may not terminate when evaluated");
+    }
+    None
+}
+
+/// Splits the converted assertions into those mentioning at least one of the defined variables and the rest.
+fn slice_assertions(
+    assertions: Vec<convert::ConvertedTerm>,
+    defined_vars: &HashSet<String>,
+) -> (Vec<convert::ConvertedTerm>, Vec<convert::ConvertedTerm>) {
+    assertions
+        .into_iter()
+        .partition(|a| is_relevant(a, defined_vars))
+}
+
+/// Returns the variables assigned by the model that neither the assertions nor the assignments of the variables they use mention.
+fn unused_assignments(
+    formula: &[convert::ConvertedTerm],
+    model: &[convert::ConvertedTerm],
+) -> Vec<String> {
+    let mut used: HashSet<&String> = formula.iter().flat_map(|a| &a.vars).collect();
+    // The values of used variables may refer to further variables
+    loop {
+        let before = used.len();
+        for m in model {
+            if assigned_var(m).is_some_and(|v| used.contains(v)) {
+                used.extend(&m.vars);
+            }
+        }
+        if used.len() == before {
+            break;
+        }
+    }
+    model
+        .iter()
+        .filter_map(assigned_var)
+        .filter(|v| !used.contains(v))
+        .cloned()
+        .collect()
+}
+
+/// Returns the variables the converted model assigns that the formula does not declare, in order.
+/// Auxiliary symbols and the helper functions the values of other variables refer to, as Z3 defines them, are left out.
+/// Each variable is paired with a similar one of the unassigned variables, if there is one.
+fn stray_assignments(
+    model: &[convert::ConvertedTerm],
+    declared: &HashSet<String>,
+    auxiliaries: &HashSet<String>,
+    unassigned: &[&String],
+) -> Vec<StrayAssignment> {
+    let referenced: HashSet<&String> = model
+        .iter()
+        .flat_map(|m| m.vars.iter().filter(move |v| assigned_var(m) != Some(*v)))
+        .collect();
     model
+        .iter()
+        .filter_map(assigned_var)
+        .filter(|v| !declared.contains(*v) && !auxiliaries.contains(*v) && !referenced.contains(v))
+        .map(|v| StrayAssignment {
+            var: v.clone(),
+            similar: similar_name(v, unassigned).map(str::to_owned),
+        })
+        .collect()
+}
+
+/// Returns the candidate most similar to the name if it is similar enough to be meant by a typo:
+/// within an edit distance of 1, or 2 for names of more than 5 characters, ignoring case.
+/// Of equally similar candidates, the first is returned.
+fn similar_name<'a>(name: &str, candidates: &[&'a String]) -> Option<&'a str> {
+    let max_distance = if name.chars().count() > 5 { 2 } else { 1 };
+    candidates
+        .iter()
+        .map(|c| {
+            let distance = edit_distance(&name.to_lowercase(), &c.to_lowercase());
+            (distance, c.as_str())
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, c)| c)
+}
+
+/// Returns the number of characters to insert, delete, or substitute, and of adjacent characters to swap,
+/// to turn one string into the other (the optimal string alignment distance).
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // The distances of the prefixes of `a` of the last two lengths to all prefixes of `b`
+    let mut before: Vec<usize> = vec![];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let substitution = previous[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            row[j] = substitution.min(previous[j] + 1).min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut previous, row);
+    }
+    previous[b.len()]
 }
 
+/// Fails with the differences between the variables of the model and the formula, if there are any:
+/// the stray assignments of the model and the missing assignments of variables the formula uses.
+fn check_alignment(stray: &[StrayAssignment], missing: &[&String]) -> Result<(), Error> {
+    if stray.is_empty() && missing.is_empty() {
+        return Ok(());
+    }
+    let mut diff = String::from("The variables of the model do not match the formula:");
+    for var in missing {
+        diff.push_str(&format!(
+            "\n  - {} (used by the formula, not assigned)",
+            var
+        ));
+    }
+    for s in stray {
+        diff.push_str(&format!(
+            "\n  + {} (assigned, not declared by the formula",
+            s.var
+        ));
+        if let Some(similar) = &s.similar {
+            diff.push_str(&format!("; did you mean {}?", similar));
+        }
+        diff.push(')');
+    }
+    Err(Error::Other(diff))
+}
+
+/// Returns the variable the converted model entry assigns, None if it is not a definition.
+fn assigned_var(entry: &convert::ConvertedTerm) -> Option<&String> {
+    match &entry.command {
+        Some(ir::Command::Definition(name, _)) => Some(name),
+        _ => None,
+    }
+}
+
+/// Returns the variables whose assignments the proven lemma can do without, given the premises assigning them.
+/// Premises are removed as long as the lemma remains provable, see [shrink::minimize];
+/// a lemma that could not be proven again keeps all of them.
+fn needless_premises(
+    checker: &mut impl LemmaChecker,
+    lemma: &lemma::Lemma,
+    premises: &[(String, String)],
+) -> Result<Vec<String>, Error> {
+    log::info!("Searching for the assignments the proof needs");
+    let mut kept = vec![true; premises.len()];
+    let mut error = None;
+    shrink::minimize(&mut kept, vec![true; premises.len()], |kept| {
+        let removed: Vec<String> = premises
+            .iter()
+            .zip(kept)
+            .filter(|(_, k)| !**k)
+            .map(|((_, p), _)| p.clone())
+            .collect();
+        match checker.check(&lemma.without_premises(&removed)) {
+            Ok(r) => r == checker::CheckResult::OK,
+            Err(e) => {
+                error.get_or_insert(e);
+                false
+            }
+        }
+    });
+    if let Some(e) = error {
+        return Err(e);
+    }
+    Ok(premises
+        .iter()
+        .zip(kept)
+        .filter(|(_, k)| !k)
+        .map(|((var, _), _)| var.clone())
+        .collect())
+}
+
+/// Returns true iff the assertion mentions a variable assigned by the model or no variable at all.
+/// Ground assertions, such as `false`, cost nothing to check and may make any model invalid.
+fn is_relevant(assertion: &convert::ConvertedTerm, defined_vars: &HashSet<String>) -> bool {
+    assertion.vars.is_empty() || !assertion.vars.is_disjoint(defined_vars)
+}
+
+/// Splits the contents of a file holding an SMT-LIB script followed by the solver's output, as in `cat script.smt2 out.txt`.
+/// The solver output starts with the first check-sat response (`sat`, `unsat`, or `unknown`) at the top level.
+/// Returns the script and the solver output (the answer followed by the model).
+/// Returns `Err` if there is no check-sat response or the solver did not answer `sat`.
+pub fn split_combined(input: &str) -> Result<(String, String), Error> {
+    let mut chars = input.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '(' => {
+                // Skip the command, the solver output is never nested
+                let close = match solver_output::matching_paren(&input[i..]) {
+                    Some(close) => i + close,
+                    None => break,
+                };
+                while chars.next_if(|(j, _)| *j <= close).is_some() {}
+            }
+            ';' => while chars.next_if(|(_, c)| *c != '\n').is_some() {},
+            c if c.is_whitespace() => (),
+            _ => {
+                let mut end = input.len();
+                while let Some((j, c)) = chars.peek() {
+                    if c.is_whitespace() || *c == '(' || *c == ')' {
+                        end = *j;
+                        break;
+                    }
+                    chars.next();
+                }
+                match &input[i..end] {
+                    "sat" => return Ok((input[..i].to_owned(), input[i..].to_owned())),
+                    answer @ ("unsat" | "unknown") => {
+                        return Err(Error::Other(format!(
+                            "Solver answered '{}', there is no model to validate",
+                            answer
+                        )))
+                    }
+                    token => log::debug!("Ignoring '{}' before the solver output", token),
+                }
+            }
+        }
+    }
+    Err(Error::Other(
+        "No check-sat response found after the script".to_owned(),
+    ))
+}
+
+#[cfg(test)]
 mod tests {
     #[allow(unused_imports)]
     use super::*;
 
     #[test]
-    fn test_sanitize_model_sat() {
-        let model = "sat\n((define-fun x () Int 1))".to_owned();
-        assert_eq!(sanitize_model(&model), "(define-fun x () Int 1)".to_owned());
+    fn test_slice_assertions() {
+        let assertion = |term: &str, vars: &[&str]| convert::ConvertedTerm {
+            term: term.to_owned(),
+            vars: vars.iter().map(|v| v.to_string()).collect(),
+            name: None,
+            unsupported: None,
+            command: None,
+        };
+        let defined = HashSet::from(["x".to_owned()]);
+        let (checked, skipped) = slice_assertions(
+            vec![
+                assertion("x = 1", &["x"]),
+                assertion("y = 2", &["y"]),
+                assertion("x = y", &["x", "y"]),
+                assertion("True", &[]),
+            ],
+            &defined,
+        );
+        assert_eq!(
+            checked.iter().map(|a| a.term.as_str()).collect::<Vec<_>>(),
+            vec!["x = 1", "x = y", "True"]
+        );
+        assert_eq!(
+            skipped.iter().map(|a| a.term.as_str()).collect::<Vec<_>>(),
+            vec!["y = 2"]
+        );
+    }
+
+    #[test]
+    fn test_split_combined() {
+        let input = "(set-logic QF_S)\n(declare-const x String)\n(assert (= x \"sat)\"))\n; sat\n(check-sat)\n(get-model)\nsat\n(\n  (define-fun x () String \"sat)\")\n)\n";
+        let (script, model) = split_combined(input).unwrap();
+        assert_eq!(
+            script,
+            "(set-logic QF_S)\n(declare-const x String)\n(assert (= x \"sat)\"))\n; sat\n(check-sat)\n(get-model)\n"
+        );
+        assert_eq!(
+            solver_output::sanitize_model(&model, ""),
+            "(define-fun x () String \"sat)\")"
+        );
+    }
+
+    #[test]
+    fn test_split_combined_without_model() {
+        assert!(split_combined("(assert false)\n(check-sat)\nunsat\n").is_err());
+        assert!(split_combined("(assert true)\n(check-sat)\n").is_err());
+    }
+
+    /// Fails the lemmas whose conclusions mention `bad`
+    struct MockChecker;
+
+    impl LemmaChecker for MockChecker {
+        fn check(&mut self, lemma: &lemma::Lemma) -> Result<checker::CheckResult, Error> {
+            if lemma
+                .conclusions()
+                .iter()
+                .any(|c| c.contains("bad") || c == "False")
+            {
+                Ok(checker::CheckResult::FailedInvalid)
+            } else {
+                Ok(checker::CheckResult::OK)
+            }
+        }
+    }
+
+    #[test]
+    fn test_cached_verdict() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            cache_dir: Some(dir.path().join("cache")),
+            ..Default::default()
+        };
+        let formula = "(declare-const x Int)(assert (> x 0))";
+        let validate = |model: &str| {
+            let mut checker = Some(CheckerPipeline::with_checker(
+                Stage::Batch,
+                Box::new(MockChecker),
+            ));
+            validate_reusing(
+                model.to_owned(),
+                formula.to_owned(),
+                dir.path(),
+                &options,
+                &mut checker,
+            )
+        };
+        let (result, report) = validate("(define-fun x () Int 1)");
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+        assert!(!report.cached);
+        let (result, report) = validate("(define-fun x () Int 1)");
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+        assert!(report.cached);
+        assert!(!validate("(define-fun x () Int 2)").1.cached);
+        // Editing a theory of the theory root invalidates the cached verdicts
+        fs::write(dir.path().join("Strings.thy"), "theory Strings").unwrap();
+        assert!(!validate("(define-fun x () Int 1)").1.cached);
+        assert!(validate("(define-fun x () Int 1)").1.cached);
+    }
+
+    #[test]
+    fn test_cache_key_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = |options: &ValidationOptions| validation_key("f", "m", dir.path(), options);
+        let options = ValidationOptions::default();
+        let changed = [
+            ValidationOptions {
+                normalize: true,
+                ..Default::default()
+            },
+            ValidationOptions {
+                split_conclusions: true,
+                ..Default::default()
+            },
+            ValidationOptions {
+                chunk_size: Some(2),
+                ..Default::default()
+            },
+        ];
+        for other in &changed {
+            assert_ne!(key(&options), key(other));
+        }
+        assert_eq!(key(&options), key(&ValidationOptions::default()));
+    }
+
+    #[test]
+    fn test_custom_checker() {
+        let root = tempfile::tempdir().unwrap();
+        let mut options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            ..Default::default()
+        };
+        options.register_checker(checker::CustomChecker::new("mock", |_| {
+            Ok(Box::new(MockChecker))
+        }));
+        let formula = "(declare-const x Int)(assert (> x 0))";
+        let (result, report) = validate_with_report(
+            "(define-fun x () Int 1)".to_owned(),
+            formula.to_owned(),
+            root.path(),
+            &options,
+        );
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+        assert_eq!(report.stages[0].stage, Stage::Custom);
+        assert!(!report.evaluated);
+        let formula = "(declare-const bad Bool)(assert bad)";
+        let result = validate(
+            "(define-fun bad () Bool false)".to_owned(),
+            formula.to_owned(),
+            root.path(),
+            &options,
+        );
+        assert_eq!(result.unwrap(), ValidationResult::Invalid);
+    }
+
+    /// Checks lemmas by running `echo`, as the checkers run their prover
+    struct EchoChecker;
+
+    impl LemmaChecker for EchoChecker {
+        fn check(&mut self, lemma: &lemma::Lemma) -> Result<checker::CheckResult, Error> {
+            let mut command = tokio::process::Command::new("echo");
+            command.arg(lemma.name());
+            checker::run_command(&mut command, None).unwrap();
+            Ok(checker::CheckResult::OK)
+        }
+    }
+
+    #[test]
+    fn test_validate_async() {
+        let root = tempfile::tempdir().unwrap();
+        let mut options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            ..Default::default()
+        };
+        options.register_checker(checker::CustomChecker::new("echo", |_| {
+            Ok(Box::new(EchoChecker))
+        }));
+        // The checker runs its process on the caller's runtime, also on a single-threaded one
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let result = runtime.block_on(validate_async(
+            "(define-fun x () Int 1)".to_owned(),
+            "(declare-const x Int)(assert (> x 0))".to_owned(),
+            root.path().to_owned(),
+            options,
+        ));
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+    }
+
+    /// Checks lemmas by running `sleep`, which only finishes if it is killed
+    struct SleepChecker;
+
+    impl LemmaChecker for SleepChecker {
+        fn check(&mut self, _lemma: &lemma::Lemma) -> Result<checker::CheckResult, Error> {
+            let mut command = tokio::process::Command::new("sleep");
+            command.arg("60");
+            match checker::run_command(&mut command, None).unwrap() {
+                Some(_) => Ok(checker::CheckResult::OK),
+                None => Ok(checker::CheckResult::FailedUnknown(checker::interruption(
+                    "sleep",
+                ))),
+            }
+        }
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let root = tempfile::tempdir().unwrap();
+        let cancellation = cancel::Cancellation::new();
+        let mut options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            cancellation: Some(cancellation.clone()),
+            ..Default::default()
+        };
+        options.register_checker(checker::CustomChecker::new("sleep", |_| {
+            Ok(Box::new(SleepChecker))
+        }));
+        let (model, formula) = (
+            "(define-fun x () Int 1)",
+            "(declare-const x Int)(assert (> x 0))",
+        );
+        let start = Instant::now();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            cancellation.cancel();
+        });
+        let result = validate(model.to_owned(), formula.to_owned(), root.path(), &options);
+        assert_eq!(
+            result.unwrap(),
+            ValidationResult::Unknown(UnknownReason::Cancelled)
+        );
+        assert!(start.elapsed() < Duration::from_secs(30));
+
+        // Dropping the future of an async validation cancels it
+        let cancellation = cancel::Cancellation::new();
+        options.cancellation = Some(cancellation.clone());
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let result = runtime.block_on(async {
+            let validation = validate_async(
+                model.to_owned(),
+                formula.to_owned(),
+                root.path().to_owned(),
+                options,
+            );
+            tokio::time::timeout(Duration::from_millis(200), validation).await
+        });
+        assert!(result.is_err());
+        assert!(cancellation.is_cancelled());
+    }
+
+    #[test]
+    fn test_log_isabelle() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("logs").join("transcript.log");
+        let mut options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            log_isabelle: Some(path.clone()),
+            ..Default::default()
+        };
+        options.register_checker(checker::CustomChecker::new("echo", |_| {
+            Ok(Box::new(EchoChecker))
+        }));
+        let formula = "(declare-const x Int)(assert (> x 0))";
+        let result = validate(
+            "(define-fun x () Int 1)".to_owned(),
+            formula.to_owned(),
+            root.path(),
+            &options,
+        );
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+        let transcript = fs::read_to_string(&path).unwrap();
+        assert!(transcript.contains("====\necho "));
+        assert!(transcript.contains("echo exited after"));
+        assert!(transcript.contains("==== Result ["));
+        assert!(transcript.ends_with("====\nValid\n"));
+    }
+
+    #[test]
+    fn test_partial_model() {
+        let root = tempfile::tempdir().unwrap();
+        let mut options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            detailed_report: true,
+            ..Default::default()
+        };
+        options.register_checker(checker::CustomChecker::new("mock", |_| {
+            Ok(Box::new(MockChecker))
+        }));
+        options.pipeline = vec![Stage::Eval, Stage::Custom];
+        let formula = "(declare-const x Int)(declare-const y Int)(assert (> (+ x (* y y)) 0))";
+        let model = "(define-fun x () Int 1)";
+        let validate = |options: &ValidationOptions| {
+            validate_with_report(model.to_owned(), formula.to_owned(), root.path(), options)
+        };
+        let (result, report) = validate(&options);
+        assert_eq!(result.unwrap(), ValidationResult::Invalid);
+        assert_eq!(report.undefined_vars, ["y"]);
+
+        options.partial_model = PartialModel::Forall;
+        let (result, report) = validate(&options);
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+        assert_eq!(report.undefined_vars, ["y"]);
+        // The evaluation cannot decide the formula without a value for y
+        assert!(!report.evaluated);
+        let lemma = report.lemma.unwrap();
+        assert!(
+            lemma.contains("fixes x :: \"int\" and y :: \"int\" assumes \"x = (1::int)\" shows")
+        );
+
+        // With y = 0, the formula evaluates to true
+        options.partial_model = PartialModel::Default;
+        let (result, report) = validate(&options);
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+        assert!(report.undefined_vars.is_empty());
+        assert_eq!(report.completed_vars, ["y"]);
+        assert!(report.evaluated);
+
+        let formula =
+            "(declare-const x Int)(declare-const b Bool)(declare-const a (Array Int Int))\
+            (assert (or b (= (select a x) 0)))";
+        let model = "(define-fun x () Int 1)";
+        let (result, report) =
+            validate_with_report(model.to_owned(), formula.to_owned(), root.path(), &options);
+        assert_eq!(result.unwrap(), ValidationResult::Invalid);
+        assert_eq!(report.undefined_vars, ["a", "b"]);
+        assert!(report.completed_vars.is_empty());
+    }
+
+    /// Proves lemmas iff a premise assigns `x`
+    struct AssignsX;
+
+    impl LemmaChecker for AssignsX {
+        fn check(&mut self, lemma: &lemma::Lemma) -> Result<checker::CheckResult, Error> {
+            if lemma.premises().iter().any(|p| p.starts_with("x = ")) {
+                Ok(checker::CheckResult::OK)
+            } else {
+                Ok(checker::CheckResult::FailedUnknown(
+                    UnknownReason::TacticFailure,
+                ))
+            }
+        }
+    }
+
+    #[test]
+    fn test_model_core() {
+        let root = tempfile::tempdir().unwrap();
+        let mut options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            model_core: true,
+            ..Default::default()
+        };
+        options.register_checker(checker::CustomChecker::new("core", |_| {
+            Ok(Box::new(AssignsX))
+        }));
+        let formula =
+            "(declare-const x Int)(declare-const y Int)(declare-const z Int)(assert (> x y))";
+        let model = "(define-fun x () Int 2)(define-fun y () Int 1)(define-fun z () Int 3)";
+        let (result, report) =
+            validate_with_report(model.to_owned(), formula.to_owned(), root.path(), &options);
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+        // z is not used at all, and the proof does not need y
+        assert_eq!(report.unused_assignments.unwrap(), ["y", "z"]);
+
+        // Evaluation needs all variables the formula uses
+        options.pipeline = vec![Stage::Eval];
+        let (result, report) =
+            validate_with_report(model.to_owned(), formula.to_owned(), root.path(), &options);
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+        assert_eq!(report.unused_assignments.unwrap(), ["z"]);
+    }
+
+    #[test]
+    fn test_string_conversions() {
+        let root = tempfile::tempdir().unwrap();
+        let options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            pipeline: vec![Stage::Eval],
+            ..Default::default()
+        };
+        let formula = "(declare-const s String)(declare-const i Int)(declare-const c Int)\
+            (assert (= i (str.to_int s)))(assert (= s (str.from_int i)))(assert (= c (str.to_code (str.at s 0))))\
+            (assert (= (str.from_code c) (str.substr s 0 1)))(assert (str.is_digit (str.at s 1)))";
+        let validate = |model: &str| {
+            let (result, _) =
+                validate_with_report(model.to_owned(), formula.to_owned(), root.path(), &options);
+            result.unwrap()
+        };
+        let valid =
+            "(define-fun s () String \"42\")(define-fun i () Int 42)(define-fun c () Int 52)";
+        assert_eq!(validate(valid), ValidationResult::Valid);
+        // Strings that are not numerals convert to -1, and -1 converts to the empty string
+        let not_numeral =
+            "(define-fun s () String \"4a\")(define-fun i () Int (- 1))(define-fun c () Int 52)";
+        assert_eq!(validate(not_numeral), ValidationResult::Invalid);
+        let wrong_code =
+            "(define-fun s () String \"42\")(define-fun i () Int 42)(define-fun c () Int 53)";
+        assert_eq!(validate(wrong_code), ValidationResult::Invalid);
+    }
+
+    #[test]
+    fn test_replace_functions() {
+        let root = tempfile::tempdir().unwrap();
+        let options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            pipeline: vec![Stage::Eval],
+            ..Default::default()
+        };
+        let formula = "(declare-const s String)(declare-const t String)\
+            (assert (= t (str.replace_re_all s (re.+ (re.range \"0\" \"9\")) \"#\")))\
+            (assert (= (str.replace_re t (str.to_re \"#\") \"\") (str.replace_all \"a-#\" \"-\" \"\")))";
+        let validate = |model: &str| {
+            let (result, _) =
+                validate_with_report(model.to_owned(), formula.to_owned(), root.path(), &options);
+            result.unwrap()
+        };
+        assert_eq!(
+            validate("(define-fun s () String \"a12\")(define-fun t () String \"a##\")"),
+            ValidationResult::Valid
+        );
+        assert_eq!(
+            validate("(define-fun s () String \"a12\")(define-fun t () String \"a#\")"),
+            ValidationResult::Invalid
+        );
+        // Model values may apply the functions as well
+        assert_eq!(
+            validate(
+                "(define-fun s () String \"a12\")\
+                (define-fun t () String (str.replace_re_all \"a12\" (re.range \"0\" \"9\") \"#\"))"
+            ),
+            ValidationResult::Valid
+        );
     }
 
     #[test]
-    fn test_sanitize_model_sat_old_z3() {
-        let model = "sat\n(model (define-fun x () Int 1))".to_owned();
-        assert_eq!(sanitize_model(&model), "(define-fun x () Int 1)".to_owned());
+    fn test_solver_symbols() {
+        let root = tempfile::tempdir().unwrap();
+        let options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            pipeline: vec![Stage::Eval],
+            ..Default::default()
+        };
+        let formula = "(declare-const s String)(assert (= (str.len s) 2))(assert (str.in_re s (re.* (str.to_re \"a\"))))";
+        let validate = |model: &str| {
+            validate_with_report(model.to_owned(), formula.to_owned(), root.path(), &options)
+        };
+        let (result, report) = validate(
+            "(define-fun s () String (seq.++ (seq.unit (_ Char 97)) (seq.unit (_ Char 97))))",
+        );
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+        assert!(report.internal_symbols.is_empty());
+
+        let (result, report) =
+            validate("(define-fun s () String (seq.++ (seq.unit (_ Char 97)) (seq.nth_u s!0 1)))");
+        assert!(matches!(result.unwrap(), ValidationResult::Unknown(_)));
+        assert_eq!(report.internal_symbols, ["s!0", "seq.nth_u"]);
+    }
+
+    #[test]
+    fn test_smtlib25() {
+        let root = tempfile::tempdir().unwrap();
+        let mut options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            pipeline: vec![Stage::Eval],
+            ..Default::default()
+        };
+        let formula = "(declare-const s String)(declare-const i Int)\
+            (assert (= i (str.to.int s)))(assert (str.in.re s (str.to.re \"\\x34\\x32\")))";
+        let model = "(define-fun s () String \"\\x34\\x32\")(define-fun i () Int 42)";
+        let (result, _) =
+            validate_with_report(model.to_owned(), formula.to_owned(), root.path(), &options);
+        // Without the compatibility layer, the old names are unknown functions
+        assert!(matches!(
+            result.unwrap(),
+            ValidationResult::Unknown(UnknownReason::Unsupported(op)) if op.starts_with("str.to.int")
+        ));
+
+        options.smtlib25 = true;
+        let (result, _) =
+            validate_with_report(model.to_owned(), formula.to_owned(), root.path(), &options);
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+        let model = "(define-fun s () String \"\\x34\")(define-fun i () Int 4)";
+        let (result, _) =
+            validate_with_report(model.to_owned(), formula.to_owned(), root.path(), &options);
+        assert_eq!(result.unwrap(), ValidationResult::Invalid);
+    }
+
+    #[test]
+    fn test_stray_assignments() {
+        let root = tempfile::tempdir().unwrap();
+        let mut options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            pipeline: vec![Stage::Eval],
+            ..Default::default()
+        };
+        let formula = "(declare-const x Int)(declare-const count Int)(assert (> x count))";
+        let model = "(define-fun X () Int 2)(define-fun cuont () Int 1)(define-fun x!1 () Int 2)\
+            (define-fun x () Int x!1)(define-fun other () Int 3)";
+        let (result, report) =
+            validate_with_report(model.to_owned(), formula.to_owned(), root.path(), &options);
+        assert_eq!(result.unwrap(), ValidationResult::Invalid);
+        assert_eq!(report.undefined_vars, ["count"]);
+        // The helper constant x!1 is not stray, and x is assigned, so X is not a typo of it
+        let stray = |var: &str, similar: Option<&str>| StrayAssignment {
+            var: var.to_owned(),
+            similar: similar.map(str::to_owned),
+        };
+        assert_eq!(
+            report.stray_assignments,
+            [
+                stray("X", None),
+                stray("cuont", Some("count")),
+                stray("other", None)
+            ]
+        );
+
+        options.strict_model = true;
+        let (result, _) =
+            validate_with_report(model.to_owned(), formula.to_owned(), root.path(), &options);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: The variables of the model do not match the formula:\n  \
+            - count (used by the formula, not assigned)\n  \
+            + X (assigned, not declared by the formula)\n  \
+            + cuont (assigned, not declared by the formula; did you mean count?)\n  \
+            + other (assigned, not declared by the formula)"
+        );
+        let model = "(define-fun x () Int 2)(define-fun count () Int 1)";
+        let (result, _) =
+            validate_with_report(model.to_owned(), formula.to_owned(), root.path(), &options);
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+    }
+
+    #[test]
+    fn test_renamed_vars() {
+        let root = tempfile::tempdir().unwrap();
+        let mut options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            detailed_report: true,
+            ..Default::default()
+        };
+        options.register_checker(checker::CustomChecker::new("mock", |_| {
+            Ok(Box::new(MockChecker))
+        }));
+        let formula = "(declare-const x!0 Int)(declare-const value Int)(assert (> x!0 value))";
+        let model = "(define-fun x!0 () Int 2)(define-fun value () Int 1)";
+        let (result, report) =
+            validate_with_report(model.to_owned(), formula.to_owned(), root.path(), &options);
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+        let lemma = report.lemma.unwrap();
+        assert!(lemma.contains("fixes smt_x_21_0 :: \"int\" and smt_value :: \"int\""));
+        assert!(lemma.contains("assumes \"smt_x_21_0 = (2::int)\" and \"smt_value = (1::int)\""));
+        assert_eq!(
+            report.renamed_vars,
+            [("smt_value", "value"), ("smt_x_21_0", "x!0")]
+                .map(|(n, s)| (n.to_owned(), s.to_owned()))
+                .into()
+        );
+    }
+
+    #[test]
+    fn test_quoted_symbols() {
+        let root = tempfile::tempdir().unwrap();
+        let options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            pipeline: vec![Stage::Eval],
+            ..Default::default()
+        };
+        let formula =
+            "(declare-const |hello world| Int)(declare-fun |f g| (Int) Int)(declare-const x Int)\
+            (assert (> (|f g| |hello world|) x))";
+        // `|x|` is the symbol `x`
+        for model in [
+            "(define-fun |hello world| () Int 1)(define-fun |f g| ((|a b| Int)) Int (+ |a b| 1))\
+            (define-fun |x| () Int 1)",
+            "(define-fun |f g| ((a Int)) Int a)((|hello world| 2) (|x| 1))",
+        ] {
+            let (result, report) =
+                validate_with_report(model.to_owned(), formula.to_owned(), root.path(), &options);
+            assert_eq!(result.unwrap(), ValidationResult::Valid, "{}", model);
+            assert!(report.undefined_vars.is_empty());
+            assert!(report.stray_assignments.is_empty());
+            assert_eq!(report.renamed_vars["smt_hello_20_world"], "hello world");
+        }
+    }
+
+    #[test]
+    fn similar_names() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "ab"), 2);
+        assert_eq!(edit_distance("count", "cuont"), 1);
+        let candidates = ["Length", "len", "y"].map(str::to_owned);
+        let candidates: Vec<&String> = candidates.iter().collect();
+        assert_eq!(similar_name("length", &candidates), Some("Length"));
+        assert_eq!(similar_name("lenght", &candidates), Some("Length"));
+        assert_eq!(similar_name("lem", &candidates), Some("len"));
+        assert_eq!(similar_name("x", &candidates), Some("y"));
+        assert_eq!(similar_name("size", &candidates), None);
+    }
+
+    #[test]
+    fn test_evaluated_verdict() {
+        let root = tempfile::tempdir().unwrap();
+        let options = ValidationOptions {
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            pipeline: vec![Stage::Eval],
+            ..Default::default()
+        };
+        let formula = "(declare-const x String)(assert (str.prefixof \"a\" (str.++ x \"b\")))";
+        // Without an Isabelle stage, the verdict can only come from the evaluation
+        let validate = |model: &str| {
+            validate_reusing(
+                model.to_owned(),
+                formula.to_owned(),
+                root.path(),
+                &options,
+                &mut None,
+            )
+        };
+        let (result, report) = validate("(define-fun x () String \"ab\")");
+        assert_eq!(result.unwrap(), ValidationResult::Valid);
+        assert!(report.evaluated);
+        assert_eq!(report.assertions[0].status, AssertionStatus::Valid);
+        let (result, report) = validate("(define-fun x () String \"\")");
+        assert_eq!(result.unwrap(), ValidationResult::Invalid);
+        assert!(report.evaluated);
+    }
+
+    #[test]
+    fn test_lenient() {
+        let root = tempfile::tempdir().unwrap();
+        let validate = |formula: &str, lenient: bool| {
+            let options = ValidationOptions {
+                builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+                lenient,
+                ..Default::default()
+            };
+            let mut checker = Some(CheckerPipeline::with_checker(
+                Stage::Batch,
+                Box::new(MockChecker),
+            ));
+            validate_reusing(
+                "(define-fun x () Int 1)(define-fun bad () Bool true)".to_owned(),
+                formula.to_owned(),
+                root.path(),
+                &options,
+                &mut checker,
+            )
+        };
+        let formula =
+            "(declare-const x Int)(declare-const y Int)\n(assert (> x 0))\n(assert (= (foo y) 1))";
+        let unsupported = ValidationResult::Unknown(UnknownReason::Unsupported(
+            "foo in (foo y) (assertion 1 at 3:1)".to_owned(),
+        ));
+        assert_eq!(validate(formula, false).0.unwrap(), unsupported);
+
+        let (result, report) = validate(formula, true);
+        assert_eq!(result.unwrap(), unsupported);
+        assert_eq!(
+            report
+                .assertions
+                .iter()
+                .map(|a| a.status)
+                .collect::<Vec<_>>(),
+            [AssertionStatus::Valid, AssertionStatus::Unknown]
+        );
+        assert!(report.undefined_vars.is_empty());
+
+        let formula = format!("{}(declare-const bad Bool)(assert bad)", formula);
+        assert_eq!(
+            validate(&formula, true).0.unwrap(),
+            ValidationResult::Invalid
+        );
+    }
+
+    #[test]
+    fn test_slice() {
+        let root = tempfile::tempdir().unwrap();
+        let validate = |formula: &str| {
+            let options = ValidationOptions {
+                builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+                slice: true,
+                ..Default::default()
+            };
+            let mut checker = Some(CheckerPipeline::with_checker(
+                Stage::Batch,
+                Box::new(MockChecker),
+            ));
+            validate_reusing(
+                "(define-fun x () Int 1)".to_owned(),
+                formula.to_owned(),
+                root.path(),
+                &options,
+                &mut checker,
+            )
+        };
+        let formula = "(declare-const x Int)(declare-const y Int)(assert (> x 0))";
+        assert_eq!(validate(formula).0.unwrap(), ValidationResult::Valid);
+
+        let formula_false = format!("{}(assert false)(assert (> y 0))", formula);
+        assert_eq!(
+            validate(&formula_false).0.unwrap(),
+            ValidationResult::Invalid
+        );
+
+        let (result, report) = validate(&format!("{}(assert (> y 0))", formula));
+        assert_eq!(
+            result.unwrap(),
+            ValidationResult::Unknown(UnknownReason::Sliced(1))
+        );
+        assert_eq!(
+            report
+                .assertions
+                .iter()
+                .map(|a| a.status)
+                .collect::<Vec<_>>(),
+            [AssertionStatus::Valid, AssertionStatus::Skipped]
+        );
+    }
+
+    #[test]
+    fn test_refuted_with_uninterpreted() {
+        let root = tempfile::tempdir().unwrap();
+        let validate = |uninterpreted: &[&str]| {
+            let options = ValidationOptions {
+                builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+                uninterpreted: uninterpreted.iter().map(|f| f.to_string()).collect(),
+                ..Default::default()
+            };
+            let mut checker = Some(CheckerPipeline::with_checker(
+                Stage::Batch,
+                Box::new(MockChecker),
+            ));
+            validate_reusing(
+                "(define-fun bad () Int 1)".to_owned(),
+                "(declare-const bad Int)(assert (= (str.len \"a\") bad))".to_owned(),
+                root.path(),
+                &options,
+                &mut checker,
+            )
+        };
+        assert_eq!(validate(&[]).0.unwrap(), ValidationResult::Invalid);
+
+        let (result, report) = validate(&["str.len"]);
+        let reason = UnknownReason::Uninterpreted(vec!["str.len".to_owned()]);
+        assert_eq!(
+            reason.to_string(),
+            "refuted with uninterpreted functions: str.len"
+        );
+        assert_eq!(result.unwrap(), ValidationResult::Unknown(reason));
+        assert_eq!(report.assertions[0].status, AssertionStatus::Unknown);
+    }
+
+    #[test]
+    fn test_assertion_status_per_chunk() {
+        let mut lemma = lemma::Lemma::new("validation");
+        lemma.add_conclusions(["ok", "bad", "ok"]);
+        let mut assertions: Vec<_> = (0..3)
+            .map(|index| AssertionReport {
+                index,
+                name: None,
+                status: AssertionStatus::Unchecked,
+                unsupported: None,
+            })
+            .collect();
+        let result = check_lemma(&mut MockChecker, &lemma, Some(1), &mut assertions).unwrap();
+        assert_eq!(result, checker::CheckResult::FailedInvalid);
+        assert_eq!(
+            assertions.iter().map(|a| a.status).collect::<Vec<_>>(),
+            vec![
+                AssertionStatus::Valid,
+                AssertionStatus::Invalid,
+                AssertionStatus::Unchecked
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assertion_status_per_conclusion() {
+        let mut lemma = lemma::Lemma::new("validation");
+        lemma.add_conclusions(["ok", "bad", "ok"]);
+        let mut assertions: Vec<_> = (0..3)
+            .map(|index| AssertionReport {
+                index,
+                name: None,
+                status: AssertionStatus::Unchecked,
+                unsupported: None,
+            })
+            .collect();
+        let result = check_split(&mut MockChecker, &lemma, &mut assertions).unwrap();
+        assert_eq!(result, checker::CheckResult::FailedInvalid);
+        // Unlike chunks, all conclusions are checked
+        assert_eq!(
+            assertions.iter().map(|a| a.status).collect::<Vec<_>>(),
+            vec![
+                AssertionStatus::Valid,
+                AssertionStatus::Invalid,
+                AssertionStatus::Valid
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_undefined_variables() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(
+            root.path().join("spec.json"),
+            r#"{ "version": "0.1", "smt-lib-version": "2.6",
+                 "specs": { "Core": { "=": { "mapsto": "=", "chainable": true } } } }"#,
+        )
+        .unwrap();
+        let (result, report) = validate_with_report(
+            "((define-fun x () Int 1))".to_owned(),
+            "(assert (= x y))(assert (= z 1))".to_owned(),
+            root.path(),
+            &ValidationOptions::default(),
+        );
+        assert_eq!(result.unwrap(), ValidationResult::Invalid);
+        assert_eq!(report.undefined_vars, vec!["y", "z"]);
+        assert_eq!(report.assertions.len(), 2);
+    }
+
+    #[test]
+    fn test_emit_only() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(
+            root.path().join("spec.json"),
+            r#"{ "version": "0.1", "smt-lib-version": "2.6",
+                 "specs": { "Core": { "=": { "mapsto": "=", "chainable": true } } } }"#,
+        )
+        .unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let options = ValidationOptions {
+            emit_only: Some(out.path().to_owned()),
+            ..Default::default()
+        };
+        let result = validate(
+            "((define-fun x () Int 1))".to_owned(),
+            "(assert (= x 1))".to_owned(),
+            root.path(),
+            &options,
+        );
+        assert_eq!(
+            result.unwrap(),
+            ValidationResult::Unknown(UnknownReason::NotChecked)
+        );
+        let theory = std::fs::read_to_string(out.path().join("Validation.thy")).unwrap();
+        assert!(theory.starts_with("theory Validation"));
+        assert!(theory.contains("lemma validation"));
+    }
+
+    #[test]
+    fn test_substituted_model_proven_by_eval() {
+        let root = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let mut options = ValidationOptions {
+            emit_only: Some(out.path().to_owned()),
+            builtin_spec: Some(convert::BuiltinSpec::QfSlia),
+            substitute_model: true,
+            ..Default::default()
+        };
+        let emit = |formula: &str, options: &ValidationOptions| {
+            validate(
+                "((define-fun x () Int 2))".to_owned(),
+                formula.to_owned(),
+                root.path(),
+                options,
+            )
+            .unwrap();
+            std::fs::read_to_string(out.path().join("Validation.thy")).unwrap()
+        };
+
+        let theory = emit("(declare-const x Int)(assert (> x 1))", &options);
+        assert!(
+            theory.contains("lemma validation: shows \"((>)  (2::int) (1::int))\"\n    by eval\n")
+        );
+        // Quantifiers are not executable
+        let theory = emit(
+            "(declare-const x Int)(assert (forall ((y Int)) (> (+ x (* y y)) 1)))",
+            &options,
+        );
+        assert!(theory.contains("apply(simp add: assms)"));
+        // Without substitution, the model is a premise
+        options.substitute_model = false;
+        let theory = emit("(declare-const x Int)(assert (> x 1))", &options);
+        assert!(theory.contains("assumes \"x = (2::int)\""));
+        assert!(theory.contains("apply(simp add: assms)"));
     }
 }